@@ -0,0 +1,32 @@
+//! Whole analyses of generated circuits, reporting the size of the tableau alongside the time taken
+mod common;
+
+use common::bench;
+use hugr::builder::{endo_sig, DFGBuilder, Dataflow, DataflowHugr};
+use hugr::extension::prelude::qb_t;
+use hugr::{Hugr, HugrView};
+use tket::TketOp;
+use tket2dataflow::stabilizer_dataflow::{AnalysisConfig, StabilizerDataflow};
+
+// A chain of n T gates on one qubit, each adding an internal input and output column to the analysis
+fn t_chain(n: usize) -> Hugr {
+    let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+    let [mut qb] = builder.input_wires_arr();
+    for _ in 0..n {
+        [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+    }
+    builder.finish_hugr_with_outputs([qb]).unwrap()
+}
+
+fn main() {
+    // Without garbage collection the tableau has two columns per T gate; collecting every internal column every 32 nodes bounds it
+    for n in [250, 500, 1000, 2000] {
+        let hugr = t_chain(n);
+        let configs = [("no gc", AnalysisConfig::default_opaque()), ("gc every 32 nodes", AnalysisConfig::default_opaque().with_column_gc(32, |_| false))];
+        for (name, config) in configs {
+            bench(&format!("T chain of {n}, {name}"), 5, || config.clone(), |config| StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), config).unwrap());
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), config).unwrap();
+            println!("  {} columns", analysis.tableau().nb_qubits);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! BitVector::set_range against setting each bit in turn, on a 10000-bit vector
+mod common;
+
+use common::bench;
+use tket2dataflow::bit_vector::BitVector;
+
+const NB_BITS: usize = 10000;
+
+fn main() {
+    // A range starting and ending mid-lane, so both the masked edges and the whole blocks between them are covered
+    let (start, end) = (3, NB_BITS - 5);
+    let per_bit = bench("set bits one at a time", 1000, || BitVector::new(NB_BITS), |mut bv| {
+        for bit in start..end {
+            if !bv.get(bit) {
+                bv.xor_bit(bit);
+            }
+        }
+        bv
+    });
+    let ranged = bench("set_range", 1000, || BitVector::new(NB_BITS), |mut bv| {
+        bv.set_range(start, end, true);
+        bv
+    });
+    bench("fill", 1000, || BitVector::new(NB_BITS), |mut bv| {
+        bv.fill(true);
+        bv
+    });
+    println!("set_range speedup: {:.0}x", per_bit.as_secs_f64() / ranged.as_secs_f64());
+}
@@ -0,0 +1,19 @@
+//! Timing shared by the benchmarks, which run as plain binaries (harness = false) so that they build on stable
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Runs f on a fresh input from setup iters times, printing and returning the mean time taken by f alone (without building its input or dropping its output)
+pub fn bench<T, R>(name: &str, iters: u32, mut setup: impl FnMut() -> T, mut f: impl FnMut(T) -> R) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..iters {
+        let input = setup();
+        let start = Instant::now();
+        let output = black_box(f(black_box(input)));
+        total += start.elapsed();
+        // Dropping the output is not part of what is timed
+        drop(output);
+    }
+    let mean = total / iters;
+    println!("{name}: {mean:?}");
+    mean
+}
@@ -0,0 +1,31 @@
+//! StabilizerDataflow::run_module_par against run_module on a module of 8 independent functions, each a chain of T gates
+mod common;
+
+use common::bench;
+use hugr::builder::{Dataflow, DataflowSubContainer, HugrBuilder, ModuleBuilder};
+use hugr::extension::prelude::qb_t;
+use hugr::types::Signature;
+use hugr::Hugr;
+use tket::TketOp;
+use tket2dataflow::stabilizer_dataflow::{FunctionOpacity, StabilizerDataflow};
+
+fn independent_functions(n_functions: usize, n_gates: usize) -> Hugr {
+    let mut module = ModuleBuilder::new();
+    for i in 0..n_functions {
+        let mut fun_builder = module.define_function(format!("f{i}"), Signature::new_endo(vec![qb_t()])).unwrap();
+        let [mut qb] = fun_builder.input_wires_arr();
+        for _ in 0..n_gates {
+            [qb] = fun_builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        }
+        fun_builder.finish_with_outputs([qb]).unwrap();
+    }
+    module.finish_hugr().unwrap()
+}
+
+fn main() {
+    let hugr = independent_functions(8, 500);
+    println!("{} threads", rayon::current_num_threads());
+    let serial = bench("run_module", 5, || (), |()| StabilizerDataflow::run_module(&hugr, &FunctionOpacity::Opaque).unwrap());
+    let parallel = bench("run_module_par", 5, || (), |()| StabilizerDataflow::run_module_par(&hugr, &FunctionOpacity::Opaque).unwrap());
+    println!("run_module_par speedup: {:.1}x", serial.as_secs_f64() / parallel.as_secs_f64());
+}
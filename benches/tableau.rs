@@ -0,0 +1,38 @@
+//! Bulk updates of TableauColumnMajor against the step-by-step updates they replace
+mod common;
+
+use common::bench;
+use tket2dataflow::tableau::TableauColumnMajor;
+
+fn main() {
+    // Extending a tableau with 1000 stabilizer rows by 100 qubits at once, or one qubit at a time as apply_analysis used to
+    let at_once = bench("extend by 100 qubits", 20, || TableauColumnMajor::new(1000), |mut tab| {
+        tab.extend(100);
+        tab
+    });
+    let one_by_one = bench("extend by 1 qubit 100 times", 20, || TableauColumnMajor::new(1000), |mut tab| {
+        for _ in 0..100 {
+            tab.extend(1);
+        }
+        tab
+    });
+    println!("extend speedup: {:.1}x", one_by_one.as_secs_f64() / at_once.as_secs_f64());
+
+    // prepend_cz against CZ as CX conjugated by H on the target, on an entangled 2000-qubit state
+    let mut entangled = TableauColumnMajor::new(2000);
+    for q in 0..2000 {
+        entangled.prepend_h(q);
+        entangled.prepend_cx(vec![q, (q + 1) % 2000]);
+    }
+    let direct = bench("prepend_cz", 1000, || entangled.clone(), |mut tab| {
+        tab.prepend_cz(vec![0, 1000]);
+        tab
+    });
+    let decomposed = bench("prepend_h, prepend_cx, prepend_h", 1000, || entangled.clone(), |mut tab| {
+        tab.prepend_h(1000);
+        tab.prepend_cx(vec![0, 1000]);
+        tab.prepend_h(1000);
+        tab
+    });
+    println!("prepend_cz speedup: {:.1}x", decomposed.as_secs_f64() / direct.as_secs_f64());
+}
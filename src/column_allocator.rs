@@ -0,0 +1,390 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use hugr::PortIndex;
+use hugr_core::{Direction, IncomingPort, OutgoingPort, Port};
+use itertools::Itertools;
+use crate::stabilizer_dataflow::NodePath;
+
+/// The qubits packed into a tuple or array wire, each as its position within the (possibly nested) tuple along with its column
+pub(crate) type PackedQubits = Vec<(Vec<usize>, usize)>;
+
+/// The kind of wire of the program that a column of the tableau is tracking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColumnRole {
+    /// An input qubit of the region
+    Input,
+    /// An output qubit of the region
+    Output,
+    /// The current end of a qubit wire, waiting to be consumed by the next node
+    Frontier,
+    /// An input qubit of a non-Clifford, opaque or hierarchical node
+    InternalIn,
+    /// An output qubit of a non-Clifford, opaque or hierarchical node
+    InternalOut,
+    /// The input qubit of a hierarchical node as seen from within its nested analysis
+    NestedIn,
+    /// The output qubit of a hierarchical node as seen from within its nested analysis
+    NestedOut,
+}
+
+/// Names a qubit wire of an analysed region, for querying the relations between wires
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WireRef<N> {
+    /// The input qubit on the given port of the region's Input node
+    Input(OutgoingPort),
+    /// The output qubit on the given port of the region's Output node
+    Output(IncomingPort),
+    /// A qubit port of the non-Clifford, opaque or hierarchical node at the given path, on its input or output side according to the direction of the port
+    NodePort(NodePath<N>, Port),
+}
+
+impl<N> WireRef<N> {
+    /// A qubit port of a node directly within the region
+    pub fn node_port(node: N, port: impl Into<Port>) -> Self {
+        WireRef::NodePort(vec![node], port.into())
+    }
+}
+
+/// Maps from wires of the program to columns of the tableau. We separately need to track columns for:
+/// - Each input qubit (indexed by OutgoingPorts of the unique Input node)
+/// - Each output qubit (indexed by IncomingPorts of the unique Output node)
+/// - A frontier that moves forward through the program (eventually becoming the output qubits and being removed from here)
+/// - Qubits packed into a tuple or array, as part of the frontier keyed by the port the tuple wire goes into, along with the position of each qubit within the (possibly nested) tuple
+/// - For any internal non-Clifford (or opaque) node, we use columns for each input and output qubit separately; for nodes with stabilizers across them (e.g. Rz has Z_i Z_o), we impose these via projections on the tableau rather than reducing the number of qubits used as this allows every node kind to be handled identically and preventing more tableau management from column elimination
+/// - For any hierarchical node, we use additional columns for each input and output port within their internal representation that we compose to "internal" columns here by projections on the tableau, again so we don't fuss with column elimination
+///
+/// Internal and nested columns are keyed by NodePath so that the internals of the same function inlined at multiple call sites are kept apart
+#[derive(Debug, Clone)]
+pub struct ColumnAllocator<N> {
+    in_cols: HashMap<OutgoingPort, usize>,
+    out_cols: HashMap<IncomingPort, usize>,
+    frontier_cols: HashMap<(N, IncomingPort), usize>,
+    packed_cols: HashMap<(N, IncomingPort), PackedQubits>,
+    internal_in_cols: HashMap<(NodePath<N>, IncomingPort), usize>,
+    internal_out_cols: HashMap<(NodePath<N>, OutgoingPort), usize>,
+    nested_in_cols: HashMap<(NodePath<N>, OutgoingPort), usize>,
+    nested_out_cols: HashMap<(NodePath<N>, IncomingPort), usize>,
+}
+
+impl<N> Default for ColumnAllocator<N> {
+    fn default() -> Self {
+        ColumnAllocator {
+            in_cols: HashMap::default(),
+            out_cols: HashMap::default(),
+            frontier_cols: HashMap::default(),
+            packed_cols: HashMap::default(),
+            internal_in_cols: HashMap::default(),
+            internal_out_cols: HashMap::default(),
+            nested_in_cols: HashMap::default(),
+            nested_out_cols: HashMap::default(),
+        }
+    }
+}
+
+impl<N: Copy + Eq + Hash + Debug> ColumnAllocator<N> {
+    pub fn new() -> Self {
+        ColumnAllocator::default()
+    }
+
+    /// Starts with just the given columns for the input and output qubits, as for the summary of a control-flow node
+    pub(crate) fn with_boundary(in_cols: HashMap<OutgoingPort, usize>, out_cols: HashMap<IncomingPort, usize>) -> Self {
+        ColumnAllocator {
+            in_cols,
+            out_cols,
+            ..ColumnAllocator::default()
+        }
+    }
+
+    /// The column of the input qubit on the given port of the region's Input node
+    pub fn column_for_input(&self, port: OutgoingPort) -> Option<usize> {
+        self.in_cols.get(&port).copied()
+    }
+
+    /// The column of the output qubit on the given port of the region's Output node
+    pub fn column_for_output(&self, port: IncomingPort) -> Option<usize> {
+        self.out_cols.get(&port).copied()
+    }
+
+    /// The frontier column for the qubit wire into the given port of node, if the analysis has reached it but not yet passed node
+    pub fn frontier(&self, node: N, port: IncomingPort) -> Option<usize> {
+        self.frontier_cols.get(&(node, port)).copied()
+    }
+
+    /// The column of the qubit at the position slots within the tuple (or Sum) wire into port of node, if the analysis followed it there
+    pub fn column_for_packed(&self, node: N, port: IncomingPort, slots: &[usize]) -> Option<usize> {
+        self.packed_cols.get(&(node, port))?.iter().find(|(s, _)| s == slots).map(|(_, col)| *col)
+    }
+
+    /// The internal column for a qubit port of the node at path, on its input or output side according to the direction of port
+    pub fn column_for_node_port(&self, path: &[N], port: impl Into<Port>) -> Option<usize> {
+        let port = port.into();
+        match port.direction() {
+            Direction::Incoming => self.internal_in_cols.get(&(path.to_vec(), IncomingPort::from(port.index()))).copied(),
+            Direction::Outgoing => self.internal_out_cols.get(&(path.to_vec(), OutgoingPort::from(port.index()))).copied(),
+        }
+    }
+
+    /// The column within the nested analysis of the hierarchical node at path for one of its qubit ports, i.e. the column of the nested Input for an incoming port or of the nested Output for an outgoing port
+    pub fn column_for_nested_port(&self, path: &[N], port: impl Into<Port>) -> Option<usize> {
+        let port = port.into();
+        match port.direction() {
+            Direction::Incoming => self.nested_in_cols.get(&(path.to_vec(), OutgoingPort::from(port.index()))).copied(),
+            Direction::Outgoing => self.nested_out_cols.get(&(path.to_vec(), IncomingPort::from(port.index()))).copied(),
+        }
+    }
+
+    /// The column for the wire, if it is tracked
+    pub fn column(&self, wire: &WireRef<N>) -> Option<usize> {
+        match wire {
+            WireRef::Input(port) => self.column_for_input(*port),
+            WireRef::Output(port) => self.column_for_output(*port),
+            WireRef::NodePort(path, port) => self.column_for_node_port(path, *port),
+        }
+    }
+
+    /// The qubit ports of the node at path with their internal columns, inputs then outputs, each in port order
+    pub fn node_ports(&self, path: &[N]) -> Vec<(Port, usize)> {
+        let ins = self.internal_in_cols.iter().filter(|((p, _), _)| p == path).map(|((_, port), col)| (Port::from(*port), *col)).sorted_by_key(|(port, _)| port.index());
+        let outs = self.internal_out_cols.iter().filter(|((p, _), _)| p == path).map(|((_, port), col)| (Port::from(*port), *col)).sorted_by_key(|(port, _)| port.index());
+        ins.chain(outs).collect()
+    }
+
+    /// The input qubits of the region with their columns, in port order
+    pub fn inputs(&self) -> impl Iterator<Item = (OutgoingPort, usize)> + '_ {
+        self.in_cols.iter().map(|(p, col)| (*p, *col)).sorted_by_key(|(p, _)| p.index())
+    }
+
+    /// The output qubits of the region with their columns, in port order
+    pub fn outputs(&self) -> impl Iterator<Item = (IncomingPort, usize)> + '_ {
+        self.out_cols.iter().map(|(p, col)| (*p, *col)).sorted_by_key(|(p, _)| p.index())
+    }
+
+    /// The input qubits of every internal node with their columns, identifying each node by its path
+    pub fn internal_inputs(&self) -> impl Iterator<Item = (&NodePath<N>, IncomingPort, usize)> + '_ {
+        self.internal_in_cols.iter().map(|((path, p), col)| (path, *p, *col))
+    }
+
+    /// The number of columns with the given role
+    pub fn count(&self, role: ColumnRole) -> usize {
+        match role {
+            ColumnRole::Input => self.in_cols.len(),
+            ColumnRole::Output => self.out_cols.len(),
+            ColumnRole::Frontier => self.frontier_cols.len() + self.packed_cols.values().map(|packed| packed.len()).sum::<usize>(),
+            ColumnRole::InternalIn => self.internal_in_cols.len(),
+            ColumnRole::InternalOut => self.internal_out_cols.len(),
+            ColumnRole::NestedIn => self.nested_in_cols.len(),
+            ColumnRole::NestedOut => self.nested_out_cols.len(),
+        }
+    }
+
+    /// The role of the wire tracked by col, if any
+    pub fn role(&self, col: usize) -> Option<ColumnRole> {
+        [
+            (ColumnRole::Input, self.in_cols.values().any(|c| *c == col)),
+            (ColumnRole::Output, self.out_cols.values().any(|c| *c == col)),
+            (ColumnRole::Frontier, self.frontier_cols.values().any(|c| *c == col) || self.packed_cols.values().flatten().any(|(_, c)| *c == col)),
+            (ColumnRole::InternalIn, self.internal_in_cols.values().any(|c| *c == col)),
+            (ColumnRole::InternalOut, self.internal_out_cols.values().any(|c| *c == col)),
+            (ColumnRole::NestedIn, self.nested_in_cols.values().any(|c| *c == col)),
+            (ColumnRole::NestedOut, self.nested_out_cols.values().any(|c| *c == col)),
+        ].into_iter().find(|(_, found)| *found).map(|(role, _)| role)
+    }
+
+    /// Describes the wire each column is tracking
+    pub fn column_to_wire(&self) -> HashMap<usize, String> {
+        let mut wires = HashMap::new();
+        for (p, col) in self.in_cols.iter() {
+            wires.insert(*col, format!("input[{}]", p.index()));
+        }
+        for (p, col) in self.out_cols.iter() {
+            wires.insert(*col, format!("output[{}]", p.index()));
+        }
+        for ((n, p), col) in self.frontier_cols.iter() {
+            wires.insert(*col, format!("frontier({:?}, port_{})", n, p.index()));
+        }
+        for ((n, p), packed) in self.packed_cols.iter() {
+            for (slots, col) in packed {
+                wires.insert(*col, format!("packed({:?}, port_{}, slot_{})", n, p.index(), slots.iter().format(".")));
+            }
+        }
+        for ((path, p), col) in self.internal_in_cols.iter() {
+            wires.insert(*col, format!("internal_in({:?}, port_{})", path.iter().format("/"), p.index()));
+        }
+        for ((path, p), col) in self.internal_out_cols.iter() {
+            wires.insert(*col, format!("internal_out({:?}, port_{})", path.iter().format("/"), p.index()));
+        }
+        for ((path, p), col) in self.nested_in_cols.iter() {
+            wires.insert(*col, format!("nested_in({:?}, port_{})", path.iter().format("/"), p.index()));
+        }
+        for ((path, p), col) in self.nested_out_cols.iter() {
+            wires.insert(*col, format!("nested_out({:?}, port_{})", path.iter().format("/"), p.index()));
+        }
+        wires
+    }
+
+    /// Describes each way the maps are inconsistent with a tableau of nb_cols columns: a column out of range, or a column both on the frontier and the internal input of a node (which consumed it)
+    pub(crate) fn consistency_errors(&self, nb_cols: usize) -> Vec<String> {
+        let mut errors = vec![];
+        for (col, wire) in self.column_to_wire().into_iter().sorted() {
+            if col >= nb_cols {
+                errors.push(format!("Column {col} for {wire} is out of range for {nb_cols} columns"));
+            }
+        }
+        let frontier = self.frontier_cols.values().copied().chain(self.packed_cols.values().flatten().map(|(_, c)| *c)).collect::<HashSet<_>>();
+        for ((path, p), col) in self.internal_in_cols.iter().sorted_by_key(|(_, col)| **col) {
+            if frontier.contains(col) {
+                errors.push(format!("Column {col} is both on the frontier and the internal input on port {} of {:?}", p.index(), path.iter().format("/")));
+            }
+        }
+        errors
+    }
+
+    pub(crate) fn set_input(&mut self, port: OutgoingPort, col: usize) {
+        self.in_cols.insert(port, col);
+    }
+
+    pub(crate) fn set_output(&mut self, port: IncomingPort, col: usize) {
+        self.out_cols.insert(port, col);
+    }
+
+    pub(crate) fn set_frontier(&mut self, node: N, port: IncomingPort, col: usize) {
+        self.frontier_cols.insert((node, port), col);
+    }
+
+    /// Removes the frontier column for the qubit wire into port of node, as the node consumes it
+    pub(crate) fn take_frontier(&mut self, node: N, port: IncomingPort) -> Option<usize> {
+        self.frontier_cols.remove(&(node, port))
+    }
+
+    /// Records the qubits packed in the tuple wire into port of node, as the position of each within the tuple with its column
+    pub(crate) fn set_packed(&mut self, node: N, port: IncomingPort, packed: PackedQubits) {
+        self.packed_cols.insert((node, port), packed);
+    }
+
+    /// Removes the record of the qubits packed in the tuple wire into port of node, as the node consumes it
+    pub(crate) fn take_packed(&mut self, node: N, port: IncomingPort) -> Option<PackedQubits> {
+        self.packed_cols.remove(&(node, port))
+    }
+
+    /// Forgets the qubits packed in any tuple wire into node, leaving their columns untouched by any later node
+    pub(crate) fn drop_packed(&mut self, node: N) {
+        self.packed_cols.retain(|(n, _), _| *n != node);
+    }
+
+    /// The ports of node with a frontier column waiting for it
+    pub(crate) fn frontier_ports(&self, node: N) -> Vec<IncomingPort> {
+        self.frontier_cols.keys().filter(|(n, _)| *n == node).map(|(_, p)| *p).collect()
+    }
+
+    pub(crate) fn set_node_port(&mut self, path: NodePath<N>, port: impl Into<Port>, col: usize) {
+        let port = port.into();
+        match port.direction() {
+            Direction::Incoming => self.internal_in_cols.insert((path, IncomingPort::from(port.index())), col),
+            Direction::Outgoing => self.internal_out_cols.insert((path, OutgoingPort::from(port.index())), col),
+        };
+    }
+
+    /// Moves the input qubits along by offset ports, e.g. for the arguments of a CallIndirect which follow the function value
+    pub(crate) fn shift_inputs(&mut self, offset: usize) {
+        self.in_cols = self.in_cols.drain().map(|(port, col)| (OutgoingPort::from(port.index() + offset), col)).collect();
+    }
+
+    /// Takes on the columns of the nested analysis of node, whose tableau has been placed from column offset onwards
+    /// The boundary of the nested analysis becomes the nested columns of node, and its internal and nested columns are re-keyed under node so the same nodes reached through different call sites stay distinct
+    pub(crate) fn absorb(&mut self, node: N, nested: &ColumnAllocator<N>, offset: usize) {
+        for (port, col) in nested.in_cols.iter() {
+            self.nested_in_cols.insert((vec![node], *port), *col + offset);
+        }
+        for (port, col) in nested.out_cols.iter() {
+            self.nested_out_cols.insert((vec![node], *port), *col + offset);
+        }
+        let call_site = |path: &NodePath<N>| [node].into_iter().chain(path.iter().copied()).collect_vec();
+        for ((path, port), col) in nested.internal_in_cols.iter() {
+            self.internal_in_cols.insert((call_site(path), *port), *col + offset);
+        }
+        for ((path, port), col) in nested.internal_out_cols.iter() {
+            self.internal_out_cols.insert((call_site(path), *port), *col + offset);
+        }
+        for ((path, port), col) in nested.nested_in_cols.iter() {
+            self.nested_in_cols.insert((call_site(path), *port), *col + offset);
+        }
+        for ((path, port), col) in nested.nested_out_cols.iter() {
+            self.nested_out_cols.insert((call_site(path), *port), *col + offset);
+        }
+    }
+
+    /// Updates every map after the (sorted) columns in removed are deleted from the tableau, dropping any wires mapped to them
+    pub(crate) fn remove_cols(&mut self, removed: &[usize]) {
+        renumber_cols(&mut self.in_cols, removed);
+        renumber_cols(&mut self.out_cols, removed);
+        renumber_cols(&mut self.frontier_cols, removed);
+        for packed in self.packed_cols.values_mut() {
+            packed.retain(|(_, col)| removed.binary_search(col).is_err());
+            for (_, col) in packed.iter_mut() {
+                *col -= removed.partition_point(|r| *r < *col);
+            }
+        }
+        renumber_cols(&mut self.internal_in_cols, removed);
+        renumber_cols(&mut self.internal_out_cols, removed);
+        renumber_cols(&mut self.nested_in_cols, removed);
+        renumber_cols(&mut self.nested_out_cols, removed);
+    }
+}
+
+/// Updates a column map after the (sorted) columns in removed are deleted from the tableau
+fn renumber_cols<K>(cols: &mut HashMap<K, usize>, removed: &[usize]) {
+    cols.retain(|_, col| removed.binary_search(col).is_err());
+    for col in cols.values_mut() {
+        let shift = removed.partition_point(|r| *r < *col);
+        *col -= shift;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hugr_core::{IncomingPort, OutgoingPort};
+
+    use super::{ColumnAllocator, ColumnRole};
+
+    #[test]
+    fn test_lookup_and_remove() {
+        let mut cols: ColumnAllocator<usize> = ColumnAllocator::new();
+        cols.set_input(OutgoingPort::from(0), 0);
+        cols.set_frontier(7, IncomingPort::from(0), 1);
+        cols.set_node_port(vec![7], IncomingPort::from(0), 2);
+        cols.set_node_port(vec![7], OutgoingPort::from(0), 3);
+        assert_eq!(cols.column_for_node_port(&[7], IncomingPort::from(0)), Some(2));
+        assert_eq!(cols.column_for_node_port(&[7], OutgoingPort::from(0)), Some(3));
+        assert_eq!(cols.role(1), Some(ColumnRole::Frontier));
+        assert_eq!(cols.role(4), None);
+        // Removing a column drops its wire and shifts the later columns down
+        cols.remove_cols(&[1]);
+        assert_eq!(cols.frontier(7, IncomingPort::from(0)), None);
+        assert_eq!(cols.column_for_input(OutgoingPort::from(0)), Some(0));
+        assert_eq!(cols.column_for_node_port(&[7], IncomingPort::from(0)), Some(1));
+        assert_eq!(cols.column_for_node_port(&[7], OutgoingPort::from(0)), Some(2));
+        assert_eq!(cols.count(ColumnRole::Frontier), 0);
+        // Qubits packed into a tuple count as frontier columns, and are renumbered in the same way
+        cols.set_packed(8, IncomingPort::from(1), vec![(vec![0], 3), (vec![1, 0], 5)]);
+        assert_eq!(cols.role(5), Some(ColumnRole::Frontier));
+        assert_eq!(cols.count(ColumnRole::Frontier), 2);
+        cols.remove_cols(&[3, 4]);
+        assert_eq!(cols.take_packed(8, IncomingPort::from(1)), Some(vec![(vec![1, 0], 3)]));
+    }
+
+    #[test]
+    fn test_absorb() {
+        let mut nested: ColumnAllocator<usize> = ColumnAllocator::new();
+        nested.set_input(OutgoingPort::from(0), 0);
+        nested.set_output(IncomingPort::from(0), 1);
+        nested.set_node_port(vec![3], IncomingPort::from(0), 2);
+        let mut cols: ColumnAllocator<usize> = ColumnAllocator::new();
+        cols.absorb(5, &nested, 10);
+        // The nested boundary is seen from the ports of the hierarchical node, and internal nodes are keyed by their call site
+        assert_eq!(cols.column_for_nested_port(&[5], IncomingPort::from(0)), Some(10));
+        assert_eq!(cols.column_for_nested_port(&[5], OutgoingPort::from(0)), Some(11));
+        assert_eq!(cols.column_for_node_port(&[5, 3], IncomingPort::from(0)), Some(12));
+        assert_eq!(cols.column_for_node_port(&[3], IncomingPort::from(0)), None);
+    }
+}
@@ -0,0 +1,132 @@
+use num_complex::Complex;
+use tket::TketOp;
+use crate::tableau::Tableau;
+
+// Below this, the overlap between the unitary and the Clifford is taken to be zero, so there is no global phase to align
+const OVERLAP_TOLERANCE: f64 = 1e-12;
+// Power iteration stops once successive estimates of the norm agree to within this, or after MAX_POWER_ITERATIONS
+const NORM_TOLERANCE: f64 = 1e-12;
+const MAX_POWER_ITERATIONS: usize = 10000;
+
+// The distance between a unitary (given by its rows) and the Clifford represented by clifford, as the operator norm of their difference once the global phase of the Clifford is aligned with the unitary
+// Basis states are indexed with qubit q as bit q of the index, so e.g. CX with control 0 and target 1 swaps the states 1 and 3
+// The phase is the one maximising |tr(C†U)|, so the distance is 0 exactly when the unitary is the Clifford up to a global phase, and at most 2 otherwise
+pub fn clifford_distance(unitary_rows: &[Vec<Complex<f64>>], clifford: &Tableau) -> f64 {
+    let c = clifford_matrix(clifford);
+    let dim = c.len();
+    assert!(unitary_rows.len() == dim && unitary_rows.iter().all(|row| row.len() == dim), "Expected a {dim}x{dim} unitary for a Clifford on {} qubits", clifford.nb_qubits);
+    let overlap: Complex<f64> = (0..dim).flat_map(|i| (0..dim).map(move |j| (i, j))).map(|(i, j)| c[i][j].conj() * unitary_rows[i][j]).sum();
+    let phase = if overlap.norm() > OVERLAP_TOLERANCE { overlap / overlap.norm() } else { Complex::new(1., 0.) };
+    let diff = (0..dim).map(|i| (0..dim).map(|j| unitary_rows[i][j] - phase * c[i][j]).collect()).collect::<Vec<Vec<_>>>();
+    operator_norm(&diff)
+}
+
+// The unitary of the circuit from to_circ(false), with qubit q as bit q of the basis state index
+// Each gate acts on the left, combining the rows of the basis states it maps between
+fn clifford_matrix(clifford: &Tableau) -> Vec<Vec<Complex<f64>>> {
+    let dim = 1 << clifford.nb_qubits;
+    let (zero, one, i) = (Complex::new(0., 0.), Complex::new(1., 0.), Complex::new(0., 1.));
+    let h = std::f64::consts::FRAC_1_SQRT_2;
+    let mut u = (0..dim).map(|r| (0..dim).map(|k| if r == k { one } else { zero }).collect::<Vec<_>>()).collect::<Vec<_>>();
+    for (gate, qubits) in clifford.to_circ(false) {
+        let m = match gate {
+            TketOp::CX => {
+                let (control, target) = (1 << qubits[0], 1 << qubits[1]);
+                for r in (0..dim).filter(|r| r & control != 0 && r & target == 0) {
+                    u.swap(r, r | target);
+                }
+                continue;
+            }
+            TketOp::H => [[one * h, one * h], [one * h, -one * h]],
+            TketOp::S => [[one, zero], [zero, i]],
+            TketOp::Sdg => [[one, zero], [zero, -i]],
+            TketOp::V => [[one * h, -i * h], [-i * h, one * h]],
+            TketOp::Vdg => [[one * h, i * h], [i * h, one * h]],
+            TketOp::X => [[zero, one], [one, zero]],
+            TketOp::Y => [[zero, -i], [i, zero]],
+            TketOp::Z => [[one, zero], [zero, -one]],
+            _ => unreachable!("to_circ only produces Clifford gates, not {gate:?}"),
+        };
+        let bit = 1 << qubits[0];
+        for r in (0..dim).filter(|r| r & bit == 0) {
+            let (a, b) = (u[r].clone(), u[r | bit].clone());
+            u[r] = a.iter().zip(&b).map(|(a, b)| m[0][0] * a + m[0][1] * b).collect();
+            u[r | bit] = a.iter().zip(&b).map(|(a, b)| m[1][0] * a + m[1][1] * b).collect();
+        }
+    }
+    u
+}
+
+// The largest singular value of the square matrix m, by power iteration on m†m
+fn operator_norm(m: &[Vec<Complex<f64>>]) -> f64 {
+    let dim = m.len();
+    let apply = |v: &[Complex<f64>], adjoint: bool| (0..dim).map(|r| (0..dim).map(|k| if adjoint { m[k][r].conj() * v[k] } else { m[r][k] * v[k] }).sum()).collect::<Vec<Complex<f64>>>();
+    let length = |v: &[Complex<f64>]| v.iter().map(|x| x.norm_sqr()).sum::<f64>().sqrt();
+    // Start from a vector with distinct entries, so that it is not orthogonal to the top singular vector for any simple structure of m
+    let mut v = (0..dim).map(|k| Complex::new(1., 0.5 + k as f64)).collect::<Vec<_>>();
+    let mut norm = 0.;
+    for _ in 0..MAX_POWER_ITERATIONS {
+        let len = length(&v);
+        if len == 0. {
+            return 0.;
+        }
+        v.iter_mut().for_each(|x| *x /= len);
+        let mv = apply(&v, false);
+        let next_norm = length(&mv);
+        v = apply(&mv, true);
+        if (next_norm - norm).abs() < NORM_TOLERANCE {
+            return next_norm;
+        }
+        norm = next_norm;
+    }
+    norm
+}
+
+#[cfg(test)]
+mod test {
+    use num_complex::Complex;
+    use crate::distance::clifford_distance;
+    use crate::tableau::Tableau;
+
+    fn matrix(entries: &[&[(f64, f64)]]) -> Vec<Vec<Complex<f64>>> {
+        entries.iter().map(|row| row.iter().map(|(re, im)| Complex::new(*re, *im)).collect()).collect()
+    }
+
+    #[test]
+    fn test_clifford_distance() {
+        let h = std::f64::consts::FRAC_1_SQRT_2;
+        let mut tab = Tableau::new(1);
+        assert!(clifford_distance(&matrix(&[&[(1., 0.), (0., 0.)], &[(0., 0.), (1., 0.)]]), &tab) < 1e-9);
+        tab.append_h(0);
+        assert!(clifford_distance(&matrix(&[&[(h, 0.), (h, 0.)], &[(h, 0.), (-h, 0.)]]), &tab) < 1e-9);
+        // S up to a global phase
+        let mut tab = Tableau::new(1);
+        tab.append_s(0);
+        assert!(clifford_distance(&matrix(&[&[(h, h), (0., 0.)], &[(0., 0.), (-h, h)]]), &tab) < 1e-9);
+        // CX with control 0 swaps the basis states 1 and 3, but not 2 and 3
+        let cx = matrix(&[
+            &[(1., 0.), (0., 0.), (0., 0.), (0., 0.)],
+            &[(0., 0.), (0., 0.), (0., 0.), (1., 0.)],
+            &[(0., 0.), (0., 0.), (1., 0.), (0., 0.)],
+            &[(0., 0.), (1., 0.), (0., 0.), (0., 0.)],
+        ]);
+        let mut tab = Tableau::new(2);
+        tab.append_cx(vec![0, 1]);
+        assert!(clifford_distance(&cx, &tab) < 1e-9);
+        let mut tab = Tableau::new(2);
+        tab.append_cx(vec![1, 0]);
+        assert!(clifford_distance(&cx, &tab) > 0.5);
+    }
+
+    #[test]
+    fn test_t_gate_distance() {
+        // With the phase aligned halfway, T differs from the identity by |1 - e^{iπ/8}| = 2 sin(π/16) on each basis state
+        let t = matrix(&[&[(1., 0.), (0., 0.)], &[(0., 0.), (std::f64::consts::FRAC_1_SQRT_2, std::f64::consts::FRAC_1_SQRT_2)]]);
+        let expected = 2. * (std::f64::consts::PI / 16.).sin();
+        assert!((clifford_distance(&t, &Tableau::new(1)) - expected).abs() < 1e-9);
+        // The nearest of S and the identity are equally far
+        let mut tab = Tableau::new(1);
+        tab.append_s(0);
+        assert!((clifford_distance(&t, &tab) - expected).abs() < 1e-9);
+    }
+}
@@ -1,5 +1,12 @@
 pub mod bit_vector;
+pub mod column_allocator;
+pub mod distance;
 pub mod pauli_product;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod simulator;
 pub mod stabilizer_dataflow;
 pub mod tableau_interface;
-pub mod tableau;
\ No newline at end of file
+pub mod tableau;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_support;
+pub mod rewrite;
\ No newline at end of file
@@ -1,6 +1,7 @@
 use crate::bit_vector::BitVector;
+use crate::tableau::Tableau;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PauliProduct {
     pub z: BitVector,
     pub x: BitVector,
@@ -29,4 +30,180 @@ impl PauliProduct {
         x1z2.and(&ac);
         self.sign ^= p.sign ^ (((ac.popcount() + 2*x1z2.popcount()) % 4) > 1);
     }
+
+    pub fn get_x(&self, qubit: usize) -> bool {
+        self.x.get(qubit)
+    }
+
+    pub fn get_z(&self, qubit: usize) -> bool {
+        self.z.get(qubit)
+    }
+
+    pub fn set_x(&mut self, qubit: usize, val: bool) {
+        if self.x.get(qubit) != val {
+            self.x.xor_bit(qubit);
+        }
+    }
+
+    pub fn set_z(&mut self, qubit: usize, val: bool) {
+        if self.z.get(qubit) != val {
+            self.z.xor_bit(qubit);
+        }
+    }
+
+    // The single-qubit Pauli acting on qubit, ignoring the sign
+    pub fn pauli_at(&self, qubit: usize) -> char {
+        match (self.get_x(qubit), self.get_z(qubit)) {
+            (false, false) => 'I',
+            (true, false) => 'X',
+            (true, true) => 'Y',
+            (false, true) => 'Z',
+        }
+    }
+
+    pub fn commutes_with(&self, p: &PauliProduct) -> bool {
+        let mut zx = self.z.clone();
+        zx.and(&p.x);
+        let mut xz = self.x.clone();
+        xz.and(&p.z);
+        zx.xor(&xz);
+        zx.popcount() % 2 == 0
+    }
+
+    // Conjugates by the Clifford of the tableau, giving C P C† (with its sign) as the product of the images of the single-qubit factors of P
+    // The image of Y = iXZ is the product of the images of X and Z, as pauli_product_mult drops the same factor of -i from the product of anticommuting Paulis
+    pub fn clifford_conjugate(&self, clifford: &Tableau) -> PauliProduct {
+        let n = clifford.nb_qubits;
+        let mut result = PauliProduct::new(BitVector::new(n), BitVector::new(n), self.sign);
+        for q in 0..n {
+            let image = match (self.get_x(q), self.get_z(q)) {
+                (false, false) => continue,
+                (true, false) => clifford.destabilizer(q),
+                (false, true) => clifford.stabilizer(q),
+                (true, true) => {
+                    let mut y = clifford.destabilizer(q);
+                    y.pauli_product_mult(&clifford.stabilizer(q));
+                    y
+                }
+            };
+            result.pauli_product_mult(&image);
+        }
+        result
+    }
+}
+
+// Written as the sign ('+' or '-') followed by the Pauli on each qubit, e.g. "-XIZ"
+impl std::fmt::Display for PauliProduct {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", if self.sign { '-' } else { '+' })?;
+        (0..self.z.nb_bits()).try_for_each(|q| write!(f, "{}", self.pauli_at(q)))
+    }
+}
+
+// Parses the form written by Display, with the sign optional (so "XIZ" is "+XIZ"), over as many qubits as there are Paulis
+impl std::str::FromStr for PauliProduct {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sign, paulis) = match s.strip_prefix('-') {
+            Some(paulis) => (true, paulis),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let n = paulis.chars().count();
+        let mut p = PauliProduct::new(BitVector::new(n), BitVector::new(n), sign);
+        for (q, c) in paulis.chars().enumerate() {
+            match c {
+                'I' => {}
+                'X' => p.set_x(q, true),
+                'Y' => {
+                    p.set_x(q, true);
+                    p.set_z(q, true);
+                }
+                'Z' => p.set_z(q, true),
+                _ => return Err(format!("Invalid Pauli {c:?} in {s:?}")),
+            }
+        }
+        Ok(p)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{bit_vector::BitVector, pauli_product::PauliProduct, tableau::Tableau};
+
+    #[test]
+    fn test_get_set() {
+        let mut p = PauliProduct::new(BitVector::new(300), BitVector::new(300), false);
+        p.set_x(3, true);
+        p.set_z(299, true);
+        assert!(p.get_x(3) && !p.get_z(3));
+        assert!(p.get_z(299) && !p.get_x(299));
+        // Setting a bit to its current value leaves it unchanged
+        p.set_x(3, true);
+        p.set_z(0, false);
+        assert!(p.get_x(3) && !p.get_z(0));
+        p.set_x(3, false);
+        p.set_z(299, false);
+        assert_eq!(p.x.popcount(), 0);
+        assert_eq!(p.z.popcount(), 0);
+    }
+
+    #[test]
+    fn test_pauli_at() {
+        let mut p = PauliProduct::new(BitVector::new(4), BitVector::new(4), true);
+        p.set_x(1, true);
+        p.set_x(2, true);
+        p.set_z(2, true);
+        p.set_z(3, true);
+        assert_eq!((0..4).map(|q| p.pauli_at(q)).collect::<String>(), "IXYZ");
+    }
+
+    #[test]
+    fn test_display_from_str() {
+        let p: PauliProduct = "-IXYZ".parse().unwrap();
+        assert!(p.sign);
+        assert_eq!((0..4).map(|q| p.pauli_at(q)).collect::<String>(), "IXYZ");
+        assert_eq!(p.to_string(), "-IXYZ");
+        // The sign is optional, and defaults to positive
+        assert_eq!("ZZ".parse::<PauliProduct>().unwrap().to_string(), "+ZZ");
+        assert_eq!("+".parse::<PauliProduct>().unwrap().to_string(), "+");
+        assert!("XA".parse::<PauliProduct>().is_err());
+        assert!("X-Z".parse::<PauliProduct>().is_err());
+    }
+
+    #[test]
+    fn test_clifford_conjugate() {
+        let pauli = |s: &str| {
+            let mut p = PauliProduct::new(BitVector::new(s.len()), BitVector::new(s.len()), false);
+            for (q, c) in s.chars().enumerate() {
+                p.set_x(q, c == 'X' || c == 'Y');
+                p.set_z(q, c == 'Z' || c == 'Y');
+            }
+            p
+        };
+        let check = |tab: &Tableau, from: &str, to: &str, sign: bool| {
+            let image = pauli(from).clifford_conjugate(tab);
+            assert_eq!(((0..from.len()).map(|q| image.pauli_at(q)).collect::<String>(), image.sign), (to.to_string(), sign), "{} under {:?}", from, tab);
+        };
+        let mut h = Tableau::new(1);
+        h.append_h(0);
+        check(&h, "X", "Z", false);
+        check(&h, "Z", "X", false);
+        check(&h, "Y", "Y", true);
+        let mut s = Tableau::new(1);
+        s.append_s(0);
+        check(&s, "X", "Y", false);
+        check(&s, "Z", "Z", false);
+        check(&s, "Y", "X", true);
+        let mut cx = Tableau::new(2);
+        cx.append_cx(vec![0, 1]);
+        check(&cx, "XI", "XX", false);
+        check(&cx, "IZ", "ZZ", false);
+        check(&cx, "ZI", "ZI", false);
+        check(&cx, "YY", "XZ", true);
+        // The sign of the Pauli carries over
+        let mut minus_x = pauli("XI");
+        minus_x.sign = true;
+        assert!(minus_x.clifford_conjugate(&cx).sign);
+    }
 }
@@ -0,0 +1,1340 @@
+use std::collections::{HashMap, HashSet};
+use hugr::builder::{endo_sig, DFGBuilder, Dataflow, DataflowHugr};
+use hugr::extension::prelude::{bool_t, qb_t};
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::hugr::views::SiblingSubgraph;
+use hugr::ops::{Const, LoadConstant, OpType, Value};
+use hugr::std_extensions::arithmetic::float_ops::{self, FloatOps};
+use hugr::types::Type;
+use hugr::PortIndex;
+use hugr_core::hugr::internal::PortgraphNodeMap;
+use hugr_core::{HugrView, IncomingPort, OutgoingPort};
+use itertools::Itertools;
+use petgraph::visit as pv;
+use tket::extension::bool::{bool_type, BoolOp, ConstBool};
+use tket::extension::rotation::{rotation_type, ConstRotation, RotationOp, ROTATION_EXTENSION};
+use tket::hugr::extension::simple_op::MakeExtensionOp;
+use tket::TketOp;
+use crate::pauli_product::PauliProduct;
+use crate::stabilizer_dataflow::{constant_half_turns, AnalysisConfig, AnalysisHugr, DataflowError, FunctionOpacity, NodePath, StabilizerDataflow};
+use crate::tableau::Tableau;
+use crate::tableau_interface::ChoiTableau;
+
+/// Checks whether the CX gates cx1 and cx2 (acting on the same control and target wires, in that order) can be cancelled, i.e. whether everything between them commutes with CX
+/// This is the case exactly when the gates between them map Z on the control and X on the target back to themselves
+/// Clifford gates between them are conjugated through directly; for any other gates, we use the relations across them from the analysis of the region containing the CXs
+pub fn can_cancel_cnots<H: HugrView>(hugr: &H, analysis: &StabilizerDataflow<H>, cx1: H::Node, cx2: H::Node) -> bool {
+    if tket_op(hugr, cx1) != Some(TketOp::CX) || tket_op(hugr, cx2) != Some(TketOp::CX) {
+        return false;
+    }
+    // Track the images of Z on the control (row 0) and X on the target (row 1) with a column for each of the two wires
+    let mut paulis = ChoiTableau::new(2);
+    paulis.add_row(paulis.pauli(&[0], &[], false));
+    paulis.add_row(paulis.pauli(&[], &[1], false));
+    let mut wires = [hugr.single_linked_input(cx1, OutgoingPort::from(0)).unwrap(), hugr.single_linked_input(cx1, OutgoingPort::from(1)).unwrap()];
+    while wires != [(cx2, IncomingPort::from(0)), (cx2, IncomingPort::from(1))] {
+        // Advance along a wire whose next gate acts only on our two wires and has all of its qubits ready
+        let qb_ins = |n: H::Node| hugr.in_value_types(n).filter(|(_, t)| *t == qb_t()).map(|(p, _)| p).collect_vec();
+        let Some(node) = wires.iter().map(|(n, _)| *n).find(|n| *n != cx2 && qb_ins(*n).iter().all(|p| wires.contains(&(*n, *p)))) else {
+            // The wires leave the pair of qubits, or reach cx2 on the wrong ports
+            return false;
+        };
+        let in_ports = qb_ins(node);
+        let out_ports = hugr.out_value_types(node).filter(|(_, t)| *t == qb_t()).map(|(p, _)| p).collect_vec();
+        if in_ports.len() != out_ports.len() {
+            return false;
+        }
+        let cols = in_ports.iter().map(|p| wires.iter().position(|w| *w == (node, *p)).unwrap()).collect_vec();
+        let clifford = match tket_op(hugr, node) {
+            Some(op) => append_clifford(&mut paulis, op, &cols),
+            None => false,
+        };
+        if !clifford {
+            // The gate must preserve each tracked Pauli, which we read off from the relation between its internal columns
+            for row in paulis.stabs.iter() {
+                let mut z = vec![];
+                let mut x = vec![];
+                for (i, col) in cols.iter().enumerate() {
+                    let (Some(col_in), Some(col_out)) = (analysis.internal_column(&[node], in_ports[i]), analysis.internal_column(&[node], out_ports[i])) else {
+                        return false;
+                    };
+                    if row.z.get(*col) {
+                        z.extend([col_in, col_out]);
+                    }
+                    if row.x.get(*col) {
+                        x.extend([col_in, col_out]);
+                    }
+                }
+                if !analysis.tableau().stabilizes(&analysis.tableau().pauli(&z, &x, false)) {
+                    return false;
+                }
+            }
+        }
+        for (col, out_port) in cols.into_iter().zip(out_ports) {
+            wires[col] = hugr.single_linked_input(node, out_port).unwrap();
+        }
+    }
+    is_pauli(&paulis.stabs[0], [true, false], [false, false]) && is_pauli(&paulis.stabs[1], [false, false], [false, true])
+}
+
+/// Removes a pair of CX gates for which can_cancel_cnots holds, reconnecting the wires through them
+pub fn cancel_cnots<H: HugrMut>(hugr: &mut H, analysis: &StabilizerDataflow<H>, cx1: H::Node, cx2: H::Node) {
+    assert!(can_cancel_cnots(hugr, analysis, cx1, cx2), "CX gates {:?} and {:?} do not cancel", cx1, cx2);
+    remove_gate(hugr, cx1);
+    remove_gate(hugr, cx2);
+}
+
+/// Removes the T gate t_node and the Tdg gate tdg_node if the analysis shows that they cancel, i.e. that the gates between them map Z after one onto +Z before the other (in either order); returns whether they were removed
+/// The analysis has already conjugated the gates between them through its tableau, so this is the relation between their internal columns that mergeable_rotations looks for, and other gates preserving Z may lie between them as well as Cliffords
+/// Where the axes are opposite (e.g. T;X;Tdg) the gates add up to an S instead of cancelling, which is left to fold_phases
+pub fn fold_t_tdg_pair<H: HugrMut>(hugr: &mut H, analysis: &StabilizerDataflow<H>, t_node: H::Node, tdg_node: H::Node) -> bool {
+    if tket_op(&*hugr, t_node) != Some(TketOp::T) || tket_op(&*hugr, tdg_node) != Some(TketOp::Tdg) {
+        return false;
+    }
+    let cancel = [(t_node, tdg_node), (tdg_node, t_node)].into_iter().any(|(first, second)| {
+        let (Some(col_out), Some(col_in)) = (analysis.internal_column(&[first], OutgoingPort::from(0)), analysis.internal_column(&[second], IncomingPort::from(0))) else {
+            return false;
+        };
+        analysis.tableau().stabilizes(&analysis.tableau().pauli(&[col_out, col_in], &[], false))
+    });
+    if cancel {
+        remove_gate(hugr, t_node);
+        remove_gate(hugr, tdg_node);
+    }
+    cancel
+}
+
+/// Checks whether the non-Clifford gate non_clifford can be moved past the Clifford gate clifford that directly follows it, i.e. whether clifford maps the Pauli that non_clifford preserves on each qubit between them back to itself, up to sign
+/// The preserved Pauli (Z for T, Tdg, Rz and CRz) is read off from the relation between the gate's internal columns in the analysis; where the sign flips, the moved gate must be inverted (e.g. T;X is X;Tdg)
+pub fn can_commute_past<H: HugrView>(hugr: &H, analysis: &StabilizerDataflow<H>, non_clifford: H::Node, clifford: H::Node) -> bool {
+    let Some(op) = tket_op(hugr, clifford) else {
+        return false;
+    };
+    let qb_ins = hugr.in_value_types(clifford).filter(|(_, t)| *t == qb_t()).map(|(p, _)| p).collect_vec();
+    let (mut z, mut x) = (vec![], vec![]);
+    for (out_port, _) in hugr.out_value_types(non_clifford).filter(|(_, t)| *t == qb_t()) {
+        let Some((node, port)) = hugr.single_linked_input(non_clifford, out_port) else {
+            continue;
+        };
+        if node != clifford {
+            continue;
+        }
+        let in_port = IncomingPort::from(out_port.index());
+        let (Some(col_in), Some(col_out)) = (analysis.internal_column(&[non_clifford], in_port), analysis.internal_column(&[non_clifford], out_port)) else {
+            return false;
+        };
+        let cols = [col_in, col_out];
+        let preserves = |pz: bool, px: bool| [false, true].into_iter().any(|sign| {
+            analysis.tableau().stabilizes(&analysis.tableau().pauli(if pz { &cols[..] } else { &[] }, if px { &cols[..] } else { &[] }, sign))
+        });
+        let Some((pz, px)) = [(true, false), (false, true)].into_iter().find(|(pz, px)| preserves(*pz, *px)) else {
+            return false;
+        };
+        let pos = qb_ins.iter().position(|p| *p == port).unwrap();
+        if pz {
+            z.push(pos);
+        }
+        if px {
+            x.push(pos);
+        }
+    }
+    if z.is_empty() && x.is_empty() {
+        return false;
+    }
+    let mut paulis = ChoiTableau::new(qb_ins.len());
+    paulis.add_row(paulis.pauli(&z, &x, false));
+    append_clifford(&mut paulis, op, &(0..qb_ins.len()).collect_vec()) && [false, true].into_iter().any(|sign| paulis.stabilizes(&paulis.pauli(&z, &x, sign)))
+}
+
+/// Replaces a convex set of Clifford gates (all with the same parent) by the circuit that Tableau::to_circ synthesises for them
+/// The analysis applies Clifford gates to its tableau in place rather than keeping a separate record of them, so we rebuild the unitary tableau of the region by conjugating through its gates in order; the analysis is used to check that it did treat each of them as a Clifford gate
+pub fn simplify_clifford_region<H: HugrMut + AnalysisHugr>(hugr: &mut H, analysis: &StabilizerDataflow<H>, region_nodes: &[H::Node]) {
+    for node in region_nodes {
+        assert!(analysis.columns().node_ports(&[*node]).is_empty(), "Node {:?} is not a Clifford gate", node);
+    }
+    let subgraph = SiblingSubgraph::try_from_nodes(region_nodes.to_vec(), &*hugr).expect("Region is not a convex set of siblings");
+    // Each input of the subgraph is a qubit, which we follow through the gates to find which output it leaves on
+    let inputs = subgraph.incoming_ports().iter().map(|ports| ports.iter().exactly_one().ok().copied().expect("Region input is not a single qubit wire")).collect_vec();
+    let mut tab = Tableau::new(inputs.len());
+    let mut wires = inputs.iter().copied().map(Some).collect_vec();
+    let mut exits = vec![None; inputs.len()];
+    let mut remaining = region_nodes.to_vec();
+    while !remaining.is_empty() {
+        let qb_ins = |n: H::Node| hugr.in_value_types(n).filter(|(_, t)| *t == qb_t()).map(|(p, _)| p).collect_vec();
+        let index = remaining.iter().position(|n| qb_ins(*n).iter().all(|p| wires.contains(&Some((*n, *p))))).expect("Region gates are not connected by qubit wires");
+        let node = remaining.swap_remove(index);
+        let qubits = qb_ins(node).iter().map(|p| wires.iter().position(|w| *w == Some((node, *p))).unwrap()).collect_vec();
+        let op = tket_op(&*hugr, node);
+        assert!(op.is_some_and(|op| append_clifford(&mut tab, op, &qubits)), "Node {:?} is not a Clifford gate", node);
+        for (i, q) in qubits.into_iter().enumerate() {
+            let out_port = OutgoingPort::from(i);
+            match hugr.single_linked_input(node, out_port) {
+                Some(next) if region_nodes.contains(&next.0) => wires[q] = Some(next),
+                _ => {
+                    wires[q] = None;
+                    exits[q] = subgraph.outgoing_ports().iter().position(|p| *p == (node, out_port));
+                }
+            }
+        }
+    }
+    let exits = exits.into_iter().map(|e| e.expect("Region output is not a qubit wire")).collect_vec();
+    // Build the synthesised circuit, routing each qubit to the output it left the region on
+    let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(); inputs.len()])).unwrap();
+    let input_wires = builder.input_wires().collect_vec();
+    let out_wires = tab.to_hugr_dfg(&mut builder, input_wires).unwrap();
+    let mut outputs = out_wires.clone();
+    for (q, wire) in out_wires.into_iter().enumerate() {
+        outputs[exits[q]] = wire;
+    }
+    let replacement = builder.finish_hugr_with_outputs(outputs).unwrap();
+    let rewrite = subgraph.create_simple_replacement(&*hugr, replacement).unwrap();
+    hugr.apply_patch(rewrite).unwrap();
+}
+
+/// Deletes the gates found by find_dead_gates, reconnecting their wires, and returns how many were removed
+/// Removing a dead gate leaves the state reaching every later gate unchanged, so they can all be removed at once
+pub fn remove_dead_gates<H: HugrMut + AnalysisHugr>(hugr: &mut H, analysis: &StabilizerDataflow<H>) -> Result<usize, DataflowError<H::Node>> {
+    let dead = analysis.find_dead_gates(&*hugr)?;
+    for node in &dead {
+        remove_gate(hugr, *node);
+    }
+    Ok(dead.len())
+}
+
+/// Moves each Measure later past the Clifford gates that map Z on the measured qubit to Z on the same qubit (e.g. a CZ, or a CX controlled on it), so that the measurement no longer separates them from earlier gates; returns how many Measure nodes were moved
+/// A measurement is not moved past a gate that depends on its outcome (e.g. through a Conditional on another qubit), as the result would not be acyclic
+pub fn defer_measurements<H: HugrMut>(hugr: &mut H) -> usize {
+    let measures = hugr.descendants(hugr.entrypoint()).filter(|n| tket_op(&*hugr, *n) == Some(TketOp::Measure)).collect_vec();
+    let mut moved = 0;
+    for measure in measures {
+        let mut deferred = false;
+        while let Some((next, next_port)) = hugr.single_linked_input(measure, OutgoingPort::from(0)) {
+            if !commutes_with_z(&*hugr, next, next_port) || depends_on(&*hugr, next, measure) {
+                break;
+            }
+            // Swap the Measure and the gate along the qubit wire
+            let out_port = OutgoingPort::from(next_port.index());
+            let (src, src_port) = hugr.single_linked_output(measure, IncomingPort::from(0)).unwrap();
+            let (dst, dst_port) = hugr.single_linked_input(next, out_port).unwrap();
+            hugr.disconnect(measure, IncomingPort::from(0));
+            hugr.disconnect(next, next_port);
+            hugr.disconnect(dst, dst_port);
+            hugr.connect(src, src_port, next, next_port);
+            hugr.connect(next, out_port, measure, IncomingPort::from(0));
+            hugr.connect(measure, OutgoingPort::from(0), dst, dst_port);
+            deferred = true;
+        }
+        if deferred {
+            moved += 1;
+        }
+    }
+    moved
+}
+
+/// Whether node is a Clifford gate mapping Z on the qubit into port to Z (with a positive sign) on the corresponding output
+fn commutes_with_z<H: HugrView>(hugr: &H, node: H::Node, port: IncomingPort) -> bool {
+    let Some(op) = tket_op(hugr, node) else {
+        return false;
+    };
+    let n_qubits = hugr.in_value_types(node).filter(|(_, t)| *t == qb_t()).count();
+    let mut paulis = ChoiTableau::new(n_qubits);
+    paulis.add_row(paulis.pauli(&[port.index()], &[], false));
+    append_clifford(&mut paulis, op, &(0..n_qubits).collect_vec()) && paulis.stabilizes(&paulis.pauli(&[port.index()], &[], false))
+}
+
+/// Whether node depends on any output of source other than through the qubit wire out of port 0, i.e. whether moving source after node would make a cycle
+fn depends_on<H: HugrView>(hugr: &H, node: H::Node, source: H::Node) -> bool {
+    let mut stack = hugr.node_outputs(source).filter(|p| p.index() != 0).flat_map(|p| hugr.linked_inputs(source, p)).map(|(n, _)| n).collect_vec();
+    let mut seen = HashSet::new();
+    while let Some(n) = stack.pop() {
+        if n == node {
+            return true;
+        }
+        if seen.insert(n) {
+            stack.extend(hugr.output_neighbours(n));
+        }
+    }
+    false
+}
+
+/// Replaces each Measure and MeasureFree in the entrypoint region whose outcome the analysis proves (see deterministic_measurements) by a LoadConstant of the outcome, then prunes each Conditional on that outcome down to its reachable case; returns the number of measurements replaced
+/// As the qubit is already in an eigenstate of Z, measuring it leaves its state unchanged, so a Measure's qubit wire is just passed through and a MeasureFree becomes a QFree
+/// A MeasureFree gives a tket.bool rather than a bool, so is replaced by a tket.bool constant, while each read of it into a bool (e.g. for a Conditional) is replaced by a bool constant in turn
+pub fn propagate_measurement_constants<H: HugrMut + AnalysisHugr>(hugr: &mut H, policy: &AnalysisConfig<H::Node>) -> Result<usize, DataflowError<H::Node>> {
+    let root = hugr.entrypoint();
+    let analysis = StabilizerDataflow::run_dfg(&*hugr, root, policy.clone())?;
+    let outcomes = analysis.deterministic_measurements(&*hugr).into_iter().filter(|(path, _)| path.len() == 1).map(|(path, outcome)| (path[0], outcome)).collect_vec();
+    for (measure, outcome) in outcomes.iter() {
+        let op = tket_op(&*hugr, *measure).unwrap();
+        let bool_port = OutgoingPort::from(if op == TketOp::Measure { 1 } else { 0 });
+        let is_read = |node: H::Node| hugr.get_optype(node).as_extension_op().is_some_and(|op| BoolOp::from_extension_op(op).ok() == Some(BoolOp::read));
+        let (reads, uses): (Vec<_>, Vec<_>) = hugr.linked_inputs(*measure, bool_port).partition(|(dst, _)| is_read(*dst));
+        let read_uses = reads.iter().flat_map(|(read, _)| hugr.linked_inputs(*read, OutgoingPort::from(0))).collect_vec();
+        let (src, src_port) = hugr.single_linked_output(*measure, IncomingPort::from(0)).unwrap();
+        let qb_dst = (op == TketOp::Measure).then(|| hugr.single_linked_input(*measure, OutgoingPort::from(0)).unwrap());
+        hugr.remove_node(*measure);
+        for (read, _) in reads {
+            hugr.remove_node(read);
+        }
+        match qb_dst {
+            Some((dst, dst_port)) => hugr.connect(src, src_port, dst, dst_port),
+            None => {
+                let free = hugr.add_node_with_parent(root, TketOp::QFree);
+                hugr.connect(src, src_port, free, IncomingPort::from(0));
+            }
+        }
+        if op == TketOp::Measure {
+            load_outcome(hugr, root, Value::from_bool(*outcome), bool_t(), uses, *outcome);
+        } else {
+            load_outcome(hugr, root, Value::extension(ConstBool::new(*outcome)), bool_type(), uses, *outcome);
+            load_outcome(hugr, root, Value::from_bool(*outcome), bool_t(), read_uses, *outcome);
+        }
+    }
+    Ok(outcomes.len())
+}
+
+/// Connects a LoadConstant of value (of type datatype) in parent to each of uses, except for the predicate of a Conditional, which is replaced by its case for outcome instead; nothing is added if no use is left
+fn load_outcome<H: HugrMut>(hugr: &mut H, parent: H::Node, value: Value, datatype: Type, uses: Vec<(H::Node, IncomingPort)>, outcome: bool) {
+    let cst = hugr.add_node_with_parent(parent, Const::new(value));
+    let load = hugr.add_node_with_parent(parent, LoadConstant { datatype });
+    hugr.connect(cst, OutgoingPort::from(0), load, IncomingPort::from(0));
+    for (dst, dst_port) in uses {
+        if matches!(hugr.get_optype(dst), OpType::Conditional(_)) && dst_port.index() == 0 {
+            // false and true are the tags 0 and 1 of the bool sum, each with no values
+            inline_case(hugr, dst, outcome as usize);
+        } else {
+            hugr.connect(load, OutgoingPort::from(0), dst, dst_port);
+        }
+    }
+    if hugr.linked_inputs(load, OutgoingPort::from(0)).next().is_none() {
+        hugr.remove_node(load);
+        hugr.remove_node(cst);
+    }
+}
+
+/// Replaces a Conditional by the contents of its case for tag, which must have no values in its sum row (as for a bool predicate), moving them into the Conditional's parent and wiring them up to its other inputs and its outputs
+fn inline_case<H: HugrMut>(hugr: &mut H, cond: H::Node, tag: usize) {
+    let parent = hugr.get_parent(cond).unwrap();
+    let case = hugr.children(cond).nth(tag).unwrap();
+    let [case_in, case_out] = hugr.get_io(case).unwrap();
+    // Input i of the case corresponds to input i + 1 of the Conditional, after the predicate
+    let srcs = hugr.in_value_types(cond).skip(1).map(|(p, _)| hugr.single_linked_output(cond, p).unwrap()).collect_vec();
+    let dsts = hugr.out_value_types(cond).map(|(p, _)| hugr.linked_inputs(cond, p).collect_vec()).collect_vec();
+    let from_in = hugr.out_value_types(case_in).flat_map(|(p, _)| hugr.linked_inputs(case_in, p).map(move |l| (p.index(), l))).collect_vec();
+    let to_out = hugr.in_value_types(case_out).map(|(p, _)| hugr.single_linked_output(case_out, p).unwrap()).collect_vec();
+    for node in hugr.children(case).filter(|n| *n != case_in && *n != case_out).collect_vec() {
+        hugr.set_parent(node, parent);
+    }
+    hugr.remove_subtree(cond);
+    for (i, (dst, dst_port)) in from_in {
+        // Wires straight through the case are connected below
+        if dst != case_out {
+            hugr.connect(srcs[i].0, srcs[i].1, dst, dst_port);
+        }
+    }
+    for (i, (src, src_port)) in to_out.into_iter().enumerate() {
+        let (src, src_port) = if src == case_in { srcs[src_port.index()] } else { (src, src_port) };
+        for (dst, dst_port) in dsts[i].iter() {
+            hugr.connect(src, src_port, *dst, *dst_port);
+        }
+    }
+}
+
+/// What fold_phases changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoldStats {
+    /// The number of rotation nodes deleted, whether absorbed into another rotation or cancelled out entirely
+    pub rotations_removed: usize,
+    /// The number of T and Tdg nodes in the region and the functions it inlines, before and after folding
+    pub t_count_before: usize,
+    pub t_count_after: usize,
+}
+
+/// The angle of a rotation in half-turns, as a constant plus a sum of rotation-valued wires (each possibly negated)
+#[derive(Debug, Clone)]
+struct Angle<N> {
+    half_turns: f64,
+    wires: Vec<(N, OutgoingPort, bool)>,
+}
+
+impl<N: Copy> Angle<N> {
+    fn negated(&self) -> Self {
+        Angle {
+            half_turns: -self.half_turns,
+            wires: self.wires.iter().map(|(n, p, neg)| (*n, *p, !neg)).collect(),
+        }
+    }
+
+    fn add(&mut self, other: Angle<N>) {
+        self.half_turns += other.half_turns;
+        self.wires.extend(other.wires);
+    }
+}
+
+/// Merges the rotations in the entrypoint region that the analysis finds to be about the same axis, as given by mergeable_rotations
+/// The earlier rotation of each pair is deleted and its angle (negated if the connecting stabilizer has a minus sign) added to the later one; where every angle is constant the result is replaced by the matching gate, or removed outright if the angles cancel
+/// Rotations inside inlined functions are only changed when the function has a single call site, and an Rz or CRz is only absorbed into a rotation in the same region, so the wire carrying its angle can be reused
+pub fn fold_phases<H: HugrMut + AnalysisHugr>(hugr: &mut H, policy: &AnalysisConfig<H::Node>) -> Result<FoldStats, DataflowError<H::Node>> {
+    let root = hugr.entrypoint();
+    let analysis = StabilizerDataflow::run_dfg(&*hugr, root, policy.clone())?;
+    // The functions whose bodies the analysis inlined, which we count T gates in as well as the region itself
+    let regions = std::iter::once(root).chain(analysis.columns().internal_inputs().filter_map(|(path, _, _)| {
+        let mut node = *path.last().unwrap();
+        while let Some(parent) = hugr.get_parent(node) {
+            if parent == root {
+                return None;
+            }
+            if matches!(hugr.get_optype(parent), OpType::FuncDefn(_)) {
+                return Some(parent);
+            }
+            node = parent;
+        }
+        None
+    })).unique().collect_vec();
+    let t_count_before = t_count(&*hugr, &regions);
+    let pairs = analysis.mergeable_rotations(&*hugr).into_iter().map(|candidate| (*candidate.first.last().unwrap(), *candidate.second.last().unwrap(), candidate.relation.sign));
+    let rotations_removed = merge_rotation_pairs(hugr, policy, pairs);
+    Ok(FoldStats { rotations_removed, t_count_before, t_count_after: t_count(&*hugr, &regions) })
+}
+
+/// Merges each pair of rotations (the earlier first) found by mergeable_rotations in the entrypoint region, skipping those fold_phases may not change; returns the number of rotation nodes deleted
+fn merge_rotation_pairs<H: HugrMut>(hugr: &mut H, policy: &AnalysisConfig<H::Node>, pairs: impl IntoIterator<Item = (H::Node, H::Node, bool)>) -> usize {
+    let root = hugr.entrypoint();
+    let mut rotations_removed = 0;
+    // The candidates refer to the rotations as they were analysed, so we track which have since been removed, and which were replaced by a new node for their merged angle
+    let mut removed: HashSet<H::Node> = HashSet::new();
+    let mut replaced: HashMap<H::Node, H::Node> = HashMap::new();
+    for (a, b, negate) in pairs {
+        if removed.contains(&a) || removed.contains(&b) {
+            continue;
+        }
+        let (a_now, b_now) = (replaced.get(&a).copied().unwrap_or(a), replaced.get(&b).copied().unwrap_or(b));
+        // Only rotations from TketOp have angles we know how to merge; interactions such as ZZPhase are left alone
+        if !is_editable(&*hugr, a_now, root) || !is_editable(&*hugr, b_now, root) || tket_op(&*hugr, a_now).is_none() || tket_op(&*hugr, b_now).is_none() {
+            continue;
+        }
+        if !initial_angle(&*hugr, a_now).wires.is_empty() && hugr.get_parent(a_now) != hugr.get_parent(b_now) {
+            continue;
+        }
+        if !policy.merges_across_barriers() && barrier_between(&*hugr, a_now, b_now, policy) {
+            continue;
+        }
+        removed.insert(a);
+        rotations_removed += 1;
+        match merge_rotation_angles(hugr, b_now, a_now, negate) {
+            Some(node) => {
+                replaced.insert(b, node);
+            }
+            None => {
+                removed.insert(b);
+                rotations_removed += 1;
+            }
+        }
+    }
+    rotations_removed
+}
+
+/// A rewrite justified by the analysis, as planned by plan_rewrites, referring to nodes as they were analysed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedRewrite<N> {
+    /// Delete a Clifford gate that acts as the identity on every state reaching it, as found by find_dead_gates
+    RemoveDeadGate(N),
+    /// Merge the rotation first into the later rotation second about the same axis, negating its angle if negate is set, as found by mergeable_rotations
+    MergeRotations { first: N, second: N, negate: bool },
+}
+
+/// What optimize_with_analysis changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptimizeStats {
+    pub dead_gates_removed: usize,
+    /// The number of rotation nodes deleted, as in FoldStats
+    pub rotations_removed: usize,
+}
+
+/// Analyses the entrypoint region and lists the rewrites the analysis justifies, without changing the hugr: the dead gates, then the pairs of mergeable rotations in the order fold_phases merges them
+pub fn plan_rewrites<H: AnalysisHugr>(hugr: &H, policy: &AnalysisConfig<H::Node>) -> Result<Vec<PlannedRewrite<H::Node>>, DataflowError<H::Node>> {
+    let analysis = StabilizerDataflow::run_dfg(hugr, hugr.entrypoint(), policy.clone())?;
+    let dead = analysis.find_dead_gates(hugr)?.into_iter().map(PlannedRewrite::RemoveDeadGate);
+    let merges = analysis.mergeable_rotations(hugr).into_iter().map(|candidate| PlannedRewrite::MergeRotations {
+        first: *candidate.first.last().unwrap(),
+        second: *candidate.second.last().unwrap(),
+        negate: candidate.relation.sign,
+    });
+    Ok(dead.chain(merges).collect())
+}
+
+/// Optimizes the entrypoint region in place, removing the dead gates and merging the rotations that the analysis finds, as remove_dead_gates and fold_phases do
+/// The analysis only reads hugr, so we first build the whole plan with plan_rewrites (dropping the analysis) and only then borrow hugr mutably to apply it
+/// Dead gates are removed first: they act trivially on every state reaching them, so removing them leaves the relations between the rotations unchanged
+pub fn optimize_with_analysis<H: HugrMut + AnalysisHugr>(hugr: &mut H, fun_op: &FunctionOpacity) -> Result<OptimizeStats, DataflowError<H::Node>> {
+    let policy = AnalysisConfig::from(fun_op);
+    let plan = plan_rewrites(&*hugr, &policy)?;
+    let mut stats = OptimizeStats::default();
+    let mut merges = vec![];
+    for rewrite in plan {
+        match rewrite {
+            PlannedRewrite::RemoveDeadGate(node) => {
+                remove_gate(hugr, node);
+                stats.dead_gates_removed += 1;
+            }
+            PlannedRewrite::MergeRotations { first, second, negate } => merges.push((first, second, negate)),
+        }
+    }
+    stats.rotations_removed = merge_rotation_pairs(hugr, &policy, merges);
+    Ok(stats)
+}
+
+/// Whether a Barrier (or another op the policy treats as transparent) lies on a path from a to b, itself or inside a container node on the path
+/// Only paths within a region are followed, so rotations in different regions (e.g. one inlined from a function) are never taken to be separated
+fn barrier_between<H: HugrView>(hugr: &H, a: H::Node, b: H::Node, policy: &AnalysisConfig<H::Node>) -> bool {
+    let reaches = |from: H::Node| {
+        let mut stack = vec![from];
+        let mut seen = HashSet::new();
+        while let Some(n) = stack.pop() {
+            if n == b {
+                return true;
+            }
+            if seen.insert(n) {
+                stack.extend(hugr.output_neighbours(n));
+            }
+        }
+        false
+    };
+    let mut stack = hugr.output_neighbours(a).collect_vec();
+    let mut seen = HashSet::new();
+    while let Some(n) = stack.pop() {
+        if n == b || !seen.insert(n) {
+            continue;
+        }
+        if hugr.descendants(n).any(|d| policy.is_transparent(hugr.get_optype(d))) && reaches(n) {
+            return true;
+        }
+        stack.extend(hugr.output_neighbours(n));
+    }
+    false
+}
+
+/// Merges the rotation absorb into the rotation keep about the same axis (each a T, Tdg, S, Sdg, Z, Rz or CRz), deleting absorb and adding its angle to that of keep, negated if negate is set as the axes are opposite
+/// Constant angles (including those loaded from constants) are folded together, so ops are only added to the Hugr to sum symbolic angles, whose wires must then be in the region of keep
+/// Where the sum is constant, keep is replaced by the matching gate if there is one; returns the node now carrying the merged rotation, or None if the angles cancelled out and keep was removed as well
+pub fn merge_rotation_angles<H: HugrMut>(hugr: &mut H, keep: H::Node, absorb: H::Node, negate: bool) -> Option<H::Node> {
+    let mut angle = initial_angle(&*hugr, keep);
+    let absorbed = initial_angle(&*hugr, absorb);
+    assert!(absorbed.wires.is_empty() || hugr.get_parent(keep) == hugr.get_parent(absorb), "The angle of {:?} is not available in the region of {:?}", absorb, keep);
+    angle.add(if negate { absorbed.negated() } else { absorbed });
+    // Constant angles were folded into angle, so the loads feeding them may be left unused
+    let loads = [keep, absorb]
+        .into_iter()
+        .filter_map(|node| match tket_op(&*hugr, node) {
+            Some(TketOp::Rz) => hugr.single_linked_output(node, IncomingPort::from(1)),
+            Some(TketOp::CRz) => hugr.single_linked_output(node, IncomingPort::from(2)),
+            _ => None,
+        })
+        .filter(|(load, _)| matches!(hugr.get_optype(*load), OpType::LoadConstant(_)))
+        .map(|(load, _)| load)
+        .collect::<Vec<_>>();
+    remove_gate(hugr, absorb);
+    let merged = set_angle(hugr, keep, &angle);
+    for load in loads {
+        if hugr.contains_node(load) && hugr.linked_inputs(load, OutgoingPort::from(0)).next().is_none() {
+            let (cst, _) = hugr.single_linked_output(load, IncomingPort::from(0)).unwrap();
+            hugr.remove_node(load);
+            if hugr.linked_inputs(cst, OutgoingPort::from(0)).next().is_none() {
+                hugr.remove_node(cst);
+            }
+        }
+    }
+    merged
+}
+
+/// A Pauli rotation found by extract_pauli_exponentials, referring back to the node it comes from
+/// The rotation is by factor times the angle of the node, taking T and Tdg to have the angles 0.25 and -0.25 half-turns (as for Rz)
+#[derive(Debug, Clone, PartialEq)]
+pub struct RotationRef<N> {
+    /// The node, after the calls it was inlined through
+    pub path: NodePath<N>,
+    pub factor: f64,
+}
+
+/// A rotation found by extract_pauli_exponentials, about the Pauli over the input qubits
+pub type PauliRotation<N> = (PauliProduct, RotationRef<N>);
+
+/// The rotations found by extract_pauli_exponentials in circuit order, followed by the final Clifford as a tableau over the input qubits
+pub type PauliExponentials<N> = (Vec<PauliRotation<N>>, Tableau);
+
+/// Expresses the unitary circuit in the entrypoint region as a sequence of Pauli rotations (phase gadgets) over its input qubits followed by a Clifford, given as a tableau over the input qubits in port order
+/// Each T, Tdg and Rz gives a rotation about the Pauli that its Z axis maps back to through the Clifford gates before it; a CRz gives two, about Z on the target by half its angle and about Z on both qubits by minus half its angle
+/// Each Pauli is returned with a positive sign, negating the factor if need be; in this form, phase folding is just merging rotations about the same Pauli with only commuting ones between them
+/// Calls are walked into where the policy inlines them; any other node acting on qubits (including allocations and measurements, which are not unitary) is unsupported
+pub fn extract_pauli_exponentials<H: HugrView>(hugr: &H, policy: &AnalysisConfig<H::Node>) -> Result<PauliExponentials<H::Node>, DataflowError<H::Node>> {
+    let root = hugr.entrypoint();
+    let [input, _] = hugr.get_io(root).ok_or(DataflowError::MissingInput { parent: root })?;
+    let n_qubits = hugr.out_value_types(input).filter(|(_, t)| *t == qb_t()).count();
+    let mut extraction = PauliExtraction { rotations: vec![], tab: Tableau::new(n_qubits) };
+    extraction.walk(hugr, root, &(0..n_qubits).collect_vec(), &[], &[root], policy)?;
+    Ok((extraction.rotations, extraction.tab))
+}
+
+/// The rotations found so far by extract_pauli_exponentials, and the Clifford applied since the start of the circuit
+struct PauliExtraction<N> {
+    rotations: Vec<PauliRotation<N>>,
+    tab: Tableau,
+}
+
+impl<N: Copy + Eq + std::hash::Hash> PauliExtraction<N> {
+    /// Walks the region under parent, where qubits gives the qubit of the tableau on each qubit input in port order, path the calls leading to the region and call_stack the functions being walked; returns the qubit on each qubit output
+    fn walk<H: HugrView<Node = N>>(&mut self, hugr: &H, parent: N, qubits: &[usize], path: &[N], call_stack: &[N], policy: &AnalysisConfig<N>) -> Result<Vec<usize>, DataflowError<N>> {
+        let mut frontier: HashMap<(N, IncomingPort), usize> = HashMap::new();
+        let mut outputs = vec![];
+        let (region, node_map) = hugr.region_portgraph(parent);
+        let mut topo = pv::Topo::new(&region);
+        while let Some(pgnode) = topo.next(&region) {
+            let node = node_map.from_portgraph(pgnode);
+            let node_path = path.iter().copied().chain([node]).collect_vec();
+            let in_qubits = hugr.in_value_types(node).filter(|(_, t)| *t == qb_t()).map(|(p, _)| frontier.remove(&(node, p)).ok_or(DataflowError::InconsistentFrontier { node, port: p })).collect::<Result<Vec<_>, _>>()?;
+            let out_ports = hugr.out_value_types(node).filter(|(_, t)| *t == qb_t()).map(|(p, _)| p).collect_vec();
+            let optype = hugr.get_optype(node);
+            let out_qubits = match (optype, tket_op(hugr, node)) {
+                (OpType::Input(_), _) => qubits.to_vec(),
+                (OpType::Output(_), _) => {
+                    outputs = in_qubits;
+                    vec![]
+                }
+                (OpType::Call(_), _) => {
+                    let call_port = optype.static_input_port().unwrap();
+                    let (fun, _) = hugr.linked_outputs(node, call_port).exactly_one().ok().ok_or(DataflowError::MissingStaticEdge { node })?;
+                    if call_stack.contains(&fun) || !matches!(policy.for_function(hugr, fun), FunctionOpacity::Inline) {
+                        return Err(DataflowError::UnsupportedOp { node });
+                    }
+                    let call_stack = call_stack.iter().copied().chain([fun]).collect_vec();
+                    self.walk(hugr, fun, &in_qubits, &node_path, &call_stack, policy)?
+                }
+                (_, Some(TketOp::T)) => self.add_rotation(&in_qubits, 0.25, node_path),
+                (_, Some(TketOp::Tdg)) => self.add_rotation(&in_qubits, -0.25, node_path),
+                (_, Some(TketOp::Rz)) => self.add_rotation(&in_qubits, 1.0, node_path),
+                (_, Some(TketOp::CRz)) => {
+                    self.add_rotation(&in_qubits[1..], 0.5, node_path.clone());
+                    self.add_rotation(&in_qubits, -0.5, node_path)
+                }
+                (_, Some(op)) if append_clifford(&mut self.tab, op, &in_qubits) => in_qubits,
+                _ if policy.is_transparent(optype) => in_qubits,
+                // Classical nodes, e.g. those computing angles
+                _ if in_qubits.is_empty() && out_ports.is_empty() => vec![],
+                _ => return Err(DataflowError::UnsupportedOp { node }),
+            };
+            for (port, qubit) in out_ports.into_iter().zip(out_qubits) {
+                let next = hugr.single_linked_input(node, port).ok_or(DataflowError::DanglingQubitWire { node, port })?;
+                frontier.insert(next, qubit);
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Records a rotation about Z on all of qubits (at this point in the circuit), pulled back through the Clifford so far; returns the qubits, which the rotation leaves in place
+    fn add_rotation(&mut self, qubits: &[usize], factor: f64, path: NodePath<N>) -> Vec<usize> {
+        let inverse = self.tab.inverse();
+        let mut axis = inverse.stabilizer(qubits[0]);
+        for q in &qubits[1..] {
+            axis.pauli_product_mult(&inverse.stabilizer(*q));
+        }
+        let factor = if axis.sign { -factor } else { factor };
+        axis.sign = false;
+        self.rotations.push((axis, RotationRef { path, factor }));
+        qubits.to_vec()
+    }
+}
+
+/// Whether we may rewrite node in place, i.e. whether it is within root or within functions with a single call site, as otherwise a change would affect other callers
+fn is_editable<H: HugrView>(hugr: &H, mut node: H::Node, root: H::Node) -> bool {
+    while node != root {
+        if let OpType::FuncDefn(_) = hugr.get_optype(node) {
+            let static_port = hugr.get_optype(node).static_output_port().unwrap();
+            if hugr.linked_inputs(node, static_port).count() != 1 {
+                return false;
+            }
+        }
+        match hugr.get_parent(node) {
+            Some(parent) => node = parent,
+            None => return true,
+        }
+    }
+    true
+}
+
+fn initial_angle<H: HugrView>(hugr: &H, node: H::Node) -> Angle<H::Node> {
+    let wire = |port: usize| {
+        if let Some(half_turns) = constant_half_turns(hugr, node, IncomingPort::from(port)) {
+            return Angle { half_turns, wires: vec![] };
+        }
+        let (n, p) = hugr.single_linked_output(node, IncomingPort::from(port)).unwrap();
+        Angle { half_turns: 0., wires: vec![(n, p, false)] }
+    };
+    match tket_op(hugr, node) {
+        Some(TketOp::T) => Angle { half_turns: 0.25, wires: vec![] },
+        Some(TketOp::Tdg) => Angle { half_turns: -0.25, wires: vec![] },
+        Some(TketOp::S) => Angle { half_turns: 0.5, wires: vec![] },
+        Some(TketOp::Sdg) => Angle { half_turns: -0.5, wires: vec![] },
+        Some(TketOp::Z) => Angle { half_turns: 1., wires: vec![] },
+        Some(TketOp::Rz) => wire(1),
+        Some(TketOp::CRz) => wire(2),
+        op => panic!("Unexpected rotation {:?}", op),
+    }
+}
+
+/// Makes node a rotation by angle, replacing it with the matching gate if the angle is constant; returns the node now carrying the rotation, or None if the angle cancelled out and the node was removed
+fn set_angle<H: HugrMut>(hugr: &mut H, node: H::Node, angle: &Angle<H::Node>) -> Option<H::Node> {
+    let parent = hugr.get_parent(node).unwrap();
+    if tket_op(&*hugr, node) == Some(TketOp::CRz) {
+        let (src, src_port) = angle_wire(hugr, parent, angle);
+        hugr.disconnect(node, IncomingPort::from(2));
+        hugr.connect(src, src_port, node, IncomingPort::from(2));
+        return Some(node);
+    }
+    let half_turns = angle.half_turns.rem_euclid(2.);
+    if angle.wires.is_empty() && (half_turns < 1e-12 || 2. - half_turns < 1e-12) {
+        remove_gate(hugr, node);
+        return None;
+    }
+    let gate = if angle.wires.is_empty() {
+        [(0.25, TketOp::T), (0.5, TketOp::S), (1., TketOp::Z), (1.5, TketOp::Sdg), (1.75, TketOp::Tdg)].into_iter().find(|(h, _)| (half_turns - h).abs() < 1e-12).map(|(_, op)| op)
+    } else {
+        None
+    };
+    let new_node = match gate {
+        Some(op) => hugr.add_node_with_parent(parent, op),
+        None => {
+            let (src, src_port) = angle_wire(hugr, parent, angle);
+            let rz = hugr.add_node_with_parent(parent, TketOp::Rz);
+            hugr.connect(src, src_port, rz, IncomingPort::from(1));
+            rz
+        }
+    };
+    let (src, src_port) = hugr.single_linked_output(node, IncomingPort::from(0)).unwrap();
+    let (dst, dst_port) = hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap();
+    hugr.remove_node(node);
+    hugr.connect(src, src_port, new_node, IncomingPort::from(0));
+    hugr.connect(new_node, OutgoingPort::from(0), dst, dst_port);
+    Some(new_node)
+}
+
+/// Adds nodes under parent computing angle as a rotation, returning the wire carrying it
+fn angle_wire<H: HugrMut>(hugr: &mut H, parent: H::Node, angle: &Angle<H::Node>) -> (H::Node, OutgoingPort) {
+    hugr.use_extension(ROTATION_EXTENSION.clone());
+    hugr.use_extension(float_ops::EXTENSION.clone());
+    let add_op = |hugr: &mut H, op: OpType, inputs: &[(H::Node, OutgoingPort)]| {
+        let node = hugr.add_node_with_parent(parent, op);
+        for (i, (src, src_port)) in inputs.iter().enumerate() {
+            hugr.connect(*src, *src_port, node, IncomingPort::from(i));
+        }
+        (node, OutgoingPort::from(0))
+    };
+    let mut wire = None;
+    for (src, src_port, negated) in angle.wires.iter() {
+        let mut term = (*src, *src_port);
+        if *negated {
+            let float = add_op(hugr, RotationOp::to_halfturns.into(), &[term]);
+            let float = add_op(hugr, FloatOps::fneg.into(), &[float]);
+            term = add_op(hugr, RotationOp::from_halfturns_unchecked.into(), &[float]);
+        }
+        wire = Some(match wire {
+            Some(sum) => add_op(hugr, RotationOp::radd.into(), &[sum, term]),
+            None => term,
+        });
+    }
+    let half_turns = angle.half_turns.rem_euclid(2.);
+    if wire.is_none() || half_turns.abs() > 1e-12 {
+        let cst = hugr.add_node_with_parent(parent, Const::new(Value::extension(ConstRotation::new(half_turns).unwrap())));
+        let load = add_op(hugr, LoadConstant { datatype: rotation_type() }.into(), &[(cst, OutgoingPort::from(0))]);
+        wire = Some(match wire {
+            Some(sum) => add_op(hugr, RotationOp::radd.into(), &[sum, load]),
+            None => load,
+        });
+    }
+    wire.unwrap()
+}
+
+/// The number of T and Tdg nodes within any of regions
+fn t_count<H: HugrView>(hugr: &H, regions: &[H::Node]) -> usize {
+    regions.iter().flat_map(|r| hugr.descendants(*r)).filter(|n| matches!(tket_op(hugr, *n), Some(TketOp::T | TketOp::Tdg))).count()
+}
+
+fn tket_op<H: HugrView>(hugr: &H, node: H::Node) -> Option<TketOp> {
+    hugr.get_optype(node).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok())
+}
+
+/// The primitive Clifford updates shared by the Choi tableau of the analysis and the unitary tableau of a circuit, so that append_clifford maps each gate to them once for both
+pub(crate) trait CliffordTableau {
+    fn append_h(&mut self, index: usize);
+    fn append_s(&mut self, index: usize);
+    fn append_v(&mut self, index: usize);
+    fn append_x(&mut self, index: usize);
+    fn append_z(&mut self, index: usize);
+    fn append_cx(&mut self, indices: Vec<usize>);
+    fn append_cz(&mut self, indices: Vec<usize>);
+}
+
+// Forwards the trait methods to the inherent ones of the same name
+macro_rules! impl_clifford_tableau {
+    ($tab:ty) => {
+        impl CliffordTableau for $tab {
+            fn append_h(&mut self, index: usize) {
+                <$tab>::append_h(self, index)
+            }
+            fn append_s(&mut self, index: usize) {
+                <$tab>::append_s(self, index)
+            }
+            fn append_v(&mut self, index: usize) {
+                <$tab>::append_v(self, index)
+            }
+            fn append_x(&mut self, index: usize) {
+                <$tab>::append_x(self, index)
+            }
+            fn append_z(&mut self, index: usize) {
+                <$tab>::append_z(self, index)
+            }
+            fn append_cx(&mut self, indices: Vec<usize>) {
+                <$tab>::append_cx(self, indices)
+            }
+            fn append_cz(&mut self, indices: Vec<usize>) {
+                <$tab>::append_cz(self, indices)
+            }
+        }
+    };
+}
+
+impl_clifford_tableau!(ChoiTableau);
+impl_clifford_tableau!(Tableau);
+
+/// Conjugates the rows of tab by a Clifford gate on the given columns (or qubits, for the unitary tableau of a circuit); returns false (leaving tab unchanged) if op is not a Clifford gate we handle
+pub(crate) fn append_clifford<T: CliffordTableau>(tab: &mut T, op: TketOp, cols: &[usize]) -> bool {
+    match op {
+        TketOp::H => tab.append_h(cols[0]),
+        TketOp::S => tab.append_s(cols[0]),
+        TketOp::Sdg => {
+            tab.append_s(cols[0]);
+            tab.append_z(cols[0]);
+        }
+        TketOp::V => tab.append_v(cols[0]),
+        TketOp::Vdg => {
+            tab.append_v(cols[0]);
+            tab.append_x(cols[0]);
+        }
+        TketOp::X => tab.append_x(cols[0]),
+        TketOp::Y => {
+            tab.append_x(cols[0]);
+            tab.append_z(cols[0]);
+        }
+        TketOp::Z => tab.append_z(cols[0]),
+        TketOp::CX => tab.append_cx(cols.to_vec()),
+        TketOp::CZ => tab.append_cz(cols.to_vec()),
+        _ => return false,
+    }
+    true
+}
+
+fn is_pauli(p: &PauliProduct, z: [bool; 2], x: [bool; 2]) -> bool {
+    (0..2).all(|q| p.z.get(q) == z[q] && p.x.get(q) == x[q]) && !p.sign
+}
+
+/// Removes a gate acting only on qubits, connecting each input wire straight through to where the corresponding output went
+fn remove_gate<H: HugrMut>(hugr: &mut H, node: H::Node) {
+    let links = hugr.out_value_types(node).map(|(p, _)| {
+        let src = hugr.single_linked_output(node, IncomingPort::from(p.index())).unwrap();
+        let dst = hugr.single_linked_input(node, p).unwrap();
+        (src, dst)
+    }).collect_vec();
+    hugr.remove_node(node);
+    for ((src, src_port), (dst, dst_port)) in links {
+        hugr.connect(src, src_port, dst, dst_port);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use hugr::{builder::{endo_sig, DFGBuilder, Dataflow, DataflowHugr, DataflowSubContainer, HugrBuilder, ModuleBuilder, SubContainer}, extension::prelude::{bool_t, qb_t}, hugr::hugrmut::HugrMut, ops::handle::NodeHandle, type_row, types::Signature, Hugr, HugrView, IncomingPort, OutgoingPort};
+    use hugr::extension::prelude::Barrier;
+    use hugr::ops::{ExtensionOp, LoadConstant, OpType, Value};
+    use tket::extension::bool::{bool_type, BoolOp};
+    use tket::extension::rotation::{rotation_type, ConstRotation, RotationOp};
+    use tket::hugr::extension::simple_op::MakeExtensionOp;
+    use tket::TketOp;
+
+    use crate::{rewrite::{can_cancel_cnots, can_commute_past, cancel_cnots, defer_measurements, extract_pauli_exponentials, fold_phases, fold_t_tdg_pair, merge_rotation_angles, optimize_with_analysis, propagate_measurement_constants, remove_dead_gates, simplify_clifford_region, tket_op, FoldStats, OptimizeStats, RotationRef}, stabilizer_dataflow::{AnalysisConfig, DataflowError, FunctionOpacity, StabilizerDataflow}, tableau::Tableau, test_support::RANDOM_EXTENSION};
+
+    #[test]
+    fn test_cancel_cnots() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let cx1 = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap();
+        let [qb0, qb1] = cx1.outputs_arr();
+        // S and T preserve Z on the control, X preserves X on the target
+        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::X, [qb1]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap().outputs_arr();
+        let cx2 = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap();
+        let [qb0, qb1] = cx2.outputs_arr();
+        let mut hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(can_cancel_cnots(&hugr, &analysis, cx1.node(), cx2.node()));
+        cancel_cnots(&mut hugr, &analysis, cx1.node(), cx2.node());
+        hugr.validate().unwrap();
+        assert!(hugr.nodes().all(|n| tket_op(&hugr, n) != Some(TketOp::CX)));
+        assert_eq!(hugr.nodes().filter(|n| tket_op(&hugr, *n).is_some()).count(), 3);
+    }
+
+    #[test]
+    fn test_cannot_cancel_cnots() {
+        // CX; H⊗H; CX is not the identity up to Cliffords between, since H⊗H maps Z on the control to X
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let cx1 = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap();
+        let [qb0, qb1] = cx1.outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        let cx2 = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap();
+        let [qb0, qb1] = cx2.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(!can_cancel_cnots(&hugr, &analysis, cx1.node(), cx2.node()));
+        // Nor can we cancel through a T on the target, which does not preserve X
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let cx1 = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap();
+        let [qb0, qb1] = cx1.outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let cx2 = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap();
+        let [qb0, qb1] = cx2.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(!can_cancel_cnots(&hugr, &analysis, cx1.node(), cx2.node()));
+    }
+
+    #[test]
+    fn test_fold_t_tdg() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::Tdg, [qb]).unwrap().outputs_arr();
+        let mut hugr = builder.finish_hugr_with_outputs([qb]).unwrap();
+        let stats = fold_phases(&mut hugr, &AnalysisConfig::default_opaque()).unwrap();
+        assert_eq!(stats, FoldStats { rotations_removed: 2, t_count_before: 2, t_count_after: 0 });
+        hugr.validate().unwrap();
+        assert!(hugr.nodes().all(|n| tket_op(&hugr, n).is_none()));
+    }
+
+    #[test]
+    fn test_fold_across_barrier() {
+        // T;Barrier;T are related through the barrier, but only merged into an S when the policy allows it
+        let build = || {
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+            let [qb] = builder.input_wires_arr();
+            let [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+            let [qb] = builder.add_dataflow_op(Barrier::new(vec![qb_t()]), [qb]).unwrap().outputs_arr();
+            let [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+            builder.finish_hugr_with_outputs([qb]).unwrap()
+        };
+        let mut hugr = build();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.mergeable_rotations(&hugr).len(), 1);
+        let stats = fold_phases(&mut hugr, &AnalysisConfig::default_opaque()).unwrap();
+        assert_eq!(stats, FoldStats { rotations_removed: 0, t_count_before: 2, t_count_after: 2 });
+        let mut hugr = build();
+        let stats = fold_phases(&mut hugr, &AnalysisConfig::default_opaque().with_merge_across_barriers(true)).unwrap();
+        assert_eq!(stats, FoldStats { rotations_removed: 1, t_count_before: 2, t_count_after: 0 });
+        hugr.validate().unwrap();
+        // An op registered as transparent acts as a barrier too
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let sync = ExtensionOp::new(RANDOM_EXTENSION.get_op("op1").unwrap().clone(), []).unwrap();
+        let [qb] = builder.add_dataflow_op(sync, [qb]).unwrap().outputs_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let mut hugr = builder.finish_hugr_with_outputs([qb]).unwrap();
+        let policy = AnalysisConfig::default_opaque().with_transparent("op1");
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), policy.clone()).unwrap();
+        assert_eq!(analysis.mergeable_rotations(&hugr).len(), 1);
+        assert_eq!(fold_phases(&mut hugr, &policy).unwrap().rotations_removed, 0);
+    }
+
+    #[test]
+    fn test_fold_rz_across_cx_pair() {
+        // Rz(a);CX;CX;Rz(b) on the control becomes a single Rz(a + b)
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t(), rotation_type(), rotation_type()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, a, b] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Rz, [qb0, a]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Rz, [qb0, b]).unwrap().outputs_arr();
+        let mut hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let stats = fold_phases(&mut hugr, &AnalysisConfig::default_opaque()).unwrap();
+        assert_eq!(stats, FoldStats { rotations_removed: 1, t_count_before: 0, t_count_after: 0 });
+        hugr.validate().unwrap();
+        let rz = hugr.nodes().filter(|n| tket_op(&hugr, *n) == Some(TketOp::Rz)).collect::<Vec<_>>();
+        assert_eq!(rz.len(), 1);
+        let (sum, _) = hugr.single_linked_output(rz[0], IncomingPort::from(1)).unwrap();
+        let sum_op = hugr.get_optype(sum).as_extension_op().and_then(|op| RotationOp::from_extension_op(op).ok());
+        assert_eq!(sum_op, Some(RotationOp::radd));
+        let [input, _] = hugr.get_io(hugr.entrypoint()).unwrap();
+        for port in 0..2 {
+            assert_eq!(hugr.single_linked_output(sum, IncomingPort::from(port)).unwrap().0, input);
+        }
+    }
+
+    #[test]
+    fn test_merge_rotation_angles_constant() {
+        // A constant Rz(1/4) absorbed into a T gives an S, and the load of the constant goes with it
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let angle = builder.add_load_value(Value::extension(ConstRotation::new(0.25).unwrap()));
+        let rz = builder.add_dataflow_op(TketOp::Rz, [qb, angle]).unwrap();
+        let t = builder.add_dataflow_op(TketOp::T, rz.outputs()).unwrap();
+        let mut hugr = builder.finish_hugr_with_outputs(t.outputs()).unwrap();
+        let merged = merge_rotation_angles(&mut hugr, t.node(), rz.node(), false).unwrap();
+        hugr.validate().unwrap();
+        assert_eq!(tket_op(&hugr, merged), Some(TketOp::S));
+        assert_eq!(hugr.nodes().filter_map(|n| tket_op(&hugr, n)).collect::<Vec<_>>(), vec![TketOp::S]);
+        assert!(hugr.nodes().all(|n| !matches!(hugr.get_optype(n), OpType::Const(_) | OpType::LoadConstant(_))));
+    }
+
+    #[test]
+    fn test_merge_rotation_angles_symbolic() {
+        // Rz(a);Rz(1/2) becomes Rz(a + 1/2), summing the input wire with a fresh constant
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), rotation_type()], vec![qb_t()])).unwrap();
+        let [qb, a] = builder.input_wires_arr();
+        let rz_a = builder.add_dataflow_op(TketOp::Rz, [qb, a]).unwrap();
+        let [qb] = rz_a.outputs_arr();
+        let half = builder.add_load_value(Value::extension(ConstRotation::new(0.5).unwrap()));
+        let rz_half = builder.add_dataflow_op(TketOp::Rz, [qb, half]).unwrap();
+        let mut hugr = builder.finish_hugr_with_outputs(rz_half.outputs()).unwrap();
+        let merged = merge_rotation_angles(&mut hugr, rz_a.node(), rz_half.node(), false).unwrap();
+        hugr.validate().unwrap();
+        assert_eq!(hugr.nodes().filter_map(|n| tket_op(&hugr, n)).collect::<Vec<_>>(), vec![TketOp::Rz]);
+        let (sum, _) = hugr.single_linked_output(merged, IncomingPort::from(1)).unwrap();
+        let sum_op = hugr.get_optype(sum).as_extension_op().and_then(|op| RotationOp::from_extension_op(op).ok());
+        assert_eq!(sum_op, Some(RotationOp::radd));
+        let [input, _] = hugr.get_io(hugr.entrypoint()).unwrap();
+        assert_eq!(hugr.single_linked_output(sum, IncomingPort::from(0)).unwrap().0, input);
+        let (load, _) = hugr.single_linked_output(sum, IncomingPort::from(1)).unwrap();
+        assert!(matches!(hugr.get_optype(load), OpType::LoadConstant(_)));
+    }
+
+    #[test]
+    fn test_merge_rotation_angles_negated() {
+        // Absorbing a T with a negated sign cancels a T
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let t1 = builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let t2 = builder.add_dataflow_op(TketOp::T, t1.outputs()).unwrap();
+        let mut hugr = builder.finish_hugr_with_outputs(t2.outputs()).unwrap();
+        assert_eq!(merge_rotation_angles(&mut hugr, t2.node(), t1.node(), true), None);
+        hugr.validate().unwrap();
+        assert!(hugr.nodes().all(|n| tket_op(&hugr, n).is_none()));
+
+        // Absorbing Rz(a) into Rz(b) with a negated sign gives Rz(b - a), negating a through float ops
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), rotation_type(), rotation_type()], vec![qb_t()])).unwrap();
+        let [qb, a, b] = builder.input_wires_arr();
+        let rz_a = builder.add_dataflow_op(TketOp::Rz, [qb, a]).unwrap();
+        let [qb] = rz_a.outputs_arr();
+        let rz_b = builder.add_dataflow_op(TketOp::Rz, [qb, b]).unwrap();
+        let mut hugr = builder.finish_hugr_with_outputs(rz_b.outputs()).unwrap();
+        let merged = merge_rotation_angles(&mut hugr, rz_b.node(), rz_a.node(), true).unwrap();
+        hugr.validate().unwrap();
+        let (sum, _) = hugr.single_linked_output(merged, IncomingPort::from(1)).unwrap();
+        let (negated, _) = hugr.single_linked_output(sum, IncomingPort::from(1)).unwrap();
+        let negated_op = hugr.get_optype(negated).as_extension_op().and_then(|op| RotationOp::from_extension_op(op).ok());
+        assert_eq!(negated_op, Some(RotationOp::from_halfturns_unchecked));
+    }
+
+    #[test]
+    fn test_fold_across_inline_call() {
+        // A T in main and a T in an inlined function called once merge into an S in the function
+        let mut module = ModuleBuilder::new();
+        let mut fun_builder = module.define_function("t_gate", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = fun_builder.input_wires_arr();
+        let [qb] = fun_builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let fun = fun_builder.finish_with_outputs([qb]).unwrap();
+        let mut main_builder = module.define_function("main", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = main_builder.input_wires_arr();
+        let [qb] = main_builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let call = main_builder.call(fun.handle(), &[], [qb]).unwrap();
+        let main = main_builder.finish_with_outputs(call.outputs()).unwrap();
+        let mut hugr = module.finish_hugr().unwrap();
+        hugr.set_entrypoint(main.node());
+        let stats = fold_phases(&mut hugr, &AnalysisConfig::default_inline()).unwrap();
+        assert_eq!(stats, FoldStats { rotations_removed: 1, t_count_before: 2, t_count_after: 0 });
+        hugr.validate().unwrap();
+        assert!(hugr.descendants(main.node()).all(|n| tket_op(&hugr, n).is_none()));
+        assert_eq!(hugr.descendants(fun.node()).filter_map(|n| tket_op(&hugr, n)).collect::<Vec<_>>(), vec![TketOp::S]);
+    }
+
+    #[test]
+    fn test_simplify_clifford_region() {
+        // H;S;S;H is X and the pairs of CX and Z cancel, so the ten gates implement X on qubit 0 and H;X on qubit 1
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [mut qb0, mut qb1] = builder.input_wires_arr();
+        let mut nodes = vec![];
+        for (op, qubits) in [(TketOp::H, vec![0]), (TketOp::S, vec![0]), (TketOp::S, vec![0]), (TketOp::H, vec![0]), (TketOp::CX, vec![0, 1]), (TketOp::CX, vec![0, 1]), (TketOp::H, vec![1]), (TketOp::X, vec![1]), (TketOp::Z, vec![0]), (TketOp::Z, vec![0])] {
+            let wires = qubits.iter().map(|q| if *q == 0 { qb0 } else { qb1 }).collect::<Vec<_>>();
+            let gate = builder.add_dataflow_op(op, wires).unwrap();
+            for (q, wire) in qubits.iter().zip(gate.outputs()) {
+                if *q == 0 {
+                    qb0 = wire;
+                } else {
+                    qb1 = wire;
+                }
+            }
+            nodes.push(gate.node());
+        }
+        let mut hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let original = hugr.clone();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        simplify_clifford_region(&mut hugr, &analysis, &nodes);
+        hugr.validate().unwrap();
+        assert!(hugr.nodes().filter(|n| tket_op(&hugr, *n).is_some()).count() < 10);
+        let before = StabilizerDataflow::run_dfg(&original, original.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let after = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(StabilizerDataflow::check_clifford_equivalence(&before, &after));
+    }
+
+    #[test]
+    fn test_remove_dead_gates() {
+        // S and then CX controlled on the fresh qubit both act trivially on |0>
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [a] = builder.input_wires_arr();
+        let [fresh] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [fresh] = builder.add_dataflow_op(TketOp::S, [fresh]).unwrap().outputs_arr();
+        let [fresh, a] = builder.add_dataflow_op(TketOp::CX, [fresh, a]).unwrap().outputs_arr();
+        let [a] = builder.add_dataflow_op(TketOp::H, [a]).unwrap().outputs_arr();
+        builder.add_dataflow_op(TketOp::QFree, [fresh]).unwrap();
+        let mut hugr = builder.finish_hugr_with_outputs([a]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(remove_dead_gates(&mut hugr, &analysis).unwrap(), 2);
+        hugr.validate().unwrap();
+        assert_eq!(hugr.nodes().filter_map(|n| tket_op(&hugr, n)).sorted_by_key(|op| format!("{:?}", op)).collect::<Vec<_>>(), vec![TketOp::H, TketOp::QAlloc, TketOp::QFree]);
+    }
+
+    #[test]
+    fn test_extract_pauli_exponentials() {
+        let gadgets = |rotations: Vec<(crate::pauli_product::PauliProduct, RotationRef<hugr::Node>)>| rotations.into_iter().map(|(p, r)| ((0..2).map(|q| p.pauli_at(q)).collect::<String>(), r.path, r.factor)).collect::<Vec<_>>();
+        // T;CX;T on the target;H;Rz: the second T is about ZZ, and the Rz about X0 pulled back through the CX
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t(), rotation_type()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, a] = builder.input_wires_arr();
+        let t0 = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [t0.out_wire(0), qb1]).unwrap().outputs_arr();
+        let t1 = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let rz = builder.add_dataflow_op(TketOp::Rz, [qb0, a]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([rz.out_wire(0), t1.out_wire(0)]).unwrap();
+        let (rotations, tab) = extract_pauli_exponentials(&hugr, &AnalysisConfig::default_opaque()).unwrap();
+        assert_eq!(gadgets(rotations), vec![
+            ("ZI".to_string(), vec![t0.node()], 0.25),
+            ("ZZ".to_string(), vec![t1.node()], 0.25),
+            ("XX".to_string(), vec![rz.node()], 1.0),
+        ]);
+        let mut expected = Tableau::new(2);
+        expected.append_cx(vec![0, 1]);
+        expected.append_h(0);
+        assert!(tab.z == expected.z && tab.x == expected.x && tab.signs == expected.signs);
+        // X before a CRz flips the sign of Z on the control, so the rotation about ZZ changes sign
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t(), rotation_type()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, a] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::X, [qb0]).unwrap().outputs_arr();
+        let crz = builder.add_dataflow_op(TketOp::CRz, [qb0, qb1, a]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(crz.outputs_arr::<2>()).unwrap();
+        let (rotations, _) = extract_pauli_exponentials(&hugr, &AnalysisConfig::default_opaque()).unwrap();
+        assert_eq!(gadgets(rotations), vec![("IZ".to_string(), vec![crz.node()], 0.5), ("ZZ".to_string(), vec![crz.node()], 0.5)]);
+        // Measurements are not unitary
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let meas = builder.add_dataflow_op(TketOp::Measure, [qb]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([meas.out_wire(0)]).unwrap();
+        assert!(matches!(extract_pauli_exponentials(&hugr, &AnalysisConfig::default_opaque()), Err(DataflowError::UnsupportedOp { node }) if node == meas.node()));
+    }
+
+    #[test]
+    fn test_defer_measurements() {
+        // Measure;CZ on the measured qubit becomes CZ;Measure, but the H after the CZ does not commute with the measurement
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![qb_t(), qb_t(), bool_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let measure = builder.add_dataflow_op(TketOp::Measure, [qb0]).unwrap();
+        let [qb0, b] = measure.outputs_arr();
+        let cz = builder.add_dataflow_op(TketOp::CZ, [qb0, qb1]).unwrap();
+        let [qb0, qb1] = cz.outputs_arr();
+        let h = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap();
+        let mut hugr = builder.finish_hugr_with_outputs([h.out_wire(0), qb1, b]).unwrap();
+        assert_eq!(defer_measurements(&mut hugr), 1);
+        hugr.validate().unwrap();
+        let [input, _] = hugr.get_io(hugr.entrypoint()).unwrap();
+        assert_eq!(hugr.single_linked_output(cz.node(), IncomingPort::from(0)).unwrap().0, input);
+        assert_eq!(hugr.single_linked_output(measure.node(), IncomingPort::from(0)).unwrap().0, cz.node());
+        assert_eq!(hugr.single_linked_output(h.node(), IncomingPort::from(0)).unwrap().0, measure.node());
+        // Nothing is left to move
+        assert_eq!(defer_measurements(&mut hugr), 0);
+    }
+
+    #[test]
+    fn test_defer_measurements_classical_control() {
+        // The CZ depends on the outcome through the Conditional on the other qubit, so the Measure must stay before it
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0, b] = builder.add_dataflow_op(TketOp::Measure, [qb0]).unwrap().outputs_arr();
+        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], b), [(qb_t(), qb1)], vec![qb_t()].into()).unwrap();
+        let cond0_builder = cond_builder.case_builder(0).unwrap();
+        let [c0q] = cond0_builder.input_wires_arr();
+        cond0_builder.finish_with_outputs([c0q]).unwrap();
+        let mut cond1_builder = cond_builder.case_builder(1).unwrap();
+        let [c1q] = cond1_builder.input_wires_arr();
+        let [c1q] = cond1_builder.add_dataflow_op(TketOp::X, [c1q]).unwrap().outputs_arr();
+        cond1_builder.finish_with_outputs([c1q]).unwrap();
+        let [qb1] = cond_builder.finish_sub_container().unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CZ, [qb0, qb1]).unwrap().outputs_arr();
+        let mut hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        assert_eq!(defer_measurements(&mut hugr), 0);
+        hugr.validate().unwrap();
+    }
+
+    #[test]
+    fn test_propagate_measurement_constants() {
+        // Reset;Measure always gives false, so the Conditional collapses to its false case (an H) and the measurement to a constant
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![qb_t(), qb_t(), bool_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Reset, [qb0]).unwrap().outputs_arr();
+        let [qb0, b] = builder.add_dataflow_op(TketOp::Measure, [qb0]).unwrap().outputs_arr();
+        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], b), [(qb_t(), qb1)], vec![qb_t()].into()).unwrap();
+        let mut cond0_builder = cond_builder.case_builder(0).unwrap();
+        let [c0q] = cond0_builder.input_wires_arr();
+        let h = cond0_builder.add_dataflow_op(TketOp::H, [c0q]).unwrap();
+        cond0_builder.finish_with_outputs(h.outputs()).unwrap();
+        let mut cond1_builder = cond_builder.case_builder(1).unwrap();
+        let [c1q] = cond1_builder.input_wires_arr();
+        let [c1q] = cond1_builder.add_dataflow_op(TketOp::X, [c1q]).unwrap().outputs_arr();
+        cond1_builder.finish_with_outputs([c1q]).unwrap();
+        let [qb1] = cond_builder.finish_sub_container().unwrap().outputs_arr();
+        let mut hugr = builder.finish_hugr_with_outputs([qb0, qb1, b]).unwrap();
+        assert_eq!(propagate_measurement_constants(&mut hugr, &AnalysisConfig::default_opaque()), Ok(1));
+        hugr.validate().unwrap();
+        let root = hugr.entrypoint();
+        assert!(hugr.children(root).all(|n| !matches!(hugr.get_optype(n), OpType::Conditional(_)) && tket_op(&hugr, n) != Some(TketOp::Measure)));
+        let [input, output] = hugr.get_io(root).unwrap();
+        assert_eq!(hugr.get_parent(h.node()), Some(root));
+        assert_eq!(hugr.single_linked_output(h.node(), IncomingPort::from(0)).unwrap(), (input, OutgoingPort::from(1)));
+        assert_eq!(hugr.single_linked_output(output, IncomingPort::from(1)).unwrap(), (h.node(), OutgoingPort::from(0)));
+        let (load, _) = hugr.single_linked_output(output, IncomingPort::from(2)).unwrap();
+        assert!(matches!(hugr.get_optype(load), OpType::LoadConstant(_)));
+        // Nothing else is left to propagate
+        assert_eq!(propagate_measurement_constants(&mut hugr, &AnalysisConfig::default_opaque()), Ok(0));
+        // Reset;X;MeasureFree always gives true, as a tket.bool kept as an output and read into the predicate of a Conditional, which collapses to its true case (an X)
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![qb_t(), bool_type()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Reset, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::X, [qb0]).unwrap().outputs_arr();
+        let [b] = builder.add_dataflow_op(TketOp::MeasureFree, [qb0]).unwrap().outputs_arr();
+        let [pred] = builder.add_dataflow_op(BoolOp::read, [b]).unwrap().outputs_arr();
+        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], pred), [(qb_t(), qb1)], vec![qb_t()].into()).unwrap();
+        let mut cond0_builder = cond_builder.case_builder(0).unwrap();
+        let [c0q] = cond0_builder.input_wires_arr();
+        let [c0q] = cond0_builder.add_dataflow_op(TketOp::H, [c0q]).unwrap().outputs_arr();
+        cond0_builder.finish_with_outputs([c0q]).unwrap();
+        let mut cond1_builder = cond_builder.case_builder(1).unwrap();
+        let [c1q] = cond1_builder.input_wires_arr();
+        let x = cond1_builder.add_dataflow_op(TketOp::X, [c1q]).unwrap();
+        cond1_builder.finish_with_outputs(x.outputs()).unwrap();
+        let [qb1] = cond_builder.finish_sub_container().unwrap().outputs_arr();
+        let mut hugr = builder.finish_hugr_with_outputs([qb1, b]).unwrap();
+        assert_eq!(propagate_measurement_constants(&mut hugr, &AnalysisConfig::default_opaque()), Ok(1));
+        hugr.validate().unwrap();
+        let root = hugr.entrypoint();
+        assert!(hugr.children(root).all(|n| !matches!(hugr.get_optype(n), OpType::Conditional(_)) && tket_op(&hugr, n) != Some(TketOp::MeasureFree)));
+        assert_eq!(hugr.get_parent(x.node()), Some(root));
+        let output = hugr.get_io(root).unwrap()[1];
+        let (load, _) = hugr.single_linked_output(output, IncomingPort::from(1)).unwrap();
+        assert_eq!(hugr.get_optype(load), &OpType::from(LoadConstant { datatype: bool_type() }));
+    }
+
+    #[test]
+    fn test_can_commute_past() {
+        // T commutes past S (which maps Z to Z) and X (which maps Z to -Z) but not past H (which maps Z to X)
+        for (op, commutes) in [(TketOp::S, true), (TketOp::X, true), (TketOp::H, false)] {
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+            let [qb] = builder.input_wires_arr();
+            let t = builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+            let clifford = builder.add_dataflow_op(op, t.outputs()).unwrap();
+            let hugr = builder.finish_hugr_with_outputs(clifford.outputs()).unwrap();
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            assert_eq!(can_commute_past(&hugr, &analysis, t.node(), clifford.node()), commutes, "{:?}", op);
+        }
+        // T on the control of a CX commutes past it, but not on the target
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let t0 = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let t1 = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap();
+        let cx = builder.add_dataflow_op(TketOp::CX, [t0.out_wire(0), t1.out_wire(0)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(cx.outputs()).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(can_commute_past(&hugr, &analysis, t0.node(), cx.node()));
+        assert!(!can_commute_past(&hugr, &analysis, t1.node(), cx.node()));
+        // Nor can a Clifford gate be moved as if it were non-Clifford
+        assert!(!can_commute_past(&hugr, &analysis, cx.node(), t0.node()));
+    }
+
+    #[test]
+    fn test_fold_t_tdg_pair() {
+        // T;S;Tdg;Sdg is the identity, as S commutes with the Z rotations
+        let build = |middle: TketOp| {
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+            let [qb] = builder.input_wires_arr();
+            let t = builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+            let [qb] = builder.add_dataflow_op(middle, t.outputs()).unwrap().outputs_arr();
+            let tdg = builder.add_dataflow_op(TketOp::Tdg, [qb]).unwrap();
+            let [qb] = builder.add_dataflow_op(TketOp::Sdg, tdg.outputs()).unwrap().outputs_arr();
+            (builder.finish_hugr_with_outputs([qb]).unwrap(), t.node(), tdg.node())
+        };
+        let (mut hugr, t, tdg) = build(TketOp::S);
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // The arguments must be a T and a Tdg
+        assert!(!fold_t_tdg_pair(&mut hugr, &analysis, tdg, t));
+        assert!(fold_t_tdg_pair(&mut hugr, &analysis, t, tdg));
+        hugr.validate().unwrap();
+        assert_eq!(hugr.nodes().filter_map(|n| tket_op(&hugr, n)).collect_vec(), vec![TketOp::S, TketOp::Sdg]);
+        // H maps Z to X and X maps Z to -Z, so in neither case do the gates cancel
+        for middle in [TketOp::H, TketOp::X] {
+            let (mut hugr, t, tdg) = build(middle);
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            assert!(!fold_t_tdg_pair(&mut hugr, &analysis, t, tdg));
+            assert_eq!(hugr.nodes().filter(|n| tket_op(&hugr, *n).is_some()).count(), 4);
+        }
+    }
+
+    #[test]
+    fn test_optimize_with_analysis() {
+        // In T;H;Tdg;H the H maps the axis of T to X, so the rotations are not related and only the dead S is removed
+        let build = |middle: &[TketOp]| {
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+            let [qb] = builder.input_wires_arr();
+            let [mut qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+            for op in middle {
+                [qb] = builder.add_dataflow_op(*op, [qb]).unwrap().outputs_arr();
+            }
+            let [qb] = builder.add_dataflow_op(TketOp::Tdg, [qb]).unwrap().outputs_arr();
+            let [qb] = builder.add_dataflow_op(TketOp::H, [qb]).unwrap().outputs_arr();
+            // S on a fresh qubit acts trivially on |0>
+            let [fresh] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+            let [fresh] = builder.add_dataflow_op(TketOp::S, [fresh]).unwrap().outputs_arr();
+            builder.add_dataflow_op(TketOp::QFree, [fresh]).unwrap();
+            builder.finish_hugr_with_outputs([qb]).unwrap()
+        };
+        let ops = |hugr: &Hugr| hugr.nodes().filter_map(|n| tket_op(hugr, n)).sorted_by_key(|op| format!("{:?}", op)).collect_vec();
+        let mut hugr = build(&[TketOp::H]);
+        let stats = optimize_with_analysis(&mut hugr, &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(stats, OptimizeStats { dead_gates_removed: 1, rotations_removed: 0 });
+        hugr.validate().unwrap();
+        assert_eq!(ops(&hugr), vec![TketOp::H, TketOp::H, TketOp::QAlloc, TketOp::QFree, TketOp::T, TketOp::Tdg]);
+        // With H;Z;H between them instead, Z after T is related to -Z before Tdg, so the rotations add up to Tdg;Tdg = Sdg
+        let mut hugr = build(&[TketOp::H, TketOp::Z, TketOp::H]);
+        let stats = optimize_with_analysis(&mut hugr, &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(stats, OptimizeStats { dead_gates_removed: 1, rotations_removed: 1 });
+        hugr.validate().unwrap();
+        assert_eq!(ops(&hugr), vec![TketOp::H, TketOp::H, TketOp::H, TketOp::QAlloc, TketOp::QFree, TketOp::Sdg, TketOp::Z]);
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use std::fmt;
+use tket::TketOp;
+
+// A dense stabilizer simulator, deliberately independent of the bit-packed tableaux, used as an oracle for the dataflow analysis
+// The state is the Choi state of a circuit on nb_qubits qubits: 2 * nb_qubits qubits, where qubit k is the reference for input k and qubit nb_qubits + k carries wire k of the circuit
+// Non-Clifford Z rotations (T, Tdg, Rz) are handled by following both Z-projection branches, so the state is the set of pure stabilizer states for every choice of I or Z at each rotation
+// The stabilizers that hold for every angle are exactly those common to both branches at each rotation, as Rz(θ) = cos(θ/2) I - i sin(θ/2) Z
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DensePauli {
+    pub x: Vec<bool>,
+    pub z: Vec<bool>,
+    pub sign: bool,
+}
+
+impl DensePauli {
+    pub fn identity(nb_qubits: usize) -> Self {
+        DensePauli { x: vec![false; nb_qubits], z: vec![false; nb_qubits], sign: false }
+    }
+
+    // The product self * other, which must commute so that the result is Hermitian
+    // The phase follows Aaronson and Gottesman, summing the power of i picked up on each qubit
+    pub fn mult(&self, other: &DensePauli) -> DensePauli {
+        let mut phase = 2 * (self.sign as i32 + other.sign as i32);
+        let qubits = self.x.iter().zip(&self.z).zip(other.x.iter().zip(&other.z));
+        for ((&x1, &z1), (&x2, &z2)) in qubits {
+            let (x1, z1, x2, z2) = (x1 as i32, z1 as i32, x2 as i32, z2 as i32);
+            phase += match (x1, z1) {
+                (0, 0) => 0,
+                (1, 1) => z2 - x2,
+                (1, 0) => z2 * (2 * x2 - 1),
+                _ => x2 * (1 - 2 * z2),
+            };
+        }
+        assert!(phase.rem_euclid(2) == 0, "The product of anticommuting Paulis is not Hermitian");
+        DensePauli {
+            x: self.x.iter().zip(&other.x).map(|(a, b)| a ^ b).collect(),
+            z: self.z.iter().zip(&other.z).map(|(a, b)| a ^ b).collect(),
+            sign: phase.rem_euclid(4) == 2,
+        }
+    }
+}
+
+// The same form as PauliProduct, e.g. "-XIY", so that the two can be compared as strings
+impl fmt::Display for DensePauli {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", if self.sign { '-' } else { '+' })?;
+        self.x.iter().zip(&self.z).try_for_each(|(x, z)| write!(f, "{}", match (x, z) {
+            (false, false) => 'I',
+            (true, false) => 'X',
+            (true, true) => 'Y',
+            (false, true) => 'Z',
+        }))
+    }
+}
+
+// A pure stabilizer state, as one generator per qubit updated by conjugation with the CHP rules
+#[derive(Debug, Clone)]
+pub struct DenseStabilizerState {
+    pub nb_qubits: usize,
+    pub stabs: Vec<DensePauli>,
+}
+
+impl DenseStabilizerState {
+    // The Choi state of the identity on nb_qubits qubits, with X⊗X and Z⊗Z on each reference and wire pair
+    pub fn choi_identity(nb_qubits: usize) -> Self {
+        let mut stabs = vec![];
+        for k in 0..nb_qubits {
+            for is_x in [true, false] {
+                let mut p = DensePauli::identity(2 * nb_qubits);
+                let paulis = if is_x { &mut p.x } else { &mut p.z };
+                paulis[k] = true;
+                paulis[nb_qubits + k] = true;
+                stabs.push(p);
+            }
+        }
+        DenseStabilizerState { nb_qubits: 2 * nb_qubits, stabs }
+    }
+
+    pub fn apply_h(&mut self, q: usize) {
+        for p in self.stabs.iter_mut() {
+            p.sign ^= p.x[q] && p.z[q];
+            std::mem::swap(&mut p.x[q], &mut p.z[q]);
+        }
+    }
+
+    pub fn apply_s(&mut self, q: usize) {
+        for p in self.stabs.iter_mut() {
+            p.sign ^= p.x[q] && p.z[q];
+            p.z[q] ^= p.x[q];
+        }
+    }
+
+    pub fn apply_cx(&mut self, control: usize, target: usize) {
+        for p in self.stabs.iter_mut() {
+            p.sign ^= p.x[control] && p.z[target] && !(p.x[target] ^ p.z[control]);
+            p.x[target] ^= p.x[control];
+            p.z[control] ^= p.z[target];
+        }
+    }
+
+    // A Pauli gate negates the stabilizers that anticommute with it
+    pub fn apply_pauli(&mut self, q: usize, x: bool, z: bool) {
+        for p in self.stabs.iter_mut() {
+            p.sign ^= (x && p.z[q]) ^ (z && p.x[q]);
+        }
+    }
+
+    // Applies a Clifford TketOp to the given qubits, built up from H, S and CX as in CHP; panics on any other op, which is fine for a test oracle
+    pub fn apply_clifford(&mut self, op: TketOp, qubits: &[usize]) {
+        match op {
+            TketOp::H => self.apply_h(qubits[0]),
+            TketOp::S => self.apply_s(qubits[0]),
+            TketOp::Sdg => {
+                self.apply_s(qubits[0]);
+                self.apply_pauli(qubits[0], false, true);
+            }
+            TketOp::V | TketOp::Vdg => {
+                self.apply_h(qubits[0]);
+                self.apply_clifford(if op == TketOp::V { TketOp::S } else { TketOp::Sdg }, qubits);
+                self.apply_h(qubits[0]);
+            }
+            TketOp::X => self.apply_pauli(qubits[0], true, false),
+            TketOp::Y => self.apply_pauli(qubits[0], true, true),
+            TketOp::Z => self.apply_pauli(qubits[0], false, true),
+            TketOp::CX => self.apply_cx(qubits[0], qubits[1]),
+            TketOp::CZ => {
+                self.apply_h(qubits[1]);
+                self.apply_cx(qubits[0], qubits[1]);
+                self.apply_h(qubits[1]);
+            }
+            TketOp::CY => {
+                self.apply_clifford(TketOp::Sdg, &qubits[1..]);
+                self.apply_cx(qubits[0], qubits[1]);
+                self.apply_s(qubits[1]);
+            }
+            _ => panic!("{op:?} is not a Clifford gate"),
+        }
+    }
+
+    // Every element of the stabilizer group, of which there are 2^nb_qubits, so only for small states
+    pub fn group(&self) -> HashSet<DensePauli> {
+        let mut group = HashSet::from([DensePauli::identity(self.nb_qubits)]);
+        for stab in &self.stabs {
+            let products = group.iter().map(|p| p.mult(stab)).collect::<Vec<_>>();
+            group.extend(products);
+        }
+        group
+    }
+}
+
+// The stabilizers of the Choi state of circuit (in the form of Tableau::to_circ) on nb_qubits qubits that hold whatever the angles of its Z rotations, over the inputs followed by the outputs
+// Each T, Tdg and Rz doubles the number of branches followed, so this is only for small circuits
+pub fn choi_stabilizers(nb_qubits: usize, circuit: &[(TketOp, Vec<usize>)]) -> HashSet<DensePauli> {
+    let mut branches = vec![DenseStabilizerState::choi_identity(nb_qubits)];
+    for (op, qubits) in circuit {
+        let wires = qubits.iter().map(|q| nb_qubits + q).collect::<Vec<_>>();
+        match op {
+            TketOp::T | TketOp::Tdg | TketOp::Rz => {
+                let projected = branches.iter().cloned().map(|mut state| {
+                    state.apply_pauli(wires[0], false, true);
+                    state
+                }).collect::<Vec<_>>();
+                branches.extend(projected);
+            }
+            _ => branches.iter_mut().for_each(|state| state.apply_clifford(*op, &wires)),
+        }
+    }
+    let mut groups = branches.iter().map(DenseStabilizerState::group);
+    let first = groups.next().unwrap();
+    groups.fold(first, |common, group| common.intersection(&group).cloned().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use hugr::{builder::{DFGBuilder, Dataflow, DataflowHugr}, extension::prelude::qb_t, types::Signature, HugrView};
+    use itertools::Itertools;
+    use proptest::prelude::*;
+    use tket::extension::rotation::rotation_type;
+    use tket::TketOp;
+
+    use crate::simulator::{choi_stabilizers, DensePauli};
+    use crate::stabilizer_dataflow::{FunctionOpacity, StabilizerDataflow};
+
+    const GATES: [TketOp; 14] = [
+        TketOp::H, TketOp::S, TketOp::Sdg, TketOp::V, TketOp::Vdg, TketOp::X, TketOp::Y, TketOp::Z,
+        TketOp::T, TketOp::Tdg, TketOp::Rz, TketOp::CX, TketOp::CZ, TketOp::CY,
+    ];
+
+    fn strings(group: &HashSet<DensePauli>) -> HashSet<String> {
+        group.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_choi_stabilizers() {
+        // The Choi state of CX has X on the control copied to the target, and Z on the target copied to the control
+        let cx = strings(&choi_stabilizers(2, &[(TketOp::CX, vec![0, 1])]));
+        assert_eq!(cx.len(), 16);
+        assert!(cx.contains("+XIXX") && cx.contains("+IZZZ") && cx.contains("+ZIZI") && cx.contains("-YIYX"));
+        // H;S maps Z to Y (transposing the input negates it), and a T keeps only the relation between Zs
+        assert!(strings(&choi_stabilizers(1, &[(TketOp::H, vec![0]), (TketOp::S, vec![0])])).contains("+ZY"));
+        assert_eq!(strings(&choi_stabilizers(1, &[(TketOp::T, vec![0])])), HashSet::from(["+II".to_string(), "+ZZ".to_string()]));
+        // T;Z;Tdg still has -Z⊗Z, as do both branches
+        assert!(strings(&choi_stabilizers(1, &[(TketOp::T, vec![0]), (TketOp::X, vec![0]), (TketOp::Tdg, vec![0])])).contains("-ZZ"));
+    }
+
+    proptest! {
+        // The analysis of a random Clifford+Rz circuit, with the angles of the rotations as inputs, relates its inputs and outputs exactly as the dense simulator does
+        #[test]
+        fn prop_analysis_matches_simulator((nb_qubits, gates) in (1usize..4).prop_flat_map(|n| (
+            Just(n),
+            prop::collection::vec((0..GATES.len(), 0..n, 1..n.max(2)), 0..16),
+        ))) {
+            // Two-qubit gates act on q0 and a distinct q1, so are left out on a single qubit
+            let circuit = gates.into_iter().filter_map(|(gate, q0, offset)| match GATES[gate] {
+                op @ (TketOp::CX | TketOp::CZ | TketOp::CY) => (nb_qubits > 1).then(|| (op, vec![q0, (q0 + offset) % nb_qubits])),
+                op => Some((op, vec![q0])),
+            }).collect_vec();
+            let nb_angles = circuit.iter().filter(|(op, _)| *op == TketOp::Rz).count();
+            let inputs = [vec![qb_t(); nb_qubits], vec![rotation_type(); nb_angles]].concat();
+            let mut builder = DFGBuilder::new(Signature::new(inputs, vec![qb_t(); nb_qubits])).unwrap();
+            let mut wires = builder.input_wires().collect_vec();
+            let mut angles = wires.split_off(nb_qubits).into_iter();
+            for (op, qubits) in &circuit {
+                let mut ins = qubits.iter().map(|q| wires[*q]).collect_vec();
+                ins.extend((*op == TketOp::Rz).then(|| angles.next().unwrap()));
+                let outs = builder.add_dataflow_op(*op, ins).unwrap().outputs().collect_vec();
+                qubits.iter().zip(outs).for_each(|(q, wire)| wires[*q] = wire);
+            }
+            let hugr = builder.finish_hugr_with_outputs(wires).unwrap();
+
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            let boundary = analysis.columns().inputs().map(|(_, col)| col).chain(analysis.columns().outputs().map(|(_, col)| col)).collect_vec();
+            let rows = analysis.relations_over(&boundary).canonical_rows().iter().map(|p| p.to_string()).collect_vec();
+            let expected = strings(&choi_stabilizers(nb_qubits, &circuit));
+            // The rows are independent, so they generate 2^rows elements, all of which are in the expected group when the rows are
+            prop_assert_eq!(expected.len(), 1 << rows.len(), "Analysis rows {:?} do not generate the group of the simulator", rows);
+            for row in rows {
+                prop_assert!(expected.contains(&row), "Analysis has {} which the simulator does not", row);
+            }
+        }
+    }
+}
@@ -1,19 +1,112 @@
 // Going to base this on Mark's python phase folding implementation instead of the hugr dataflow framework which I struggle to see how to adapt to relational values since we can't easily attribute them to individual wires
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
 use hugr::ops::DataflowOpTrait;
 use hugr::PortIndex;
-use hugr_core::hugr::internal::PortgraphNodeMap;
-use hugr_core::{HugrView, IncomingPort, OutgoingPort};
-use hugr_core::ops::OpType;
-use hugr::extension::prelude::qb_t;
+use hugr_core::hugr::internal::{HugrInternals, PortgraphNodeMap};
+use hugr_core::{HugrView, IncomingPort, OutgoingPort, Port};
+use hugr_core::ops::{OpType, Value};
+use hugr::extension::prelude::{qb_t, Barrier, MakeTuple, Noop, UnpackTuple};
+use hugr::ops::ExtensionOp;
+use hugr::std_extensions::collections::array::{ArrayOp, ArrayOpDef};
+use hugr::std_extensions::arithmetic::float_types::ConstF64;
+use hugr::std_extensions::logic::LogicOp;
 use itertools::Itertools;
+use petgraph::graph::Graph;
 use petgraph::visit as pv;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use tket::hugr::extension::simple_op::MakeExtensionOp;
+use tket::extension::bool::BoolOp;
+use tket::extension::rotation::{ConstRotation, RotationOp};
 use tket::TketOp;
 use crate::bit_vector::BitVector;
+use crate::column_allocator::{ColumnAllocator, ColumnRole, WireRef};
 use crate::pauli_product::PauliProduct;
+use crate::rewrite::append_clifford;
 use crate::tableau::Tableau;
+use crate::tableau_interface::ChoiTableau;
+
+/// Name of the tket1 operation computing a classical expression; it never acts on qubits, so any Qubit wires routed through it are passed on unchanged
+const CLASSICAL_EXP_BOX: &str = "ClassicalExpBox";
+
+/// Name of the tket1 operation swapping two classical bits; it is not a TketOp, so reaches us as an ExtensionOp or OpaqueOp of a tket1 extension, and has no effect on any qubit
+const CLASSICAL_SWAP: &str = "SWAPbits";
+
+/// How far (in quarter turns) the constant angle of an Rz or CRz may be from a multiple of a quarter turn for the gate to be treated as Clifford, allowing for the rounding of float encodings
+const CLIFFORD_ANGLE_TOLERANCE: f64 = 1e-9;
+
+/// Node metadata key for the stabilizers of an op the analysis would otherwise treat as opaque, as a list of signed Pauli strings over its qubit ports (e.g. `["+ZI", "-XX"]`)
+/// Each string P (with sign s, + if omitted) declares that the op maps P on its qubit inputs to sP on its qubit outputs, the k-th character acting on the k-th qubit input and output
+pub const STABILIZERS_METADATA_KEY: &str = "tket2.dataflow.stabilizers";
+
+/// Safety cap on the fixpoint iteration for TailLoops and CFGs; each iteration that has not converged loses at least one stabilizer, so this should never be reached
+const MAX_LOOP_ITERATIONS: usize = 1000;
+
+/// The format of AnalysisSummary written by to_summary; summaries in any other format are never applied
+pub const SUMMARY_FORMAT_VERSION: u32 = 1;
+
+/// The most independent rotation axes t_count_lower_bound will compute the stabilizer nullity over, as it checks each of the 2^rank parities they span
+const MAX_NULLITY_RANK: usize = 16;
+
+/// How many nodes apart run_dfg keeps the checkpoints that reanalyze resumes from, unless changed by AnalysisConfig::with_checkpoints or AnalysisConfig::without_checkpoints
+pub const DEFAULT_CHECKPOINT_INTERVAL: usize = 32;
+
+/// Errors from analysing a hugr that is malformed or has a structure we do not expect, identifying the node (and port) at fault
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum DataflowError<N> {
+    /// The dataflow region under parent does not have a unique Input node
+    #[error("Region under {parent:?} has no unique Input node")]
+    MissingInput { parent: N },
+    /// A qubit output of node is not connected to anything
+    #[error("Qubit output {port:?} of {node:?} is not connected")]
+    DanglingQubitWire { node: N, port: OutgoingPort },
+    /// There is no column for a qubit input of node, e.g. because the wire into it comes from a node outside the region
+    #[error("No column is tracking qubit input {port:?} of {node:?}")]
+    InconsistentFrontier { node: N, port: IncomingPort },
+    /// A Call or LoadFunction node has no static edge from the function it refers to
+    #[error("{node:?} has no static edge from a function")]
+    MissingStaticEdge { node: N },
+    /// The node does not have the structure expected of its op, e.g. a Conditional case whose signature does not match
+    #[error("{node:?} does not have the structure expected of its op")]
+    UnexpectedOp { node: N },
+    /// The node acts on qubits in a way the requested operation cannot represent, e.g. a measurement in a circuit expected to be unitary
+    #[error("{node:?} cannot be handled here")]
+    UnsupportedOp { node: N },
+    /// The stabilizers declared for node (by metadata or an OpacityHints entry) are malformed, do not match its qubit ports or contradict each other
+    #[error("Invalid stabilizers declared for {node:?}")]
+    InvalidStabilizers { node: N },
+    /// The AnalysisSummary given for the function called by node is malformed, e.g. with a row of the wrong length or columns out of range
+    #[error("Invalid analysis summary for the function called by {node:?}")]
+    InvalidSummary { node: N },
+}
+
+/// Errors from StabilizerDataflow::clifford_equivalence, when either region cannot be compared as a Clifford unitary
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum NonCliffordError<N> {
+    #[error(transparent)]
+    Dataflow(#[from] DataflowError<N>),
+    /// The analysis of the region under root does not fully relate its inputs and outputs as a unitary would, e.g. because of a non-Clifford gate, a measurement or an opaque op
+    #[error("The region under {root:?} is not a Clifford unitary")]
+    NotClifford { root: N },
+    /// The regions act on different numbers of qubits
+    #[error("The regions act on {a} and {b} qubits")]
+    QubitCountMismatch { a: usize, b: usize },
+}
+
+/// How two Clifford regions compare, as found by StabilizerDataflow::clifford_equivalence
+#[derive(Debug, Clone)]
+pub enum Equivalence {
+    /// The regions implement the same unitary up to a global phase
+    Equal,
+    /// Following the first region by the Pauli (over its qubit outputs, in port order) gives the second, up to a global phase
+    UpToPauli(PauliProduct),
+    /// The first stabilizer of the first region not in the second, even up to sign, over its inputs then its outputs in port order (in reduced row echelon form)
+    Different(PauliProduct),
+}
 
 /// Sets behaviour for function calls in dataflow analysis
 #[derive(Clone)]
@@ -26,118 +119,979 @@ pub enum FunctionOpacity {
     Inline,
 }
 
+/// The Pauli a two-qubit interaction acts by on both of its qubits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InteractionAxis {
+    ZZ,
+    XX,
+}
+
+/// A two-qubit interaction exp(-iπα/2 P⊗P) by an angle α in half-turns on the qubits at ports 0 and 1, such as the ZZPhase and XXPhase gates of hardware-native circuits
+/// The angle is either read from an input port (as a rotation or float) or fixed for the op, e.g. ZZMax is ZZPhase(1/2)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interaction {
+    pub axis: InteractionAxis,
+    pub angle_port: Option<usize>,
+    pub half_turns: f64,
+}
+
+impl Interaction {
+    /// An interaction whose angle is given on angle_port
+    pub fn parametric(axis: InteractionAxis, angle_port: usize) -> Self {
+        Interaction { axis, angle_port: Some(angle_port), half_turns: 0. }
+    }
+
+    /// An interaction by a fixed angle
+    pub fn fixed(axis: InteractionAxis, half_turns: f64) -> Self {
+        Interaction { axis, angle_port: None, half_turns }
+    }
+}
+
+/// The two-qubit interaction ops outside TketOp that the analysis understands, keyed by the unqualified name of the op (whether an ExtensionOp or an OpaqueOp)
+/// The default registry has ZZPhase and XXPhase (with the angle on port 2) and ZZMax; further interaction types can be added with `with`
+#[derive(Debug, Clone)]
+pub struct InteractionRegistry {
+    ops: HashMap<String, Interaction>,
+}
+
+impl InteractionRegistry {
+    /// A registry without any interactions
+    pub fn empty() -> Self {
+        InteractionRegistry { ops: HashMap::default() }
+    }
+
+    pub fn with(mut self, name: impl Into<String>, interaction: Interaction) -> Self {
+        self.ops.insert(name.into(), interaction);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Interaction> {
+        self.ops.get(name)
+    }
+
+    /// The interaction the node applies, if it is an ExtensionOp or OpaqueOp with a registered name
+    fn for_node<H: HugrView>(&self, hugr: &H, node: H::Node) -> Option<Interaction> {
+        match hugr.get_optype(node) {
+            OpType::ExtensionOp(op) => self.get(op.unqualified_id()).copied(),
+            OpType::OpaqueOp(op) => self.get(op.unqualified_id()).copied(),
+            _ => None,
+        }
+    }
+}
+
+impl Default for InteractionRegistry {
+    fn default() -> Self {
+        InteractionRegistry::empty()
+            .with("ZZPhase", Interaction::parametric(InteractionAxis::ZZ, 2))
+            .with("XXPhase", Interaction::parametric(InteractionAxis::XX, 2))
+            .with("ZZMax", Interaction::fixed(InteractionAxis::ZZ, 0.5))
+    }
+}
+
+/// Stabilizers for ops that cannot be annotated with STABILIZERS_METADATA_KEY, keyed by the unqualified name of the op and given in the same form as the metadata
+/// Metadata on a node takes precedence over the hints for its op
+/// The default hints cover the controlled gates outside TketOp that are not Clifford but still preserve some Paulis: CS and CSdg are diagonal, so preserve Z on both qubits, while CSX and CSXdg preserve Z on the control and X on the target
+#[derive(Debug, Clone)]
+pub struct OpacityHints {
+    by_name: HashMap<String, Vec<String>>,
+}
+
+impl OpacityHints {
+    /// Hints without any ops
+    pub fn empty() -> Self {
+        OpacityHints { by_name: HashMap::default() }
+    }
+
+    /// Declares the stabilizers of every op with the given name, e.g. `OpacityHints::default().with("global_rz", ["Z"])`
+    pub fn with(mut self, name: impl Into<String>, stabilizers: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.by_name.insert(name.into(), stabilizers.into_iter().map(|p| p.into()).collect());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.by_name.get(name).map(|stabilizers| stabilizers.as_slice())
+    }
+}
+
+/// A relation declared for an opaque op by its stabilizers, as the Z and X columns (over its qubit inputs followed by its qubit outputs) and the sign
+type DeclaredRelation = (Vec<usize>, Vec<usize>, bool);
+
+impl Default for OpacityHints {
+    fn default() -> Self {
+        OpacityHints::empty()
+            .with("CS", ["ZI", "IZ"])
+            .with("CSdg", ["ZI", "IZ"])
+            .with("CSX", ["ZI", "IX"])
+            .with("CSXdg", ["ZI", "IX"])
+    }
+}
+
+/// Chooses which columns StabilizerDataflow::gc_internal_columns keeps, by their role, as given to AnalysisConfig::with_column_gc
+pub type ColumnFilter = fn(ColumnRole) -> bool;
+
+/// Configures a run of the analysis, threaded through every region analysed; any FunctionOpacity converts into the configuration with that default and nothing else changed
+/// It chooses the FunctionOpacity for each function called, with a default and overrides for particular functions; overrides for a FuncDefn node take precedence over overrides for the function's name, e.g. `AnalysisConfig::default_boundary().inline("prepare_ghz").opaque("oracle")`
+/// It also carries the InteractionRegistry used to recognise two-qubit interaction ops, the OpacityHints for opaque ops and the names of ops that are transparent like Barrier, along with the optional features (column budget, column GC, provenance, classical tracking), each set by a with_ builder and defaulting to the behaviour of a bare FunctionOpacity
+#[derive(Clone)]
+pub struct AnalysisConfig<N> {
+    default: FunctionOpacity,
+    by_name: HashMap<String, FunctionOpacity>,
+    by_node: HashMap<N, FunctionOpacity>,
+    interactions: InteractionRegistry,
+    hints: OpacityHints,
+    transparent: HashSet<String>,
+    merge_across_barriers: bool,
+    known_classical: HashMap<(N, OutgoingPort), bool>,
+    max_columns: Option<usize>,
+    column_gc: Option<(usize, ColumnFilter)>,
+    checkpoint_interval: Option<usize>,
+    provenance: bool,
+    track_classical: bool,
+    summaries: HashMap<String, AnalysisSummary>,
+}
+
+/// The former name of AnalysisConfig, from when it only chose the opacity of functions
+#[deprecated(note = "renamed to AnalysisConfig")]
+pub type OpacityPolicy<N> = AnalysisConfig<N>;
+
+impl<N: Copy + Eq + Hash> AnalysisConfig<N> {
+    pub fn new(default: FunctionOpacity) -> Self {
+        AnalysisConfig {
+            default,
+            by_name: HashMap::default(),
+            by_node: HashMap::default(),
+            interactions: InteractionRegistry::default(),
+            hints: OpacityHints::default(),
+            transparent: HashSet::default(),
+            merge_across_barriers: false,
+            known_classical: HashMap::default(),
+            max_columns: None,
+            column_gc: None,
+            checkpoint_interval: Some(DEFAULT_CHECKPOINT_INTERVAL),
+            provenance: false,
+            track_classical: true,
+            summaries: HashMap::default(),
+        }
+    }
+
+    pub fn default_opaque() -> Self {
+        AnalysisConfig::new(FunctionOpacity::Opaque)
+    }
+
+    pub fn default_boundary() -> Self {
+        AnalysisConfig::new(FunctionOpacity::Boundary)
+    }
+
+    pub fn default_inline() -> Self {
+        AnalysisConfig::new(FunctionOpacity::Inline)
+    }
+
+    /// Overrides the opacity for every function with the given name
+    pub fn with_name(mut self, name: impl Into<String>, fun_op: FunctionOpacity) -> Self {
+        self.by_name.insert(name.into(), fun_op);
+        self
+    }
+
+    /// Overrides the opacity for the function defined at fun_def_node
+    pub fn with_node(mut self, fun_def_node: N, fun_op: FunctionOpacity) -> Self {
+        self.by_node.insert(fun_def_node, fun_op);
+        self
+    }
+
+    /// Replaces the registry of two-qubit interaction ops, e.g. to add a hardware-specific interaction
+    pub fn with_interactions(mut self, interactions: InteractionRegistry) -> Self {
+        self.interactions = interactions;
+        self
+    }
+
+    /// Replaces the stabilizers declared for opaque ops without metadata
+    pub fn with_hints(mut self, hints: OpacityHints) -> Self {
+        self.hints = hints;
+        self
+    }
+
+    /// Treats ops with the given name as the identity on their qubits, like Barrier, e.g. for scheduling hints that do not act on the state; each input must pass to the output on the same port
+    pub fn with_transparent(mut self, name: impl Into<String>) -> Self {
+        self.transparent.insert(name.into());
+        self
+    }
+
+    /// Allows fold_phases to merge rotations separated by a Barrier (or another transparent op), which it otherwise keeps apart as a scheduling boundary
+    pub fn with_merge_across_barriers(mut self, allow: bool) -> Self {
+        self.merge_across_barriers = allow;
+        self
+    }
+
+    /// Declares the bool out of port of node to always have the given value, e.g. as found by a constant-propagation pass or fixed for a particular run; a Conditional on a known bool only analyses the case it takes
+    pub fn with_known_classical(mut self, node: N, port: OutgoingPort, value: bool) -> Self {
+        self.known_classical.insert((node, port), value);
+        self
+    }
+
+    /// Bounds the columns of each tableau: once a tableau has max columns, the rest of its region is analysed without internal columns, forgetting every relation on the qubits of any node that would need them (see AnalysisStats::truncated_nodes)
+    /// Nodes already being applied when the budget is reached still finish, so a tableau may end up with a few more columns than max
+    pub fn with_max_columns(mut self, max: usize) -> Self {
+        self.max_columns = Some(max);
+        self
+    }
+
+    /// Runs StabilizerDataflow::gc_internal_columns with keep after every interval nodes applied in each region, bounding the tableau on long circuits at the cost of whatever keep lets go
+    /// With keep_node_columns nothing any query looks up is lost; rejecting InternalIn and InternalOut as well keeps only the relations between the boundary and the frontier
+    /// Rejecting every internal column every 32 nodes keeps a chain of 2000 T gates under 70 columns rather than 4002, and analyses it in about 17ms rather than 0.9s (see benches/analysis.rs)
+    pub fn with_column_gc(mut self, interval: usize, keep: ColumnFilter) -> Self {
+        assert!(interval > 0, "Column GC interval must be positive");
+        self.column_gc = Some((interval, keep));
+        self
+    }
+
+    /// Keeps the state of the region analysed by run_dfg before every interval nodes applied in it (DEFAULT_CHECKPOINT_INTERVAL by default), so that reanalyze resumes from the last one before the first node invalidated rather than applying every node again
+    /// Each checkpoint holds a copy of the tableau and columns, so a shorter interval trades memory for less work in reanalyze
+    pub fn with_checkpoints(mut self, interval: usize) -> Self {
+        assert!(interval > 0, "Checkpoint interval must be positive");
+        self.checkpoint_interval = Some(interval);
+        self
+    }
+
+    /// Keeps no checkpoints, so that reanalyze always applies the whole region again, e.g. for an analysis that is never brought up to date
+    pub fn without_checkpoints(mut self) -> Self {
+        self.checkpoint_interval = None;
+        self
+    }
+
+    /// Records for each row of the tableaux the nodes whose handling added or changed it, as reported by StabilizerDataflow::explain_relation; this costs a set of nodes per row, so is off by default
+    pub fn with_provenance(mut self, track: bool) -> Self {
+        self.provenance = track;
+        self
+    }
+
+    /// Tracks the outcomes of measurements as bits (see StabilizerDataflow::classical_relation), which also lets a Conditional on a measured bit act as a Pauli frame update; this is on by default, and turning it off only leaves constant bools tracked
+    pub fn with_classical_tracking(mut self, track: bool) -> Self {
+        self.track_classical = track;
+        self
+    }
+
+    /// Uses summary (as from StabilizerDataflow::to_summary, possibly saved by an earlier run) for calls to the function with the given name that are analysed with FunctionOpacity::Boundary, instead of analysing its body
+    /// The summary is only used while it is in the current format and the body still has the content hash it was made from, so a stale summary falls back to analysing the body
+    pub fn with_summary(mut self, name: impl Into<String>, summary: AnalysisSummary) -> Self {
+        self.summaries.insert(name.into(), summary);
+        self
+    }
+
+    /// The summary given by with_summary for the function defined at fun_def_node, if it is still valid for the body
+    fn summary_for<H: HugrView<Node = N>>(&self, hugr: &H, fun_def_node: N) -> Option<&AnalysisSummary> {
+        let summary = hugr.get_optype(fun_def_node).as_func_defn().and_then(|defn| self.summaries.get(defn.func_name()))?;
+        (summary.version == SUMMARY_FORMAT_VERSION && summary.content_hash == AnalysisSummary::content_hash(hugr, fun_def_node)).then_some(summary)
+    }
+
+    pub(crate) fn known_classical(&self, node: N, port: OutgoingPort) -> Option<bool> {
+        self.known_classical.get(&(node, port)).copied()
+    }
+
+    /// Whether a tableau with n_cols columns has spent the budget set by with_max_columns
+    pub(crate) fn exceeds_max_columns(&self, n_cols: usize) -> bool {
+        self.max_columns.is_some_and(|max| n_cols >= max)
+    }
+
+    pub(crate) fn tracks_classical(&self) -> bool {
+        self.track_classical
+    }
+
+    pub(crate) fn merges_across_barriers(&self) -> bool {
+        self.merge_across_barriers
+    }
+
+    /// Whether optype is a Barrier or an op registered with with_transparent
+    pub(crate) fn is_transparent(&self, optype: &OpType) -> bool {
+        match optype {
+            OpType::ExtensionOp(op) => Barrier::from_extension_op(op).is_ok() || self.transparent.contains(op.unqualified_id()),
+            OpType::OpaqueOp(op) => self.transparent.contains(op.unqualified_id().as_str()),
+            _ => false,
+        }
+    }
+
+    pub fn opaque(self, name: impl Into<String>) -> Self {
+        self.with_name(name, FunctionOpacity::Opaque)
+    }
+
+    pub fn boundary(self, name: impl Into<String>) -> Self {
+        self.with_name(name, FunctionOpacity::Boundary)
+    }
+
+    pub fn inline(self, name: impl Into<String>) -> Self {
+        self.with_name(name, FunctionOpacity::Inline)
+    }
+
+    /// The opacity to use for calls to the function defined at fun_def_node
+    pub fn for_function<H: HugrView<Node = N>>(&self, hugr: &H, fun_def_node: N) -> &FunctionOpacity {
+        if let Some(fun_op) = self.by_node.get(&fun_def_node) {
+            return fun_op;
+        }
+        hugr.get_optype(fun_def_node).as_func_defn().and_then(|defn| self.by_name.get(defn.func_name())).unwrap_or(&self.default)
+    }
+}
+
+impl<N: Copy + Eq + Hash> From<FunctionOpacity> for AnalysisConfig<N> {
+    fn from(fun_op: FunctionOpacity) -> Self {
+        AnalysisConfig::new(fun_op)
+    }
+}
+
+impl<N: Copy + Eq + Hash> From<&FunctionOpacity> for AnalysisConfig<N> {
+    fn from(fun_op: &FunctionOpacity) -> Self {
+        AnalysisConfig::new(fun_op.clone())
+    }
+}
+
+/// Identifies a node by the chain of hierarchical nodes (e.g. Calls) leading to it from the analysed region, ending with the node itself
+/// Nodes within the region itself just have a path of length one, but a function body inlined at several call sites gets a distinct path at each
+pub type NodePath<N> = Vec<N>;
+
+/// The analysis of each function of a module keyed by its FuncDefn node, as found by StabilizerDataflow::run_module
+pub type ModuleAnalysis<H> = HashMap<<H as HugrInternals>::Node, StabilizerDataflow<H>>;
+
+/// The analysis of a function over its boundary, without any nodes of the hugr, so that it can be saved (e.g. as JSON) and used for calls to the function in later runs through AnalysisConfig::with_summary
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnalysisSummary {
+    /// The format of the summary, SUMMARY_FORMAT_VERSION when it was made
+    pub version: u32,
+    /// The content_hash of the function body summarised
+    pub content_hash: u64,
+    /// The number of columns of the tableau, one for each qubit input and output
+    pub nb_cols: usize,
+    /// The rows of the tableau, each as its sign ('+' or '-') followed by the Pauli ('I', 'X', 'Y' or 'Z') on each column
+    pub rows: Vec<String>,
+    /// The column of each qubit input, as (port index, column)
+    pub inputs: Vec<(usize, usize)>,
+    /// The column of each qubit output, as (port index, column)
+    pub outputs: Vec<(usize, usize)>,
+}
+
+impl AnalysisSummary {
+    /// A hash of the ops and edges of every node under parent (and of parent itself), identifying a function body across runs regardless of node numbering
+    /// Nodes are taken in hierarchy order and edges named by the positions of their ends in that order, so the hash is FNV-1a over a canonical description of the body
+    /// Functions called from the body are only identified by their signature, so a summary can outlive a change to a function it calls
+    pub fn content_hash<H: HugrView>(hugr: &H, parent: H::Node) -> u64 {
+        let nodes = hugr.descendants(parent).collect_vec();
+        let index: HashMap<H::Node, usize> = nodes.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+        let mut description = String::new();
+        for node in nodes.iter() {
+            // The definitions of extension ops are left out, as their Debug output is not stable across runs
+            match hugr.get_optype(*node) {
+                OpType::ExtensionOp(op) => description.push_str(&format!("{}{:?};", op.qualified_id(), op.args())),
+                optype => description.push_str(&format!("{optype:?};")),
+            }
+            for port in hugr.node_outputs(*node) {
+                for (target, target_port) in hugr.linked_inputs(*node, port) {
+                    description.push_str(&format!("{}->{:?}:{};", port.index(), index.get(&target), target_port.index()));
+                }
+            }
+            description.push('\n');
+        }
+        description.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    /// The tableau the rows describe, if they are well-formed
+    fn tableau(&self) -> Option<ChoiTableau> {
+        let mut tab = ChoiTableau::new(self.nb_cols);
+        for row in self.rows.iter() {
+            let row: PauliProduct = row.parse().ok()?;
+            if row.z.nb_bits() != self.nb_cols {
+                return None;
+            }
+            tab.add_row(row);
+        }
+        Some(tab)
+    }
+}
+
+/// A Measure and a later Reset that could be replaced by an X conditioned on its outcome, with whether the relation between them is negated, as found by StabilizerDataflow::measure_reset_pairs
+pub type MeasureResetPair<N> = (NodePath<N>, NodePath<N>, bool);
+
+/// A pair of rotations about the same axis (up to sign) that could be merged into one, as found by mergeable_rotations
+#[derive(Debug, Clone)]
+pub struct MergeCandidate<N> {
+    pub first: NodePath<N>,
+    pub second: NodePath<N>,
+    /// The stabilizer (over the columns of the tableau) relating Z after the first rotation to Z before the second, taking the product over both qubits for CRz and the axis P⊗P of an interaction
+    /// If its sign is set, the rotation axes are opposite and the angles subtract rather than add
+    pub relation: PauliProduct,
+}
+
+/// The stabilizer relations across the qubit ports of a single non-Clifford, opaque or hierarchical node, as found by relations_for_node
+#[derive(Debug, Clone)]
+pub struct NodeRelationSummary<N> {
+    pub node: NodePath<N>,
+    /// The qubit ports of the node, inputs then outputs, each in port order; these index the qubits of the relations
+    pub ports: Vec<Port>,
+    /// A canonical (row-reduced) generating set for the stabilizers over just the ports of the node
+    pub relations: Vec<PauliProduct>,
+}
+
+/// A summary of the non-Clifford content of an analysed region and of the work the analysis did, as given by statistics
+/// Counts include every nested analysis (e.g. conditional branches, loop bodies and analysed calls), so a function called at several sites is counted at each
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnalysisStats {
+    /// T and Tdg gates
+    pub t_count: usize,
+    pub rz_count: usize,
+    pub crz_count: usize,
+    pub toffoli_count: usize,
+    /// Nodes with qubit ports treated as opaque, including calls treated as opaque
+    pub opaque_nodes: usize,
+    /// Columns added to the tableaux, including those for the input qubits
+    pub columns_allocated: usize,
+    /// Rows removed from the tableaux when imposing relations or projecting out columns
+    pub rows_projected: usize,
+    pub nested_analyses: usize,
+    /// Nodes with qubit ports applied without internal columns because the column budget of AnalysisConfig::with_max_columns was spent, forgetting every relation on their qubits; any of these means the analysis is truncated
+    pub truncated_nodes: usize,
+    /// Pairs of rotations found by mergeable_rotations over the outermost region
+    pub mergeable_pairs: usize,
+}
+
+/// Where the analysis lost information, node by node, as given by report, to find which node destroyed a relation
+/// Nodes within nested analyses are keyed by their path from the region, as for internal columns
+#[derive(Debug, Clone)]
+pub struct DataflowReport<N> {
+    /// Rows removed from the tableau while applying each node, when imposing relations or projecting out columns
+    pub dropped_rows: HashMap<NodePath<N>, usize>,
+    /// For each opaque node, the relations across it that are unknown: one for each of its qubit inputs and outputs (as many as a known op would give), less those declared as its stabilizers
+    pub unknown_relations: HashMap<NodePath<N>, usize>,
+}
+
+impl<N: Eq + Hash> PartialEq for DataflowReport<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.dropped_rows == other.dropped_rows && self.unknown_relations == other.unknown_relations
+    }
+}
+
+impl<N: Eq + Hash> Eq for DataflowReport<N> {}
+
+impl<N> Default for DataflowReport<N> {
+    fn default() -> Self {
+        DataflowReport { dropped_rows: HashMap::default(), unknown_relations: HashMap::default() }
+    }
+}
+
+impl<N: Copy + Eq + Hash> DataflowReport<N> {
+    /// The information lost at each node (its dropped rows and unknown relations together), most first, leaving out nodes that lost nothing
+    /// Nodes losing the same amount are ordered by path length, so ties are only broken arbitrarily between nodes at the same depth
+    pub fn worst_nodes(&self) -> Vec<(NodePath<N>, usize)> {
+        let mut lost: HashMap<NodePath<N>, usize> = self.dropped_rows.clone();
+        for (path, n) in self.unknown_relations.iter() {
+            *lost.entry(path.clone()).or_default() += n;
+        }
+        lost.into_iter().filter(|(_, n)| *n > 0).sorted_by_key(|(path, n)| (std::cmp::Reverse(*n), path.len())).collect()
+    }
+
+    /// Adds the report of the nested analysis of node, keying its nodes by their paths through node
+    fn absorb(&mut self, node: N, nested: DataflowReport<N>) {
+        let call_site = |path: NodePath<N>| [node].into_iter().chain(path).collect_vec();
+        for (path, n) in nested.dropped_rows {
+            *self.dropped_rows.entry(call_site(path)).or_default() += n;
+        }
+        for (path, n) in nested.unknown_relations {
+            *self.unknown_relations.entry(call_site(path)).or_default() += n;
+        }
+    }
+}
+
+/// A classical bit, as the parity of the outcomes of a set of measurements in the region (each given by Z on the internal input column of the measurement), negated if flip is set
+#[derive(Debug, Clone)]
+struct BitParity<N> {
+    measurements: HashSet<N>,
+    flip: bool,
+}
+
+/// A Conditional on a bool whose true case is its false case followed by a Pauli correction, as found by run_conditional
+struct PauliFrame {
+    /// The false case, over the columns of the Conditional's summary
+    base: ChoiTableau,
+    /// The correction, over the columns of the summary's output qubits
+    correction: PauliProduct,
+}
+
+/// The summary of a Conditional found by run_conditional, with its Pauli frame if it has one
+type ConditionalAnalysis<H> = (StabilizerDataflow<H>, Option<PauliFrame>);
+
+/// The hugrs the analysis runs on: any HugrView
+/// Only run_module_par (with the rayon feature) also needs the hugr to be shareable between threads
+pub trait AnalysisHugr: HugrView {}
+impl<H: HugrView> AnalysisHugr for H {}
+
+/// The state of the analysis of a region before one of the nodes applied directly in it, as kept by AnalysisConfig::with_checkpoints
+/// Whatever the analysis keys by node (nested analyses, bits, qubit counts and the report) is not copied, as the entries for the nodes applied before the checkpoint never change afterwards
+#[derive(Debug, Clone)]
+struct Checkpoint<N> {
+    /// The number of nodes applied before the checkpoint, which are the first ones of StabilizerDataflow::applied
+    n_applied: usize,
+    tab: ChoiTableau,
+    cols: ColumnAllocator<N>,
+    stats: AnalysisStats,
+    tags: Vec<N>,
+}
+
+/// The stabilizer relations that hold across a dataflow region, as found by run_dfg
+/// The public methods are the stable interface: the tableau and the columns tracking the inputs, outputs and internal ports of nodes, the nested analyses, and the queries built on them
+/// The fields, including how rows are stored and which internal columns are kept or eliminated, are internal and may change; only the numbering of columns described under cols is relied on by the accessors
 pub struct StabilizerDataflow<H: HugrView> {
     /// Relational dataflow value captured as a set of stabilizer relations on the Choi-state of the circuit skeleton
-    tab: Tableau,
-    /// Maps from wires of the program to columns of the tableau. We separately need to track columns for:
-    /// - Each input qubit (indexed by OutgoingPorts of the unique Input node)
-    /// - Each output qubit (indexed by IncomingPorts of the unique Output node)
-    /// - A frontier that moves forward through the program (eventually becoming the output qubits and being removed from here)
-    /// - For any internal non-Clifford (or opaque) node, we use columns for each input and output qubit separately; for nodes with stabilizers across them (e.g. Rz has Z_i Z_o), we impose these via projections on the tableau rather than reducing the number of qubits used as this allows every node kind to be handled identically and preventing more tableau management from column elimination
-    /// - For any hierarchical node, we use additional columns for each input and output port within their internal representation that we compose to "internal" columns here by projections on the tableau, again so we don't fuss with column elimination
-    in_cols: HashMap<OutgoingPort, usize>,
-    out_cols: HashMap<IncomingPort, usize>,
-    frontier_cols: HashMap<(H::Node, IncomingPort), usize>,
-    internal_in_cols: HashMap<(H::Node, IncomingPort), usize>,
-    internal_out_cols: HashMap<(H::Node, OutgoingPort), usize>,
-    nested_in_cols: HashMap<(H::Node, OutgoingPort), usize>,
-    nested_out_cols: HashMap<(H::Node, IncomingPort), usize>,
+    tab: ChoiTableau,
+    /// The wire of the program each column of the tableau is tracking
+    /// Column numbering is deterministic, and never depends on the iteration order of the maps: the input qubits take columns 2k (with frontier 2k + 1) in port order, then new columns are appended as nodes are visited in topological order, in port order within each node, with a nested analysis placed after all existing columns
+    /// Columns are only ever looked up by key or renumbered by position when they are removed, so the same hugr always gives the same columns
+    cols: ColumnAllocator<H::Node>,
 
     // For any control-flow region or hierarchical node, store the analysis for its internal calculations
     nested_analysis: HashMap<H::Node, StabilizerDataflow<H>>,
+
+    // The parity each tracked bool wire carries, keyed by the port producing it
+    bits: HashMap<(H::Node, OutgoingPort), BitParity<H::Node>>,
+
+    // Counters updated as nodes are applied, excluding those of the nested analyses
+    stats: AnalysisStats,
+
+    // The number of qubit wires into and out of each node applied directly in the region
+    qubit_counts: HashMap<H::Node, (usize, usize)>,
+
+    // The interaction ops recognised, from the policy the analysis was run with
+    interactions: InteractionRegistry,
+
+    // The stabilizers declared for opaque ops without metadata, from the policy the analysis was run with
+    hints: OpacityHints,
+
+    // The policy the analysis was run with, to run it again in reanalyze
+    policy: AnalysisConfig<H::Node>,
+
+    // The nodes reported changed by invalidate_region since the analysis was last brought up to date
+    dirty: HashSet<H::Node>,
+
+    // The nodes applied directly in the region, in the order they were applied
+    applied: Vec<H::Node>,
+
+    // The state of the region every AnalysisConfig::with_checkpoints interval nodes applied, in order, for reanalyze to resume from; only kept for the region of run_dfg
+    checkpoints: Vec<Checkpoint<H::Node>>,
+
+    // The node for each tag in the provenance of the rows of tab, if the policy tracks provenance
+    tags: Vec<H::Node>,
+
+    // The information lost at each node applied directly in the region
+    report: DataflowReport<H::Node>,
+
+    // The node whose region was analysed (or summarised, for control flow)
+    parent: H::Node,
 }
 
+// The accessors, which need nothing of the hugr type, so are available wherever the analysis is
 impl<H: HugrView> StabilizerDataflow<H> {
-    fn new(hugr: &H, parent: H::Node) -> Self {
-        let mut in_cols: HashMap<OutgoingPort, usize> = HashMap::default();
-        let mut frontier_cols: HashMap<(H::Node, IncomingPort), usize> = HashMap::default();
+    /// The mapping between wires of the program and columns of the tableau
+    pub fn columns(&self) -> &ColumnAllocator<H::Node> {
+        &self.cols
+    }
+
+    /// The stabilizer relations found, over the columns described by columns()
+    pub fn tableau(&self) -> &ChoiTableau {
+        &self.tab
+    }
+
+    /// The column tracking the qubit out of the Input node of the region on port, if it is a qubit port
+    pub fn input_column(&self, port: OutgoingPort) -> Option<usize> {
+        self.cols.column_for_input(port)
+    }
+
+    /// The column tracking the qubit into the Output node of the region on port, if it is a qubit port
+    pub fn output_column(&self, port: IncomingPort) -> Option<usize> {
+        self.cols.column_for_output(port)
+    }
+
+    /// The internal column for a qubit port of the node at path, the node itself for one directly in the region, or e.g. [call, node] for a node inside an inlined function
+    /// Only nodes that are not applied to the tableau in place (e.g. non-Clifford gates, opaque ops and nested regions) have internal columns
+    pub fn internal_column(&self, path: &[H::Node], port: impl Into<Port>) -> Option<usize> {
+        self.cols.column_for_node_port(path, port)
+    }
+
+    /// Every internal column of the node at path as (port, column), in port order; empty if the node was applied in place
+    pub fn internal_columns(&self, path: &[H::Node]) -> Vec<(Port, usize)> {
+        self.cols.node_ports(path)
+    }
+
+    /// The separate analysis of the region inside node (e.g. a Conditional, TailLoop, CFG or DFG), if node is directly in the region and was analysed separately
+    pub fn nested(&self, node: H::Node) -> Option<&StabilizerDataflow<H>> {
+        self.nested_analysis.get(&node)
+    }
+}
+
+// The parallel entry points, for hugrs that can be shared between threads
+#[cfg(feature = "rayon")]
+impl<H: AnalysisHugr<Node: Send + Sync> + Sync> StabilizerDataflow<H> {
+    /// Analyses each function of a module as run_module does, but in parallel, giving the same analyses as in series
+    /// The speedup over run_module on 8 independent functions is measured by benches/run_module_par.rs; with a single thread both take about 0.3s on 8 chains of 500 T gates, so the parallel path adds no overhead of note
+    pub fn run_module_par(hugr: &H, policy: impl Into<AnalysisConfig<H::Node>>) -> Result<ModuleAnalysis<H>, DataflowError<H::Node>> {
+        use rayon::prelude::*;
+        let policy = policy.into();
+        module_functions(hugr).into_par_iter().map(|f| Ok((f, StabilizerDataflow::run_function(hugr, f, policy.clone())?))).collect()
+    }
+}
+
+impl<H: AnalysisHugr> StabilizerDataflow<H> {
+    fn new(hugr: &H, parent: H::Node, policy: &AnalysisConfig<H::Node>) -> Result<Self, DataflowError<H::Node>> {
+        let mut cols = ColumnAllocator::new();
         let mut n_in_qubits = 0;
-        let inp = hugr.children(parent).filter(|n| matches!(hugr.get_optype(*n), OpType::Input(_))).exactly_one().ok().unwrap();
+        let inp = hugr.children(parent).filter(|n| matches!(hugr.get_optype(*n), OpType::Input(_))).exactly_one().ok().ok_or(DataflowError::MissingInput { parent })?;
         for (out, out_type) in hugr.out_value_types(inp) {
             if out_type == qb_t() {
-                in_cols.insert(out, 2*n_in_qubits);
-                let (next, next_p) = hugr.single_linked_input(inp, out).unwrap();
-                frontier_cols.insert((next, next_p), 2*n_in_qubits + 1);
-                n_in_qubits = n_in_qubits + 1;
-            }
-        }
-        let tab = Tableau::new(2*n_in_qubits);
-        //TODO:: Add rows to tableau
-        Self{
-            tab: tab,
-            in_cols: in_cols,
-            out_cols: HashMap::default(),
-            frontier_cols: frontier_cols,
-            internal_in_cols: HashMap::default(),
-            internal_out_cols: HashMap::default(),
-            nested_in_cols: HashMap::default(),
-            nested_out_cols: HashMap::default(),
+                cols.set_input(out, 2*n_in_qubits);
+                let (next, next_p) = hugr.single_linked_input(inp, out).ok_or(DataflowError::DanglingQubitWire { node: inp, port: out })?;
+                cols.set_frontier(next, next_p, 2*n_in_qubits + 1);
+                n_in_qubits += 1;
+            }
+        }
+        // Each input qubit starts as the identity between its input column and its frontier column
+        let mut tab = ChoiTableau::new(2*n_in_qubits);
+        if policy.provenance {
+            tab = tab.with_provenance();
+        }
+        for q in 0..n_in_qubits {
+            let xx = tab.pauli(&[], &[2*q, 2*q + 1], false);
+            tab.add_row(xx);
+            let zz = tab.pauli(&[2*q, 2*q + 1], &[], false);
+            tab.add_row(zz);
+        }
+        Ok(StabilizerDataflow {
+            tab,
+            cols,
+            stats: AnalysisStats { columns_allocated: 2*n_in_qubits, ..AnalysisStats::default() },
+            ..StabilizerDataflow::empty(parent, policy)
+        })
+    }
+
+    /// An analysis of the region under parent with no columns or rows yet, nor anything found for its nodes, to be filled in by the caller
+    fn empty(parent: H::Node, policy: &AnalysisConfig<H::Node>) -> Self {
+        StabilizerDataflow {
+            tab: ChoiTableau::new(0),
+            cols: ColumnAllocator::new(),
             nested_analysis: HashMap::default(),
+            bits: HashMap::default(),
+            stats: AnalysisStats::default(),
+            qubit_counts: HashMap::default(),
+            interactions: policy.interactions.clone(),
+            hints: policy.hints.clone(),
+            policy: policy.clone(),
+            dirty: HashSet::default(),
+            applied: vec![],
+            checkpoints: vec![],
+            tags: vec![],
+            report: DataflowReport::default(),
+            parent,
+        }
+    }
+
+    /// Analyses the dataflow region under parent, deciding how to treat each function call by policy (which may just be a FunctionOpacity for all calls)
+    /// In debug builds, the result is checked with verify_consistency
+    pub fn run_dfg(hugr: &H, parent: H::Node, policy: impl Into<AnalysisConfig<H::Node>>) -> Result<StabilizerDataflow<H>, DataflowError<H::Node>> {
+        let policy = policy.into();
+        let mut analysis = StabilizerDataflow::new(hugr, parent, &policy)?;
+        analysis.apply_region(hugr, &policy, &[parent], &mut HashMap::default(), true)?;
+        #[cfg(debug_assertions)]
+        if let Err(errors) = analysis.verify_consistency() {
+            panic!("Inconsistent analysis of {parent:?}:\n{}", errors.join("\n"));
         }
+        Ok(analysis)
+    }
+
+    /// Analyses a single function, given the FuncDefn node
+    pub fn run_function(hugr: &H, func_node: H::Node, policy: impl Into<AnalysisConfig<H::Node>>) -> Result<StabilizerDataflow<H>, DataflowError<H::Node>> {
+        if !matches!(hugr.get_optype(func_node), OpType::FuncDefn(_)) {
+            return Err(DataflowError::UnexpectedOp { node: func_node });
+        }
+        StabilizerDataflow::run_dfg(hugr, func_node, policy)
+    }
+
+    /// Analyses each function of a module, returning the analysis of each keyed by its FuncDefn node
+    /// If the entrypoint of the hugr is a FuncDefn, only that function is analysed; otherwise we analyse every FuncDefn at the top level of the module
+    /// Calls are still analysed at each call site according to policy, as the analysis of a call to a recursive function depends on the call chain leading to it
+    pub fn run_module(hugr: &H, policy: impl Into<AnalysisConfig<H::Node>>) -> Result<ModuleAnalysis<H>, DataflowError<H::Node>> {
+        let policy = policy.into();
+        module_functions(hugr).into_iter().map(|f| Ok((f, StabilizerDataflow::run_function(hugr, f, policy.clone())?))).collect()
+    }
+
+    /// Marks nodes as changed by a rewrite of the hugr, so that the next reanalyze recomputes everything depending on them
+    /// Pass every node added, replaced or rewired; for a node removed, pass its former parent, as a node no longer in the hugr cannot be located and makes reanalyze start afresh
+    pub fn invalidate_region(&mut self, nodes: &[H::Node]) {
+        self.dirty.extend(nodes.iter().copied());
+    }
+
+    /// Brings an analysis from run_dfg or run_function up to date with hugr after the rewrites reported to invalidate_region, giving the same result as analysing it afresh (up to the numbering of internal columns), and returns the number of nodes of the region applied again
+    /// With AnalysisConfig::with_checkpoints, the region resumes from the last checkpoint before the first node applied that was invalidated (or contains one), keeping everything found for the nodes before it; otherwise it is traversed again from the start
+    /// Either way, the analysis of each DFG, TailLoop and CFG node applied again is reused if it contains no invalidated node; Conditionals (whose analysis depends on the bits of the region) and calls (which depend on functions outside it) are recomputed
+    /// A nested analysis is reused or recomputed as a whole, and the region starts afresh, recomputing any nested analysis containing a call, whenever a node outside the region was invalidated, as it may be in the function called
+    /// Rows are not patched in place, since the relations lost when imposing a non-Clifford relation or projecting out a column cannot be recovered once the node responsible changes
+    pub fn reanalyze(&mut self, hugr: &H) -> Result<usize, DataflowError<H::Node>> {
+        if self.dirty.is_empty() {
+            return Ok(0);
+        }
+        let dirty = std::mem::take(&mut self.dirty);
+        let within = |n: H::Node, ancestor: H::Node| std::iter::successors(Some(n), |n| hugr.get_parent(*n)).any(|a| a == ancestor);
+        let located = dirty.iter().all(|n| hugr.contains_node(*n) && *n != self.parent);
+        let outside = dirty.iter().any(|n| !within(*n, self.parent));
+        // Resume before the first node applied that is invalidated or contains an invalidated node; a node added since has no position, but whatever it feeds was rewired so is invalidated too
+        // A removed node (reported by its former parent) or one outside the region may have affected any node, so we start afresh
+        let first_dirty = match located && !outside {
+            true => dirty.iter().filter_map(|n| std::iter::successors(Some(*n), |n| hugr.get_parent(*n)).find(|a| hugr.get_parent(*a) == Some(self.parent))).filter_map(|child| self.applied.iter().position(|a| *a == child)).min().unwrap_or(self.applied.len()),
+            false => 0,
+        };
+        let checkpoint = self.checkpoints.iter().rposition(|c| c.n_applied <= first_dirty).filter(|_| first_dirty > 0);
+        let mut reuse = HashMap::default();
+        let mut kept = HashMap::default();
+        let prefix: HashSet<H::Node> = checkpoint.map_or(HashSet::default(), |i| self.applied[..self.checkpoints[i].n_applied].iter().copied().collect());
+        if located {
+            for (node, nested) in std::mem::take(&mut self.nested_analysis) {
+                let reusable = hugr.get_parent(node) == Some(self.parent)
+                    && matches!(hugr.get_optype(node), OpType::DFG(_) | OpType::TailLoop(_) | OpType::CFG(_))
+                    && !dirty.iter().any(|n| within(*n, node))
+                    && !(outside && hugr.descendants(node).any(|d| matches!(hugr.get_optype(d), OpType::Call(_) | OpType::CallIndirect(_))));
+                if prefix.contains(&node) {
+                    kept.insert(node, nested);
+                } else if reusable {
+                    reuse.insert(node, nested);
+                }
+            }
+        }
+        let policy = self.policy.clone();
+        let mut analysis = StabilizerDataflow::new(hugr, self.parent, &policy)?;
+        if let Some(i) = checkpoint {
+            let Checkpoint { n_applied, tab, cols, stats, tags } = self.checkpoints[i].clone();
+            let in_prefix = |node: &H::Node| prefix.contains(node);
+            analysis.tab = tab;
+            analysis.cols = cols;
+            analysis.stats = stats;
+            analysis.tags = tags;
+            analysis.nested_analysis = kept;
+            analysis.bits = std::mem::take(&mut self.bits).into_iter().filter(|((node, _), _)| in_prefix(node)).collect();
+            analysis.qubit_counts = std::mem::take(&mut self.qubit_counts).into_iter().filter(|(node, _)| in_prefix(node)).collect();
+            analysis.report.dropped_rows = std::mem::take(&mut self.report.dropped_rows).into_iter().filter(|(path, _)| in_prefix(&path[0])).collect();
+            analysis.report.unknown_relations = std::mem::take(&mut self.report.unknown_relations).into_iter().filter(|(path, _)| in_prefix(&path[0])).collect();
+            analysis.applied = self.applied[..n_applied].to_vec();
+            analysis.checkpoints = self.checkpoints.drain(..=i).collect();
+        }
+        let n_resumed = analysis.applied.len();
+        analysis.apply_region(hugr, &policy, &[self.parent], &mut reuse, true)?;
+        *self = analysis;
+        Ok(self.applied.len() - n_resumed)
+    }
+
+    /// Analyses the dataflow region under parent, where call_stack holds the function definitions (and the outermost region) whose analysis is in progress
+    /// A call to any of these would recurse, so is treated as opaque rather than being analysed again
+    fn run_region(hugr: &H, parent: H::Node, policy: &AnalysisConfig<H::Node>, call_stack: &[H::Node]) -> Result<StabilizerDataflow<H>, DataflowError<H::Node>> {
+        StabilizerDataflow::run_region_reusing(hugr, parent, policy, call_stack, &mut HashMap::default())
+    }
+
+    /// Analyses the dataflow region under parent as run_region, but takes the analysis of any DFG, TailLoop or CFG node in the region from reuse instead of computing it
+    fn run_region_reusing(hugr: &H, parent: H::Node, policy: &AnalysisConfig<H::Node>, call_stack: &[H::Node], reuse: &mut HashMap<H::Node, StabilizerDataflow<H>>) -> Result<StabilizerDataflow<H>, DataflowError<H::Node>> {
+        let mut analysis = StabilizerDataflow::new(hugr, parent, policy)?;
+        analysis.apply_region(hugr, policy, call_stack, reuse, false)?;
+        Ok(analysis)
     }
 
-    pub fn run_dfg(hugr: &H, parent: H::Node, fun_op: &FunctionOpacity) -> StabilizerDataflow<H> {
-        let mut analysis = StabilizerDataflow::new(hugr, parent);
-        let (region, node_map) = hugr.region_portgraph(parent);
+    /// Applies the nodes of the region in topological order, skipping those already applied (when resuming from a checkpoint), and keeping checkpoints as the policy asks if checkpoint is set
+    fn apply_region(&mut self, hugr: &H, policy: &AnalysisConfig<H::Node>, call_stack: &[H::Node], reuse: &mut HashMap<H::Node, StabilizerDataflow<H>>, checkpoint: bool) -> Result<(), DataflowError<H::Node>> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("stabilizer_dataflow", parent = ?self.parent).entered();
+        let analysis = self;
+        let (region, node_map) = hugr.region_portgraph(analysis.parent);
         let mut topo = pv::Topo::new(&region);
+        let done: HashSet<H::Node> = analysis.applied.iter().copied().collect();
+        let mut n_applied = analysis.applied.len();
         while let Some(pgnode) = topo.next(&region) {
             let node = node_map.from_portgraph(pgnode);
+            if done.contains(&node) {
+                continue;
+            }
+            if let Some(interval) = policy.checkpoint_interval.filter(|_| checkpoint) {
+                if n_applied % interval == 0 {
+                    analysis.checkpoints.push(Checkpoint { n_applied, tab: analysis.tab.clone(), cols: analysis.cols.clone(), stats: analysis.stats.clone(), tags: analysis.tags.clone() });
+                }
+            }
             let optype: &OpType = hugr.get_optype(node);
+            n_applied += 1;
+            analysis.applied.push(node);
+            if analysis.tab.provenance.is_some() {
+                analysis.tab.current_tag = Some(analysis.tag(node));
+            }
+            #[cfg(feature = "tracing")]
+            let _node_span = tracing::debug_span!("visit_node", node = ?node, op = ?optype).entered();
+            #[cfg(feature = "tracing")]
+            tracing::debug!(name: "run_dfg_node", node = ?node, op = ?optype, n_frontier_cols = analysis.cols.count(ColumnRole::Frontier), n_internal_cols = analysis.cols.count(ColumnRole::InternalIn) + analysis.cols.count(ColumnRole::InternalOut));
+            let rows_projected = analysis.stats.rows_projected;
+            // Qubits packed into a tuple or array are only followed through the ops that move them around; any other node consuming the tuple is opaque to them, so they stay on the columns they reached, which no later node touches
+            let plumbing = match optype {
+                OpType::ExtensionOp(op) => plumbing_op(op),
+                _ => None,
+            };
+            // The Output keeps the qubits packed into the Sum of a TailLoop body or CFG block, for the summary to follow
+            if plumbing.is_none() && !matches!(optype, OpType::Tag(_) | OpType::Output(_)) && !policy.is_transparent(optype) {
+                analysis.cols.drop_packed(node);
+            }
+            if !matches!(optype, OpType::Input(_) | OpType::Output(_)) {
+                let n_in = hugr.in_value_types(node).filter(|(_, t)| *t == qb_t()).count();
+                let n_out = hugr.out_value_types(node).filter(|(_, t)| *t == qb_t()).count();
+                if n_in + n_out > 0 {
+                    analysis.qubit_counts.insert(node, (n_in, n_out));
+                }
+            }
             match optype {
+                _ if policy.is_transparent(optype) => analysis.apply_barrier(hugr, node)?,
+                // Once the column budget is spent, anything that would add columns (including the whole of a nested analysis) is applied without them
+                _ if policy.exceeds_max_columns(analysis.tab.nb_qubits) && allocates_columns(hugr, node) => analysis.apply_degraded(hugr, node)?,
                 OpType::ExtensionOp(op) => {
                     match TketOp::from_extension_op(op) {
-                        Ok(tkop) => analysis.apply_quantum_gate(hugr, node, tkop),
-                        Err(_) => analysis.apply_opaque(hugr, node)
+                        Ok(tkop) => analysis.apply_quantum_gate(hugr, node, tkop)?,
+                        Err(_) if op.unqualified_id() == CLASSICAL_EXP_BOX => analysis.apply_identity(hugr, node)?,
+                        Err(_) if op.unqualified_id() == CLASSICAL_SWAP => analysis.apply_bit_swap(hugr, node),
+                        Err(_) if policy.interactions.get(op.unqualified_id()).is_some() => analysis.apply_interaction(hugr, node)?,
+                        Err(_) => match (plumbing, logic_op(op)) {
+                            (Some(Plumbing::Pack), _) => analysis.apply_pack(hugr, node)?,
+                            (Some(Plumbing::Unpack), _) => analysis.apply_unpack(hugr, node)?,
+                            (Some(Plumbing::Noop), _) => analysis.apply_noop(hugr, node)?,
+                            (None, Some(logic_op)) => analysis.apply_logic(hugr, node, logic_op),
+                            (None, None) => analysis.apply_opaque(hugr, node)?,
+                        },
                     }
                 }
+                OpType::OpaqueOp(op) if op.unqualified_id() == CLASSICAL_EXP_BOX => {
+                    // Unresolved extensions leave the box as an OpaqueOp, but we still know it is classical
+                    analysis.apply_identity(hugr, node)?
+                }
+                OpType::OpaqueOp(op) if op.unqualified_id() == CLASSICAL_SWAP => analysis.apply_bit_swap(hugr, node),
+                OpType::OpaqueOp(op) if policy.interactions.get(op.unqualified_id()).is_some() => analysis.apply_interaction(hugr, node)?,
                 OpType::Conditional(_) => {
-                    let cond_analysis = StabilizerDataflow::run_conditional(hugr, node, fun_op);
-                    analysis.nested_analysis.insert(node, cond_analysis);
-                    analysis.apply_analysis(hugr, node);
+                    let bit = hugr.single_linked_output(node, IncomingPort::from(0)).and_then(|src| analysis.bits.get(&src));
+                    // A predicate known to be constant (from a LoadConstant or the policy) selects a single case, whose relations are kept exactly rather than joined with those of the other cases
+                    let known_case = bit.filter(|bit| bit.measurements.is_empty()).map(|bit| bit.flip as usize);
+                    let (mut cond_analysis, frame) = StabilizerDataflow::run_conditional(hugr, node, known_case, policy, call_stack)?;
+                    // If the predicate is a tracked bit and the true case is just a Pauli correction after the false case, the correction is a Pauli frame update controlled on the measurements the bit depends on, which (unlike the join of the cases) keeps every relation
+                    let controls = bit.and_then(|bit| bit.measurements.iter().map(|m| analysis.cols.column_for_node_port(&[*m], IncomingPort::from(0))).collect::<Option<Vec<_>>>());
+                    match (frame, bit.map(|bit| bit.flip), controls) {
+                        (Some(frame), Some(flip), Some(controls)) => {
+                            cond_analysis.tab = frame.base;
+                            analysis.nested_analysis.insert(node, cond_analysis);
+                            analysis.apply_analysis(hugr, node)?;
+                            analysis.apply_pauli_frame(hugr, node, &frame.correction, &controls, flip)?;
+                        }
+                        _ => {
+                            analysis.nested_analysis.insert(node, cond_analysis);
+                            analysis.apply_analysis(hugr, node)?;
+                        }
+                    }
                 }
                 OpType::TailLoop(_) => {
-                    let loop_analysis = StabilizerDataflow::run_tail_loop(hugr, node, fun_op);
+                    let loop_analysis = match reuse.remove(&node) {
+                        Some(loop_analysis) => loop_analysis,
+                        None => StabilizerDataflow::run_tail_loop(hugr, node, policy, call_stack)?,
+                    };
                     analysis.nested_analysis.insert(node, loop_analysis);
-                    analysis.apply_analysis(hugr, node);
+                    analysis.apply_analysis(hugr, node)?;
                 }
-                OpType::Call(_) => {
-                    match *fun_op {
-                        FunctionOpacity::Opaque => {
-                            analysis.apply_opaque(hugr, node);
-                        }
-                        FunctionOpacity::Boundary => {
-                            let call_port = optype.static_input_port().unwrap();
-                            let (fun_def_node, _) = hugr.linked_outputs(node, call_port).exactly_one().ok().unwrap();
-                            let fun_analysis = StabilizerDataflow::run_dfg(hugr, fun_def_node, fun_op);
-                            //TODO:: Project out non-IO columns
-                            analysis.nested_analysis.insert(node, fun_analysis);
-                            analysis.apply_analysis(hugr, node);
+                OpType::DFG(_) => {
+                    // Nested dataflow regions are summarised over their boundary in the same way as calls with FunctionOpacity::Boundary
+                    let dfg_analysis = match reuse.remove(&node) {
+                        Some(dfg_analysis) => dfg_analysis,
+                        None => {
+                            let mut dfg_analysis = StabilizerDataflow::run_region(hugr, node, policy, call_stack)?;
+                            dfg_analysis.project_to_boundary();
+                            dfg_analysis
                         }
-                        FunctionOpacity::Inline => {
-                            let call_port = optype.static_input_port().unwrap();
-                            let (fun_def_node, _) = hugr.linked_outputs(node, call_port).exactly_one().ok().unwrap();
-                            let fun_analysis = StabilizerDataflow::run_dfg(hugr, fun_def_node, fun_op);
-                            analysis.nested_analysis.insert(node, fun_analysis);
-                            analysis.apply_analysis(hugr, node);
+                    };
+                    analysis.nested_analysis.insert(node, dfg_analysis);
+                    analysis.apply_analysis(hugr, node)?;
+                }
+                OpType::CFG(_) => {
+                    let cfg_analysis = match reuse.remove(&node) {
+                        Some(cfg_analysis) => cfg_analysis,
+                        None => StabilizerDataflow::run_cfg(hugr, node, policy, call_stack)?,
+                    };
+                    analysis.nested_analysis.insert(node, cfg_analysis);
+                    analysis.apply_analysis(hugr, node)?;
+                }
+                OpType::Call(_) => {
+                    let call_port = optype.static_input_port().unwrap();
+                    let (fun_def_node, _) = hugr.linked_outputs(node, call_port).exactly_one().ok().ok_or(DataflowError::MissingStaticEdge { node })?;
+                    analysis.apply_call(hugr, node, fun_def_node, 0, policy, call_stack)?;
+                }
+                OpType::CallIndirect(_) => {
+                    // Port 0 is the function value; if it comes straight from a LoadFunction of a FuncDefn, we know which function is called
+                    // Otherwise we treat the call as opaque, which only ever looks at the qubit ports and so skips the function value
+                    let load_fun = hugr.single_linked_output(node, IncomingPort::from(0)).map(|(n, _)| n).filter(|n| matches!(hugr.get_optype(*n), OpType::LoadFunction(_)));
+                    let fun_def_node = match load_fun {
+                        Some(n) => {
+                            let static_port = hugr.get_optype(n).static_input_port().unwrap();
+                            let (f, _) = hugr.linked_outputs(n, static_port).exactly_one().ok().ok_or(DataflowError::MissingStaticEdge { node: n })?;
+                            Some(f).filter(|f| matches!(hugr.get_optype(*f), OpType::FuncDefn(_)))
                         }
+                        None => None,
+                    };
+                    match fun_def_node {
+                        Some(fun_def_node) => analysis.apply_call(hugr, node, fun_def_node, 1, policy, call_stack)?,
+                        None => analysis.apply_opaque(hugr, node)?,
                     }
                 }
+                OpType::Tag(_) => {
+                    // Lifts its inputs into a variant of a Sum, so packs any qubits among them as a MakeTuple would, each in the slot of its field of the variant
+                    // A TailLoop or CFG whose Sum is built directly by the Tag follows them out of the region; any other node consuming the Sum leaves them where they reached
+                    analysis.apply_pack(hugr, node)?
+                }
+                OpType::LoadConstant(_) => {
+                    // Constants are always classical values (a Const cannot hold a qubit), so there is nothing to track on the qubits; the values only matter for where they are routed, e.g. as the predicate of a Conditional
+                    // A bool constant is tracked as a bit, so that parities can be compared against it and a Conditional on it only analyses the case it takes
+                    let const_node = hugr.single_linked_output(node, optype.static_input_port().unwrap()).map(|(n, _)| n);
+                    let value = const_node.and_then(|n| hugr.get_optype(n).as_const()).map(|c| c.value());
+                    if let Some(flip) = [false, true].into_iter().find(|b| value == Some(&Value::from_bool(*b))) {
+                        analysis.bits.insert((node, OutgoingPort::from(0)), BitParity { measurements: HashSet::new(), flip });
+                    }
+                }
+                OpType::AliasDecl(_) | OpType::AliasDefn(_) => {
+                    // Type aliases have no ports, and so nothing to track
+                }
+                OpType::FuncDefn(_) => {
+                    // A local function definition does nothing where it is defined; each Call to it is analysed at the call site according to the policy (inlining or summarising the body unless it is opaque), so there is nothing to recurse into here
+                }
+                OpType::Input(_) => {
+                    // Columns for the inputs were already set up in new()
+                }
                 OpType::Output(_) => {
-                    // Frontier finished, move it to out_cols
-                    for ((_, port), col) in analysis.frontier_cols.iter() {
-                        analysis.out_cols.insert(*port, *col);
+                    // Frontier finished, move it to the outputs
+                    // Only take the wires into the Output node; other sinks (e.g. a QFree) may not have been visited yet
+                    for port in analysis.cols.frontier_ports(node) {
+                        let col = analysis.take_frontier(node, port)?;
+                        analysis.cols.set_output(port, col);
                     }
-                    analysis.frontier_cols = HashMap::default();
                 }
                 _ => {
-                    analysis.apply_opaque(hugr, node)
+                    analysis.apply_opaque(hugr, node)?
+                }
+            }
+            if analysis.stats.rows_projected > rows_projected {
+                analysis.report.dropped_rows.insert(vec![node], analysis.stats.rows_projected - rows_projected);
+            }
+            // Values the policy declares known override whatever we found for the bits out of node
+            for (port, _) in hugr.out_value_types(node) {
+                if let Some(flip) = policy.known_classical(node, port) {
+                    analysis.bits.insert((node, port), BitParity { measurements: HashSet::new(), flip });
+                }
+            }
+            if let Some((interval, keep)) = policy.column_gc {
+                if n_applied % interval == 0 {
+                    analysis.gc_internal_columns(&keep);
                 }
             }
         }
-        analysis
+        analysis.tab.current_tag = None;
+        Ok(())
     }
 
-    fn run_conditional(hugr: &H, node: H::Node, fun_op: &FunctionOpacity) -> StabilizerDataflow<H> {
+    /// Summarises a Conditional by the join of its cases, also giving the Pauli frame update it amounts to if it has a bool predicate and its cases differ only by a Pauli after the false case
+    /// If the case taken is known, only that case is analysed and the summary is exactly its relations
+    fn run_conditional(hugr: &H, node: H::Node, case: Option<usize>, policy: &AnalysisConfig<H::Node>, call_stack: &[H::Node]) -> Result<ConditionalAnalysis<H>, DataflowError<H::Node>> {
         // Assume no information is passed about Qubits within the Sum types, so our summary only incorporates the Qubits in the other args
         let cond = hugr.get_optype(node).as_conditional().unwrap();
         let sig = cond.signature();
@@ -147,814 +1101,4503 @@ impl<H: HugrView> StabilizerDataflow<H> {
         for in_port in sig.input_ports() {
             if *sig.in_port_type(in_port).unwrap() == qb_t() {
                 unified_in_cols.insert(OutgoingPort::from(in_port.index()), n_unified_qbs);
-                n_unified_qbs = n_unified_qbs + 1;
+                n_unified_qbs += 1;
             }
         }
         let mut unified_out_cols : HashMap<IncomingPort, usize> = HashMap::default();
         for out_port in sig.output_ports() {
             if *sig.out_port_type(out_port).unwrap() == qb_t() {
                 unified_out_cols.insert(IncomingPort::from(out_port.index()), n_unified_qbs);
-                n_unified_qbs = n_unified_qbs + 1;
+                n_unified_qbs += 1;
             }
         }
         let mut summary: Option<StabilizerDataflow<H>> = None;
+        let mut case_tabs = vec![];
         for (cond_i, cond_node) in hugr.children(node).enumerate() {
-            let analysis = StabilizerDataflow::run_dfg(hugr, cond_node, fun_op);
-            let mut tab = analysis.tab.clone();
+            if case.is_some_and(|case| case != cond_i) {
+                continue;
+            }
+            let analysis = StabilizerDataflow::run_region(hugr, cond_node, policy, call_stack)?;
             // Number of ports from the condition row; given port p on input, corresponds to IncomingPort::from(p + 1 - cond_len) to the Conditional
-            let cond_len = cond.sum_rows.get(cond_i).unwrap().len();
-            //TODO:: Project out non-IO columns
-            //TODO:: Reorder and remove columns of tab to match summ
+            let cond_len = cond.sum_rows.get(cond_i).ok_or(DataflowError::UnexpectedOp { node })?.len();
+            let mismatch = DataflowError::UnexpectedOp { node: cond_node };
+            let mut unified_index: HashMap<usize, usize> = HashMap::default();
+            for (port, col) in analysis.cols.inputs() {
+                if port.index() >= cond_len {
+                    unified_index.insert(col, *unified_in_cols.get(&OutgoingPort::from(port.index() + 1 - cond_len)).ok_or(mismatch.clone())?);
+                }
+            }
+            for (port, col) in analysis.cols.outputs() {
+                unified_index.insert(col, *unified_out_cols.get(&port).ok_or(mismatch.clone())?);
+            }
+            // Any qubits unpacked from the condition are projected out along with the internal columns
+            let tab = restrict_tab(&analysis.tab, &unified_index);
+            case_tabs.push(tab.clone());
             match summary {
                 Some(ref mut summ) => {
-                    //TODO:: Compute join of tab and summ.tab
+                    #[cfg(feature = "tracing")]
+                    let nb_stabs = summ.tab.nb_stabs;
+                    summ.tab = summ.tab.join(&tab);
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(name: "join", node = ?node, case = ?cond_node, rows_before = nb_stabs, rows_after = summ.tab.nb_stabs);
                     summ.nested_analysis.insert(cond_node, analysis);
                 }
                 None => {
                     summary = Some(StabilizerDataflow {
-                        tab: tab,
-                        in_cols: unified_in_cols.clone(),
-                        out_cols: unified_out_cols.clone(),
-                        frontier_cols: HashMap::default(),
-                        internal_in_cols: HashMap::default(),
-                        internal_out_cols: HashMap::default(),
-                        nested_in_cols: HashMap::default(),
-                        nested_out_cols: HashMap::default(),
-                        nested_analysis: HashMap::default(),
+                        tab,
+                        cols: ColumnAllocator::with_boundary(unified_in_cols.clone(), unified_out_cols.clone()),
+                        ..StabilizerDataflow::empty(node, policy)
                     });
                     summary.as_mut().unwrap().nested_analysis.insert(cond_node, analysis);
                 }
             }
         }
-        summary.unwrap()
+        let summary = summary.ok_or(DataflowError::UnexpectedOp { node })?;
+        let frame = match case_tabs.as_slice() {
+            [base, corrected] if cond.sum_rows.iter().all(|row| row.is_empty()) => {
+                let out_cols = unified_out_cols.values().copied().sorted().collect_vec();
+                pauli_correction(base, corrected, &out_cols).map(|correction| PauliFrame { base: base.clone(), correction })
+            }
+            _ => None,
+        };
+        Ok((summary, frame))
     }
 
-    fn run_tail_loop(hugr: &H, node: H::Node, fun_op: &FunctionOpacity) -> StabilizerDataflow<H> {
-        let child_node = hugr.children(node).exactly_one().ok().unwrap();
-        let child_analysis = StabilizerDataflow::run_dfg(hugr, child_node, fun_op);
-        let mut analysis = StabilizerDataflow{
-            tab: Tableau::new(0),
-            in_cols: HashMap::default(),
-            out_cols: HashMap::default(),
-            frontier_cols: HashMap::default(),
-            internal_in_cols: HashMap::default(),
-            internal_out_cols: HashMap::default(),
-            nested_in_cols: HashMap::default(),
-            nested_out_cols: HashMap::default(),
-            nested_analysis: HashMap::default()
-        };
+    fn run_tail_loop(hugr: &H, node: H::Node, policy: &AnalysisConfig<H::Node>, call_stack: &[H::Node]) -> Result<StabilizerDataflow<H>, DataflowError<H::Node>> {
+        // The body is the dataflow region directly under the TailLoop, with the Input and Output as its first children
+        let child_node = node;
+        let child_analysis = StabilizerDataflow::run_region(hugr, child_node, policy, call_stack)?;
+        let mismatch = DataflowError::UnexpectedOp { node: child_node };
+        let mut analysis = StabilizerDataflow::empty(node, policy);
         let tl = hugr.get_optype(node).as_tail_loop().unwrap();
-        // tl.just_inputs only appear in final signature within a Sum, so qubits there will be projected away
-        // tl.just_outputs do appear in the final signature, but we will not have any information about the qubits there
+        // Columns of the child analysis for the rest qubits, mapped to the columns of the summary
+        let mut unified_index: HashMap<usize, usize> = HashMap::default();
+        // tl.rest appear in the final input signature from port (tl.just_inputs.len()) onwards and in the output signature from port (tl.just_outputs.len()) onwards
+        // In the body, they appear on the Input from port (tl.just_inputs.len()) onwards and on the Output from port 1 onwards (after the Sum)
+        for (port_index, port_type) in tl.rest.iter().enumerate() {
+            if *port_type == qb_t() {
+                let in_col = analysis.tab.add_col();
+                analysis.cols.set_input(OutgoingPort::from(port_index + tl.just_inputs.len()), in_col);
+                let out_col = analysis.tab.add_col();
+                analysis.cols.set_output(IncomingPort::from(port_index + tl.just_outputs.len()), out_col);
+                // Seed the summary with the identity, from which the fixpoint iteration below only loses information
+                analysis.add_identity_rows(in_col, out_col);
+                unified_index.insert(child_analysis.cols.column_for_input(OutgoingPort::from(port_index + tl.just_inputs.len())).ok_or(mismatch.clone())?, in_col);
+                unified_index.insert(child_analysis.cols.column_for_output(IncomingPort::from(port_index + 1)).ok_or(mismatch.clone())?, out_col);
+            }
+        }
+        // The loop may continue for any number of iterations, so iterate the summary as the join of itself and itself followed by another iteration of the body until it converges
+        let body = restrict_tab(&child_analysis.tab, &unified_index);
+        let n_rest_qbs = analysis.tab.nb_qubits / 2;
+        // Connect the out column of the summary to the in column of the body for each qubit; the composite then has the summary's in columns followed by the body's out columns
+        let wires = (0..n_rest_qbs).map(|k| (2*k + 1, 2*k)).collect_vec();
+        let new_index = (0..n_rest_qbs).map(|k| 2*k).chain((0..n_rest_qbs).map(|k| 2*k + 1)).collect_vec();
+        for _ in 0..MAX_LOOP_ITERATIONS {
+            let mut next = analysis.tab.compose(&body, &wires);
+            next.reorder_cols(&new_index);
+            let next = analysis.tab.join(&next);
+            if next.equivalent(&analysis.tab) {
+                break;
+            }
+            analysis.tab = next;
+        }
+        // tl.just_outputs only come out of the Sum on the final iteration, when the body breaks
+        // If the Sum is built directly by a Tag, we can follow the qubits going into it; otherwise we have no information about them
+        let [_, body_output] = hugr.get_io(child_node).ok_or(mismatch.clone())?;
+        let break_tag = hugr.single_linked_output(body_output, IncomingPort::from(0)).map(|(n, _)| n).filter(|n| matches!(hugr.get_optype(*n), OpType::Tag(tag) if tag.tag == 1));
+        let mut break_tab = child_analysis.tab.clone();
+        let mut break_index = unified_index.clone();
+        let mut n_just_out_qbs = 0;
         for (out_port, out_type) in tl.just_outputs.iter().enumerate() {
-            let new_col = analysis.tab.add_col();
-            analysis.out_cols.insert(IncomingPort::from(out_port), new_col);
+            if *out_type == qb_t() {
+                let out_col = 2*n_rest_qbs + n_just_out_qbs;
+                n_just_out_qbs += 1;
+                analysis.cols.set_output(IncomingPort::from(out_port), out_col);
+                let body_col = match break_tag {
+                    Some(tag) => child_analysis.cols.column_for_packed(body_output, IncomingPort::from(0), &[out_port]).ok_or(DataflowError::UnexpectedOp { node: tag })?,
+                    None => break_tab.add_col(),
+                };
+                break_index.insert(body_col, out_col);
+            }
+        }
+        // Finish the summary with the final iteration of the body, which provides the just_outputs
+        let break_tab = restrict_tab(&break_tab, &break_index);
+        let mut tab = analysis.tab.compose(&break_tab, &wires);
+        tab.reorder_cols(&new_index.iter().copied().chain((2*n_rest_qbs)..break_tab.nb_qubits).collect_vec());
+        analysis.tab = tab;
+        // tl.just_inputs only pass into the first iteration of the body, so they are projected out along with the body's other internal columns
+        for (in_port, in_type) in tl.just_inputs.iter().enumerate() {
+            if *in_type == qb_t() {
+                let new_col = analysis.tab.add_col();
+                analysis.cols.set_input(OutgoingPort::from(in_port), new_col);
+            }
         }
-        // tl.rest appear in the final input signature from port 1 onwards and in the output signature from port (tl.just_outputs.len()) onwards
-        for (port_index, port_type) in tl.rest.iter().enumerate() {
-            let in_col = analysis.tab.add_col();
-            analysis.in_cols.insert(OutgoingPort::from(port_index + 1), in_col);
-            let out_col = analysis.tab.add_col();
-            analysis.out_cols.insert(IncomingPort::from(port_index + tl.just_outputs.len()), out_col);
-            //TODO:: Add rows for identity in_col--out_col
-        }
-        let mut tab = child_analysis.tab.clone();
-        //TODO:: Project out non-IO columns and those not shared by input and output
-        //TODO:: Reorder and remove columns of tab to match analysis.tab
-        //TODO:: Compute join of tabs
         analysis.nested_analysis.insert(child_node, child_analysis);
-        analysis
+        Ok(analysis)
+    }
+
+    fn run_cfg(hugr: &H, node: H::Node, policy: &AnalysisConfig<H::Node>, call_stack: &[H::Node]) -> Result<StabilizerDataflow<H>, DataflowError<H::Node>> {
+        let mut analysis = StabilizerDataflow::empty(node, policy);
+        let mut n_in_qbs = 0;
+        for (port, port_type) in hugr.in_value_types(node) {
+            if port_type == qb_t() {
+                analysis.cols.set_input(OutgoingPort::from(port.index()), n_in_qbs);
+                n_in_qbs += 1;
+            }
+        }
+        let mut n_out_qbs = 0;
+        for (port, port_type) in hugr.out_value_types(node) {
+            if port_type == qb_t() {
+                analysis.cols.set_output(IncomingPort::from(port.index()), n_in_qbs + n_out_qbs);
+                n_out_qbs += 1;
+            }
+        }
+        let entry = hugr.children(node).next().ok_or(DataflowError::UnexpectedOp { node })?;
+        let exit = hugr.children(node).find(|n| matches!(hugr.get_optype(*n), OpType::ExitBlock(_))).ok_or(DataflowError::UnexpectedOp { node })?;
+        // For each block, summarise the relation from its input qubits to the input qubits of each successor
+        // The columns of each relation are the block's input qubits followed by the successor's input qubits, both in port order
+        let mut edges: HashMap<H::Node, Vec<(H::Node, ChoiTableau)>> = HashMap::default();
+        for block in hugr.children(node).filter(|n| *n != exit) {
+            let block_analysis = StabilizerDataflow::run_region(hugr, block, policy, call_stack)?;
+            let mismatch = DataflowError::UnexpectedOp { node: block };
+            let dfb = hugr.get_optype(block).as_dataflow_block().ok_or(mismatch.clone())?;
+            let block_index: HashMap<usize, usize> = block_analysis.cols.inputs().enumerate().map(|(k, (_, col))| (col, k)).collect();
+            let [_, block_output] = hugr.get_io(block).ok_or(mismatch.clone())?;
+            let mut block_edges = vec![];
+            for (i, sum_row) in dfb.sum_rows.iter().enumerate() {
+                let (succ, _) = hugr.single_linked_input(block, OutgoingPort::from(i)).ok_or(mismatch.clone())?;
+                // Successor i receives sum_rows[i] followed by other_outputs (which are on the Output from port 1 onwards, after the Sum)
+                // As for TailLoops, qubits in the Sum can only be followed if it is built directly by a Tag for this successor
+                let tag = hugr.single_linked_output(block_output, IncomingPort::from(0)).map(|(n, _)| n).filter(|n| matches!(hugr.get_optype(*n), OpType::Tag(tag) if tag.tag == i));
+                let mut edge_tab = block_analysis.tab.clone();
+                let mut edge_index = block_index.clone();
+                for (j, port_type) in sum_row.iter().chain(dfb.other_outputs.iter()).enumerate() {
+                    if *port_type == qb_t() {
+                        let col = if j < sum_row.len() {
+                            match tag {
+                                Some(tag) => block_analysis.cols.column_for_packed(block_output, IncomingPort::from(0), &[j]).ok_or(DataflowError::UnexpectedOp { node: tag })?,
+                                None => edge_tab.add_col(),
+                            }
+                        } else {
+                            block_analysis.cols.column_for_output(IncomingPort::from(j + 1 - sum_row.len())).ok_or(mismatch.clone())?
+                        };
+                        edge_index.insert(col, edge_index.len());
+                    }
+                }
+                block_edges.push((succ, restrict_tab(&edge_tab, &edge_index)));
+            }
+            edges.insert(block, block_edges);
+            analysis.nested_analysis.insert(block, block_analysis);
+        }
+        // For each block reached so far, the relation from the qubit inputs of the CFG to the input qubits of the block, joined over all paths reaching it
+        // The entry block receives the inputs of the CFG directly
+        let mut states: HashMap<H::Node, ChoiTableau> = HashMap::default();
+        let mut entry_state = ChoiTableau::new(2*n_in_qbs);
+        for k in 0..n_in_qbs {
+            entry_state.add_row(entry_state.pauli(&[], &[k, n_in_qbs + k], false));
+            entry_state.add_row(entry_state.pauli(&[k, n_in_qbs + k], &[], false));
+        }
+        states.insert(entry, entry_state);
+        // Propagate along the edges until no state changes; every change after a block is first reached loses at least one stabilizer, so this converges whatever the shape of the CFG (including irreducible ones)
+        let mut worklist = VecDeque::from([entry]);
+        let mut n_visits = 0;
+        let mut converged = true;
+        while let Some(block) = worklist.pop_front() {
+            n_visits += 1;
+            if n_visits > MAX_LOOP_ITERATIONS * edges.len() {
+                converged = false;
+                break;
+            }
+            let state = states.get(&block).unwrap().clone();
+            // Connect the block's columns of the state to the block's columns of each edge; the composite then has the CFG inputs followed by the successor's input qubits
+            let wires = (n_in_qbs..state.nb_qubits).enumerate().map(|(k, col)| (col, k)).collect_vec();
+            for (succ, edge_tab) in edges.get(&block).unwrap() {
+                let next = state.compose(edge_tab, &wires);
+                let next = match states.get(succ) {
+                    Some(old) => {
+                        let joined = old.join(&next);
+                        if joined.equivalent(old) {
+                            continue;
+                        }
+                        joined
+                    }
+                    None => next,
+                };
+                states.insert(*succ, next);
+                if *succ != exit && !worklist.contains(succ) {
+                    worklist.push_back(*succ);
+                }
+            }
+        }
+        // If the exit is never reached, there are no executions to relate the outputs to, so we leave the summary without any stabilizers
+        // Should the safety cap be hit, the states are not a fixpoint and may claim relations some path breaks, so the CFG is treated as opaque instead, again with a summary without any stabilizers
+        analysis.tab = match states.remove(&exit) {
+            Some(state) if converged => state,
+            _ => ChoiTableau::new(n_in_qbs + n_out_qbs),
+        };
+        Ok(analysis)
     }
 
-    fn apply_quantum_gate(&mut self, hugr : &H, node: H::Node, op: TketOp) {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(node = ?node, op = ?op)))]
+    fn apply_quantum_gate(&mut self, hugr : &H, node: H::Node, op: TketOp) -> Result<(), DataflowError<H::Node>> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(name: "apply_quantum_gate", n_frontier_cols = self.cols.count(ColumnRole::Frontier), n_internal_cols = self.cols.count(ColumnRole::InternalIn) + self.cols.count(ColumnRole::InternalOut));
+        let clifford_gates = clifford_rotation(hugr, node, op);
+        self.count_gate(op, clifford_gates.is_some());
+        if let Some(gates) = clifford_gates {
+            // An Rz or CRz by a constant multiple of a quarter turn (e.g. Rz(π/2) = S) is a Clifford gate, so is applied in place without any internal columns
+            let n_qubits = if op == TketOp::CRz { 2 } else { 1 };
+            let cols = (0..n_qubits).map(|p| self.take_frontier(node, IncomingPort::from(p))).collect::<Result<Vec<_>, _>>()?;
+            for (gate, qubits) in gates {
+                append_clifford(&mut self.tab, gate, &qubits.iter().map(|q| cols[*q]).collect_vec());
+            }
+            for (p, col) in cols.into_iter().enumerate() {
+                self.push_frontier(hugr, node, OutgoingPort::from(p), col)?;
+            }
+            return Ok(());
+        }
+        // The outcome of a measurement is the Z value of the measured qubit, which is left on the internal input column below
+        let bit_port = match op {
+            TketOp::Measure => Some(OutgoingPort::from(1)),
+            TketOp::MeasureFree => Some(OutgoingPort::from(0)),
+            _ => None,
+        };
+        if let Some(port) = bit_port.filter(|_| self.policy.tracks_classical()) {
+            self.bits.insert((node, port), BitParity { measurements: HashSet::from([node]), flip: false });
+        }
         match op {
             TketOp::H => {
-                let col: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
+                let col: usize = self.take_frontier(node, IncomingPort::from(0))?;
                 self.tab.append_h(col);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col)?;
             }
             TketOp::CX => {
-                let col0: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                let col1: usize = self.frontier_cols.remove(&(node, IncomingPort::from(1))).unwrap();
+                let col0: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                let col1: usize = self.take_frontier(node, IncomingPort::from(1))?;
                 self.tab.append_cx(vec![col0, col1]);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col0);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(1)).unwrap(), col1);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col0)?;
+                self.push_frontier(hugr, node, OutgoingPort::from(1), col1)?;
             }
             TketOp::CY => {
-                let col0: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                let col1: usize = self.frontier_cols.remove(&(node, IncomingPort::from(1))).unwrap();
+                let col0: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                let col1: usize = self.take_frontier(node, IncomingPort::from(1))?;
                 self.tab.append_s(col1);
                 self.tab.append_z(col1);
                 self.tab.append_cx(vec![col0, col1]);
                 self.tab.append_s(col1);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col0);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(1)).unwrap(), col1);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col0)?;
+                self.push_frontier(hugr, node, OutgoingPort::from(1), col1)?;
             }
             TketOp::CZ => {
-                let col0: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                let col1: usize = self.frontier_cols.remove(&(node, IncomingPort::from(1))).unwrap();
+                let col0: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                let col1: usize = self.take_frontier(node, IncomingPort::from(1))?;
                 self.tab.append_cz(vec![col0, col1]);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col0);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(1)).unwrap(), col1);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col0)?;
+                self.push_frontier(hugr, node, OutgoingPort::from(1), col1)?;
             }
             TketOp::CRz => {
-                let col_in0: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                let col_in1: usize = self.frontier_cols.remove(&(node, IncomingPort::from(1))).unwrap();
-                let col_out0: usize = self.tab.add_col();
-                let col_out1: usize = self.tab.add_col();
-                let col_front0: usize = self.tab.add_col();
-                let col_front1: usize = self.tab.add_col();
-                //TODO:: Add rows for identities col_out0/1--col_front0/1
-                //TODO:: Add rows for ZZ over col_in0/1--col_out0/1 and project to commuting
-                self.internal_in_cols.insert((node, IncomingPort::from(0)), col_in0);
-                self.internal_in_cols.insert((node, IncomingPort::from(1)), col_in1);
-                self.internal_out_cols.insert((node, OutgoingPort::from(0)), col_out0);
-                self.internal_out_cols.insert((node, OutgoingPort::from(1)), col_out1);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col_front0);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(1)).unwrap(), col_front1);
+                let col_in0: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                let col_in1: usize = self.take_frontier(node, IncomingPort::from(1))?;
+                let col_out0: usize = self.add_col();
+                let col_out1: usize = self.add_col();
+                let col_front0: usize = self.add_col();
+                let col_front1: usize = self.add_col();
+                self.add_identity_rows(col_out0, col_front0);
+                self.add_identity_rows(col_out1, col_front1);
+                self.add_relation(self.tab.pauli(&[col_in0, col_out0], &[], false));
+                self.add_relation(self.tab.pauli(&[col_in1, col_out1], &[], false));
+                self.cols.set_node_port(vec![node], IncomingPort::from(0), col_in0);
+                self.cols.set_node_port(vec![node], IncomingPort::from(1), col_in1);
+                self.cols.set_node_port(vec![node], OutgoingPort::from(0), col_out0);
+                self.cols.set_node_port(vec![node], OutgoingPort::from(1), col_out1);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col_front0)?;
+                self.push_frontier(hugr, node, OutgoingPort::from(1), col_front1)?;
             }
             TketOp::T | TketOp::Tdg | TketOp::Rz | TketOp::Measure => {
-                let col_in: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                let col_out: usize = self.tab.add_col();
-                let col_front: usize = self.tab.add_col();
-                //TODO:: Add rows for identity col_out--col_front
-                //TODO:: Add row for ZZ over col_in--col_out and project to commuting
-                self.internal_in_cols.insert((node, IncomingPort::from(0)), col_in);
-                self.internal_out_cols.insert((node, OutgoingPort::from(0)), col_out);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col_front);
+                let col_in: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                let col_out: usize = self.add_col();
+                let col_front: usize = self.add_col();
+                self.add_identity_rows(col_out, col_front);
+                self.add_relation(self.tab.pauli(&[col_in, col_out], &[], false));
+                self.cols.set_node_port(vec![node], IncomingPort::from(0), col_in);
+                self.cols.set_node_port(vec![node], OutgoingPort::from(0), col_out);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col_front)?;
             }
             TketOp::S => {
-                let col: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
+                let col: usize = self.take_frontier(node, IncomingPort::from(0))?;
                 self.tab.append_s(col);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col)?;
             }
             TketOp::Sdg => {
-                let col: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
+                let col: usize = self.take_frontier(node, IncomingPort::from(0))?;
                 self.tab.append_s(col);
                 self.tab.append_z(col);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col)?;
             }
             TketOp::X => {
-                let col: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
+                let col: usize = self.take_frontier(node, IncomingPort::from(0))?;
                 self.tab.append_x(col);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col)?;
             }
             TketOp::Y => {
-                let col: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
+                let col: usize = self.take_frontier(node, IncomingPort::from(0))?;
                 self.tab.append_x(col);
                 self.tab.append_z(col);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col)?;
             }
             TketOp::Z => {
-                let col: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
+                let col: usize = self.take_frontier(node, IncomingPort::from(0))?;
                 self.tab.append_z(col);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col)?;
             }
             TketOp::Rx => {
-                let col_in: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                let col_out: usize = self.tab.add_col();
-                let col_front: usize = self.tab.add_col();
-                //TODO:: Add rows for identity col_out--col_front
-                //TODO:: Add row for XX over col_in--col_out and project to commuting
-                self.internal_in_cols.insert((node, IncomingPort::from(0)), col_in);
-                self.internal_out_cols.insert((node, OutgoingPort::from(0)), col_out);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col_front);
+                let col_in: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                let col_out: usize = self.add_col();
+                let col_front: usize = self.add_col();
+                self.add_identity_rows(col_out, col_front);
+                self.add_relation(self.tab.pauli(&[], &[col_in, col_out], false));
+                self.cols.set_node_port(vec![node], IncomingPort::from(0), col_in);
+                self.cols.set_node_port(vec![node], OutgoingPort::from(0), col_out);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col_front)?;
             }
             TketOp::Ry => {
-                let col_in: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                let col_out: usize = self.tab.add_col();
-                let col_front: usize = self.tab.add_col();
-                //TODO:: Add rows for identity col_out--col_front
-                //TODO:: Add row for YY over col_in--col_out and project to commuting
-                self.internal_in_cols.insert((node, IncomingPort::from(0)), col_in);
-                self.internal_out_cols.insert((node, OutgoingPort::from(0)), col_out);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col_front);
+                let col_in: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                let col_out: usize = self.add_col();
+                let col_front: usize = self.add_col();
+                self.add_identity_rows(col_out, col_front);
+                // The identity has -YY (= XX.ZZ) rather than +YY, so this is the sign preserved by a Y rotation
+                self.add_relation(self.tab.pauli(&[col_in, col_out], &[col_in, col_out], true));
+                self.cols.set_node_port(vec![node], IncomingPort::from(0), col_in);
+                self.cols.set_node_port(vec![node], OutgoingPort::from(0), col_out);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col_front)?;
             }
             TketOp::Toffoli => {
-                let col_in0: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                let col_in1: usize = self.frontier_cols.remove(&(node, IncomingPort::from(1))).unwrap();
-                let col_in2: usize = self.frontier_cols.remove(&(node, IncomingPort::from(2))).unwrap();
-                let col_out0: usize = self.tab.add_col();
-                let col_out1: usize = self.tab.add_col();
-                let col_out2: usize = self.tab.add_col();
-                let col_front0: usize = self.tab.add_col();
-                let col_front1: usize = self.tab.add_col();
-                let col_front2: usize = self.tab.add_col();
-                //TODO:: Add rows for identities col_out0/1/2--col_front0/1/2
-                //TODO:: Add rows for ZZ/ZZ/XX over col_in0/1/2--col_out0/1/2 and project to commuting
-                self.internal_in_cols.insert((node, IncomingPort::from(0)), col_in0);
-                self.internal_in_cols.insert((node, IncomingPort::from(1)), col_in1);
-                self.internal_in_cols.insert((node, IncomingPort::from(2)), col_in2);
-                self.internal_out_cols.insert((node, OutgoingPort::from(0)), col_out0);
-                self.internal_out_cols.insert((node, OutgoingPort::from(1)), col_out1);
-                self.internal_out_cols.insert((node, OutgoingPort::from(2)), col_out2);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col_front0);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(1)).unwrap(), col_front1);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(2)).unwrap(), col_front2);
+                let col_in0: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                let col_in1: usize = self.take_frontier(node, IncomingPort::from(1))?;
+                let col_in2: usize = self.take_frontier(node, IncomingPort::from(2))?;
+                let col_out0: usize = self.add_col();
+                let col_out1: usize = self.add_col();
+                let col_out2: usize = self.add_col();
+                let col_front0: usize = self.add_col();
+                let col_front1: usize = self.add_col();
+                let col_front2: usize = self.add_col();
+                self.add_identity_rows(col_out0, col_front0);
+                self.add_identity_rows(col_out1, col_front1);
+                self.add_identity_rows(col_out2, col_front2);
+                self.add_relation(self.tab.pauli(&[col_in0, col_out0], &[], false));
+                self.add_relation(self.tab.pauli(&[col_in1, col_out1], &[], false));
+                self.add_relation(self.tab.pauli(&[], &[col_in2, col_out2], false));
+                self.cols.set_node_port(vec![node], IncomingPort::from(0), col_in0);
+                self.cols.set_node_port(vec![node], IncomingPort::from(1), col_in1);
+                self.cols.set_node_port(vec![node], IncomingPort::from(2), col_in2);
+                self.cols.set_node_port(vec![node], OutgoingPort::from(0), col_out0);
+                self.cols.set_node_port(vec![node], OutgoingPort::from(1), col_out1);
+                self.cols.set_node_port(vec![node], OutgoingPort::from(2), col_out2);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col_front0)?;
+                self.push_frontier(hugr, node, OutgoingPort::from(1), col_front1)?;
+                self.push_frontier(hugr, node, OutgoingPort::from(2), col_front2)?;
             }
             TketOp::MeasureFree => {
-                let col_in: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                self.internal_in_cols.insert((node, IncomingPort::from(0)), col_in);
+                let col_in: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                // The measurement destroys every relation with X or Y on the qubit, leaving only those with Z (which give the parity the outcome is drawn from)
+                self.project(&[(col_in, true)]);
+                self.cols.set_node_port(vec![node], IncomingPort::from(0), col_in);
             }
             TketOp::QAlloc => {
-                let col_front: usize = self.tab.add_col();
-                //TODO:: Add row for Z over col_front
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col_front);
+                let col_front: usize = self.add_col();
+                // The fresh qubit is in |0>
+                self.tab.add_row(self.tab.pauli(&[col_front], &[], false));
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col_front)?;
             }
             TketOp::QFree => {
-                let col_in: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                //TODO:: Project out non-commuting rows and remove column from tableau
+                let col_in: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                // Trace out the qubit, keeping only the relations that do not involve it, then drop its column
+                self.project(&[(col_in, false), (col_in, true)]);
+                self.remove_cols(&[col_in]);
             }
             TketOp::Reset => {
-                let col_in: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
-                //TODO:: Project out non-commuting rows
-                // Reuse col_in for the output qubit
-                //TODO:: Add row for Z over col_in
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col_in);
+                let col_in: usize = self.take_frontier(node, IncomingPort::from(0))?;
+                // The discarded qubit is left on col_in as the internal input of the Reset, which no later gate touches, so it is traced out of every relation over the other columns
+                // Keeping the column (rather than projecting it away) lets us still ask what state the qubit was in, e.g. for redundant_resets
+                self.cols.set_node_port(vec![node], IncomingPort::from(0), col_in);
+                // The output qubit starts afresh in |0>
+                let col_front: usize = self.add_col();
+                self.tab.add_row(self.tab.pauli(&[col_front], &[], false));
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col_front)?;
             }
             TketOp::V => {
-                let col: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
+                let col: usize = self.take_frontier(node, IncomingPort::from(0))?;
                 self.tab.append_v(col);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col)?;
             }
             TketOp::Vdg => {
-                let col: usize = self.frontier_cols.remove(&(node, IncomingPort::from(0))).unwrap();
+                let col: usize = self.take_frontier(node, IncomingPort::from(0))?;
                 self.tab.append_v(col);
                 self.tab.append_x(col);
-                self.frontier_cols.insert(hugr.single_linked_input(node, OutgoingPort::from(0)).unwrap(), col);
+                self.push_frontier(hugr, node, OutgoingPort::from(0), col)?;
             }
             _ => {
                 // Only other remaining TketOp option at time of writing is TryQAlloc which has no qubits in its signature (the output is a Sum and therefore we currently don't track any relations involving it)
                 // In case other options are added later on, handle them as opaque unless we explicitly add a custom handler for them
-                self.apply_opaque(hugr, node)
+                self.apply_opaque(hugr, node)?
             }
         }
+        Ok(())
+    }
+
+    /// Counts op in the statistics, where clifford is set for an Rz or CRz by a multiple of a quarter turn
+    fn count_gate(&mut self, op: TketOp, clifford: bool) {
+        match op {
+            TketOp::T | TketOp::Tdg => self.stats.t_count += 1,
+            TketOp::Rz | TketOp::CRz if clifford => {}
+            TketOp::Rz => self.stats.rz_count += 1,
+            TketOp::CRz => self.stats.crz_count += 1,
+            TketOp::Toffoli => self.stats.toffoli_count += 1,
+            _ => {}
+        }
     }
 
-    fn apply_opaque(&mut self, hugr: &H, node: H::Node) {
-        // For each Qubit input, move the column from frontier_cols to internal_in_cols
+    /// Applies node once the column budget is spent, like apply_opaque but without any internal columns: every relation on the qubit inputs is projected out, leaving their columns free to carry the qubit outputs
+    /// Outputs beyond the inputs get fresh columns, and inputs beyond the outputs are removed, so the tableau never grows by more than the qubits the node creates
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(node = ?node, op = ?hugr.get_optype(node))))]
+    fn apply_degraded(&mut self, hugr: &H, node: H::Node) -> Result<(), DataflowError<H::Node>> {
+        self.stats.truncated_nodes += 1;
+        if let OpType::ExtensionOp(op) = hugr.get_optype(node) {
+            if let Ok(tkop) = TketOp::from_extension_op(op) {
+                self.count_gate(tkop, false);
+            }
+        }
+        let mut free_cols = VecDeque::new();
         for (p, t) in hugr.in_value_types(node) {
             if t == qb_t() {
-                let col: usize = self.frontier_cols.remove(&(node, p)).unwrap();
-                self.internal_in_cols.insert((node, p), col);
+                let col: usize = self.take_frontier(node, p)?;
+                self.project(&[(col, false), (col, true)]);
+                free_cols.push_back(col);
             }
         }
-        // For each Qubit output, create a pair of columns with the identity for internal_out_cols and frontier_cols
         for (p, t) in hugr.out_value_types(node) {
             if t == qb_t() {
-                let col_out = self.tab.add_col();
-                let col_front = self.tab.add_col();
-                //TODO:: Add rows for identity col_out--col_front
-                self.internal_out_cols.insert((node, p), col_out);
-                self.frontier_cols.insert(hugr.single_linked_input(node, p).unwrap(), col_front);
+                let col = match free_cols.pop_front() {
+                    Some(col) => col,
+                    None => self.add_col(),
+                };
+                self.push_frontier(hugr, node, p, col)?;
             }
         }
+        self.remove_cols(&free_cols.into_iter().collect_vec());
+        Ok(())
     }
 
-    /// Suppose we have already recursively calculated a StabilizerDataflow for node and stored it in nested_analysis; performs sequential composition to append it to the appropriate qubits here
-    fn apply_analysis(&mut self, hugr: &H, node: H::Node) {
-        let node_analysis : &StabilizerDataflow<H> = self.nested_analysis.get(&node).unwrap();
-        let old_n_qbs = self.tab.nb_qubits;
-        let n_added_qbs = node_analysis.tab.nb_qubits;
-        for i in 0..n_added_qbs {
-            self.tab.add_col();
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(node = ?node, op = ?hugr.get_optype(node))))]
+    fn apply_opaque(&mut self, hugr: &H, node: H::Node) -> Result<(), DataflowError<H::Node>> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(name: "apply_opaque", n_frontier_cols = self.cols.count(ColumnRole::Frontier), n_internal_cols = self.cols.count(ColumnRole::InternalIn) + self.cols.count(ColumnRole::InternalOut));
+        if hugr.in_value_types(node).map(|(_, t)| t).chain(hugr.out_value_types(node).map(|(_, t)| t)).contains(&qb_t()) {
+            self.stats.opaque_nodes += 1;
         }
-        for (port, col) in node_analysis.in_cols.iter() {
-            self.nested_in_cols.insert((node, *port), *col + old_n_qbs);
+        let relations = self.declared_relations(hugr, node)?;
+        let n_qubits = hugr.in_value_types(node).map(|(_, t)| t).chain(hugr.out_value_types(node).map(|(_, t)| t)).filter(|t| *t == qb_t()).count();
+        if n_qubits > relations.len() {
+            self.report.unknown_relations.insert(vec![node], n_qubits - relations.len());
         }
-        for (port, col) in node_analysis.out_cols.iter() {
-            self.nested_out_cols.insert((node, *port), *col + old_n_qbs);
-        }
-        // It is decided at analysis construction whether or not we project to IO or keep internals, so always copy any internals that have remained (e.g. for inlining function calls)
-        for (node_port, col) in node_analysis.internal_in_cols.iter() {
-            self.internal_in_cols.insert(*node_port, *col + old_n_qbs);
-        }
-        for (node_port, col) in node_analysis.internal_out_cols.iter() {
-            self.internal_out_cols.insert(*node_port, *col + old_n_qbs);
-        }
-        for (node_port, col) in node_analysis.nested_in_cols.iter() {
-            self.nested_in_cols.insert(*node_port, *col + old_n_qbs);
-        }
-        for (node_port, col) in node_analysis.nested_out_cols.iter() {
-            self.nested_out_cols.insert(*node_port, *col + old_n_qbs);
-        }
-        for i in 0..node_analysis.tab.nb_stabs {
-            let stab : &PauliProduct = node_analysis.tab.stabs.get(i);
-            let mut new_z = BitVector::new(old_n_qbs);
-            new_z.extend_vec(stab.z.get_boolean_vec(), old_n_qbs);
-            let mut new_x = BitVector::new(old_n_qbs);
-            new_x.extend_vec(stab.x.get_boolean_vec(), old_n_qbs);
-            self.tab.add_row(PauliProduct{
-                z: new_z,
-                x: new_x,
-                sign: stab.sign
-            });
+        // For each Qubit input, move the column from the frontier to the node's internal input
+        let mut node_cols = vec![];
+        for (p, t) in hugr.in_value_types(node) {
+            if t == qb_t() {
+                let col: usize = self.take_frontier(node, p)?;
+                self.cols.set_node_port(vec![node], p, col);
+                node_cols.push(col);
+            }
         }
-        for port in hugr.node_inputs(node) {
-            let out_port = OutgoingPort::from(port.index());
-            let internal_col = self.frontier_cols.remove(&(node, port)).unwrap();
-            self.internal_in_cols.insert((node, port), internal_col);
-            let nested_col = self.nested_in_cols.get(&(node, out_port));
-            //TODO:: Project ZZ and XX to compose nested_col and internal_col
+        // For each Qubit output, create a pair of columns with the identity for the node's internal output and the frontier
+        for (p, t) in hugr.out_value_types(node) {
+            if t == qb_t() {
+                let col_out = self.add_col();
+                let col_front = self.add_col();
+                self.add_identity_rows(col_out, col_front);
+                self.cols.set_node_port(vec![node], p, col_out);
+                self.push_frontier(hugr, node, p, col_front)?;
+                node_cols.push(col_out);
+            }
         }
-        for port in hugr.node_outputs(node) {
-            let in_port = IncomingPort::from(port.index());
-            let nested_col = self.nested_out_cols.get(&(node, in_port));
-            let internal_col = self.tab.add_col();
-            self.internal_out_cols.insert((node, port), internal_col);
-            let front_col = self.tab.add_col();
-            self.frontier_cols.insert(hugr.single_linked_input(node, port).unwrap(), front_col);
-            //TODO:: Add rows for identity internal_col--front_col
-            //TODO:: Project ZZ and XX to compose nested_col and internal_col
+        // Declared stabilizers relate the internal columns, where otherwise there would be no relation across the node at all
+        for (z, x, sign) in relations {
+            let z = z.iter().map(|k| node_cols[*k]).collect_vec();
+            let x = x.iter().map(|k| node_cols[*k]).collect_vec();
+            self.add_relation(self.tab.pauli(&z, &x, sign));
         }
+        Ok(())
     }
 
-}
+    /// The relations declared for node by STABILIZERS_METADATA_KEY or the OpacityHints for its op, over its qubit inputs followed by its qubit outputs (as the Z and X columns and sign of each)
+    /// A stabilizer P with sign s gives the relation P^T on the inputs and sP on the outputs, where the transpose negates each Y; the relations must be consistent, and any implied by the others are dropped
+    fn declared_relations(&self, hugr: &H, node: H::Node) -> Result<Vec<DeclaredRelation>, DataflowError<H::Node>> {
+        let invalid = DataflowError::InvalidStabilizers { node };
+        let stabilizers: Vec<String> = match hugr.get_metadata(node, STABILIZERS_METADATA_KEY) {
+            Some(metadata) => metadata.as_array().ok_or(invalid.clone())?.iter().map(|p| p.as_str().map(|p| p.to_string())).collect::<Option<_>>().ok_or(invalid.clone())?,
+            None => {
+                let name = match hugr.get_optype(node) {
+                    OpType::ExtensionOp(op) => Some(op.unqualified_id()),
+                    OpType::OpaqueOp(op) => Some(op.unqualified_id().as_str()),
+                    _ => None,
+                };
+                match name.and_then(|name| self.hints.get(name)) {
+                    Some(stabilizers) => stabilizers.to_vec(),
+                    None => return Ok(vec![]),
+                }
+            }
+        };
+        let n_in = hugr.in_value_types(node).filter(|(_, t)| *t == qb_t()).count();
+        let n_out = hugr.out_value_types(node).filter(|(_, t)| *t == qb_t()).count();
+        if n_in != n_out {
+            return Err(invalid);
+        }
+        let mut declared = ChoiTableau::new(2 * n_in);
+        let mut relations = vec![];
+        for stabilizer in stabilizers {
+            let (negated, paulis) = match stabilizer.strip_prefix('-') {
+                Some(paulis) => (true, paulis),
+                None => (false, stabilizer.strip_prefix('+').unwrap_or(&stabilizer)),
+            };
+            if paulis.chars().count() != n_in {
+                return Err(invalid);
+            }
+            let (mut z, mut x, mut sign) = (vec![], vec![], negated);
+            for (k, pauli) in paulis.chars().enumerate() {
+                let (has_z, has_x) = match pauli {
+                    'I' => (false, false),
+                    'X' => (false, true),
+                    'Y' => (true, true),
+                    'Z' => (true, false),
+                    _ => return Err(invalid),
+                };
+                for col in [k, n_in + k] {
+                    if has_z {
+                        z.push(col);
+                    }
+                    if has_x {
+                        x.push(col);
+                    }
+                }
+                sign ^= pauli == 'Y';
+            }
+            let relation = declared.pauli(&z, &x, sign);
+            if declared.stabilizes(&relation) {
+                continue;
+            }
+            if declared.stabilizes(&declared.pauli(&z, &x, !sign)) || declared.stabs.iter().any(|row| !row.commutes_with(&relation)) {
+                return Err(invalid);
+            }
+            declared.add_row(relation);
+            relations.push((z, x, sign));
+        }
+        Ok(relations)
+    }
 
-#[cfg(test)]
-mod test {
-    use hugr::{builder::{endo_sig, ConditionalBuilder, DFGBuilder, Dataflow, DataflowHugr, DataflowSubContainer, HugrBuilder, SubContainer}, extension::prelude::{bool_t, qb_t, usize_t}, ops::{handle::NodeHandle, OpType, OpaqueOp}, type_row, types::Signature, HugrView, IncomingPort, OutgoingPort};
-    use tket::TketOp;
+    /// Applies a two-qubit interaction exp(-iπα/2 P⊗P) registered in the InteractionRegistry
+    /// A constant angle that is a multiple of a quarter turn gives a Clifford gate, applied in place; otherwise, as for CRz, we add internal columns related by what the interaction preserves, which is P on each qubit and the other Pauli on both (e.g. Z⊗I, I⊗Z and X⊗X for ZZPhase)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(node = ?node)))]
+    fn apply_interaction(&mut self, hugr: &H, node: H::Node) -> Result<(), DataflowError<H::Node>> {
+        let interaction = self.interactions.for_node(hugr, node).ok_or(DataflowError::UnexpectedOp { node })?;
+        let half_turns = match interaction.angle_port {
+            Some(port) => constant_half_turns(hugr, node, IncomingPort::from(port)),
+            None => Some(interaction.half_turns),
+        };
+        let cols_in = [self.take_frontier(node, IncomingPort::from(0))?, self.take_frontier(node, IncomingPort::from(1))?];
+        if let Some(gates) = half_turns.and_then(|half_turns| clifford_interaction(interaction.axis, half_turns)) {
+            for (gate, qubits) in gates {
+                append_clifford(&mut self.tab, gate, &qubits.iter().map(|q| cols_in[*q]).collect_vec());
+            }
+            for (p, col) in cols_in.into_iter().enumerate() {
+                self.push_frontier(hugr, node, OutgoingPort::from(p), col)?;
+            }
+            return Ok(());
+        }
+        let mut cols_out = vec![];
+        for (p, col_in) in cols_in.into_iter().enumerate() {
+            let col_out = self.add_col();
+            let col_front = self.add_col();
+            self.add_identity_rows(col_out, col_front);
+            self.cols.set_node_port(vec![node], IncomingPort::from(p), col_in);
+            self.cols.set_node_port(vec![node], OutgoingPort::from(p), col_out);
+            cols_out.push((col_out, col_front));
+        }
+        let all_cols = [cols_in[0], cols_in[1], cols_out[0].0, cols_out[1].0];
+        for p in 0..2 {
+            let single = [cols_in[p], cols_out[p].0];
+            let relation = match interaction.axis {
+                InteractionAxis::ZZ => self.tab.pauli(&single, &[], false),
+                InteractionAxis::XX => self.tab.pauli(&[], &single, false),
+            };
+            self.add_relation(relation);
+        }
+        let relation = match interaction.axis {
+            InteractionAxis::ZZ => self.tab.pauli(&[], &all_cols, false),
+            InteractionAxis::XX => self.tab.pauli(&all_cols, &[], false),
+        };
+        self.add_relation(relation);
+        for (p, (_, col_front)) in cols_out.into_iter().enumerate() {
+            self.push_frontier(hugr, node, OutgoingPort::from(p), col_front)?;
+        }
+        Ok(())
+    }
 
-    use crate::{bit_vector::BitVector, pauli_product::PauliProduct, stabilizer_dataflow::{FunctionOpacity, StabilizerDataflow}};
+    /// Tracks the bool output of a logic op whose result is linear in its inputs (Xor, Eq and Not) where every input is tracked; anything else leaves the output untracked
+    fn apply_logic(&mut self, hugr: &H, node: H::Node, op: LogicOp) {
+        if !matches!(op, LogicOp::Xor | LogicOp::Eq | LogicOp::Not) {
+            return;
+        }
+        let Some(inputs) = hugr.in_value_types(node).map(|(p, _)| hugr.single_linked_output(node, p).and_then(|src| self.bits.get(&src))).collect::<Option<Vec<_>>>() else {
+            return;
+        };
+        let mut bit = BitParity { measurements: HashSet::new(), flip: matches!(op, LogicOp::Eq | LogicOp::Not) };
+        for input in inputs {
+            bit.measurements = bit.measurements.symmetric_difference(&input.measurements).copied().collect();
+            bit.flip ^= input.flip;
+        }
+        self.bits.insert((node, OutgoingPort::from(0)), bit);
+    }
 
+    /// Swaps the parities tracked on the two bits into a SWAPbits onto its outputs; the op acts only on classical bits, so nothing on the tableau changes
+    /// Bits we do not track stay untracked after the swap, as for any other classical op
+    fn apply_bit_swap(&mut self, hugr: &H, node: H::Node) {
+        for (in_port, out_port) in [(0, 1), (1, 0)] {
+            if let Some(bit) = hugr.single_linked_output(node, IncomingPort::from(in_port)).and_then(|src| self.bits.get(&src)).cloned() {
+                self.bits.insert((node, OutgoingPort::from(out_port)), bit);
+            }
+        }
+    }
 
-    #[test]
-    fn test_empty_analysis() {
-        let builder = DFGBuilder::new(endo_sig(vec![])).unwrap();
-        let hugr = builder.finish_hugr().unwrap();
-        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.module_root(), &FunctionOpacity::Opaque);
-        assert_eq!(analysis.tab.nb_qubits, 0);
-        assert_eq!(analysis.tab.nb_stabs, 0);
+    /// Applies the Pauli correction (over the columns of the summary of node) to the frontier columns of node's output qubits, controlled on the parity of the given Z-only columns and negated if flip is set
+    /// As no relation has X on a control column, a controlled Pauli from it is a classically controlled correction, with no back-action
+    fn apply_pauli_frame(&mut self, hugr: &H, node: H::Node, correction: &PauliProduct, controls: &[usize], flip: bool) -> Result<(), DataflowError<H::Node>> {
+        let summary_cols = &self.nested_analysis.get(&node).unwrap().cols;
+        let targets = hugr.out_value_types(node).filter(|(_, t)| *t == qb_t()).map(|(port, _)| {
+            let summary_col = summary_cols.column_for_output(IncomingPort::from(port.index())).ok_or(DataflowError::UnexpectedOp { node })?;
+            let (next, next_port) = hugr.single_linked_input(node, port).ok_or(DataflowError::DanglingQubitWire { node, port })?;
+            let col = self.cols.frontier(next, next_port).ok_or(DataflowError::InconsistentFrontier { node: next, port: next_port })?;
+            Ok::<_, DataflowError<H::Node>>((col, correction.get_x(summary_col), correction.get_z(summary_col)))
+        }).collect::<Result<Vec<_>, _>>()?;
+        for (col, x, z) in targets {
+            for control in controls {
+                if x {
+                    self.tab.append_cx(vec![*control, col]);
+                }
+                if z {
+                    self.tab.append_cz(vec![*control, col]);
+                }
+            }
+            if flip && x {
+                self.tab.append_x(col);
+            }
+            if flip && z {
+                self.tab.append_z(col);
+            }
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_identity_analysis() {
-        // Add an extra integer input to make sure we only track the qubits
-        let builder = DFGBuilder::new(endo_sig(vec![usize_t(), qb_t(), qb_t()])).unwrap();
-        let [_, qb0, qb1] = builder.input_wires_arr();
-        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
-        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.module_root(), &FunctionOpacity::Opaque);
-        assert_eq!(analysis.tab.nb_qubits, 4);
-        assert_eq!(analysis.tab.nb_stabs, 4);
-        // Check the right ports are stored for tracking the qubits
-        assert_eq!(analysis.in_cols.len(), 2);
-        assert_eq!(analysis.out_cols.len(), 2);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(1)).unwrap(), 0);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(0)).unwrap(), 1);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(2)).unwrap(), 2);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(1)).unwrap(), 3);
-        // Check that the rows correspond to the identity operations
-        //TODO:: Reduce analysis.tab to row echelon form
-        assert_eq!(analysis.tab.stabs.get(0).x.get_boolean_vec(), vec![true, true, false, false]);
-        assert_eq!(analysis.tab.stabs.get(0).z.get_boolean_vec(), vec![false; 4]);
-        assert_eq!(analysis.tab.stabs.get(0).sign, false);
-        assert_eq!(analysis.tab.stabs.get(1).x.get_boolean_vec(), vec![false; 4]);
-        assert_eq!(analysis.tab.stabs.get(1).z.get_boolean_vec(), vec![true, true, false, false]);
-        assert_eq!(analysis.tab.stabs.get(1).sign, false);
-        assert_eq!(analysis.tab.stabs.get(2).x.get_boolean_vec(), vec![false, false, true, true]);
-        assert_eq!(analysis.tab.stabs.get(2).z.get_boolean_vec(), vec![false; 4]);
-        assert_eq!(analysis.tab.stabs.get(2).sign, false);
-        assert_eq!(analysis.tab.stabs.get(3).x.get_boolean_vec(), vec![false; 4]);
-        assert_eq!(analysis.tab.stabs.get(3).z.get_boolean_vec(), vec![false, false, true, true]);
-        assert_eq!(analysis.tab.stabs.get(3).sign, false);
+    fn apply_identity(&mut self, hugr: &H, node: H::Node) -> Result<(), DataflowError<H::Node>> {
+        // Match up the i-th Qubit input with the i-th Qubit output and move the frontier column across without touching the tableau; classical ports are irrelevant
+        let in_ports = hugr.in_value_types(node).filter(|(_, t)| *t == qb_t()).map(|(p, _)| p).collect_vec();
+        let out_ports = hugr.out_value_types(node).filter(|(_, t)| *t == qb_t()).map(|(p, _)| p).collect_vec();
+        for (in_port, out_port) in in_ports.into_iter().zip(out_ports) {
+            let col: usize = self.take_frontier(node, in_port)?;
+            self.push_frontier(hugr, node, out_port, col)?;
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_bell_state() {
-        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
-        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
-        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
-        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
-        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
-        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
-        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.module_root(), &FunctionOpacity::Opaque);
-        assert_eq!(analysis.tab.nb_qubits, 2);
-        assert_eq!(analysis.tab.nb_stabs, 2);
-        // Check that the rows correspond to the Bell state stabilizers
-        //TODO:: Reduce analysis.tab to row echelon form
-        assert_eq!(analysis.tab.stabs.get(0).x.get_boolean_vec(), vec![true; 2]);
-        assert_eq!(analysis.tab.stabs.get(0).z.get_boolean_vec(), vec![false; 2]);
-        assert_eq!(analysis.tab.stabs.get(0).sign, false);
-        assert_eq!(analysis.tab.stabs.get(1).x.get_boolean_vec(), vec![false; 2]);
-        assert_eq!(analysis.tab.stabs.get(1).z.get_boolean_vec(), vec![true; 2]);
-        assert_eq!(analysis.tab.stabs.get(1).sign, false);
+    /// Packs the inputs of a MakeTuple (or the elements of a new array) into a tuple, moving the frontier columns of its qubits (including those already packed in a nested tuple) onto the tuple wire along with the position of each in the tuple
+    fn apply_pack(&mut self, hugr: &H, node: H::Node) -> Result<(), DataflowError<H::Node>> {
+        let mut packed = vec![];
+        for (port, port_type) in hugr.in_value_types(node).collect_vec() {
+            if port_type == qb_t() {
+                packed.push((vec![port.index()], self.take_frontier(node, port)?));
+            } else if let Some(inner) = self.cols.take_packed(node, port) {
+                packed.extend(inner.into_iter().map(|(slots, col)| ([port.index()].into_iter().chain(slots).collect_vec(), col)));
+            }
+        }
+        if !packed.is_empty() {
+            self.push_packed(hugr, node, OutgoingPort::from(0), packed)?;
+        }
+        Ok(())
     }
-    
-    #[test]
-    fn test_opaque() {
-        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
-        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
-        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
-        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
-        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
-        let op = OpaqueOp::new(
-            "ext".try_into().unwrap(),
-            "op",
-            vec![],
-            Signature::new_endo(vec![qb_t()])
-        );
-        let opaque_op = builder.add_dataflow_op(OpType::OpaqueOp(op), [qb1]).unwrap();
-        let [qb1] = builder.add_dataflow_op(TketOp::H, [opaque_op.out_wire(0)]).unwrap().outputs_arr();
-        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
-        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
-        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.module_root(), &FunctionOpacity::Opaque);
-        assert_eq!(analysis.tab.nb_qubits, 4);
-        assert_eq!(analysis.tab.nb_stabs, 2);
-        // Reduce analysis.tab to row echelon form with qubit ordering [out0, op_in, op_out, out1]
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(0)).unwrap(), 0);
-        assert_eq!(*analysis.internal_in_cols.get(&(opaque_op.node(), IncomingPort::from(0))).unwrap(), 1);
-        assert_eq!(*analysis.internal_out_cols.get(&(opaque_op.node(), OutgoingPort::from(0))).unwrap(), 2);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(1)).unwrap(), 3);
-        //TODO:: Row echelon
-        // Check the rows
-        assert_eq!(analysis.tab.stabs.get(0).x.get_boolean_vec(), vec![true, false, true, true]);
-        assert_eq!(analysis.tab.stabs.get(0).z.get_boolean_vec(), vec![false; 4]);
-        assert_eq!(analysis.tab.stabs.get(0).sign, false);
-        assert_eq!(analysis.tab.stabs.get(1).x.get_boolean_vec(), vec![false; 4]);
-        assert_eq!(analysis.tab.stabs.get(1).z.get_boolean_vec(), vec![true, false, true, false]);
-        assert_eq!(analysis.tab.stabs.get(1).sign, false);
-        assert_eq!(analysis.tab.stabs.get(2).x.get_boolean_vec(), vec![false, true, false, false]);
-        assert_eq!(analysis.tab.stabs.get(2).z.get_boolean_vec(), vec![false, false, true, true]);
-        assert_eq!(analysis.tab.stabs.get(2).sign, false);
-        assert_eq!(analysis.tab.stabs.get(3).x.get_boolean_vec(), vec![false, false, false, true]);
-        assert_eq!(analysis.tab.stabs.get(3).z.get_boolean_vec(), vec![false, true, false, false]);
-        assert_eq!(analysis.tab.stabs.get(3).sign, false);
+
+    /// Unpacks a tuple with UnpackTuple (or an array with its unpack op), moving the column of each qubit packed in it onto the matching output (or the record of it onto a nested tuple)
+    /// A tuple whose qubits we did not follow in, e.g. one from an opaque op, an array op indexed at runtime or an input of the region, leaves its qubits unknown as for an opaque op
+    fn apply_unpack(&mut self, hugr: &H, node: H::Node) -> Result<(), DataflowError<H::Node>> {
+        let Some(packed) = self.cols.take_packed(node, IncomingPort::from(0)) else {
+            return self.apply_opaque(hugr, node);
+        };
+        let mut nested: HashMap<usize, Vec<(Vec<usize>, usize)>> = HashMap::default();
+        for (slots, col) in packed {
+            match slots.as_slice() {
+                [slot] => self.push_frontier(hugr, node, OutgoingPort::from(*slot), col)?,
+                [slot, rest @ ..] => nested.entry(*slot).or_default().push((rest.to_vec(), col)),
+                [] => return Err(DataflowError::UnexpectedOp { node }),
+            }
+        }
+        for (slot, inner) in nested {
+            self.push_packed(hugr, node, OutgoingPort::from(slot), inner)?;
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_clifford_gates() {
-        // Need to cover H, CX, CY, CZ, S, Sdg, X, Y, Z, V, Vdg
-        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), qb_t()])).unwrap();
-        let [qb0, qb1, qb2] = builder.input_wires_arr();
-        // H;S;V;S = I
-        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
-        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
-        let [qb0] = builder.add_dataflow_op(TketOp::V, [qb0]).unwrap().outputs_arr();
-        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
-        // CX;IH;CZ;IH = II
-        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
-        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
-        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CZ, [qb0, qb1]).unwrap().outputs_arr();
-        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
-        // CX;IS;CY;ISdg = II
-        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
-        let [qb1] = builder.add_dataflow_op(TketOp::S, [qb1]).unwrap().outputs_arr();
-        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CY, [qb0, qb1]).unwrap().outputs_arr();
-        let [qb1] = builder.add_dataflow_op(TketOp::Sdg, [qb1]).unwrap().outputs_arr();
-        // S;Sdg = II
-        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
-        let [qb0] = builder.add_dataflow_op(TketOp::Sdg, [qb0]).unwrap().outputs_arr();
-        // V;Vdh = II
-        let [qb1] = builder.add_dataflow_op(TketOp::V, [qb1]).unwrap().outputs_arr();
-        let [qb1] = builder.add_dataflow_op(TketOp::Vdg, [qb1]).unwrap().outputs_arr();
-        // Test Paulis explicitly
-        let [qb0] = builder.add_dataflow_op(TketOp::Z, [qb0]).unwrap().outputs_arr();
-        let [qb1] = builder.add_dataflow_op(TketOp::X, [qb1]).unwrap().outputs_arr();
-        let [qb2] = builder.add_dataflow_op(TketOp::Y, [qb2]).unwrap().outputs_arr();
-        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, qb2]).unwrap();
-        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.module_root(), &FunctionOpacity::Opaque);
-        assert_eq!(analysis.tab.nb_qubits, 6);
-        assert_eq!(analysis.tab.nb_stabs, 6);
-        // Reduce analysis.tab to row echelon form with qubit ordering [in0, out0, in1, out1, in2, out2]
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(0)).unwrap(), 0);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(0)).unwrap(), 1);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(1)).unwrap(), 2);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(1)).unwrap(), 3);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(2)).unwrap(), 4);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(2)).unwrap(), 5);
-        //TODO:: Row echelon
-        // Check the rows
-        assert_eq!(analysis.tab.stabs.get(0).x.get_boolean_vec(), vec![true, true, false, false, false, false]);
-        assert_eq!(analysis.tab.stabs.get(0).z.get_boolean_vec(), vec![false; 6]);
-        assert_eq!(analysis.tab.stabs.get(0).sign, true);
-        assert_eq!(analysis.tab.stabs.get(1).x.get_boolean_vec(), vec![false; 6]);
-        assert_eq!(analysis.tab.stabs.get(1).z.get_boolean_vec(), vec![true, true, false, false, false, false]);
-        assert_eq!(analysis.tab.stabs.get(1).sign, false);
-        assert_eq!(analysis.tab.stabs.get(2).x.get_boolean_vec(), vec![false, false, true, true, false, false]);
-        assert_eq!(analysis.tab.stabs.get(2).z.get_boolean_vec(), vec![false; 6]);
-        assert_eq!(analysis.tab.stabs.get(2).sign, false);
-        assert_eq!(analysis.tab.stabs.get(3).x.get_boolean_vec(), vec![false; 6]);
-        assert_eq!(analysis.tab.stabs.get(3).z.get_boolean_vec(), vec![false, false, true, true, false, false]);
-        assert_eq!(analysis.tab.stabs.get(3).sign, true);
-        assert_eq!(analysis.tab.stabs.get(4).x.get_boolean_vec(), vec![false, false, false, false, true, true]);
-        assert_eq!(analysis.tab.stabs.get(4).z.get_boolean_vec(), vec![false; 6]);
-        assert_eq!(analysis.tab.stabs.get(4).sign, true);
-        assert_eq!(analysis.tab.stabs.get(5).x.get_boolean_vec(), vec![false; 6]);
-        assert_eq!(analysis.tab.stabs.get(5).z.get_boolean_vec(), vec![false, false, false, false, true, true]);
-        assert_eq!(analysis.tab.stabs.get(5).sign, true);
+    /// Passes each qubit (or tuple of qubits) straight across a Barrier or other transparent op, from each input to the output on the same port, keeping every relation across it
+    fn apply_barrier(&mut self, hugr: &H, node: H::Node) -> Result<(), DataflowError<H::Node>> {
+        for (port, port_type) in hugr.in_value_types(node).collect_vec() {
+            let out_port = OutgoingPort::from(port.index());
+            if port_type == qb_t() {
+                let col = self.take_frontier(node, port)?;
+                self.push_frontier(hugr, node, out_port, col)?;
+            } else if let Some(packed) = self.cols.take_packed(node, port) {
+                self.push_packed(hugr, node, out_port, packed)?;
+            }
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_nonclifford() {
-        // Need to cover the separate logic for CRz, T/Tdg/Rz/Measure, Rx, Ry, Toffoli
-        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), qb_t()])).unwrap();
-        let [qb0, qb1, qb2] = builder.input_wires_arr();
-        let t = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
-        let tdg = builder.add_dataflow_op(TketOp::Tdg, [t.out_wire(0)]).unwrap();
-        let rz = builder.add_dataflow_op(TketOp::Rz, [tdg.out_wire(0)]).unwrap();
-        let meas = builder.add_dataflow_op(TketOp::Measure, [rz.out_wire(0)]).unwrap();
-        let ry = builder.add_dataflow_op(TketOp::Ry, [qb1]).unwrap();
-        let rx = builder.add_dataflow_op(TketOp::Rx, [qb2]).unwrap();
-        let crz = builder.add_dataflow_op(TketOp::CRz, [meas.out_wire(0), ry.out_wire(0)]).unwrap();
-        let toffoli = builder.add_dataflow_op(TketOp::Toffoli, [crz.out_wire(0), crz.out_wire(1), rx.out_wire(0)]).unwrap();
-        let hugr = builder.finish_hugr_with_outputs(toffoli.outputs_arr::<3>()).unwrap();
-        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.module_root(), &FunctionOpacity::Opaque);
-        assert_eq!(analysis.tab.nb_qubits, 28);
-        assert_eq!(analysis.tab.nb_stabs, 28);
-        // Reduce analysis.tab to row echelon form with qubit ordering:
-        // [in0, t.in, in1, ry.in, in2, rx.in, t.out, tdg.in, ry.out, crz.in1, rx.out, toffoli.in2,
-        // tdg.out, rz.in, rz.out, meas.in, meas.out, crz.in0, crz.out0, crz.out1, toffoli.in0, toffoli.in1
-        // toffoli.out0, toffoli.out1, toffoli.out2, out0, out1, out2]
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(0)).unwrap(), 0);
-        assert_eq!(*analysis.internal_in_cols.get(&(t.node(), IncomingPort::from(0))).unwrap(), 1);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(1)).unwrap(), 2);
-        assert_eq!(*analysis.internal_in_cols.get(&(ry.node(), IncomingPort::from(0))).unwrap(), 3);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(2)).unwrap(), 4);
-        assert_eq!(*analysis.internal_in_cols.get(&(rx.node(), IncomingPort::from(0))).unwrap(), 5);
-        assert_eq!(*analysis.internal_out_cols.get(&(t.node(), OutgoingPort::from(0))).unwrap(), 6);
-        assert_eq!(*analysis.internal_in_cols.get(&(tdg.node(), IncomingPort::from(0))).unwrap(), 7);
-        assert_eq!(*analysis.internal_out_cols.get(&(ry.node(), OutgoingPort::from(0))).unwrap(), 8);
-        assert_eq!(*analysis.internal_in_cols.get(&(crz.node(), IncomingPort::from(1))).unwrap(), 9);
-        assert_eq!(*analysis.internal_out_cols.get(&(rx.node(), OutgoingPort::from(0))).unwrap(), 10);
-        assert_eq!(*analysis.internal_in_cols.get(&(toffoli.node(), IncomingPort::from(2))).unwrap(), 11);
-        assert_eq!(*analysis.internal_out_cols.get(&(tdg.node(), OutgoingPort::from(0))).unwrap(), 12);
-        assert_eq!(*analysis.internal_in_cols.get(&(rz.node(), IncomingPort::from(0))).unwrap(), 13);
-        assert_eq!(*analysis.internal_out_cols.get(&(rz.node(), OutgoingPort::from(0))).unwrap(), 14);
-        assert_eq!(*analysis.internal_in_cols.get(&(meas.node(), IncomingPort::from(0))).unwrap(), 15);
-        assert_eq!(*analysis.internal_out_cols.get(&(meas.node(), OutgoingPort::from(0))).unwrap(), 16);
-        assert_eq!(*analysis.internal_in_cols.get(&(crz.node(), IncomingPort::from(0))).unwrap(), 17);
-        assert_eq!(*analysis.internal_out_cols.get(&(crz.node(), OutgoingPort::from(0))).unwrap(), 18);
-        assert_eq!(*analysis.internal_out_cols.get(&(crz.node(), OutgoingPort::from(1))).unwrap(), 19);
-        assert_eq!(*analysis.internal_in_cols.get(&(toffoli.node(), IncomingPort::from(0))).unwrap(), 20);
-        assert_eq!(*analysis.internal_in_cols.get(&(toffoli.node(), IncomingPort::from(1))).unwrap(), 21);
-        assert_eq!(*analysis.internal_out_cols.get(&(toffoli.node(), OutgoingPort::from(0))).unwrap(), 22);
-        assert_eq!(*analysis.internal_out_cols.get(&(toffoli.node(), OutgoingPort::from(1))).unwrap(), 23);
-        assert_eq!(*analysis.internal_out_cols.get(&(toffoli.node(), OutgoingPort::from(2))).unwrap(), 24);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(0)).unwrap(), 25);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(1)).unwrap(), 26);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(2)).unwrap(), 27);
-        //TODO:: Row echelon
-        // Check the rows
-        // Xin0
-        assert_eq!(analysis.tab.stabs.get(0).x.get_integer_vec(), vec![0b1100001100001111111010100100i128]);
-        assert_eq!(analysis.tab.stabs.get(0).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(0).sign, false);
-        // Zin0
-        assert_eq!(analysis.tab.stabs.get(1).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(1).z.get_integer_vec(), vec![0b1000000000000000000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(1).sign, false);
-        // Zt.in
-        assert_eq!(analysis.tab.stabs.get(2).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(2).z.get_integer_vec(), vec![0b0100000000000000000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(2).sign, false);
-        // Xin1
-        assert_eq!(analysis.tab.stabs.get(3).x.get_integer_vec(), vec![0b0011000011000000000101010010i128]);
-        assert_eq!(analysis.tab.stabs.get(3).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(3).sign, false);
-        // Zin1
-        assert_eq!(analysis.tab.stabs.get(4).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(4).z.get_integer_vec(), vec![0b0011000010000000000000000010i128]);
-        assert_eq!(analysis.tab.stabs.get(4).sign, false);
-        // Yry.in
-        assert_eq!(analysis.tab.stabs.get(5).x.get_integer_vec(), vec![0b0001000001000000000101010010i128]);
-        assert_eq!(analysis.tab.stabs.get(5).z.get_integer_vec(), vec![0b0001000001000000000000000000i128]);
-        assert_eq!(analysis.tab.stabs.get(5).sign, false);
-        // Xin2
-        assert_eq!(analysis.tab.stabs.get(6).x.get_integer_vec(), vec![0b0000100000000000000000000001i128]);
-        assert_eq!(analysis.tab.stabs.get(6).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(6).sign, false);
-        // Zin2
-        assert_eq!(analysis.tab.stabs.get(7).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(7).z.get_integer_vec(), vec![0b0000110000110000000000001001i128]);
-        assert_eq!(analysis.tab.stabs.get(7).sign, false);
-        // Xrx.in
-        assert_eq!(analysis.tab.stabs.get(8).x.get_integer_vec(), vec![0b0000010000000000000000000001i128]);
-        assert_eq!(analysis.tab.stabs.get(8).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(8).sign, false);
-        // Zt.out
-        assert_eq!(analysis.tab.stabs.get(9).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(9).z.get_integer_vec(), vec![0b0000001000000000000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(9).sign, false);
-        // Ztdg.in
-        assert_eq!(analysis.tab.stabs.get(10).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(10).z.get_integer_vec(), vec![0b0000000100000000000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(10).sign, false);
-        // Yry.out
-        assert_eq!(analysis.tab.stabs.get(11).x.get_integer_vec(), vec![0b0000000011000000000101010010i128]);
-        assert_eq!(analysis.tab.stabs.get(11).z.get_integer_vec(), vec![0b0000000011000000000000000000i128]);
-        assert_eq!(analysis.tab.stabs.get(11).sign, false);
-        // Zcrz.in1
-        assert_eq!(analysis.tab.stabs.get(12).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(12).z.get_integer_vec(), vec![0b0000000001000000000000000010i128]);
-        assert_eq!(analysis.tab.stabs.get(12).sign, false);
-        // Xrx.out
-        assert_eq!(analysis.tab.stabs.get(13).x.get_integer_vec(), vec![0b0000000000100000000000000001i128]);
-        assert_eq!(analysis.tab.stabs.get(13).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(13).sign, false);
-        // Xtoffoli.in2
-        assert_eq!(analysis.tab.stabs.get(14).x.get_integer_vec(), vec![0b0000000000010000000000000001i128]);
-        assert_eq!(analysis.tab.stabs.get(14).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(14).sign, false);
-        // Ztdg.out
-        assert_eq!(analysis.tab.stabs.get(15).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(15).z.get_integer_vec(), vec![0b0000000000001000000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(15).sign, false);
-        // Zrz.in
-        assert_eq!(analysis.tab.stabs.get(16).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(16).z.get_integer_vec(), vec![0b0000000000000100000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(16).sign, false);
-        // Zrz.out
-        assert_eq!(analysis.tab.stabs.get(17).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(17).z.get_integer_vec(), vec![0b0000000000000010000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(17).sign, false);
-        // Zmeas.in
-        assert_eq!(analysis.tab.stabs.get(18).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(18).z.get_integer_vec(), vec![0b0000000000000001000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(18).sign, false);
-        // Zmeas.out
-        assert_eq!(analysis.tab.stabs.get(19).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(19).z.get_integer_vec(), vec![0b0000000000000000100000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(19).sign, false);
-        // Zcrz.in0
-        assert_eq!(analysis.tab.stabs.get(20).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(20).z.get_integer_vec(), vec![0b0000000000000000010000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(20).sign, false);
-        // Zcrz.out0
-        assert_eq!(analysis.tab.stabs.get(21).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(21).z.get_integer_vec(), vec![0b0000000000000000001000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(21).sign, false);
-        // Zcrz.out1
-        assert_eq!(analysis.tab.stabs.get(22).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(22).z.get_integer_vec(), vec![0b0000000000000000000100000010i128]);
-        assert_eq!(analysis.tab.stabs.get(22).sign, false);
-        // Ztoffoli.in0
-        assert_eq!(analysis.tab.stabs.get(23).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(23).z.get_integer_vec(), vec![0b0000000000000000000010000100i128]);
-        assert_eq!(analysis.tab.stabs.get(23).sign, false);
-        // Ztoffoli.in1
-        assert_eq!(analysis.tab.stabs.get(24).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(24).z.get_integer_vec(), vec![0b0000000000000000000001000010i128]);
-        assert_eq!(analysis.tab.stabs.get(24).sign, false);
-        // Ztoffoli.out0
-        assert_eq!(analysis.tab.stabs.get(25).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(25).z.get_integer_vec(), vec![0b0000000000000000000000100100i128]);
-        assert_eq!(analysis.tab.stabs.get(25).sign, false);
-        // Ztoffoli.out1
-        assert_eq!(analysis.tab.stabs.get(26).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(26).z.get_integer_vec(), vec![0b0000000000000000000000010010i128]);
-        assert_eq!(analysis.tab.stabs.get(26).sign, false);
-        // Xtoffoli.out2
-        assert_eq!(analysis.tab.stabs.get(27).x.get_integer_vec(), vec![0b0000000000000000000000001001i128]);
-        assert_eq!(analysis.tab.stabs.get(27).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(27).sign, false);
+    /// Passes a qubit, or a tuple of qubits, through a Noop
+    fn apply_noop(&mut self, hugr: &H, node: H::Node) -> Result<(), DataflowError<H::Node>> {
+        if let Some(packed) = self.cols.take_packed(node, IncomingPort::from(0)) {
+            return self.push_packed(hugr, node, OutgoingPort::from(0), packed);
+        }
+        self.apply_identity(hugr, node)
     }
 
-    #[test]
-    fn test_mixed_clifford() {
-        // Need to cover MeasureFree, QFree and Reset
-        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![qb_t()])).unwrap();
-        let [qb0, qb1] = builder.input_wires_arr();
-        let [qb2] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
-        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
-        let [qb0] = builder.add_dataflow_op(TketOp::Reset, [qb0]).unwrap().outputs_arr();
-        let meas = builder.add_dataflow_op(TketOp::MeasureFree, [qb1]).unwrap().node();
-        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
-        let [qb0, qb2] = builder.add_dataflow_op(TketOp::CX, [qb0, qb2]).unwrap().outputs_arr();
-        builder.add_dataflow_op(TketOp::QFree, [qb2]);
-        let hugr = builder.finish_hugr_with_outputs([qb0]).unwrap();
-        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.module_root(), &FunctionOpacity::Opaque);
-        assert_eq!(analysis.tab.nb_qubits, 4);
-        // Input wires, alloc, and reset-alloc give 6 qubits/stabs
-        // Reset-free and QFree remove 2 each
-        // MeasureFree removes 1
-        assert_eq!(analysis.tab.nb_stabs, 1);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(0)).unwrap(), 0);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(1)).unwrap(), 1);
-        assert_eq!(*analysis.internal_in_cols.get(&(meas, IncomingPort::from(0))).unwrap(), 2);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(0)).unwrap(), 3);
-        assert_eq!(analysis.tab.stabs.get(0).x.get_boolean_vec(), vec![false; 6]);
-        assert_eq!(analysis.tab.stabs.get(0).z.get_boolean_vec(), vec![true, true, true, false]);
-        assert_eq!(analysis.tab.stabs.get(0).sign, false);
+    /// Applies a call to the function defined at fun_def_node according to the FunctionOpacity policy gives it
+    /// The arguments of the function are on the ports of node from in_offset onwards, e.g. after the function value of a CallIndirect
+    /// Recursive calls (those to a function already in call_stack) are treated as opaque, which is sound and guarantees termination
+    fn apply_call(&mut self, hugr: &H, node: H::Node, fun_def_node: H::Node, in_offset: usize, policy: &AnalysisConfig<H::Node>, call_stack: &[H::Node]) -> Result<(), DataflowError<H::Node>> {
+        if call_stack.contains(&fun_def_node) {
+            return self.apply_opaque(hugr, node);
+        }
+        let call_stack = call_stack.iter().copied().chain([fun_def_node]).collect_vec();
+        let mut fun_analysis = match *policy.for_function(hugr, fun_def_node) {
+            FunctionOpacity::Opaque => return self.apply_opaque(hugr, node),
+            FunctionOpacity::Boundary => match policy.summary_for(hugr, fun_def_node) {
+                Some(summary) => StabilizerDataflow::from_summary(summary, fun_def_node, policy).ok_or(DataflowError::InvalidSummary { node })?,
+                None => {
+                    let mut fun_analysis = StabilizerDataflow::run_region(hugr, fun_def_node, policy, &call_stack)?;
+                    fun_analysis.project_to_boundary();
+                    fun_analysis
+                }
+            },
+            FunctionOpacity::Inline => StabilizerDataflow::run_region(hugr, fun_def_node, policy, &call_stack)?,
+        };
+        fun_analysis.cols.shift_inputs(in_offset);
+        self.nested_analysis.insert(node, fun_analysis);
+        self.apply_analysis(hugr, node)
     }
 
-    #[test]
-    fn test_if_simple() {
-        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), bool_t()])).unwrap();
-        let [qb0, qb1, b] = builder.input_wires_arr();
-        let t = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
-        let [qb0] = t.outputs_arr();
-        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], b), [(qb_t(), qb0), (qb_t(), qb1)], vec![qb_t(); 2].into()).unwrap();
-        let mut cond0_builder = cond_builder.case_builder(0).unwrap();
-        let [c0q0, c0q1] = cond0_builder.input_wires_arr();
-        let [c0q0, c0q1] = cond0_builder.add_dataflow_op(TketOp::CX, [c0q0, c0q1]).unwrap().outputs_arr();
-        cond0_builder.finish_with_outputs([c0q0, c0q1]);
-        let cond1_builder = cond_builder.case_builder(1).unwrap();
-        let [c1c0, c1q1] = cond1_builder.input_wires_arr();
-        cond1_builder.finish_with_outputs([c1c0, c1q1]);
-        let cond = cond_builder.finish_sub_container().unwrap();
-        let [qb0, qb1] = cond.outputs_arr();
-        let tdg = builder.add_dataflow_op(TketOp::Tdg, [qb0]).unwrap();
-        let [qb0] = tdg.outputs_arr();
-        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, b]).unwrap();
-        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.module_root(), &FunctionOpacity::Opaque);
-        assert_eq!(analysis.tab.nb_qubits, 16);
-        assert_eq!(analysis.tab.nb_stabs, 14);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(0)).unwrap(), 0);
-        assert_eq!(*analysis.internal_in_cols.get(&(t.node(), IncomingPort::from(0))).unwrap(), 1);
-        assert_eq!(*analysis.in_cols.get(&OutgoingPort::from(1)).unwrap(), 2);
-        assert_eq!(*analysis.internal_in_cols.get(&(cond.node(), IncomingPort::from(1))).unwrap(), 3);
-        assert_eq!(*analysis.internal_out_cols.get(&(t.node(), OutgoingPort::from(0))).unwrap(), 4);
-        assert_eq!(*analysis.internal_in_cols.get(&(cond.node(), IncomingPort::from(0))).unwrap(), 5);
-        assert_eq!(*analysis.nested_in_cols.get(&(cond.node(), OutgoingPort::from(0))).unwrap(), 6);
-        assert_eq!(*analysis.nested_in_cols.get(&(cond.node(), OutgoingPort::from(1))).unwrap(), 7);
-        assert_eq!(*analysis.nested_out_cols.get(&(cond.node(), IncomingPort::from(0))).unwrap(), 8);
-        assert_eq!(*analysis.nested_out_cols.get(&(cond.node(), IncomingPort::from(1))).unwrap(), 9);
-        assert_eq!(*analysis.internal_out_cols.get(&(cond.node(), OutgoingPort::from(0))).unwrap(), 10);
-        assert_eq!(*analysis.internal_in_cols.get(&(tdg.node(), IncomingPort::from(0))).unwrap(), 11);
-        assert_eq!(*analysis.internal_out_cols.get(&(cond.node(), OutgoingPort::from(1))).unwrap(), 12);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(1)).unwrap(), 13);
-        assert_eq!(*analysis.internal_out_cols.get(&(tdg.node(), OutgoingPort::from(0))).unwrap(), 14);
-        assert_eq!(*analysis.out_cols.get(&IncomingPort::from(0)).unwrap(), 15);
-        //TODO:: Row echelon
-        // Zin0
-        assert_eq!(analysis.tab.stabs.get(0).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(0).z.get_integer_vec(), vec![0b1000000000000001i128]);
-        assert_eq!(analysis.tab.stabs.get(0).sign, false);
-        // Zt.in
-        assert_eq!(analysis.tab.stabs.get(1).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(1).z.get_integer_vec(), vec![0b0100000000000001i128]);
-        assert_eq!(analysis.tab.stabs.get(1).sign, false);
-        // Xin1
-        assert_eq!(analysis.tab.stabs.get(2).x.get_integer_vec(), vec![0b0010000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(2).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(2).sign, false);
-        // Xcond.in1
-        assert_eq!(analysis.tab.stabs.get(3).x.get_integer_vec(), vec![0b0001000000000100i128]);
-        assert_eq!(analysis.tab.stabs.get(3).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(3).sign, false);
-        // Zt.out
-        assert_eq!(analysis.tab.stabs.get(4).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(4).z.get_integer_vec(), vec![0b0000100000000001i128]);
-        assert_eq!(analysis.tab.stabs.get(4).sign, false);
-        // Zcond.in0
-        assert_eq!(analysis.tab.stabs.get(5).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(5).z.get_integer_vec(), vec![0b0000010000000001i128]);
-        assert_eq!(analysis.tab.stabs.get(5).sign, false);
-        // Zcond.nin0
-        assert_eq!(analysis.tab.stabs.get(6).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(6).z.get_integer_vec(), vec![0b0000001000000001i128]);
-        assert_eq!(analysis.tab.stabs.get(6).sign, false);
-        // Xcond.nin1
-        assert_eq!(analysis.tab.stabs.get(7).x.get_integer_vec(), vec![0b0000000100000100i128]);
-        assert_eq!(analysis.tab.stabs.get(7).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(7).sign, false);
-        // Zcond.nout0
-        assert_eq!(analysis.tab.stabs.get(8).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(8).z.get_integer_vec(), vec![0b0000000010000001i128]);
-        assert_eq!(analysis.tab.stabs.get(8).sign, false);
-        // Xcond.nout1
-        assert_eq!(analysis.tab.stabs.get(9).x.get_integer_vec(), vec![0b0000000001000100i128]);
-        assert_eq!(analysis.tab.stabs.get(9).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(9).sign, false);
-        // Zcond.out0
-        assert_eq!(analysis.tab.stabs.get(10).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(10).z.get_integer_vec(), vec![0b0000000000100001i128]);
-        assert_eq!(analysis.tab.stabs.get(10).sign, false);
-        // Ztdg.in
-        assert_eq!(analysis.tab.stabs.get(11).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(11).z.get_integer_vec(), vec![0b0000000000010001i128]);
-        assert_eq!(analysis.tab.stabs.get(11).sign, false);
-        // Xcond.out1
-        assert_eq!(analysis.tab.stabs.get(12).x.get_integer_vec(), vec![0b0000000000001100i128]);
-        assert_eq!(analysis.tab.stabs.get(12).z.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(12).sign, false);
-        // Ztdg.out
-        assert_eq!(analysis.tab.stabs.get(13).x.get_integer_vec(), vec![0i128]);
-        assert_eq!(analysis.tab.stabs.get(13).z.get_integer_vec(), vec![0b0000000000000011i128]);
-        assert_eq!(analysis.tab.stabs.get(13).sign, false);
+    /// Summarises the analysis over the boundary of its region, as for a call analysed with FunctionOpacity::Boundary, in a form that does not refer to the nodes of hugr
+    /// This is meant for the analysis of a function (from run_function), whose summary can then be used for calls to it through AnalysisConfig::with_summary
+    pub fn to_summary(&self, hugr: &H) -> AnalysisSummary {
+        let boundary: HashSet<usize> = self.cols.inputs().map(|(_, col)| col).chain(self.cols.outputs().map(|(_, col)| col)).collect();
+        let removed = (0..self.tab.nb_qubits).filter(|c| !boundary.contains(c)).collect_vec();
+        let mut tab = self.tab.clone();
+        tab.project(&removed.iter().flat_map(|c| [(*c, false), (*c, true)]).collect_vec());
+        tab.remove_cols(&removed);
+        let mut cols = self.cols.clone();
+        cols.remove_cols(&removed);
+        AnalysisSummary {
+            version: SUMMARY_FORMAT_VERSION,
+            content_hash: AnalysisSummary::content_hash(hugr, self.parent),
+            nb_cols: tab.nb_qubits,
+            rows: tab.stabs.iter().map(|row| row.to_string()).collect(),
+            inputs: cols.inputs().map(|(port, col)| (port.index(), col)).sorted().collect(),
+            outputs: cols.outputs().map(|(port, col)| (port.index(), col)).sorted().collect(),
+        }
     }
 
-    #[test]
-    fn test_loop_null() {
-        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), bool_t()])).unwrap();
-        let [qb0, qb1, b] = builder.input_wires_arr();
-        let reset = builder.add_dataflow_op(TketOp::Reset, [qb0]).unwrap();
-        let [qb0] = reset.outputs_arr();
-        let t = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap();
-        let [qb1] = t.outputs_arr();
-        let mut loop_builder = builder.tail_loop_builder([(bool_t(), b)], [(qb_t(), qb0), (qb_t(), qb1), (bool_t(), b)], type_row![]).unwrap();
-        let [loop_qb0, loop_qb1, loop_b] = loop_builder.input_wires_arr();
-        let loop_t = loop_builder.add_dataflow_op(TketOp::T, [loop_qb0]).unwrap();
-        let [loop_qb0] = loop_t.outputs_arr();
-        let loop_tdg = loop_builder.add_dataflow_op(TketOp::Tdg, [loop_qb1]).unwrap();
-        let [loop_qb1] = loop_tdg.outputs_arr();
-        // let loop_b = loop_builder.make_break(loop_builder.loop_signature().unwrap().clone(), []).unwrap();
-        let tl = loop_builder.finish_with_outputs(loop_b, [loop_qb0, loop_qb1, loop_b]).unwrap();
-        let [qb0, qb1, b] = tl.outputs_arr();
-        let tdg = builder.add_dataflow_op(TketOp::Tdg, [qb1]).unwrap();
-        let [qb1] = tdg.outputs_arr();
-        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, b]).unwrap();
-        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.module_root(), &FunctionOpacity::Opaque);
+    /// The analysis of the function defined at fun_def_node given by its summary, with no nested analyses or statistics, or None if the summary is malformed
+    fn from_summary(summary: &AnalysisSummary, fun_def_node: H::Node, policy: &AnalysisConfig<H::Node>) -> Option<Self> {
+        if summary.inputs.iter().chain(summary.outputs.iter()).any(|(_, col)| *col >= summary.nb_cols) {
+            return None;
+        }
+        let mut tab = summary.tableau()?;
+        if policy.provenance {
+            tab = tab.with_provenance();
+        }
+        let in_cols = summary.inputs.iter().map(|(port, col)| (OutgoingPort::from(*port), *col)).collect();
+        let out_cols = summary.outputs.iter().map(|(port, col)| (IncomingPort::from(*port), *col)).collect();
+        Some(StabilizerDataflow {
+            tab,
+            cols: ColumnAllocator::with_boundary(in_cols, out_cols),
+            ..StabilizerDataflow::empty(fun_def_node, policy)
+        })
     }
-}
\ No newline at end of file
+
+    /// Suppose we have already recursively calculated a StabilizerDataflow for node and stored it in nested_analysis; performs sequential composition to append it to the appropriate qubits here
+    fn apply_analysis(&mut self, hugr: &H, node: H::Node) -> Result<(), DataflowError<H::Node>> {
+        let node_analysis : &StabilizerDataflow<H> = self.nested_analysis.get(&node).unwrap();
+        // Place the nested tableau alongside ours; its rows are copied over with the column offset, along with their provenance in terms of our tags
+        let old_n_qbs = if self.tab.provenance.is_some() && node_analysis.tab.provenance.is_some() {
+            let mut nested_tab = node_analysis.tab.clone();
+            let nested_nodes = node_analysis.tags.clone();
+            for tags in nested_tab.provenance.iter_mut().flatten() {
+                *tags = tags.iter().map(|t| self.tag(nested_nodes[*t])).collect();
+            }
+            self.tab.tensor(&nested_tab)
+        } else {
+            self.tab.tensor(&node_analysis.tab)
+        };
+        let node_analysis : &StabilizerDataflow<H> = self.nested_analysis.get(&node).unwrap();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(name: "apply_analysis", node = ?node, offset = old_n_qbs, n_nested_cols = node_analysis.tab.nb_qubits, n_nested_rows = node_analysis.tab.nb_stabs);
+        // It is decided at analysis construction whether or not we project to IO or keep internals, so always copy any internals that have remained (e.g. for inlining function calls)
+        self.cols.absorb(node, &node_analysis.cols, old_n_qbs);
+        for (port, t) in hugr.in_value_types(node) {
+            if t == qb_t() {
+                let internal_col = self.take_frontier(node, port)?;
+                self.cols.set_node_port(vec![node], port, internal_col);
+                let nested_col = self.cols.column_for_nested_port(&[node], port).ok_or(DataflowError::UnexpectedOp { node })?;
+                self.compose_cols(internal_col, nested_col);
+            }
+        }
+        for (port, t) in hugr.out_value_types(node) {
+            if t == qb_t() {
+                let nested_col = self.cols.column_for_nested_port(&[node], port).ok_or(DataflowError::UnexpectedOp { node })?;
+                let internal_col = self.add_col();
+                self.cols.set_node_port(vec![node], port, internal_col);
+                let front_col = self.add_col();
+                self.push_frontier(hugr, node, port, front_col)?;
+                self.add_identity_rows(internal_col, front_col);
+                self.compose_cols(nested_col, internal_col);
+            }
+        }
+        Ok(())
+    }
+
+    /// The stabilizer relations between two wires of the region, with every other column eliminated
+    /// Each relation is returned as the single-qubit Paulis on a and b together with the sign of their product, in a canonical (row-reduced) form; an empty result means the wires are uncorrelated, or that one of them is not tracked by the analysis
+    /// If a and b are tracked by the same column (e.g. the same wire named by the port at either end), the result is the stabilizers of that single wire, with the Pauli on a and the identity on b
+    pub fn relation(&self, a: WireRef<H::Node>, b: WireRef<H::Node>) -> Vec<(PauliProduct, PauliProduct, bool)> {
+        let Some(cols) = self.relation_columns(&a, &b) else {
+            return vec![];
+        };
+        let single = |row: &PauliProduct, q: Option<usize>| {
+            let mut p = PauliProduct::new(BitVector::new(1), BitVector::new(1), false);
+            if let Some(q) = q {
+                p.set_z(0, row.get_z(q));
+                p.set_x(0, row.get_x(q));
+            }
+            p
+        };
+        self.relations_over(&cols).stabs.iter().map(|row| (single(row, Some(0)), single(row, (cols.len() == 2).then_some(1)), row.sign)).collect()
+    }
+
+    /// The distinct columns tracking the wires a and b, i.e. a single column if both are tracked by the same one, or None if either is untracked
+    fn relation_columns(&self, a: &WireRef<H::Node>, b: &WireRef<H::Node>) -> Option<Vec<usize>> {
+        let (col_a, col_b) = (self.cols.column(a)?, self.cols.column(b)?);
+        Some(if col_a == col_b { vec![col_a] } else { vec![col_a, col_b] })
+    }
+
+    /// The nodes responsible for the relations between two wires, in the order they were applied: those whose handling added or changed a row of the tableau that went into the relations returned by relation
+    /// Only available if the policy tracks provenance, and otherwise empty; nodes inside a nested analysis are named when it was itself analysed row by row (as for DFGs and analysed calls), while the summary of a Conditional, TailLoop or CFG is only attributed to the node itself
+    pub fn explain_relation(&self, a: WireRef<H::Node>, b: WireRef<H::Node>) -> Vec<H::Node> {
+        let Some(cols) = self.relation_columns(&a, &b) else {
+            return vec![];
+        };
+        let relations = self.relations_over(&cols);
+        let tags: BTreeSet<usize> = relations.provenance.iter().flatten().flatten().copied().collect();
+        tags.into_iter().map(|t| self.tags[t]).collect()
+    }
+
+    /// The number of qubits the node takes in and gives out, as (inputs, outputs), for any node with qubit ports applied in the region or in a nested analysis (e.g. within a Conditional or an inlined function)
+    /// Gates give the same number out as they take in, while e.g. a QAlloc gives (0, 1) and a QFree (1, 0); returns (0, 0) for nodes the analysis did not apply or that have no qubit ports
+    pub fn node_qubit_count(&self, node: H::Node) -> (usize, usize) {
+        self.qubit_counts.get(&node).copied().or_else(|| self.nested_analysis.values().map(|nested| nested.node_qubit_count(node)).find(|counts| *counts != (0, 0))).unwrap_or((0, 0))
+    }
+
+    /// The qubit counts given by node_qubit_count for every node with qubit ports applied in the region or in a nested analysis
+    pub fn all_node_qubit_counts(&self) -> HashMap<H::Node, (usize, usize)> {
+        let mut counts = HashMap::default();
+        for nested in self.nested_analysis.values() {
+            counts.extend(nested.all_node_qubit_counts());
+        }
+        counts.extend(self.qubit_counts.iter().map(|(node, count)| (*node, *count)));
+        counts
+    }
+
+    /// The parity of two bool wires of the region (each given by the port producing it) where the analysis can prove it, i.e. Some(false) if the bits are always equal and Some(true) if they always differ
+    /// Bits are tracked from the Measure and MeasureFree nodes directly within the region through Xor, Eq and Not, unless turned off by AnalysisConfig::with_classical_tracking; None is returned if either bit is untracked or their parity can vary
+    pub fn classical_relation(&self, a: (H::Node, OutgoingPort), b: (H::Node, OutgoingPort)) -> Option<bool> {
+        let (bit_a, bit_b) = (self.bits.get(&a)?, self.bits.get(&b)?);
+        let cols = bit_a.measurements.symmetric_difference(&bit_b.measurements).map(|m| self.cols.column_for_node_port(&[*m], IncomingPort::from(0))).collect::<Option<Vec<_>>>()?;
+        // +Z over the columns means the outcomes have even parity
+        [false, true].into_iter().find(|sign| self.tab.stabilizes(&self.tab.pauli(&cols, &[], *sign))).map(|sign| sign ^ bit_a.flip ^ bit_b.flip)
+    }
+
+    /// The stabilizer relations across the qubit ports of the node at path, with every other column eliminated
+    /// Returns None if the analysis tracks no internal columns for the node, i.e. if it is a Clifford gate that was applied directly to the tableau
+    pub fn relations_for_node(&self, path: &[H::Node]) -> Option<NodeRelationSummary<H::Node>> {
+        let ports = self.cols.node_ports(path);
+        if ports.is_empty() {
+            return None;
+        }
+        let tab = self.relations_over(&ports.iter().map(|(_, col)| *col).collect_vec());
+        Some(NodeRelationSummary {
+            node: path.to_vec(),
+            ports: ports.into_iter().map(|(port, _)| port).collect(),
+            relations: tab.stabs,
+        })
+    }
+
+    /// Restricts the tableau to the given columns (renumbered in the order given) and puts the remaining rows in reduced row echelon form
+    pub(crate) fn relations_over(&self, cols: &[usize]) -> ChoiTableau {
+        let unified_index: HashMap<usize, usize> = cols.iter().enumerate().map(|(k, col)| (*col, k)).collect();
+        let mut tab = restrict_tab(&self.tab, &unified_index);
+        tab.echelon(&(0..cols.len()).flat_map(|k| [(k, false), (k, true)]).collect_vec());
+        tab
+    }
+
+    /// Describes the wire each column of the tableau is tracking
+    /// Intended for debugging, e.g. to interpret the columns of a tableau printed in an error message
+    pub fn column_to_wire(&self) -> HashMap<usize, String> {
+        self.cols.column_to_wire()
+    }
+
+    /// Renders the region analysed as a Graphviz digraph, for debugging and figures
+    /// Each qubit wire is labelled with the columns tracking it, nodes with internal columns (the non-Clifford ones) are boxed with their in and out columns, and a legend beside the Output lists the stabilizers over all columns in reduced row echelon form, sorted, as Pauli strings indexed by column
+    /// Nodes are numbered in the order of the children of the region, so the output is deterministic for a given hugr
+    pub fn to_dot(&self, hugr: &H) -> String {
+        let nodes = hugr.children(self.parent).collect_vec();
+        let index: HashMap<H::Node, usize> = nodes.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+        let mut dot = String::from("digraph analysis {\n");
+        for (i, node) in nodes.iter().enumerate() {
+            let label = match hugr.get_optype(*node) {
+                OpType::ExtensionOp(op) => op.unqualified_id().to_string(),
+                OpType::OpaqueOp(op) => op.unqualified_id().to_string(),
+                // The Debug output of other ops starts with the name of their variant
+                optype => format!("{optype:?}").split(['(', ' ']).next().unwrap_or_default().to_string(),
+            };
+            let ports = self.cols.node_ports(&[*node]);
+            if ports.is_empty() {
+                dot.push_str(&format!("  n{i} [label=\"{label}\"];\n"));
+            } else {
+                let cols = |incoming: bool| ports.iter().filter(|(p, _)| p.as_incoming().is_ok() == incoming).map(|(p, col)| (p.index(), *col)).sorted().map(|(_, col)| col).join(" ");
+                dot.push_str(&format!("  n{i} [label=\"{label}\\nin: {}\\nout: {}\", shape=box];\n", cols(true), cols(false)));
+            }
+        }
+        for (i, node) in nodes.iter().enumerate() {
+            for (port, typ) in hugr.out_value_types(*node) {
+                for (target, target_port) in hugr.linked_inputs(*node, port) {
+                    let Some(j) = index.get(&target) else {
+                        continue;
+                    };
+                    if typ != qb_t() {
+                        dot.push_str(&format!("  n{i} -> n{j} [style=dashed];\n"));
+                        continue;
+                    }
+                    let from = match hugr.get_optype(*node) {
+                        OpType::Input(_) => self.cols.column_for_input(port),
+                        _ => self.cols.column_for_node_port(&[*node], port),
+                    };
+                    let to = match hugr.get_optype(target) {
+                        OpType::Output(_) => self.cols.column_for_output(target_port),
+                        _ => self.cols.column_for_node_port(&[target], target_port),
+                    };
+                    let cols = [from, to].into_iter().flatten().unique().collect_vec();
+                    if cols.is_empty() {
+                        dot.push_str(&format!("  n{i} -> n{j};\n"));
+                    } else {
+                        dot.push_str(&format!("  n{i} -> n{j} [label=\"{}\"];\n", cols.iter().join("=")));
+                    }
+                }
+            }
+        }
+        let stabilizers = self.tab.canonical_rows().iter().map(|row| row.to_string()).sorted().collect_vec();
+        dot.push_str(&format!("  legend [label=\"{}\\l\", shape=note];\n", stabilizers.join("\\l")));
+        if let Some(out) = nodes.iter().position(|n| matches!(hugr.get_optype(*n), OpType::Output(_))) {
+            dot.push_str(&format!("  {{ rank=same; n{out}; legend; }}\n"));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The first and last node acting on each input qubit of a region analysed by run_dfg, keyed by the input column of the qubit
+    /// The qubit is followed along its wire, passing through each node from its k-th qubit input to its k-th qubit output (as for every TketOp), until the wire reaches the Output node or a node with no such output, e.g. a QFree or a MakeTuple
+    /// Input qubits wired straight to the Output have no entry, nor do qubits allocated within the region, as no column identifies them once the analysis is done
+    pub fn qubit_lifetimes(&self, hugr: &H) -> HashMap<usize, (H::Node, H::Node)> {
+        let mut lifetimes = HashMap::new();
+        let Some([inp, _]) = hugr.get_io(self.parent) else {
+            return lifetimes;
+        };
+        for (port, col) in self.cols.inputs() {
+            let mut next = hugr.single_linked_input(inp, port);
+            while let Some((node, in_port)) = next.filter(|(node, _)| !matches!(hugr.get_optype(*node), OpType::Output(_))) {
+                lifetimes.entry(col).and_modify(|(_, last)| *last = node).or_insert((node, node));
+                let k = hugr.in_value_types(node).filter(|(_, t)| *t == qb_t()).position(|(p, _)| p == in_port);
+                let out_port = k.and_then(|k| hugr.out_value_types(node).filter(|(_, t)| *t == qb_t()).nth(k)).map(|(p, _)| p);
+                next = out_port.and_then(|p| hugr.single_linked_input(node, p));
+            }
+        }
+        lifetimes
+    }
+
+    /// The unitary tableau of node over its qubits in port order, if it is a Clifford gate: one of the Clifford TketOps, or an Rz or CRz by a constant multiple of a quarter turn (up to global phase), as the analysis applies in place without internal columns
+    /// Other nodes give None, including non-Clifford gates, measurements, allocations and anything not a TketOp
+    pub fn gate_clifford_tableau(hugr: &H, node: H::Node) -> Option<Tableau> {
+        let op = hugr.get_optype(node).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok())?;
+        let gates = match op {
+            TketOp::H | TketOp::S | TketOp::Sdg | TketOp::V | TketOp::Vdg | TketOp::X | TketOp::Y | TketOp::Z => vec![(op, vec![0])],
+            TketOp::CX | TketOp::CZ => vec![(op, vec![0, 1])],
+            // As applied by apply_quantum_gate, CY is CX conjugated by S on the target
+            TketOp::CY => vec![(TketOp::Sdg, vec![1]), (TketOp::CX, vec![0, 1]), (TketOp::S, vec![1])],
+            TketOp::Rz | TketOp::CRz => clifford_rotation(hugr, node, op)?,
+            _ => return None,
+        };
+        let mut tab = Tableau::new(hugr.in_value_types(node).filter(|(_, t)| *t == qb_t()).count());
+        for (gate, qubits) in gates {
+            append_clifford(&mut tab, gate, &qubits);
+        }
+        Some(tab)
+    }
+
+    /// Checks whether two analysed regions implement the same Clifford channel between their inputs and outputs, i.e. whether they have the same stabilizer group over the boundary qubits
+    /// Qubits are matched up by port, so the regions must have qubits on the same input and output ports; relations lost to non-Clifford gates are lost from both, so the comparison is only up to the non-Clifford phases the analysis cannot see
+    pub fn check_clifford_equivalence(a: &StabilizerDataflow<H>, b: &StabilizerDataflow<H>) -> bool {
+        let in_ports = |analysis: &StabilizerDataflow<H>| analysis.cols.inputs().map(|(p, _)| p).collect_vec();
+        let out_ports = |analysis: &StabilizerDataflow<H>| analysis.cols.outputs().map(|(p, _)| p).collect_vec();
+        if in_ports(a) != in_ports(b) || out_ports(a) != out_ports(b) {
+            return false;
+        }
+        a.boundary_tab().equivalent(&b.boundary_tab())
+    }
+
+    /// Checks whether the regions under root_a and root_b, which must be Clifford circuits, implement the same unitary up to a global phase, analysing calls within them inline
+    /// Qubits are matched up in port order; unlike check_clifford_equivalence, the regions must each relate their inputs and outputs completely, so a difference can always be reported as a Pauli layer or a stabilizer of one region that the other lacks
+    pub fn clifford_equivalence(hugr_a: &H, root_a: H::Node, hugr_b: &H, root_b: H::Node) -> Result<Equivalence, NonCliffordError<H::Node>> {
+        let a = StabilizerDataflow::run_dfg(hugr_a, root_a, &FunctionOpacity::Inline)?;
+        let b = StabilizerDataflow::run_dfg(hugr_b, root_b, &FunctionOpacity::Inline)?;
+        let (n_a, n_b) = (a.cols.inputs().count(), b.cols.inputs().count());
+        if n_a != n_b {
+            return Err(NonCliffordError::QubitCountMismatch { a: n_a, b: n_b });
+        }
+        let mut boundaries = vec![];
+        for (analysis, root) in [(&a, root_a), (&b, root_b)] {
+            let boundary = analysis.boundary_tab();
+            // A unitary relates every input to the outputs, leaving no relation over the inputs or the outputs alone
+            let ins: HashMap<usize, usize> = (0..n_a).map(|k| (k, k)).collect();
+            let outs: HashMap<usize, usize> = (0..n_a).map(|k| (n_a + k, k)).collect();
+            if analysis.cols.outputs().count() != n_a || boundary.nb_stabs != 2*n_a || restrict_tab(&boundary, &ins).nb_stabs != 0 || restrict_tab(&boundary, &outs).nb_stabs != 0 {
+                return Err(NonCliffordError::NotClifford { root });
+            }
+            boundaries.push(boundary);
+        }
+        let [mut tab_a, tab_b] = boundaries.try_into().unwrap();
+        tab_a.echelon(&(0..2*n_a).flat_map(|k| [(k, false), (k, true)]).collect_vec());
+        for row in tab_a.stabs.iter() {
+            let mut negated = row.clone();
+            negated.sign = !negated.sign;
+            if !tab_b.stabilizes(row) && !tab_b.stabilizes(&negated) {
+                return Ok(Equivalence::Different(row.clone()));
+            }
+        }
+        if tab_a.equivalent(&tab_b) {
+            return Ok(Equivalence::Equal);
+        }
+        // The groups agree up to signs, which a Pauli on the outputs of a unitary can always correct
+        let out_cols = (n_a..2*n_a).collect_vec();
+        let correction = pauli_correction(&tab_a, &tab_b, &out_cols).expect("Signs of a unitary are corrected by a Pauli on its outputs");
+        let layer = ChoiTableau::new(n_a);
+        let on = |get: fn(&PauliProduct, usize) -> bool| (0..n_a).filter(|k| get(&correction, n_a + k)).collect_vec();
+        Ok(Equivalence::UpToPauli(layer.pauli(&on(PauliProduct::get_z), &on(PauliProduct::get_x), false)))
+    }
+
+    /// The tableau restricted to the inputs followed by the outputs, both in port order
+    fn boundary_tab(&self) -> ChoiTableau {
+        let ins = self.cols.inputs().map(|(_, col)| col);
+        let outs = self.cols.outputs().map(|(_, col)| col);
+        let unified_index: HashMap<usize, usize> = ins.chain(outs).enumerate().map(|(k, col)| (col, k)).collect();
+        restrict_tab(&self.tab, &unified_index)
+    }
+
+    /// Extracts the phase polynomial of the region, mapping each parity of input qubits (as the sorted list of their input columns) to the total angle in half-turns of the Z rotations applied to it
+    /// A rotation contributes when the analysis relates Z on its input to a product of Zs on the input qubits, as for every rotation in a circuit of CX and diagonal gates
+    /// A rotation acting on a negated parity contributes its negated angle, which is equal up to global phase; rotations whose angle is not known statically (Rz) or whose parity cannot be expressed over the inputs (e.g. after an H) are omitted
+    pub fn phase_polynomial(&self, hugr: &H) -> HashMap<Vec<usize>, f64> {
+        let in_cols: HashSet<usize> = self.cols.inputs().map(|(_, col)| col).collect();
+        let mut polynomial: HashMap<Vec<usize>, f64> = HashMap::new();
+        for (path, _, col) in self.cols.internal_inputs() {
+            let angle = match hugr.get_optype(*path.last().unwrap()).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok()) {
+                Some(TketOp::T) => 0.25,
+                Some(TketOp::Tdg) => -0.25,
+                _ => continue,
+            };
+            // Keep only the rows made of Zs over the input columns and the rotation's input column
+            let mut tab = self.tab.clone();
+            let cols = (0..tab.nb_qubits).flat_map(|c| {
+                if c == col || in_cols.contains(&c) { vec![(c, true)] } else { vec![(c, false), (c, true)] }
+            }).collect_vec();
+            tab.project(&cols);
+            if tab.echelon(&[(col, false)]) == 0 {
+                continue;
+            }
+            let parity = tab.stabs[0].z.get_all_ones(tab.nb_qubits).into_iter().filter(|c| *c != col).collect_vec();
+            let angle = if tab.stabs[0].sign { -angle } else { angle };
+            *polynomial.entry(parity).or_insert(0.) += angle;
+        }
+        // Angles are periodic, so drop any terms that cancel out
+        polynomial.retain(|_, angle| {
+            *angle = angle.rem_euclid(2.);
+            *angle != 0.
+        });
+        polynomial
+    }
+
+    /// A lower bound on the number of T gates in any Clifford+T circuit (with ancillas) implementing the region, from the stabilizer nullity of its rotations
+    /// Each T, Tdg and Rz by a constant multiple of an eighth turn is a rotation about the Pauli over the input qubits that the analysis relates Z on its input to; rotations about the same Pauli are merged, keeping those by an odd number of eighth turns
+    /// When these axes all commute, a Clifford takes them to Z parities, so up to Cliffords the region is a diagonal phase polynomial D and the nullity of D|+…+> bounds its T-count, as each T gate raises the nullity of a state by at most one
+    /// Merely counting the rotations, or the rank of their axes, is not a lower bound: T;T is an S gate, and the 15 T gates on every parity of four qubits multiply to the identity
+    /// Any other node with internal columns (opaque, hierarchical or non-unitary), a rotation by another angle, axes that do not commute or are not related to the inputs, or more than MAX_NULLITY_RANK independent axes give the trivial bound of 0
+    pub fn t_count_lower_bound(&self, hugr: &H) -> usize {
+        let inputs = self.cols.inputs().map(|(_, col)| col).collect_vec();
+        let in_cols: HashSet<usize> = inputs.iter().copied().collect();
+        // Whether an odd number of odd rotations act about each axis, given by its Z then X components on the inputs in port order
+        let mut axes: HashMap<Vec<bool>, bool> = HashMap::new();
+        for (path, _, col) in self.cols.internal_inputs() {
+            let node = *path.last().unwrap();
+            let eighth_turns = match hugr.get_optype(node).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok()) {
+                Some(TketOp::T | TketOp::Tdg) => 1.,
+                Some(TketOp::Rz) => match constant_half_turns(hugr, node, IncomingPort::from(1)) {
+                    Some(half_turns) => 4. * half_turns,
+                    None => return 0,
+                },
+                _ => return 0,
+            };
+            if (eighth_turns - eighth_turns.round()).abs() > CLIFFORD_ANGLE_TOLERANCE {
+                return 0;
+            }
+            if (eighth_turns.round() as i64).rem_euclid(2) == 0 {
+                continue;
+            }
+            // Keep only the rows made of Z on the rotation's input column and any Paulis over the input columns
+            let mut tab = self.tab.clone();
+            let cols = (0..tab.nb_qubits).flat_map(|c| {
+                if c == col { vec![(c, true)] } else if in_cols.contains(&c) { vec![] } else { vec![(c, false), (c, true)] }
+            }).collect_vec();
+            tab.project(&cols);
+            if tab.echelon(&[(col, false)]) == 0 {
+                return 0;
+            }
+            let axis = inputs.iter().map(|c| tab.stabs[0].z.get(*c)).chain(inputs.iter().map(|c| tab.stabs[0].x.get(*c))).collect_vec();
+            *axes.entry(axis).or_insert(false) ^= true;
+        }
+        // Rotations about the identity (e.g. on a freshly allocated qubit) are just a global phase
+        let axes = axes.into_iter().filter(|(axis, odd)| *odd && axis.contains(&true)).map(|(axis, _)| axis).collect_vec();
+        let n = inputs.len();
+        let commute = |a: &Vec<bool>, b: &Vec<bool>| (0..n).filter(|i| (a[*i] && b[n + i]) != (a[n + i] && b[*i])).count() % 2 == 0;
+        if !axes.iter().tuple_combinations().all(|(a, b)| commute(a, b)) {
+            return 0;
+        }
+        stabilizer_nullity(&axes).unwrap_or(0)
+    }
+
+    /// Finds every pair of rotations (T, Tdg, Rz or CRz) whose axes the analysis relates, i.e. where Z after the first rotation is a stabilizer-equivalent of ±Z before the second
+    /// Such a pair can be merged into a single rotation, since everything between them maps the axis of one onto the other; the first of each pair is the one whose input column was allocated first
+    /// Single-qubit rotations only pair with each other, and CRz only with CRz when the controls are related with a positive sign (flipping the control of CRz does not simply negate its angle)
+    /// Two-qubit interactions from the InteractionRegistry (e.g. ZZPhase) pair with interactions about the same axis
+    pub fn mergeable_rotations(&self, hugr: &H) -> Vec<MergeCandidate<H::Node>> {
+        let rotation_op = |path: &NodePath<H::Node>| match hugr.get_optype(*path.last().unwrap()).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok()) {
+            Some(op @ (TketOp::T | TketOp::Tdg | TketOp::Rz | TketOp::CRz)) => Some(op),
+            _ => None,
+        };
+        let rotations = self.cols.internal_inputs()
+            .filter(|(path, port, _)| port.index() == 0 && rotation_op(path).is_some())
+            .sorted_by_key(|(_, _, col)| *col)
+            .map(|(path, _, _)| path.clone())
+            .collect_vec();
+        let mut candidates = vec![];
+        for (first, second) in rotations.iter().tuple_combinations() {
+            let nb_qubits = match (rotation_op(first) == Some(TketOp::CRz), rotation_op(second) == Some(TketOp::CRz)) {
+                (false, false) => 1,
+                (true, true) => 2,
+                _ => continue,
+            };
+            let mut relation = self.tab.pauli(&[], &[], false);
+            let related = (0..nb_qubits).all(|q| {
+                // Either column may have been eliminated by gc_internal_columns, leaving nothing to relate
+                let (Some(col_out), Some(col_in)) = (self.cols.column_for_node_port(first, OutgoingPort::from(q)), self.cols.column_for_node_port(second, IncomingPort::from(q))) else {
+                    return false;
+                };
+                let Some(p) = [false, true].into_iter().map(|sign| self.tab.pauli(&[col_out, col_in], &[], sign)).find(|p| self.tab.stabilizes(p)) else {
+                    return false;
+                };
+                // The control of a CRz (port 0) must be related with a positive sign
+                if nb_qubits == 2 && q == 0 && p.sign {
+                    return false;
+                }
+                relation.pauli_product_mult(&p);
+                true
+            });
+            if related {
+                candidates.push(MergeCandidate { first: first.clone(), second: second.clone(), relation });
+            }
+        }
+        // Interactions pair with those about the same axis, when P⊗P after the first relates to ±P⊗P before the second (in either order of the qubits, as P⊗P is symmetric)
+        let interaction_axis = |path: &NodePath<H::Node>| self.interactions.for_node(hugr, *path.last().unwrap()).map(|interaction| interaction.axis);
+        let interactions = self.cols.internal_inputs()
+            .filter(|(path, port, _)| port.index() == 0 && interaction_axis(path).is_some())
+            .sorted_by_key(|(_, _, col)| *col)
+            .map(|(path, _, _)| path.clone())
+            .collect_vec();
+        for (first, second) in interactions.iter().tuple_combinations() {
+            let axis = interaction_axis(first);
+            if axis != interaction_axis(second) {
+                continue;
+            }
+            let Some(cols) = (0..2).map(|q| self.cols.column_for_node_port(first, OutgoingPort::from(q)))
+                .chain((0..2).map(|q| self.cols.column_for_node_port(second, IncomingPort::from(q))))
+                .collect::<Option<Vec<_>>>() else {
+                continue;
+            };
+            let relation = [false, true].into_iter().map(|sign| match axis {
+                Some(InteractionAxis::XX) => self.tab.pauli(&[], &cols, sign),
+                _ => self.tab.pauli(&cols, &[], sign),
+            }).find(|p| self.tab.stabilizes(p));
+            if let Some(relation) = relation {
+                candidates.push(MergeCandidate { first: first.clone(), second: second.clone(), relation });
+            }
+        }
+        candidates
+    }
+
+    /// The stabilizers of the state on the output qubits when every input qubit starts in |0>, as Pauli strings over the outputs in port order
+    /// We fix each input column to |0> and then project out every column other than the outputs; just picking out the rows already acting only on the outputs would miss products of rows that cancel on the other columns
+    pub fn output_state_stabilizers(&self) -> Vec<PauliProduct> {
+        let mut tab = self.tab.clone();
+        for (_, col) in self.cols.inputs() {
+            let z = tab.pauli(&[col], &[], false);
+            tab.project_commuting_with(&z);
+            tab.add_row(z);
+        }
+        let unified_index: HashMap<usize, usize> = self.cols.outputs().enumerate().map(|(k, (_, col))| (col, k)).collect();
+        restrict_tab(&tab, &unified_index).stabs
+    }
+
+    /// Finds the Clifford gates directly in the region that act as the identity (up to a global phase) on every state that can reach them, so can be deleted
+    /// We walk the region again with a forward stabilizer state, purified so that it is always pure: input qubits start in a Bell pair with a reference column (so are unconstrained), allocations start in |0>, and the qubits out of any other node are fresh Bell pairs, with the columns going into it left behind
+    /// A gate is then dead exactly when applying it leaves the stabilizer group of the state unchanged; only gates the analysis applied as Cliffords (i.e. without internal columns) are considered
+    /// Fails if a qubit wire into a node of the region does not come from a node walked before it, e.g. because it crosses the region boundary
+    pub fn find_dead_gates(&self, hugr: &H) -> Result<Vec<H::Node>, DataflowError<H::Node>> {
+        let mut state = ChoiTableau::new(0);
+        let mut frontier: HashMap<(H::Node, IncomingPort), usize> = HashMap::default();
+        let mut dead = vec![];
+        let (region, node_map) = hugr.region_portgraph(self.parent);
+        let mut topo = pv::Topo::new(&region);
+        while let Some(pgnode) = topo.next(&region) {
+            let node = node_map.from_portgraph(pgnode);
+            let in_cols = hugr.in_value_types(node).filter(|(_, t)| *t == qb_t()).map(|(port, _)| frontier.remove(&(node, port)).ok_or(DataflowError::InconsistentFrontier { node, port })).collect::<Result<Vec<_>, _>>()?;
+            let out_ports = hugr.out_value_types(node).filter(|(_, t)| *t == qb_t()).map(|(p, _)| p).collect_vec();
+            let op = hugr.get_optype(node).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok());
+            let mut next = state.clone();
+            let out_cols = if matches!(op, Some(TketOp::QAlloc | TketOp::Reset)) {
+                let col = state.add_col();
+                state.add_row(state.pauli(&[col], &[], false));
+                vec![col]
+            } else if op.is_some_and(|op| self.cols.node_ports(&[node]).is_empty() && append_clifford(&mut next, op, &in_cols)) {
+                if next.equivalent(&state) {
+                    dead.push(node);
+                } else {
+                    state = next;
+                }
+                in_cols
+            } else {
+                out_ports.iter().map(|_| {
+                    let reference = state.add_col();
+                    let col = state.add_col();
+                    state.add_row(state.pauli(&[], &[reference, col], false));
+                    state.add_row(state.pauli(&[reference, col], &[], false));
+                    col
+                }).collect_vec()
+            };
+            for (port, col) in out_ports.into_iter().zip(out_cols) {
+                if let Some(next) = hugr.single_linked_input(node, port) {
+                    frontier.insert(next, col);
+                }
+            }
+        }
+        Ok(dead)
+    }
+
+    /// Follows row row_index of the tableau back from the Output through the gates of the region, giving the Pauli the generator is on the wires just after each node it passes through, starting with the last
+    /// Only the part of the row on the output columns is traced, as a Pauli over the qubit outputs in port order; going back through a Clifford gate conjugates it by the gate, while the wires of any other node (an allocation, the Input, a non-Clifford gate or a nested region) end there, keeping the Pauli they had
+    /// Nodes are given in reverse topological order, and only those with a traced wire out of them; panics if row_index is not a row of the tableau
+    pub fn trace_row(&self, hugr: &H, row_index: usize) -> Vec<(H::Node, PauliProduct)> {
+        let row = &self.tab.stabs[row_index];
+        let Some([_, out]) = hugr.get_io(self.parent) else {
+            return vec![];
+        };
+        let out_cols = hugr.in_value_types(out).filter(|(_, t)| *t == qb_t()).map(|(p, _)| (p, self.cols.column_for_output(p))).collect_vec();
+        let mut wires: HashMap<(H::Node, OutgoingPort), usize> = HashMap::default();
+        for (slot, (port, _)) in out_cols.iter().enumerate() {
+            if let Some(source) = hugr.single_linked_output(out, *port) {
+                wires.insert(source, slot);
+            }
+        }
+        // A single row tableau over the slots, conjugated in place
+        let slots_with = |get: fn(&PauliProduct, usize) -> bool| out_cols.iter().enumerate().filter(|(_, (_, col))| col.is_some_and(|c| get(row, c))).map(|(slot, _)| slot).collect_vec();
+        let mut pauli = ChoiTableau::new(out_cols.len());
+        pauli.add_row(pauli.pauli(&slots_with(PauliProduct::get_z), &slots_with(PauliProduct::get_x), row.sign));
+        let (region, node_map) = hugr.region_portgraph(self.parent);
+        let mut topo = pv::Topo::new(&region);
+        let mut order = vec![];
+        while let Some(pgnode) = topo.next(&region) {
+            order.push(node_map.from_portgraph(pgnode));
+        }
+        let mut trace = vec![];
+        for node in order.into_iter().rev() {
+            let slots = hugr.out_value_types(node).filter(|(_, t)| *t == qb_t()).map(|(p, _)| wires.remove(&(node, p))).collect_vec();
+            if slots.iter().all(Option::is_none) {
+                continue;
+            }
+            trace.push((node, pauli.stabs[0].clone()));
+            let op = hugr.get_optype(node).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok());
+            // The generator before the gate G is G†PG, i.e. P conjugated by the inverse of G
+            let inverse = op.map(Tableau::inverse_gate);
+            let Some(slots) = slots.into_iter().collect::<Option<Vec<usize>>>() else {
+                continue;
+            };
+            if self.cols.node_ports(&[node]).is_empty() && inverse.is_some_and(|op| append_clifford(&mut pauli, op, &slots)) {
+                for ((port, _), slot) in hugr.in_value_types(node).filter(|(_, t)| *t == qb_t()).zip(slots) {
+                    if let Some(source) = hugr.single_linked_output(node, port) {
+                        wires.insert(source, slot);
+                    }
+                }
+            }
+        }
+        trace
+    }
+
+    /// Checks the internal invariants of the analysis and every nested one, as a debugging aid, describing each violation found
+    /// Every column in the column maps must be in range for the tableau and no column may be both on the frontier and the internal input of a node; the tableau must have as many rows as it claims, none acting beyond its columns, and they must generate a valid stabilizer group
+    /// The number of rows is not compared with the number of columns, as relations lost to non-Clifford gates or projections leave fewer rows than columns
+    pub fn verify_consistency(&self) -> Result<(), Vec<String>> {
+        let mut errors = self.cols.consistency_errors(self.tab.nb_qubits);
+        if self.tab.stabs.len() != self.tab.nb_stabs {
+            errors.push(format!("The tableau has {} rows but records {}", self.tab.stabs.len(), self.tab.nb_stabs));
+        }
+        for (r, row) in self.tab.stabs.iter().enumerate() {
+            if let Some(col) = (self.tab.nb_qubits..row.z.nb_bits().max(row.x.nb_bits())).find(|c| row.z.get(*c) || row.x.get(*c)) {
+                errors.push(format!("Row {r} acts on column {col}, beyond the {} columns of the tableau", self.tab.nb_qubits));
+            }
+        }
+        // The group is only checked when the row count is right, as the tableau's own operations index the rows up to the count it records
+        if self.tab.stabs.len() == self.tab.nb_stabs && !self.tab.is_valid_stabilizer_group() {
+            errors.push("The rows of the tableau do not generate a valid stabilizer group".to_string());
+        }
+        for (node, nested) in self.nested_analysis.iter() {
+            if let Err(nested_errors) = nested.verify_consistency() {
+                errors.extend(nested_errors.into_iter().map(|e| format!("In the nested analysis of {node:?}: {e}")));
+            }
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Summarises the non-Clifford content of the region and the work done analysing it, over this analysis and every nested one
+    pub fn statistics(&self, hugr: &H) -> AnalysisStats {
+        AnalysisStats { mergeable_pairs: self.mergeable_rotations(hugr).len(), ..self.traversal_stats() }
+    }
+
+    /// Where the analysis lost information, over this analysis and every nested one: the rows dropped while applying each node and the relations unknown across each opaque node
+    /// Rows dropped when summarising a nested region over its boundary, or by gc_internal_columns, are not charged to any node
+    pub fn report(&self) -> DataflowReport<H::Node> {
+        let mut report = self.report.clone();
+        for (node, nested) in self.nested_analysis.iter() {
+            report.absorb(*node, nested.report());
+        }
+        report
+    }
+
+    /// Eliminates the internal and nested columns whose role keep rejects, keeping exactly the relations among the remaining columns (those stabilizers acting trivially on the eliminated ones), and returns how many columns were removed
+    /// No node acts on an internal column after the node it belongs to, so eliminating one midway through the analysis gives the same relations on the other columns as eliminating it at the end; the input, output and frontier columns are always kept
+    /// Queries on the eliminated columns lose their answers, e.g. mergeable_rotations and relations_for_node no longer see a node whose columns went, and a Conditional can no longer be corrected by a Pauli frame controlled on a measurement whose internal input went, falling back to the join of its cases
+    pub fn gc_internal_columns(&mut self, keep: &dyn Fn(ColumnRole) -> bool) -> usize {
+        let collectable = |role: ColumnRole| matches!(role, ColumnRole::InternalIn | ColumnRole::InternalOut | ColumnRole::NestedIn | ColumnRole::NestedOut) && !keep(role);
+        let cols = (0..self.tab.nb_qubits).filter(|col| self.cols.role(*col).is_some_and(collectable)).collect_vec();
+        self.project(&cols.iter().flat_map(|col| [(*col, false), (*col, true)]).collect_vec());
+        self.remove_cols(&cols);
+        cols.len()
+    }
+
+    /// The counters gathered while applying nodes, summed with those of the nested analyses
+    fn traversal_stats(&self) -> AnalysisStats {
+        let mut stats = self.stats.clone();
+        for nested in self.nested_analysis.values() {
+            let nested_stats = nested.traversal_stats();
+            stats.t_count += nested_stats.t_count;
+            stats.rz_count += nested_stats.rz_count;
+            stats.crz_count += nested_stats.crz_count;
+            stats.toffoli_count += nested_stats.toffoli_count;
+            stats.opaque_nodes += nested_stats.opaque_nodes;
+            stats.columns_allocated += nested_stats.columns_allocated;
+            stats.rows_projected += nested_stats.rows_projected;
+            stats.nested_analyses += nested_stats.nested_analyses + 1;
+            stats.truncated_nodes += nested_stats.truncated_nodes;
+        }
+        stats
+    }
+
+    /// Builds the dependencies between the non-Clifford (and opaque or hierarchical) nodes, i.e. those with internal input columns, each identified by its path
+    /// There is an edge from A to B when A is applied before B and some stabilizer of the tableau, in reduced row echelon form, involves both A's output columns and B's input columns, as when a wire from A reaches B through Clifford gates
+    /// The columns of diagonal gates only pass X on as a parity with the columns further along the wire, so A and B need not be related over their columns alone for such a row to exist
+    /// Edges always point from the earlier node to the later, so the graph is acyclic; it is not transitively reduced, so e.g. T;T;T on a wire has an edge from the first T to the third as well
+    pub fn non_clifford_dependency_graph(&self) -> Graph<NodePath<H::Node>, ()> {
+        // Columns are only ever allocated (or renumbered in order), and a node's output columns are allocated when it is applied, after its input columns; so the largest column of a node orders it after every node it depends on
+        let nodes = self.cols.internal_inputs().map(|(path, _, _)| path.clone()).unique().map(|path| {
+            let ports = self.cols.node_ports(&path);
+            let in_cols = ports.iter().filter(|(p, _)| p.as_incoming().is_ok()).map(|(_, col)| *col).collect_vec();
+            let out_cols = ports.iter().filter(|(p, _)| p.as_outgoing().is_ok()).map(|(_, col)| *col).collect_vec();
+            let order = ports.iter().map(|(_, col)| *col).max().unwrap();
+            (path, in_cols, out_cols, order)
+        }).sorted_by_key(|(_, _, _, order)| *order).collect_vec();
+        let mut graph = Graph::new();
+        let indices = nodes.iter().map(|(path, _, _, _)| graph.add_node(path.clone())).collect_vec();
+        // Reduced row echelon form keeps the rows over independent sets of columns apart, so unrelated nodes never share a row
+        let rows = self.relations_over(&(0..self.tab.nb_qubits).collect_vec()).stabs;
+        let involves = |row: &PauliProduct, cols: &[usize]| cols.iter().any(|col| row.get_x(*col) || row.get_z(*col));
+        for (a, (_, _, a_outs, _)) in nodes.iter().enumerate() {
+            for (b, (_, b_ins, _, _)) in nodes.iter().enumerate().skip(a + 1) {
+                if rows.iter().any(|row| involves(row, a_outs) && involves(row, b_ins)) {
+                    graph.add_edge(indices[a], indices[b], ());
+                }
+            }
+        }
+        graph
+    }
+
+    /// Finds the measurements (Measure or MeasureFree) whose outcome the analysis can prove, i.e. where ±Z on the measured qubit is in the stabilizer group, paired with the forced outcome
+    /// Measurements within nested regions or inlined functions are identified by their path, as the same node may have a different outcome at each call site
+    pub fn deterministic_measurements(&self, hugr: &H) -> Vec<(NodePath<H::Node>, bool)> {
+        self.cols.internal_inputs()
+            .filter(|(path, _, _)| {
+                let op = hugr.get_optype(*path.last().unwrap()).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok());
+                matches!(op, Some(TketOp::Measure | TketOp::MeasureFree))
+            })
+            .sorted_by_key(|(_, _, col)| *col)
+            .filter_map(|(path, _, col)| {
+                // Z with a negative sign means the qubit is in |1>
+                [false, true].into_iter().find(|sign| self.tab.stabilizes(&self.tab.pauli(&[col], &[], *sign))).map(|outcome| (path.clone(), outcome))
+            })
+            .collect()
+    }
+
+    /// Finds the Reset nodes whose input is provably already in |0>, i.e. where +Z on the Reset's input column is in the stabilizer group, so that the Reset is a no-op and can be removed
+    pub fn redundant_resets(&self, hugr: &H) -> Vec<NodePath<H::Node>> {
+        self.resets(hugr)
+            .filter(|(_, col)| self.tab.stabilizes(&self.tab.pauli(&[*col], &[], false)))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Finds pairs of a Measure and a later Reset where the qubit reaching the Reset is provably in the Z eigenstate given by the measurement outcome (or its negation), so the Reset could be replaced by an X conditioned on the outcome
+    /// Each pair comes with whether the relation is negated: if false the X is needed when the outcome is 1, and if true when it is 0
+    pub fn measure_reset_pairs(&self, hugr: &H) -> Vec<MeasureResetPair<H::Node>> {
+        let measures = self.cols.internal_inputs()
+            .filter(|(path, _, _)| hugr.get_optype(*path.last().unwrap()).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok()) == Some(TketOp::Measure))
+            .filter_map(|(path, _, _)| Some((path.clone(), self.cols.column_for_node_port(path, OutgoingPort::from(0))?)))
+            .sorted_by_key(|(_, col)| *col)
+            .collect_vec();
+        let mut pairs = vec![];
+        for (reset, reset_col) in self.resets(hugr) {
+            for (measure, measure_col) in measures.iter() {
+                if let Some(negated) = [false, true].into_iter().find(|sign| self.tab.stabilizes(&self.tab.pauli(&[*measure_col, reset_col], &[], *sign))) {
+                    pairs.push((measure.clone(), reset.clone(), negated));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// The Reset nodes of the region with their input columns, in column order
+    fn resets<'a>(&'a self, hugr: &'a H) -> impl Iterator<Item = (&'a NodePath<H::Node>, usize)> + 'a {
+        self.cols.internal_inputs()
+            .filter(move |(path, _, _)| hugr.get_optype(*path.last().unwrap()).as_extension_op().and_then(|op| TketOp::from_extension_op(op).ok()) == Some(TketOp::Reset))
+            .map(|(path, _, col)| (path, col))
+            .sorted_by_key(|(_, col)| *col)
+    }
+
+    /// Removes the frontier column for the qubit wire into port of node, to be consumed by the node
+    fn take_frontier(&mut self, node: H::Node, port: IncomingPort) -> Result<usize, DataflowError<H::Node>> {
+        self.cols.take_frontier(node, port).ok_or(DataflowError::InconsistentFrontier { node, port })
+    }
+
+    /// Moves the frontier column col onto the qubit wire out of port of node
+    fn push_frontier(&mut self, hugr: &H, node: H::Node, port: OutgoingPort, col: usize) -> Result<(), DataflowError<H::Node>> {
+        let next = hugr.single_linked_input(node, port).ok_or(DataflowError::DanglingQubitWire { node, port })?;
+        self.cols.set_frontier(next.0, next.1, col);
+        Ok(())
+    }
+
+    /// Moves the record of the qubits packed in a tuple onto the tuple wire out of port of node
+    fn push_packed(&mut self, hugr: &H, node: H::Node, port: OutgoingPort, packed: Vec<(Vec<usize>, usize)>) -> Result<(), DataflowError<H::Node>> {
+        let next = hugr.single_linked_input(node, port).ok_or(DataflowError::DanglingQubitWire { node, port })?;
+        self.cols.set_packed(next.0, next.1, packed);
+        Ok(())
+    }
+
+    /// Adds a new column to the tableau, with no rows acting on it yet
+    fn add_col(&mut self) -> usize {
+        self.stats.columns_allocated += 1;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(name: "add_col", col = self.tab.nb_qubits);
+        self.tab.add_col()
+    }
+
+    /// Projects out the given columns (as Z or X), keeping only the rows that do not act on them
+    fn project(&mut self, cols: &[(usize, bool)]) {
+        let nb_stabs = self.tab.nb_stabs;
+        self.tab.project(cols);
+        self.stats.rows_projected += nb_stabs - self.tab.nb_stabs;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(name: "project", n_cols = cols.len(), rows_dropped = nb_stabs - self.tab.nb_stabs);
+    }
+
+    /// The tag for node in the provenance of the rows of tab, allocating a new one the first time node is seen
+    fn tag(&mut self, node: H::Node) -> usize {
+        match self.tags.iter().position(|n| *n == node) {
+            Some(t) => t,
+            None => {
+                self.tags.push(node);
+                self.tags.len() - 1
+            }
+        }
+    }
+
+    /// Adds the rows XX and ZZ for the identity channel between two columns
+    fn add_identity_rows(&mut self, col_a: usize, col_b: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(name: "add_identity_rows", col_a, col_b);
+        self.tab.add_row(self.tab.pauli(&[], &[col_a, col_b], false));
+        self.tab.add_row(self.tab.pauli(&[col_a, col_b], &[], false));
+    }
+
+    /// Imposes a stabilizer relation by keeping only those rows commuting with it before adding it as a new row
+    fn add_relation(&mut self, p: PauliProduct) {
+        let nb_stabs = self.tab.nb_stabs;
+        self.tab.project_commuting_with(&p);
+        self.stats.rows_projected += nb_stabs - self.tab.nb_stabs;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(name: "add_relation", rows_dropped = nb_stabs - self.tab.nb_stabs);
+        self.tab.add_row(p);
+    }
+
+    /// Connects two columns as though by a wire, projecting the pair onto the Bell state with XX and ZZ
+    fn compose_cols(&mut self, col_a: usize, col_b: usize) {
+        self.add_relation(self.tab.pauli(&[], &[col_a, col_b], false));
+        self.add_relation(self.tab.pauli(&[col_a, col_b], &[], false));
+    }
+
+    /// Eliminates every column other than those of the inputs and outputs, leaving only the stabilizers over the boundary qubits
+    fn project_to_boundary(&mut self) {
+        let boundary: HashSet<usize> = self.cols.inputs().map(|(_, col)| col).chain(self.cols.outputs().map(|(_, col)| col)).collect();
+        let cols = (0..self.tab.nb_qubits).filter(|c| !boundary.contains(c)).collect_vec();
+        self.project(&cols.iter().flat_map(|c| [(*c, false), (*c, true)]).collect_vec());
+        self.remove_cols(&cols);
+    }
+
+    /// Removes columns from the tableau, dropping any wires mapped to them and renumbering the rest
+    fn remove_cols(&mut self, cols: &[usize]) {
+        let mut cols = cols.to_vec();
+        cols.sort();
+        self.tab.remove_cols(&cols);
+        self.cols.remove_cols(&cols);
+    }
+
+}
+
+/// The ops that only move qubits between wires, possibly packed into tuples or arrays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Plumbing {
+    /// Packs each input into a slot of the single output, as MakeTuple and the new_array op of the array extension
+    Pack,
+    /// Unpacks the single input into a slot on each output, as UnpackTuple and the unpack op of the array extension
+    Unpack,
+    Noop,
+}
+
+/// The columns that gc_internal_columns keeps by default: the internal columns of nodes, which mergeable_rotations, relations_for_node and the Pauli frames of Conditionals look up, leaving only the boundary columns of nested analyses absorbed into the tableau to be collected
+pub fn keep_node_columns(role: ColumnRole) -> bool {
+    !matches!(role, ColumnRole::NestedIn | ColumnRole::NestedOut)
+}
+
+/// The functions run_module analyses: the entrypoint if it is a FuncDefn, and otherwise every FuncDefn at the top level of the module
+fn module_functions<H: HugrView>(hugr: &H) -> Vec<H::Node> {
+    let is_func_defn = |n: &H::Node| matches!(hugr.get_optype(*n), OpType::FuncDefn(_));
+    if hugr.entrypoint() != hugr.module_root() && is_func_defn(&hugr.entrypoint()) {
+        vec![hugr.entrypoint()]
+    } else {
+        hugr.children(hugr.module_root()).filter(is_func_defn).collect_vec()
+    }
+}
+
+/// Whether applying node may allocate columns beyond those already tracking its qubits, as for non-Clifford gates, opaque ops and nested analyses; Clifford gates and ops that only move qubits around act in place
+/// Transparent ops are not considered here, as run_region always passes them through
+fn allocates_columns<H: HugrView>(hugr: &H, node: H::Node) -> bool {
+    let has_qubits = hugr.in_value_types(node).map(|(_, t)| t).chain(hugr.out_value_types(node).map(|(_, t)| t)).contains(&qb_t());
+    match hugr.get_optype(node) {
+        _ if !has_qubits => false,
+        OpType::Input(_) | OpType::Output(_) => false,
+        OpType::ExtensionOp(op) => match TketOp::from_extension_op(op) {
+            Ok(TketOp::H | TketOp::CX | TketOp::CY | TketOp::CZ | TketOp::S | TketOp::Sdg | TketOp::X | TketOp::Y | TketOp::Z | TketOp::V | TketOp::Vdg | TketOp::MeasureFree | TketOp::QFree) => false,
+            Ok(tkop) => clifford_rotation(hugr, node, tkop).is_none(),
+            Err(_) => op.unqualified_id() != CLASSICAL_EXP_BOX && plumbing_op(op).is_none(),
+        },
+        OpType::OpaqueOp(op) => op.unqualified_id() != CLASSICAL_EXP_BOX,
+        _ => true,
+    }
+}
+
+/// Recognises the ops that only move qubits between wires
+/// Other array ops (get, set, swap, pop_left and pop_right) return Sums, or take indices that may only be known at runtime, so they are left opaque: the qubits in the array they consume are dropped and unpacking the array they return gives fresh columns
+fn plumbing_op(op: &ExtensionOp) -> Option<Plumbing> {
+    if MakeTuple::from_extension_op(op).is_ok() {
+        return Some(Plumbing::Pack);
+    }
+    if UnpackTuple::from_extension_op(op).is_ok() {
+        return Some(Plumbing::Unpack);
+    }
+    if Noop::from_extension_op(op).is_ok() {
+        return Some(Plumbing::Noop);
+    }
+    match ArrayOp::from_extension_op(op).ok()?.def {
+        ArrayOpDef::new_array => Some(Plumbing::Pack),
+        ArrayOpDef::unpack => Some(Plumbing::Unpack),
+        _ => None,
+    }
+}
+
+/// The logic op computed by op, either a LogicOp on bools or its counterpart on the tket.bool bits output by MeasureFree
+/// Converting between the two bool types with read or make_opaque keeps the parity of the single input, as a Xor of one bit does
+fn logic_op(op: &ExtensionOp) -> Option<LogicOp> {
+    if let Ok(logic_op) = LogicOp::from_extension_op(op) {
+        return Some(logic_op);
+    }
+    Some(match BoolOp::from_extension_op(op).ok()? {
+        BoolOp::read | BoolOp::make_opaque | BoolOp::xor => LogicOp::Xor,
+        BoolOp::eq => LogicOp::Eq,
+        BoolOp::not => LogicOp::Not,
+        BoolOp::and => LogicOp::And,
+        BoolOp::or => LogicOp::Or,
+        _ => return None,
+    })
+}
+
+/// The constant angle (in half-turns) of the rotation wire into port of node, if it can be traced back to constants through the ops of the rotation extension
+pub(crate) fn constant_half_turns<H: HugrView>(hugr: &H, node: H::Node, port: IncomingPort) -> Option<f64> {
+    let (src, _) = hugr.single_linked_output(node, port)?;
+    match hugr.get_optype(src) {
+        OpType::LoadConstant(_) => {
+            let static_port = hugr.get_optype(src).static_input_port()?;
+            let (const_node, _) = hugr.single_linked_output(src, static_port)?;
+            let value = hugr.get_optype(const_node).as_const()?.value();
+            value.get_custom_value::<ConstRotation>().map(|r| r.half_turns()).or_else(|| value.get_custom_value::<ConstF64>().map(|f| f.value()))
+        }
+        OpType::ExtensionOp(op) => match RotationOp::from_extension_op(op).ok()? {
+            RotationOp::radd => Some(constant_half_turns(hugr, src, IncomingPort::from(0))? + constant_half_turns(hugr, src, IncomingPort::from(1))?),
+            // Conversions between rotations and floats of half-turns keep the value
+            RotationOp::from_halfturns_unchecked | RotationOp::to_halfturns => constant_half_turns(hugr, src, IncomingPort::from(0)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The stabilizer nullity of the state made by applying to |+…+> an odd number of eighth turns about each of the parities given by axes (over any number of qubits)
+/// It stabilizes X on a parity v (up to Zs) exactly when, summing a a^T over the axes a with a·v odd, every entry is even; this only depends on the linear relations between the axes, so we work in coordinates over a basis of their span and count the v of the 2^rank there that pass, which form a subspace
+/// Returns None if the rank is above MAX_NULLITY_RANK
+fn stabilizer_nullity(axes: &[Vec<bool>]) -> Option<usize> {
+    // Row reduce the matrix whose columns are the axes; its first rank rows then give the coordinates of each axis
+    let width = axes.first().map_or(0, |axis| axis.len());
+    let mut rows = (0..width).map(|i| axes.iter().map(|axis| axis[i]).collect_vec()).collect_vec();
+    let mut rank = 0;
+    for c in 0..axes.len() {
+        let Some(pivot) = (rank..width).find(|r| rows[*r][c]) else {
+            continue;
+        };
+        rows.swap(rank, pivot);
+        let pivot_row = rows[rank].clone();
+        for (r, row) in rows.iter_mut().enumerate() {
+            if r != rank && row[c] {
+                row.iter_mut().zip(&pivot_row).for_each(|(b, p)| *b ^= p);
+            }
+        }
+        rank += 1;
+    }
+    if rank > MAX_NULLITY_RANK {
+        return None;
+    }
+    let coords = (0..axes.len()).map(|a| (0..rank).filter(|r| rows[*r][a]).fold(0u32, |acc, r| acc | 1 << r)).collect_vec();
+    let stabilized = (0..1u32 << rank).filter(|v| {
+        // sums[i] has bit j set when the sum of a_i a_j is odd
+        let mut sums = vec![0u32; rank];
+        for a in coords.iter().filter(|a| (*a & v).count_ones() % 2 == 1) {
+            for (i, sum) in sums.iter_mut().enumerate() {
+                if a >> i & 1 == 1 {
+                    *sum ^= a;
+                }
+            }
+        }
+        sums.iter().all(|sum| *sum == 0)
+    }).count();
+    Some(rank - stabilized.trailing_zeros() as usize)
+}
+
+/// The Clifford gates (on the qubits of node in port order) that an Rz or CRz amounts to up to global phase, if its angle is a constant multiple of a quarter turn
+/// Rz is periodic in 2 half-turns up to phase, but CRz only in 4, as Rz(2) = -I is a Z on the control once controlled; likewise a CRz by an odd number of quarter turns is a controlled S, which is not Clifford
+fn clifford_rotation<H: HugrView>(hugr: &H, node: H::Node, op: TketOp) -> Option<Vec<(TketOp, Vec<usize>)>> {
+    let (angle_port, period) = match op {
+        TketOp::Rz => (1, 2.),
+        TketOp::CRz => (2, 4.),
+        _ => return None,
+    };
+    let quarter_turns = 2. * constant_half_turns(hugr, node, IncomingPort::from(angle_port))?.rem_euclid(period);
+    if (quarter_turns - quarter_turns.round()).abs() > CLIFFORD_ANGLE_TOLERANCE {
+        return None;
+    }
+    let quarter_turns = (quarter_turns.round() as usize) % (2 * period as usize);
+    match (op, quarter_turns) {
+        (_, 0) => Some(vec![]),
+        (TketOp::Rz, 1) => Some(vec![(TketOp::S, vec![0])]),
+        (TketOp::Rz, 2) => Some(vec![(TketOp::Z, vec![0])]),
+        (TketOp::Rz, 3) => Some(vec![(TketOp::Sdg, vec![0])]),
+        // CRz(1) is CZ with a phase of -i on the control, and CRz(3) with a phase of i
+        (TketOp::CRz, 2) => Some(vec![(TketOp::CZ, vec![0, 1]), (TketOp::Sdg, vec![0])]),
+        (TketOp::CRz, 4) => Some(vec![(TketOp::Z, vec![0])]),
+        (TketOp::CRz, 6) => Some(vec![(TketOp::CZ, vec![0, 1]), (TketOp::S, vec![0])]),
+        _ => None,
+    }
+}
+
+/// The Clifford gates on qubits 0 and 1 that an interaction about axis by a constant angle amounts to (up to a global phase), if the angle is a multiple of a quarter turn
+/// exp(-iπα/2 Z⊗Z) is CX;Rz(α) on qubit 1;CX, and the X⊗X interaction is the same conjugated by H on both qubits
+fn clifford_interaction(axis: InteractionAxis, half_turns: f64) -> Option<Vec<(TketOp, Vec<usize>)>> {
+    let quarter_turns = 2. * half_turns.rem_euclid(2.);
+    if (quarter_turns - quarter_turns.round()).abs() > CLIFFORD_ANGLE_TOLERANCE {
+        return None;
+    }
+    let phase = match (quarter_turns.round() as usize) % 4 {
+        0 => return Some(vec![]),
+        1 => TketOp::S,
+        2 => TketOp::Z,
+        _ => TketOp::Sdg,
+    };
+    let basis = match axis {
+        InteractionAxis::ZZ => vec![],
+        InteractionAxis::XX => vec![(TketOp::H, vec![0]), (TketOp::H, vec![1])],
+    };
+    Some(basis.clone().into_iter().chain([(TketOp::CX, vec![0, 1]), (phase, vec![1]), (TketOp::CX, vec![0, 1])]).chain(basis).collect())
+}
+
+/// Copies tab restricted to the columns in unified_index, projecting out all others and moving each remaining column c to unified_index[c]
+/// The columns of unified_index should map onto 0..unified_index.len(), matching the column indexing of a summary
+fn restrict_tab(tab: &ChoiTableau, unified_index: &HashMap<usize, usize>) -> ChoiTableau {
+    let mut restricted = tab.clone();
+    let cols = (0..tab.nb_qubits).filter(|c| !unified_index.contains_key(c)).collect_vec();
+    restricted.project(&cols.iter().flat_map(|c| [(*c, false), (*c, true)]).collect_vec());
+    restricted.remove_cols(&cols);
+    let new_index = (0..tab.nb_qubits).filter(|c| unified_index.contains_key(c)).map(|c| *unified_index.get(&c).unwrap()).collect_vec();
+    restricted.reorder_cols(&new_index);
+    restricted
+}
+
+/// Finds a Pauli over cols such that appending it to base gives the same stabilizer group as corrected, if there is one
+/// Every row of base must be in corrected up to sign, and the Pauli must anticommute with exactly the rows whose sign differs, which is a linear system over the Z and X parts of the Pauli
+fn pauli_correction(base: &ChoiTableau, corrected: &ChoiTableau, cols: &[usize]) -> Option<PauliProduct> {
+    let n = cols.len();
+    // Each row gives an equation over bits 0..n (Z on cols) and n..2n (X on cols), with the sign flip in bit 2n
+    let mut eqs = vec![];
+    for row in base.stabs.iter() {
+        let mut negated = row.clone();
+        negated.sign = !negated.sign;
+        let flip = if corrected.stabilizes(row) { false } else if corrected.stabilizes(&negated) { true } else { return None };
+        let mut eq = BitVector::new(2*n + 1);
+        for (k, col) in cols.iter().enumerate() {
+            // Z anticommutes with the X part of the row, and X with its Z part
+            if row.get_x(*col) {
+                eq.xor_bit(k);
+            }
+            if row.get_z(*col) {
+                eq.xor_bit(n + k);
+            }
+        }
+        if flip {
+            eq.xor_bit(2*n);
+        }
+        eqs.push(eq);
+    }
+    let mut pivots = vec![];
+    for k in 0..2*n {
+        let Some(r) = (pivots.len()..eqs.len()).find(|r| eqs[*r].get(k)) else {
+            continue;
+        };
+        eqs.swap(pivots.len(), r);
+        let pivot = eqs[pivots.len()].clone();
+        for (i, eq) in eqs.iter_mut().enumerate() {
+            if i != pivots.len() && eq.get(k) {
+                eq.xor(&pivot);
+            }
+        }
+        pivots.push(k);
+    }
+    // An equation reduced to just its right-hand side has no solution
+    if eqs[pivots.len()..].iter().any(|eq| eq.get(2*n)) {
+        return None;
+    }
+    let mut p = base.pauli(&[], &[], false);
+    let mut candidate = base.clone();
+    for (i, k) in pivots.into_iter().enumerate() {
+        if eqs[i].get(2*n) {
+            if k < n {
+                p.set_z(cols[k], true);
+                candidate.append_z(cols[k]);
+            } else {
+                p.set_x(cols[k - n], true);
+                candidate.append_x(cols[k - n]);
+            }
+        }
+    }
+    // The rows of corrected may still generate a different group
+    candidate.equivalent(corrected).then_some(p)
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use hugr::{builder::{endo_sig, Container, DFGBuilder, Dataflow, DataflowHugr, DataflowSubContainer, FunctionBuilder, HugrBuilder, ModuleBuilder, SubContainer}, extension::{prelude::{bool_t, qb_t, usize_t, Noop, UnpackTuple}, Extension, Version}, ops::{handle::{FuncID, NodeHandle}, AliasDecl, CallIndirect, ExtensionOp, OpTrait, OpType, Tag, Value}, hugr::hugrmut::HugrMut, type_row, types::{Signature, Type, TypeBound}, HugrView, IncomingPort, Node, OutgoingPort, Port};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{Arc, LazyLock};
+    use hugr::std_extensions::collections::array::{array_type, ArrayOpDef};
+    use hugr::std_extensions::logic::LogicOp;
+    use hugr_core::hugr::internal::HugrMutInternals;
+    use tket::extension::bool::{bool_type, BoolOp};
+    use tket::extension::rotation::{rotation_type, ConstRotation};
+    use tket::TketOp;
+
+    use crate::test_support::{assert_analysis_stabilizers, random_circuit, RandomCircuitConfig};
+    use crate::{column_allocator::{ColumnRole, WireRef}, pauli_product::PauliProduct, tableau::{Command, Tableau}, tableau_interface::ChoiTableau, stabilizer_dataflow::{pauli_correction, restrict_tab, AnalysisConfig, DEFAULT_CHECKPOINT_INTERVAL, AnalysisStats, AnalysisSummary, SUMMARY_FORMAT_VERSION, DataflowError, Equivalence, NonCliffordError, FunctionOpacity, Interaction, InteractionAxis, InteractionRegistry, OpacityHints, StabilizerDataflow, keep_node_columns, STABILIZERS_METADATA_KEY}};
+
+    /// The extension defining the ops of the tests that neither tket nor hugr has, which the analysis only knows by name (if at all): the tket1 classical ops, the interactions, the stand-ins for the gates of OpacityHints, and opaque ops on qubits, tuples, arrays and the linear "handle" type it also defines
+    /// Defining them (rather than using OpaqueOps) lets the test hugrs be validated
+    static TEST_EXTENSION: LazyLock<Arc<Extension>> = LazyLock::new(|| {
+        Extension::new_arc("ext".try_into().unwrap(), Version::new(0, 1, 0), |ext, ext_ref| {
+            ext.add_type("handle".into(), vec![], String::new(), TypeBound::Linear.into(), ext_ref).unwrap();
+            let handle_t = Type::new_extension(ext.get_type("handle").unwrap().instantiate([]).unwrap());
+            let swapped_t = OpType::from(ArrayOpDef::swap.to_concrete(qb_t(), 2)).dataflow_signature().unwrap().output()[0].clone();
+            let interaction = Signature::new(vec![qb_t(), qb_t(), rotation_type()], vec![qb_t(), qb_t()]);
+            let signatures = [
+                ("op", Signature::new_endo(vec![qb_t()])),
+                ("noise", Signature::new_endo(vec![qb_t()])),
+                ("tuple_op", Signature::new_endo(vec![Type::new_tuple(vec![qb_t()])])),
+                ("unwrap", Signature::new(vec![swapped_t], vec![array_type(2, qb_t())])),
+                ("acquire", Signature::new_endo(vec![qb_t(), handle_t])),
+                ("ClassicalExpBox", Signature::new(vec![bool_t(), qb_t()], vec![qb_t(), bool_t()])),
+                ("SWAPbits", Signature::new(vec![bool_t(), bool_t()], vec![bool_t(), bool_t()])),
+                ("ZZPhase", interaction.clone()),
+                ("XXPhase", interaction.clone()),
+                ("Rzz", interaction),
+                ("ZZMax", Signature::new_endo(vec![qb_t(), qb_t()])),
+                ("CS", Signature::new_endo(vec![qb_t(), qb_t()])),
+                ("CSdg", Signature::new_endo(vec![qb_t(), qb_t()])),
+                ("CSX", Signature::new_endo(vec![qb_t(), qb_t()])),
+                ("other", Signature::new_endo(vec![qb_t(), qb_t()])),
+            ];
+            for (name, signature) in signatures {
+                ext.add_op(name.into(), String::new(), signature, ext_ref).unwrap();
+            }
+        })
+    });
+
+    /// The op of TEST_EXTENSION called name
+    fn test_op(name: &str) -> OpType {
+        ExtensionOp::new(TEST_EXTENSION.get_op(name).unwrap().clone(), []).unwrap().into()
+    }
+
+    #[test]
+    fn test_empty_analysis() {
+        let builder = DFGBuilder::new(endo_sig(vec![])).unwrap();
+        let hugr = builder.finish_hugr().unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.tableau().nb_qubits, 0);
+        assert_eq!(analysis.tableau().nb_stabs, 0);
+    }
+
+    #[test]
+    fn test_identity_analysis() {
+        // Add an extra integer input to make sure we only track the qubits
+        let builder = DFGBuilder::new(Signature::new(vec![usize_t(), qb_t(), qb_t()], vec![qb_t(), qb_t()])).unwrap();
+        let [_, qb0, qb1] = builder.input_wires_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.tableau().nb_qubits, 4);
+        assert_eq!(analysis.tableau().nb_stabs, 4);
+        // Check the right ports are stored for tracking the qubits
+        assert_eq!(analysis.columns().count(ColumnRole::Input), 2);
+        assert_eq!(analysis.columns().count(ColumnRole::Output), 2);
+        assert_eq!(analysis.input_column(OutgoingPort::from(1)).unwrap(), 0);
+        assert_eq!(analysis.output_column(IncomingPort::from(0)).unwrap(), 1);
+        assert_eq!(analysis.input_column(OutgoingPort::from(2)).unwrap(), 2);
+        assert_eq!(analysis.output_column(IncomingPort::from(1)).unwrap(), 3);
+        // Check that the rows correspond to the identity operations
+        //TODO:: Reduce analysis.tab to row echelon form
+        assert_eq!(analysis.tableau().stabs[0].x.get_boolean_vec(), vec![true, true, false, false]);
+        assert_eq!(analysis.tableau().stabs[0].z.get_boolean_vec(), vec![false; 4]);
+        assert!(!analysis.tableau().stabs[0].sign);
+        assert_eq!(analysis.tableau().stabs[1].x.get_boolean_vec(), vec![false; 4]);
+        assert_eq!(analysis.tableau().stabs[1].z.get_boolean_vec(), vec![true, true, false, false]);
+        assert!(!analysis.tableau().stabs[1].sign);
+        assert_eq!(analysis.tableau().stabs[2].x.get_boolean_vec(), vec![false, false, true, true]);
+        assert_eq!(analysis.tableau().stabs[2].z.get_boolean_vec(), vec![false; 4]);
+        assert!(!analysis.tableau().stabs[2].sign);
+        assert_eq!(analysis.tableau().stabs[3].x.get_boolean_vec(), vec![false; 4]);
+        assert_eq!(analysis.tableau().stabs[3].z.get_boolean_vec(), vec![false, false, true, true]);
+        assert!(!analysis.tableau().stabs[3].sign);
+    }
+
+    #[test]
+    fn test_column_to_wire() {
+        let builder = DFGBuilder::new(Signature::new(vec![usize_t(), qb_t(), qb_t()], vec![qb_t(), qb_t()])).unwrap();
+        let [_, qb0, qb1] = builder.input_wires_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let wires = analysis.column_to_wire();
+        assert!((0..analysis.tableau().nb_qubits).all(|c| wires.contains_key(&c)));
+        assert_eq!(wires.get(&0).unwrap(), "input[1]");
+        assert_eq!(wires.get(&1).unwrap(), "output[0]");
+        assert_eq!(wires.get(&2).unwrap(), "input[2]");
+        assert_eq!(wires.get(&3).unwrap(), "output[1]");
+    }
+
+    #[test]
+    fn test_bell_state() {
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.tableau().nb_qubits, 2);
+        assert_eq!(analysis.tableau().nb_stabs, 2);
+        assert_analysis_stabilizers(&hugr, hugr.entrypoint(), &["XX", "ZZ"], &[WireRef::Output(IncomingPort::from(0)), WireRef::Output(IncomingPort::from(1))]);
+    }
+    
+    #[test]
+    fn test_to_dot() {
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // The wires into the Output are labelled with the output columns, whose order depends on the order the nodes were applied in
+        let (col0, col1) = (analysis.output_column(IncomingPort::from(0)).unwrap(), analysis.output_column(IncomingPort::from(1)).unwrap());
+        let expected = format!(concat!(
+            "digraph analysis {{\n",
+            "  n0 [label=\"Input\"];\n",
+            "  n1 [label=\"Output\"];\n",
+            "  n2 [label=\"QAlloc\"];\n",
+            "  n3 [label=\"QAlloc\"];\n",
+            "  n4 [label=\"H\"];\n",
+            "  n5 [label=\"CX\"];\n",
+            "  n2 -> n4;\n",
+            "  n3 -> n5;\n",
+            "  n4 -> n5;\n",
+            "  n5 -> n1 [label=\"{}\"];\n",
+            "  n5 -> n1 [label=\"{}\"];\n",
+            "  legend [label=\"+XX\\l+ZZ\\l\", shape=note];\n",
+            "  {{ rank=same; n1; legend; }}\n",
+            "}}\n",
+        ), col0, col1);
+        assert_eq!(analysis.to_dot(&hugr), expected);
+        // A T is boxed with its internal columns, which also label the wires into and out of it
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(t.outputs()).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let t_in = analysis.internal_column(&[t.node()], IncomingPort::from(0)).unwrap();
+        let t_out = analysis.internal_column(&[t.node()], OutgoingPort::from(0)).unwrap();
+        let dot = analysis.to_dot(&hugr);
+        assert!(dot.contains(&format!("  n2 [label=\"T\\nin: {t_in}\\nout: {t_out}\", shape=box];\n")));
+        assert!(dot.lines().any(|line| line.starts_with("  n0 -> n2 [label=") && line.contains(&t_in.to_string())));
+        assert!(dot.lines().any(|line| line.starts_with("  n2 -> n1 [label=") && line.contains(&t_out.to_string())));
+    }
+
+    #[test]
+    fn test_trace_row() {
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let alloc0 = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap();
+        let alloc1 = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap();
+        let h = builder.add_dataflow_op(TketOp::H, alloc0.outputs()).unwrap();
+        let cx = builder.add_dataflow_op(TketOp::CX, [h.out_wire(0), alloc1.out_wire(0)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(cx.outputs()).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let zz = analysis.tableau().stabs.iter().position(|row| row.z.get_boolean_vec() == vec![true; 2] && row.x.get_boolean_vec() == vec![false; 2]).unwrap();
+        let trace = analysis.trace_row(&hugr, zz);
+        assert_eq!(trace.len(), 4);
+        let paulis: HashMap<Node, String> = trace.iter().map(|(node, p)| (*node, (0..2).map(|q| p.pauli_at(q)).collect())).collect();
+        // ZZ after the CX, which came from Z on the second qubit alone, so the H leaves it alone and it starts as the Z stabilizer of the second allocation
+        assert_eq!(trace[0].0, cx.node());
+        assert_eq!(paulis[&cx.node()], "ZZ");
+        assert_eq!(paulis[&h.node()], "IZ");
+        assert_eq!(paulis[&alloc0.node()], "IZ");
+        assert_eq!(paulis[&alloc1.node()], "IZ");
+        assert!(trace.iter().all(|(_, p)| !p.sign));
+        // XX came from X on the first qubit, which the H turns into Z
+        let xx = analysis.tableau().stabs.iter().position(|row| row.x.get_boolean_vec() == vec![true; 2] && row.z.get_boolean_vec() == vec![false; 2]).unwrap();
+        let paulis: HashMap<Node, String> = analysis.trace_row(&hugr, xx).iter().map(|(node, p)| (*node, (0..2).map(|q| p.pauli_at(q)).collect())).collect();
+        assert_eq!(paulis[&cx.node()], "XX");
+        assert_eq!(paulis[&h.node()], "XI");
+        assert_eq!(paulis[&alloc0.node()], "ZI");
+    }
+
+    #[test]
+    fn test_clifford_equivalence_hugrs() {
+        // The gates of test_clifford_gates, which leave just the Paulis at the end
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, qb2] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::V, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CZ, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::S, [qb1]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CY, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::Sdg, [qb1]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Sdg, [qb0]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::V, [qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::Vdg, [qb1]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Z, [qb0]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::X, [qb1]).unwrap().outputs_arr();
+        let [qb2] = builder.add_dataflow_op(TketOp::Y, [qb2]).unwrap().outputs_arr();
+        let gates = builder.finish_hugr_with_outputs([qb0, qb1, qb2]).unwrap();
+        let builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), qb_t()])).unwrap();
+        let wires = builder.input_wires().collect_vec();
+        let wires = builder.finish_hugr_with_outputs(wires).unwrap();
+        let Ok(Equivalence::UpToPauli(layer)) = StabilizerDataflow::clifford_equivalence(&gates, gates.entrypoint(), &wires, wires.entrypoint()) else {
+            panic!("Expected the circuits to differ by a Pauli layer");
+        };
+        assert_eq!((0..3).map(|q| layer.pauli_at(q)).collect::<String>(), "ZXY");
+        // CX built directly and from CZ conjugated by H on the target
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let cx = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap();
+        let cx = builder.finish_hugr_with_outputs(cx.outputs()).unwrap();
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CZ, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        let hczh = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        assert!(matches!(StabilizerDataflow::clifford_equivalence(&cx, cx.entrypoint(), &hczh, hczh.entrypoint()), Ok(Equivalence::Equal)));
+        // CX with the control and target swapped acts differently on the first qubit
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb1, qb0] = builder.add_dataflow_op(TketOp::CX, [qb1, qb0]).unwrap().outputs_arr();
+        let xc = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let Ok(Equivalence::Different(row)) = StabilizerDataflow::clifford_equivalence(&cx, cx.entrypoint(), &xc, xc.entrypoint()) else {
+            panic!("Expected the circuits to differ");
+        };
+        let analysis = StabilizerDataflow::run_dfg(&cx, cx.entrypoint(), &FunctionOpacity::Inline).unwrap();
+        let boundary = analysis.boundary_tab();
+        assert!(boundary.stabilizes(&row));
+        // A T gate or a different number of qubits cannot be compared
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let t = builder.finish_hugr_with_outputs(t.outputs()).unwrap();
+        assert_eq!(StabilizerDataflow::clifford_equivalence(&t, t.entrypoint(), &t, t.entrypoint()).err(), Some(NonCliffordError::NotClifford { root: t.entrypoint() }));
+        assert_eq!(StabilizerDataflow::clifford_equivalence(&cx, cx.entrypoint(), &wires, wires.entrypoint()).err(), Some(NonCliffordError::QubitCountMismatch { a: 2, b: 3 }));
+    }
+
+    #[test]
+    fn test_gate_clifford_tableau() {
+        let gate_tableau = |op: TketOp, n_qubits: usize, half_turns: Option<f64>| {
+            let mut sig = vec![qb_t(); n_qubits];
+            sig.extend(half_turns.map(|_| rotation_type()));
+            let mut builder = DFGBuilder::new(Signature::new(sig, vec![qb_t(); n_qubits])).unwrap();
+            let mut inputs = builder.input_wires().take(n_qubits).collect_vec();
+            if let Some(half_turns) = half_turns {
+                inputs.push(builder.add_load_value(Value::extension(ConstRotation::new(half_turns).unwrap())));
+            }
+            let gate = builder.add_dataflow_op(op, inputs).unwrap();
+            let hugr = builder.finish_hugr_with_outputs(gate.outputs()).unwrap();
+            StabilizerDataflow::gate_clifford_tableau(&hugr, gate.node()).map(|tab| tab.to_binary_matrix())
+        };
+        let expected = |n_qubits: usize, build: &dyn Fn(&mut Tableau)| {
+            let mut tab = Tableau::new(n_qubits);
+            build(&mut tab);
+            Some(tab.to_binary_matrix())
+        };
+        assert_eq!(gate_tableau(TketOp::H, 1, None), expected(1, &|tab: &mut Tableau| tab.append_h(0)));
+        assert_eq!(gate_tableau(TketOp::S, 1, None), expected(1, &|tab: &mut Tableau| tab.append_s(0)));
+        assert_eq!(gate_tableau(TketOp::Sdg, 1, None), expected(1, &|tab: &mut Tableau| { tab.append_s(0); tab.append_z(0); }));
+        assert_eq!(gate_tableau(TketOp::V, 1, None), expected(1, &|tab: &mut Tableau| tab.append_v(0)));
+        assert_eq!(gate_tableau(TketOp::Vdg, 1, None), expected(1, &|tab: &mut Tableau| { tab.append_v(0); tab.append_x(0); }));
+        assert_eq!(gate_tableau(TketOp::X, 1, None), expected(1, &|tab: &mut Tableau| tab.append_x(0)));
+        assert_eq!(gate_tableau(TketOp::Y, 1, None), expected(1, &|tab: &mut Tableau| { tab.append_x(0); tab.append_z(0); }));
+        assert_eq!(gate_tableau(TketOp::Z, 1, None), expected(1, &|tab: &mut Tableau| tab.append_z(0)));
+        assert_eq!(gate_tableau(TketOp::CX, 2, None), expected(2, &|tab: &mut Tableau| tab.append_cx(vec![0, 1])));
+        assert_eq!(gate_tableau(TketOp::CZ, 2, None), expected(2, &|tab: &mut Tableau| tab.append_cz(vec![0, 1])));
+        // CY maps X on the control to XY, and Z on the target to ZZ
+        let mut tab = Tableau::new(2);
+        tab.append_s(1);
+        tab.append_z(1);
+        tab.append_cx(vec![0, 1]);
+        tab.append_s(1);
+        assert_eq!(gate_tableau(TketOp::CY, 2, None), Some(tab.to_binary_matrix()));
+        assert_eq!((0..2).map(|q| tab.destabilizer(0).pauli_at(q)).collect::<String>(), "XY");
+        assert_eq!((0..2).map(|q| tab.stabilizer(1).pauli_at(q)).collect::<String>(), "ZZ");
+        // Rotations by quarter turns are Clifford, and others are not
+        assert_eq!(gate_tableau(TketOp::Rz, 1, Some(0.5)), expected(1, &|tab: &mut Tableau| tab.append_s(0)));
+        assert_eq!(gate_tableau(TketOp::Rz, 1, Some(1.0)), expected(1, &|tab: &mut Tableau| tab.append_z(0)));
+        assert_eq!(gate_tableau(TketOp::CRz, 2, Some(1.0)), expected(2, &|tab: &mut Tableau| { tab.append_cz(vec![0, 1]); tab.append_s(0); tab.append_z(0); }));
+        assert_eq!(gate_tableau(TketOp::Rz, 1, Some(0.25)), None);
+        assert_eq!(gate_tableau(TketOp::T, 1, None), None);
+        assert_eq!(gate_tableau(TketOp::Tdg, 1, None), None);
+    }
+
+    #[test]
+    fn test_opaque() {
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let opaque_op = builder.add_dataflow_op(test_op("op"), [qb1]).unwrap();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [opaque_op.out_wire(0)]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.tableau().nb_qubits, 4);
+        // The order of the columns depends on the order the two QAllocs are visited in, but each wire has one of its own
+        let cols = [
+            analysis.output_column(IncomingPort::from(0)).unwrap(),
+            analysis.internal_column(&[opaque_op.node()], IncomingPort::from(0)).unwrap(),
+            analysis.internal_column(&[opaque_op.node()], OutgoingPort::from(0)).unwrap(),
+            analysis.output_column(IncomingPort::from(1)).unwrap(),
+        ];
+        assert_eq!(cols.into_iter().sorted().collect_vec(), (0..4).collect_vec());
+        // The op's input is the Bell partner of the first qubit, while its output is entangled with the second output qubit by the final H and CX
+        let wires = [
+            WireRef::Output(IncomingPort::from(0)),
+            WireRef::node_port(opaque_op.node(), IncomingPort::from(0)),
+            WireRef::node_port(opaque_op.node(), OutgoingPort::from(0)),
+            WireRef::Output(IncomingPort::from(1)),
+        ];
+        assert_analysis_stabilizers(&hugr, hugr.entrypoint(), &["XXIX", "ZZII", "ZIXZ", "IIZX"], &wires);
+    }
+
+    #[test]
+    fn test_report() {
+        // The circuit of test_opaque, with a T after the first QAlloc
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [t.out_wire(0)]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let opaque_op = builder.add_dataflow_op(test_op("op"), [qb1]).unwrap();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [opaque_op.out_wire(0)]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let report = analysis.report();
+        // Nothing is known across the opaque op, losing both the relations a single-qubit gate would give, while the T only drops the row it replaces with its own relation
+        assert_eq!(report.unknown_relations, HashMap::from([(vec![opaque_op.node()], 2)]));
+        assert_eq!(report.dropped_rows, HashMap::from([(vec![t.node()], 1)]));
+        assert_eq!(report.worst_nodes(), vec![(vec![opaque_op.node()], 2), (vec![t.node()], 1)]);
+        // Within a nested DFG, the opaque op is charged under the path through the DFG
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let mut nested_builder = builder.dfg_builder(endo_sig(vec![qb_t()]), [qb]).unwrap();
+        let [qb] = nested_builder.input_wires_arr();
+        let opaque_op = nested_builder.add_dataflow_op(test_op("op"), [qb]).unwrap();
+        let nested = nested_builder.finish_with_outputs(opaque_op.outputs()).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(nested.outputs()).unwrap();
+        let report = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap().report();
+        assert_eq!(report.unknown_relations, HashMap::from([(vec![nested.node(), opaque_op.node()], 2)]));
+    }
+
+    #[test]
+    fn test_clifford_gates() {
+        // Need to cover H, CX, CY, CZ, S, Sdg, X, Y, Z, V, Vdg
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, qb2] = builder.input_wires_arr();
+        // H;S;V;S = I
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::V, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
+        // CX;IH;CZ;IH = II
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CZ, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        // CX;IS;CY;ISdg = II
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::S, [qb1]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CY, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::Sdg, [qb1]).unwrap().outputs_arr();
+        // S;Sdg = II
+        let [qb0] = builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Sdg, [qb0]).unwrap().outputs_arr();
+        // V;Vdh = II
+        let [qb1] = builder.add_dataflow_op(TketOp::V, [qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::Vdg, [qb1]).unwrap().outputs_arr();
+        // Test Paulis explicitly
+        let [qb0] = builder.add_dataflow_op(TketOp::Z, [qb0]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::X, [qb1]).unwrap().outputs_arr();
+        let [qb2] = builder.add_dataflow_op(TketOp::Y, [qb2]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, qb2]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.tableau().nb_qubits, 6);
+        assert_eq!(analysis.tableau().nb_stabs, 6);
+        // Reduce analysis.tab to row echelon form with qubit ordering [in0, out0, in1, out1, in2, out2]
+        assert_eq!(analysis.input_column(OutgoingPort::from(0)).unwrap(), 0);
+        assert_eq!(analysis.output_column(IncomingPort::from(0)).unwrap(), 1);
+        assert_eq!(analysis.input_column(OutgoingPort::from(1)).unwrap(), 2);
+        assert_eq!(analysis.output_column(IncomingPort::from(1)).unwrap(), 3);
+        assert_eq!(analysis.input_column(OutgoingPort::from(2)).unwrap(), 4);
+        assert_eq!(analysis.output_column(IncomingPort::from(2)).unwrap(), 5);
+        //TODO:: Row echelon
+        // Check the rows
+        assert_eq!(analysis.tableau().stabs[0].x.get_boolean_vec(), vec![true, true, false, false, false, false]);
+        assert_eq!(analysis.tableau().stabs[0].z.get_boolean_vec(), vec![false; 6]);
+        assert!(analysis.tableau().stabs[0].sign);
+        assert_eq!(analysis.tableau().stabs[1].x.get_boolean_vec(), vec![false; 6]);
+        assert_eq!(analysis.tableau().stabs[1].z.get_boolean_vec(), vec![true, true, false, false, false, false]);
+        assert!(!analysis.tableau().stabs[1].sign);
+        assert_eq!(analysis.tableau().stabs[2].x.get_boolean_vec(), vec![false, false, true, true, false, false]);
+        assert_eq!(analysis.tableau().stabs[2].z.get_boolean_vec(), vec![false; 6]);
+        assert!(!analysis.tableau().stabs[2].sign);
+        assert_eq!(analysis.tableau().stabs[3].x.get_boolean_vec(), vec![false; 6]);
+        assert_eq!(analysis.tableau().stabs[3].z.get_boolean_vec(), vec![false, false, true, true, false, false]);
+        assert!(analysis.tableau().stabs[3].sign);
+        assert_eq!(analysis.tableau().stabs[4].x.get_boolean_vec(), vec![false, false, false, false, true, true]);
+        assert_eq!(analysis.tableau().stabs[4].z.get_boolean_vec(), vec![false; 6]);
+        assert!(analysis.tableau().stabs[4].sign);
+        assert_eq!(analysis.tableau().stabs[5].x.get_boolean_vec(), vec![false; 6]);
+        assert_eq!(analysis.tableau().stabs[5].z.get_boolean_vec(), vec![false, false, false, false, true, true]);
+        assert!(analysis.tableau().stabs[5].sign);
+    }
+
+    #[test]
+    fn test_nonclifford() {
+        // Need to cover the separate logic for CRz, T/Tdg/Rz/Measure, Rx, Ry, Toffoli
+        // The rotations all take the same angle, which is an input and so could be anything
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t(), qb_t(), rotation_type()], vec![qb_t(), qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, qb2, angle] = builder.input_wires_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let tdg = builder.add_dataflow_op(TketOp::Tdg, [t.out_wire(0)]).unwrap();
+        let rz = builder.add_dataflow_op(TketOp::Rz, [tdg.out_wire(0), angle]).unwrap();
+        let meas = builder.add_dataflow_op(TketOp::Measure, [rz.out_wire(0)]).unwrap();
+        let ry = builder.add_dataflow_op(TketOp::Ry, [qb1, angle]).unwrap();
+        let rx = builder.add_dataflow_op(TketOp::Rx, [qb2, angle]).unwrap();
+        let crz = builder.add_dataflow_op(TketOp::CRz, [meas.out_wire(0), ry.out_wire(0), angle]).unwrap();
+        let toffoli = builder.add_dataflow_op(TketOp::Toffoli, [crz.out_wire(0), crz.out_wire(1), rx.out_wire(0)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(toffoli.outputs_arr::<3>()).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.tableau().nb_qubits, 28);
+        assert_eq!(analysis.tableau().nb_stabs, 28);
+        // The relations over every column, which in the order of the Pauli strings below are for the wires:
+        // [in0, t.in, in1, ry.in, in2, rx.in, t.out, tdg.in, ry.out, crz.in1, rx.out, toffoli.in2,
+        // tdg.out, rz.in, rz.out, meas.in, meas.out, crz.in0, crz.out0, crz.out1, toffoli.in0, toffoli.in1
+        // toffoli.out0, toffoli.out1, toffoli.out2, out0, out1, out2]
+        let wires = [
+            WireRef::Input(OutgoingPort::from(0)),
+            WireRef::node_port(t.node(), IncomingPort::from(0)),
+            WireRef::Input(OutgoingPort::from(1)),
+            WireRef::node_port(ry.node(), IncomingPort::from(0)),
+            WireRef::Input(OutgoingPort::from(2)),
+            WireRef::node_port(rx.node(), IncomingPort::from(0)),
+            WireRef::node_port(t.node(), OutgoingPort::from(0)),
+            WireRef::node_port(tdg.node(), IncomingPort::from(0)),
+            WireRef::node_port(ry.node(), OutgoingPort::from(0)),
+            WireRef::node_port(crz.node(), IncomingPort::from(1)),
+            WireRef::node_port(rx.node(), OutgoingPort::from(0)),
+            WireRef::node_port(toffoli.node(), IncomingPort::from(2)),
+            WireRef::node_port(tdg.node(), OutgoingPort::from(0)),
+            WireRef::node_port(rz.node(), IncomingPort::from(0)),
+            WireRef::node_port(rz.node(), OutgoingPort::from(0)),
+            WireRef::node_port(meas.node(), IncomingPort::from(0)),
+            WireRef::node_port(meas.node(), OutgoingPort::from(0)),
+            WireRef::node_port(crz.node(), IncomingPort::from(0)),
+            WireRef::node_port(crz.node(), OutgoingPort::from(0)),
+            WireRef::node_port(crz.node(), OutgoingPort::from(1)),
+            WireRef::node_port(toffoli.node(), IncomingPort::from(0)),
+            WireRef::node_port(toffoli.node(), IncomingPort::from(1)),
+            WireRef::node_port(toffoli.node(), OutgoingPort::from(0)),
+            WireRef::node_port(toffoli.node(), OutgoingPort::from(1)),
+            WireRef::node_port(toffoli.node(), OutgoingPort::from(2)),
+            WireRef::Output(IncomingPort::from(0)),
+            WireRef::Output(IncomingPort::from(1)),
+            WireRef::Output(IncomingPort::from(2)),
+        ];
+        let expected = [
+            "XXIIIIXXIIIIXXXXXXXIXIXIIXII", // Xin0
+            "ZIIIIIIIIIIIIIIIIIIIIIIIIZII", // Zin0
+            "IZIIIIIIIIIIIIIIIIIIIIIIIZII", // Zt.in
+            "IIXXIIIIXXIIIIIIIIIXIXIXIIXI", // Xin1
+            "IIZZIIIIZIIIIIIIIIIIIIIIIIZI", // Zin1
+            "IIIYIIIIIYIIIIIIIIIXIXIXIIXI", // Yry.in
+            "IIIIXIIIIIIIIIIIIIIIIIIIIIIX", // Xin2
+            "IIIIZZIIIIZZIIIIIIIIIIIIZIIZ", // Zin2
+            "IIIIIXIIIIIIIIIIIIIIIIIIIIIX", // Xrx.in
+            "IIIIIIZIIIIIIIIIIIIIIIIIIZII", // Zt.out
+            "IIIIIIIZIIIIIIIIIIIIIIIIIZII", // Ztdg.in
+            "-IIIIIIIIYYIIIIIIIIIXIXIXIIXI", // Yry.out, negated as YY = -XX.ZZ on the wire from the Ry to the CRz
+            "IIIIIIIIIZIIIIIIIIIIIIIIIIZI", // Zcrz.in1
+            "IIIIIIIIIIXIIIIIIIIIIIIIIIIX", // Xrx.out
+            "IIIIIIIIIIIXIIIIIIIIIIIIIIIX", // Xtoffoli.in2
+            "IIIIIIIIIIIIZIIIIIIIIIIIIZII", // Ztdg.out
+            "IIIIIIIIIIIIIZIIIIIIIIIIIZII", // Zrz.in
+            "IIIIIIIIIIIIIIZIIIIIIIIIIZII", // Zrz.out
+            "IIIIIIIIIIIIIIIZIIIIIIIIIZII", // Zmeas.in
+            "IIIIIIIIIIIIIIIIZIIIIIIIIZII", // Zmeas.out
+            "IIIIIIIIIIIIIIIIIZIIIIIIIZII", // Zcrz.in0
+            "IIIIIIIIIIIIIIIIIIZIIIIIIZII", // Zcrz.out0
+            "IIIIIIIIIIIIIIIIIIIZIIIIIIZI", // Zcrz.out1
+            "IIIIIIIIIIIIIIIIIIIIZIIIIZII", // Ztoffoli.in0
+            "IIIIIIIIIIIIIIIIIIIIIZIIIIZI", // Ztoffoli.in1
+            "IIIIIIIIIIIIIIIIIIIIIIZIIZII", // Ztoffoli.out0
+            "IIIIIIIIIIIIIIIIIIIIIIIZIIZI", // Ztoffoli.out1
+            "IIIIIIIIIIIIIIIIIIIIIIIIXIIX", // Xtoffoli.out2
+        ];
+        assert_analysis_stabilizers(&hugr, hugr.entrypoint(), &expected, &wires);
+    }
+
+    #[test]
+    fn test_mixed_clifford() {
+        // Need to cover MeasureFree, QFree and Reset
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb2] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let reset = builder.add_dataflow_op(TketOp::Reset, [qb0]).unwrap();
+        let [qb0] = reset.outputs_arr();
+        let reset = reset.node();
+        let meas = builder.add_dataflow_op(TketOp::MeasureFree, [qb1]).unwrap().node();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb2] = builder.add_dataflow_op(TketOp::CX, [qb0, qb2]).unwrap().outputs_arr();
+        builder.add_dataflow_op(TketOp::QFree, [qb2]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([qb0]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // The freed qubit's column is removed, leaving the two inputs, the measured qubit, the qubit discarded by the Reset and the output
+        assert_eq!(analysis.tableau().nb_qubits, 5);
+        let in0 = analysis.input_column(OutgoingPort::from(0)).unwrap();
+        let in1 = analysis.input_column(OutgoingPort::from(1)).unwrap();
+        let meas_col = analysis.internal_column(&[meas], IncomingPort::from(0)).unwrap();
+        let reset_col = analysis.internal_column(&[reset], IncomingPort::from(0)).unwrap();
+        let out = analysis.output_column(IncomingPort::from(0)).unwrap();
+        assert_eq!([in0, in1, meas_col, reset_col, out].iter().unique().count(), 5);
+        // Tracing out the discarded qubit, the only relation left is that the measurement outcome is the parity of the inputs
+        let tab = analysis.relations_over(&[in0, in1, meas_col, out]);
+        assert_eq!(tab.nb_stabs, 1);
+        assert_eq!(tab.stabs[0].x.get_all_ones(4), Vec::<usize>::new());
+        assert_eq!(tab.stabs[0].z.get_all_ones(4), vec![0, 1, 2]);
+        assert!(!tab.stabs[0].sign);
+    }
+
+    #[test]
+    fn test_stabilizer_rank() {
+        // A purely Clifford circuit has complete information between its inputs and outputs
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        analysis.project_to_boundary();
+        assert_eq!(analysis.tableau().nb_qubits, 4);
+        assert_eq!(analysis.tableau().stabilizer_rank(), 4);
+        assert_eq!(analysis.tableau().analysis_completeness(), 1.0);
+        // A single T gate loses the X relation across it
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        analysis.project_to_boundary();
+        assert_eq!(analysis.tableau().nb_qubits, 4);
+        assert_eq!(analysis.tableau().stabilizer_rank(), 3);
+        assert!(analysis.tableau().analysis_completeness() < 1.0);
+    }
+
+    #[test]
+    fn test_classical_exp_box() {
+        // Qubit wire threaded through a ClassicalExpBox alongside classical data, deliberately at a different port index on each side
+        let mut builder = DFGBuilder::new(Signature::new(vec![bool_t(), qb_t()], vec![qb_t(), bool_t()])).unwrap();
+        let [b, qb0] = builder.input_wires_arr();
+        let exp_box = builder.add_dataflow_op(test_op("ClassicalExpBox"), [b, qb0]).unwrap();
+        let [qb0, b] = exp_box.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, b]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // No columns are allocated for the box, so the qubit is just the identity wire
+        assert_eq!(analysis.tableau().nb_qubits, 2);
+        assert!(analysis.columns().count(ColumnRole::InternalIn) == 0);
+        assert!(analysis.columns().count(ColumnRole::InternalOut) == 0);
+        assert_eq!(analysis.input_column(OutgoingPort::from(1)).unwrap(), 0);
+        assert_eq!(analysis.output_column(IncomingPort::from(0)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_bit_swap() {
+        // SWAPbits on two measurement outcomes, as it comes out of a tket1 circuit
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![qb_t(), qb_t(), bool_t(), bool_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let meas0 = builder.add_dataflow_op(TketOp::Measure, [qb0]).unwrap();
+        let meas1 = builder.add_dataflow_op(TketOp::Measure, [qb1]).unwrap();
+        let swap = builder.add_dataflow_op(test_op("SWAPbits"), [meas0.out_wire(1), meas1.out_wire(1)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([meas0.out_wire(0), meas1.out_wire(0), swap.out_wire(0), swap.out_wire(1)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // The swap is not an opaque node, and only exchanges the measurements the bits are the outcomes of
+        assert_eq!(analysis.statistics(&hugr).opaque_nodes, 0);
+        assert!(analysis.relations_for_node(&[swap.node()]).is_none());
+        assert_eq!(analysis.bits[&(swap.node(), OutgoingPort::from(0))].measurements, HashSet::from([meas1.node()]));
+        assert_eq!(analysis.bits[&(swap.node(), OutgoingPort::from(1))].measurements, HashSet::from([meas0.node()]));
+    }
+
+    #[test]
+    fn test_boundary_call() {
+        let mut module = ModuleBuilder::new();
+        let mut fun_builder = module.define_function("t_gate", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = fun_builder.input_wires_arr();
+        let t = fun_builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let fun = fun_builder.finish_with_outputs(t.outputs()).unwrap();
+        let mut main_builder = module.define_function("main", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = main_builder.input_wires_arr();
+        let call0 = main_builder.call(fun.handle(), &[], [qb]).unwrap();
+        let call1 = main_builder.call(fun.handle(), &[], call0.outputs()).unwrap();
+        let main = main_builder.finish_with_outputs(call1.outputs()).unwrap();
+        let hugr = module.finish_hugr().unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), &FunctionOpacity::Boundary).unwrap();
+        // Each call only adds the nested in/out columns and an internal/frontier pair for its output, however large the function body is
+        assert_eq!(analysis.tableau().nb_qubits, 2 + 2*4);
+        assert!(analysis.internal_column(&[t.node()], IncomingPort::from(0)).is_none());
+        assert!(analysis.internal_column(&[t.node()], OutgoingPort::from(0)).is_none());
+        for call in [call0.node(), call1.node()] {
+            // The summary of the function is just ZZ over its input and output
+            let summary = analysis.nested(call).unwrap();
+            assert_eq!(summary.tableau().nb_qubits, 2);
+            assert_eq!(summary.tableau().nb_stabs, 1);
+            assert_eq!(summary.input_column(OutgoingPort::from(0)).unwrap(), 0);
+            assert_eq!(summary.output_column(IncomingPort::from(0)).unwrap(), 1);
+            assert_eq!(summary.tableau().stabs[0].z.get_all_ones(2), vec![0, 1]);
+            assert_eq!(summary.tableau().stabs[0].x.get_all_ones(2), Vec::<usize>::new());
+            assert!(!summary.tableau().stabs[0].sign);
+        }
+        // Without projection, the internal columns of the T gate in each call are kept too
+        let inlined = StabilizerDataflow::run_dfg(&hugr, main.node(), &FunctionOpacity::Inline).unwrap();
+        assert_eq!(inlined.tableau().nb_qubits, 2 + 2*6);
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut module = ModuleBuilder::new();
+        let mut fun_builder = module.define_function("f", Signature::new_endo(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = fun_builder.input_wires_arr();
+        let [qb0] = fun_builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = fun_builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = fun_builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let fun = fun_builder.finish_with_outputs([qb0, qb1]).unwrap();
+        let mut main_builder = module.define_function("main", Signature::new_endo(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = main_builder.input_wires_arr();
+        let [qb0] = main_builder.add_dataflow_op(TketOp::S, [qb0]).unwrap().outputs_arr();
+        let call = main_builder.call(fun.handle(), &[], [qb0, qb1]).unwrap();
+        let main = main_builder.finish_with_outputs(call.outputs()).unwrap();
+        let hugr = module.finish_hugr().unwrap();
+        let summary = StabilizerDataflow::run_function(&hugr, fun.node(), &FunctionOpacity::Boundary).unwrap().to_summary(&hugr);
+        assert_eq!(summary.version, SUMMARY_FORMAT_VERSION);
+        assert_eq!(summary.nb_cols, 4);
+        assert_eq!(summary.inputs.iter().map(|(port, _)| *port).collect_vec(), vec![0, 1]);
+        assert_eq!(summary.outputs.iter().map(|(port, _)| *port).collect_vec(), vec![0, 1]);
+        assert!(summary.rows.iter().all(|row| row.len() == 5));
+        let loaded: AnalysisSummary = serde_json::from_str(&serde_json::to_string(&summary).unwrap()).unwrap();
+        assert_eq!(loaded, summary);
+        // Using the summary gives the same tableau as analysing the body, without visiting its T
+        let live = StabilizerDataflow::run_dfg(&hugr, main.node(), AnalysisConfig::default_boundary()).unwrap();
+        let cached = StabilizerDataflow::run_dfg(&hugr, main.node(), AnalysisConfig::default_boundary().with_summary("f", loaded.clone())).unwrap();
+        assert_eq!(cached.tableau().nb_qubits, live.tableau().nb_qubits);
+        assert!(cached.tableau().equivalent(live.tableau()));
+        for port in 0..2 {
+            assert_eq!(cached.internal_column(&[call.node()], IncomingPort::from(port)), live.internal_column(&[call.node()], IncomingPort::from(port)));
+            assert_eq!(cached.internal_column(&[call.node()], OutgoingPort::from(port)), live.internal_column(&[call.node()], OutgoingPort::from(port)));
+        }
+        assert_eq!(live.statistics(&hugr).t_count, 1);
+        assert_eq!(cached.statistics(&hugr).t_count, 0);
+        // A summary of a different body, or in another format, is ignored
+        let stale = AnalysisSummary { content_hash: loaded.content_hash.wrapping_add(1), ..loaded.clone() };
+        let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), AnalysisConfig::default_boundary().with_summary("f", stale)).unwrap();
+        assert_eq!(analysis.statistics(&hugr).t_count, 1);
+        let old = AnalysisSummary { version: SUMMARY_FORMAT_VERSION + 1, ..loaded.clone() };
+        let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), AnalysisConfig::default_boundary().with_summary("f", old)).unwrap();
+        assert_eq!(analysis.statistics(&hugr).t_count, 1);
+        // A malformed summary is an error rather than a silently wrong tableau
+        let malformed = AnalysisSummary { rows: vec!["+ZZ".to_string()], ..loaded };
+        let err = StabilizerDataflow::run_dfg(&hugr, main.node(), AnalysisConfig::default_boundary().with_summary("f", malformed)).err();
+        assert_eq!(err, Some(DataflowError::InvalidSummary { node: call.node() }));
+    }
+
+    #[test]
+    fn test_inline_call() {
+        let mut module = ModuleBuilder::new();
+        let mut fun_builder = module.define_function("rz", Signature::new(vec![qb_t(), rotation_type()], vec![qb_t()])).unwrap();
+        let [qb, angle] = fun_builder.input_wires_arr();
+        let rz = fun_builder.add_dataflow_op(TketOp::Rz, [qb, angle]).unwrap();
+        let fun = fun_builder.finish_with_outputs(rz.outputs()).unwrap();
+        let mut main_builder = module.define_function("main", Signature::new(vec![qb_t(), rotation_type(), rotation_type()], vec![qb_t()])).unwrap();
+        let [qb, angle0, angle1] = main_builder.input_wires_arr();
+        let t = main_builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let call0 = main_builder.call(fun.handle(), &[], [t.out_wire(0), angle0]).unwrap();
+        let call1 = main_builder.call(fun.handle(), &[], [call0.out_wire(0), angle1]).unwrap();
+        let main = main_builder.finish_with_outputs(call1.outputs()).unwrap();
+        let hugr = module.finish_hugr().unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), &FunctionOpacity::Inline).unwrap();
+        // The Rz in each call gets its own pair of internal columns, keyed by the call site
+        let rz0_in = analysis.internal_column(&[call0.node(), rz.node()], IncomingPort::from(0)).unwrap();
+        let rz0_out = analysis.internal_column(&[call0.node(), rz.node()], OutgoingPort::from(0)).unwrap();
+        let rz1_in = analysis.internal_column(&[call1.node(), rz.node()], IncomingPort::from(0)).unwrap();
+        let rz1_out = analysis.internal_column(&[call1.node(), rz.node()], OutgoingPort::from(0)).unwrap();
+        assert_eq!([rz0_in, rz0_out, rz1_in, rz1_out].iter().unique().count(), 4);
+        assert!(analysis.internal_column(&[rz.node()], IncomingPort::from(0)).is_none());
+        // Each Rz can be related through the call boundaries to its neighbours
+        let t_out = analysis.internal_column(&[t.node()], OutgoingPort::from(0)).unwrap();
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[t_out, rz0_in], &[], false)));
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[rz0_out, rz1_in], &[], false)));
+        assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[rz0_out, rz1_in], false)));
+        let out = analysis.output_column(IncomingPort::from(0)).unwrap();
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[rz1_out, out], &[], false)));
+        // X only passes through the diagonal gates together with X on the columns of each of them
+        let (inp, t_in) = (analysis.input_column(OutgoingPort::from(0)).unwrap(), analysis.internal_column(&[t.node()], IncomingPort::from(0)).unwrap());
+        assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[rz1_out, out], false)));
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[inp, t_in, t_out, rz0_in, rz0_out, rz1_in, rz1_out, out], false)));
+    }
+
+    #[test]
+    fn test_if_simple() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), bool_t()])).unwrap();
+        let [qb0, qb1, b] = builder.input_wires_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let [qb0] = t.outputs_arr();
+        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], b), [(qb_t(), qb0), (qb_t(), qb1)], vec![qb_t(); 2].into()).unwrap();
+        let mut cond0_builder = cond_builder.case_builder(0).unwrap();
+        let [c0q0, c0q1] = cond0_builder.input_wires_arr();
+        let [c0q0, c0q1] = cond0_builder.add_dataflow_op(TketOp::CX, [c0q0, c0q1]).unwrap().outputs_arr();
+        cond0_builder.finish_with_outputs([c0q0, c0q1]).unwrap();
+        let cond1_builder = cond_builder.case_builder(1).unwrap();
+        let [c1c0, c1q1] = cond1_builder.input_wires_arr();
+        cond1_builder.finish_with_outputs([c1c0, c1q1]).unwrap();
+        let cond = cond_builder.finish_sub_container().unwrap();
+        let [qb0, qb1] = cond.outputs_arr();
+        let tdg = builder.add_dataflow_op(TketOp::Tdg, [qb0]).unwrap();
+        let [qb0] = tdg.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, b]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.tableau().nb_qubits, 16);
+        assert_eq!(analysis.tableau().nb_stabs, 14);
+        assert_eq!(analysis.input_column(OutgoingPort::from(0)).unwrap(), 0);
+        assert_eq!(analysis.internal_column(&[t.node()], IncomingPort::from(0)).unwrap(), 1);
+        assert_eq!(analysis.input_column(OutgoingPort::from(1)).unwrap(), 2);
+        // Port 0 of the Conditional is the Sum, so the qubits are on ports 1 and 2
+        assert_eq!(analysis.internal_column(&[cond.node()], IncomingPort::from(2)).unwrap(), 3);
+        assert_eq!(analysis.internal_column(&[t.node()], OutgoingPort::from(0)).unwrap(), 4);
+        assert_eq!(analysis.internal_column(&[cond.node()], IncomingPort::from(1)).unwrap(), 5);
+        assert_eq!(analysis.columns().column_for_nested_port(&[cond.node()], IncomingPort::from(1)).unwrap(), 6);
+        assert_eq!(analysis.columns().column_for_nested_port(&[cond.node()], IncomingPort::from(2)).unwrap(), 7);
+        assert_eq!(analysis.columns().column_for_nested_port(&[cond.node()], OutgoingPort::from(0)).unwrap(), 8);
+        assert_eq!(analysis.columns().column_for_nested_port(&[cond.node()], OutgoingPort::from(1)).unwrap(), 9);
+        assert_eq!(analysis.internal_column(&[cond.node()], OutgoingPort::from(0)).unwrap(), 10);
+        assert_eq!(analysis.internal_column(&[tdg.node()], IncomingPort::from(0)).unwrap(), 11);
+        assert_eq!(analysis.internal_column(&[cond.node()], OutgoingPort::from(1)).unwrap(), 12);
+        assert_eq!(analysis.output_column(IncomingPort::from(1)).unwrap(), 13);
+        assert_eq!(analysis.internal_column(&[tdg.node()], OutgoingPort::from(0)).unwrap(), 14);
+        assert_eq!(analysis.output_column(IncomingPort::from(0)).unwrap(), 15);
+        // Both branches preserve Z on the control, so every column along qb0 outside the Conditional is Z-correlated with out0
+        for col in [0, 1, 4, 11, 14] {
+            assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[col, 15], &[], false)));
+        }
+        // Both branches preserve X on the target, so in1 is X-correlated with out1
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[2, 13], false)));
+        // The columns of the Conditional's ports are composed with those of its summary, which leaves each pair as a Bell pair that no longer carries the wire
+        for (col, nested_col) in [(5, 6), (3, 7), (10, 8), (12, 9)] {
+            assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[col, nested_col], &[], false)));
+            assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[col, nested_col], false)));
+        }
+        // But the CX branch means Z on the target is not preserved
+        assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[2, 13], &[], false)));
+    }
+
+    #[test]
+    fn test_if_x_branch() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), bool_t()])).unwrap();
+        let [qb0, qb1, b] = builder.input_wires_arr();
+        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], b), [(qb_t(), qb0), (qb_t(), qb1)], vec![qb_t(); 2].into()).unwrap();
+        let mut cond0_builder = cond_builder.case_builder(0).unwrap();
+        let [c0q0, c0q1] = cond0_builder.input_wires_arr();
+        let [c0q0] = cond0_builder.add_dataflow_op(TketOp::X, [c0q0]).unwrap().outputs_arr();
+        cond0_builder.finish_with_outputs([c0q0, c0q1]).unwrap();
+        let cond1_builder = cond_builder.case_builder(1).unwrap();
+        let [c1q0, c1q1] = cond1_builder.input_wires_arr();
+        cond1_builder.finish_with_outputs([c1q0, c1q1]).unwrap();
+        let cond = cond_builder.finish_sub_container().unwrap();
+        let [qb0, qb1] = cond.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, b]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // The summary of the Conditional has columns for in qubits (ports 1, 2) then out qubits (ports 0, 1)
+        let cond_analysis = analysis.nested(cond.node()).unwrap();
+        assert_eq!(cond_analysis.tableau().nb_qubits, 4);
+        assert_eq!(cond_analysis.input_column(OutgoingPort::from(1)).unwrap(), 0);
+        assert_eq!(cond_analysis.input_column(OutgoingPort::from(2)).unwrap(), 1);
+        assert_eq!(cond_analysis.output_column(IncomingPort::from(0)).unwrap(), 2);
+        assert_eq!(cond_analysis.output_column(IncomingPort::from(1)).unwrap(), 3);
+        // The branches disagree on the sign of Z on qb0, so only its X relation survives the join
+        assert_eq!(cond_analysis.tableau().nb_stabs, 3);
+        assert!(cond_analysis.tableau().stabilizes(&cond_analysis.tableau().pauli(&[], &[0, 2], false)));
+        assert!(!cond_analysis.tableau().stabilizes(&cond_analysis.tableau().pauli(&[0, 2], &[], false)));
+        assert!(!cond_analysis.tableau().stabilizes(&cond_analysis.tableau().pauli(&[0, 2], &[], true)));
+        assert!(cond_analysis.tableau().stabilizes(&cond_analysis.tableau().pauli(&[1, 3], &[], false)));
+        assert!(cond_analysis.tableau().stabilizes(&cond_analysis.tableau().pauli(&[], &[1, 3], false)));
+        // The same holds between the inputs and outputs of the whole circuit
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[analysis.input_column(OutgoingPort::from(0)).unwrap(), analysis.output_column(IncomingPort::from(0)).unwrap()], false)));
+        assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[analysis.input_column(OutgoingPort::from(0)).unwrap(), analysis.output_column(IncomingPort::from(0)).unwrap()], &[], false)));
+    }
+
+    #[test]
+    fn test_random_circuits() {
+        // Random circuits mixing gates, measurements, Conditionals, TailLoops, opaque ops and allocations are all valid and analysed without errors
+        for seed in 0..1000 {
+            let hugr = random_circuit(&RandomCircuitConfig { seed, ..RandomCircuitConfig::default() });
+            hugr.validate().unwrap_or_else(|e| panic!("Random circuit for seed {seed} is invalid: {e}"));
+            StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap_or_else(|e| panic!("Analysis of the random circuit for seed {seed} failed: {e}"));
+        }
+    }
+
+    #[test]
+    fn test_loop_null() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), bool_t()])).unwrap();
+        let [qb0, qb1, b] = builder.input_wires_arr();
+        let reset = builder.add_dataflow_op(TketOp::Reset, [qb0]).unwrap();
+        let [qb0] = reset.outputs_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap();
+        let [qb1] = t.outputs_arr();
+        let mut loop_builder = builder.tail_loop_builder([], [(qb_t(), qb0), (qb_t(), qb1), (bool_t(), b)], type_row![]).unwrap();
+        let [loop_qb0, loop_qb1, loop_b] = loop_builder.input_wires_arr();
+        let loop_t = loop_builder.add_dataflow_op(TketOp::T, [loop_qb0]).unwrap();
+        let [loop_qb0] = loop_t.outputs_arr();
+        let loop_tdg = loop_builder.add_dataflow_op(TketOp::Tdg, [loop_qb1]).unwrap();
+        let [loop_qb1] = loop_tdg.outputs_arr();
+        let tl = loop_builder.finish_with_outputs(loop_b, [loop_qb0, loop_qb1, loop_b]).unwrap();
+        let [qb0, qb1, b] = tl.outputs_arr();
+        let tdg = builder.add_dataflow_op(TketOp::Tdg, [qb1]).unwrap();
+        let [qb1] = tdg.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, b]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // The summary of the loop has an in and an out column for each carried qubit
+        let tl_analysis = analysis.nested(tl.node()).unwrap();
+        assert_eq!(tl_analysis.tableau().nb_qubits, 4);
+        let tl_in0 = tl_analysis.input_column(OutgoingPort::from(0)).unwrap();
+        let tl_out0 = tl_analysis.output_column(IncomingPort::from(0)).unwrap();
+        let tl_in1 = tl_analysis.input_column(OutgoingPort::from(1)).unwrap();
+        let tl_out1 = tl_analysis.output_column(IncomingPort::from(1)).unwrap();
+        // Diagonal gates in the body preserve only the Z relations, whether or not the body is run
+        assert_eq!(tl_analysis.tableau().nb_stabs, 2);
+        assert!(tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_in0, tl_out0], &[], false)));
+        assert!(tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_in1, tl_out1], &[], false)));
+        assert!(!tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[], &[tl_in0, tl_out0], false)));
+        assert!(!tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[], &[tl_in1, tl_out1], false)));
+        // So the T before the loop and the Tdg after it act on the same Z parity
+        let t_out = analysis.internal_column(&[t.node()], OutgoingPort::from(0)).unwrap();
+        let tdg_in = analysis.internal_column(&[tdg.node()], IncomingPort::from(0)).unwrap();
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[t_out, tdg_in], &[], false)));
+    }
+
+    #[test]
+    fn test_loop_measured() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), bool_t()])).unwrap();
+        let [qb0, qb1, b] = builder.input_wires_arr();
+        let mut loop_builder = builder.tail_loop_builder([], [(qb_t(), qb0), (qb_t(), qb1), (bool_t(), b)], type_row![]).unwrap();
+        let [loop_qb0, loop_qb1, loop_b] = loop_builder.input_wires_arr();
+        // The carried qubit is measured and replaced by a fresh one on each iteration
+        let [_] = loop_builder.add_dataflow_op(TketOp::MeasureFree, [loop_qb0]).unwrap().outputs_arr();
+        let [loop_qb0] = loop_builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [loop_qb1] = loop_builder.add_dataflow_op(TketOp::T, [loop_qb1]).unwrap().outputs_arr();
+        let tl = loop_builder.finish_with_outputs(loop_b, [loop_qb0, loop_qb1, loop_b]).unwrap();
+        let [qb0, qb1, b] = tl.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, b]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let tl_analysis = analysis.nested(tl.node()).unwrap();
+        let tl_in0 = tl_analysis.input_column(OutgoingPort::from(0)).unwrap();
+        let tl_out0 = tl_analysis.output_column(IncomingPort::from(0)).unwrap();
+        let tl_in1 = tl_analysis.input_column(OutgoingPort::from(1)).unwrap();
+        let tl_out1 = tl_analysis.output_column(IncomingPort::from(1)).unwrap();
+        // No relation survives between the input and output of the measured qubit, though the output is always freshly allocated in |0>
+        // The other qubit keeps its Z relation
+        assert_eq!(tl_analysis.tableau().nb_stabs, 2);
+        assert!(tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_out0], &[], false)));
+        assert!(!tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_in0, tl_out0], &[], false)));
+        assert!(!tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[], &[tl_in0, tl_out0], false)));
+        assert!(tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_in1, tl_out1], &[], false)));
+    }
+
+    #[test]
+    fn test_loop_fixpoint() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), bool_t()])).unwrap();
+        let [qb0, qb1, b] = builder.input_wires_arr();
+        let mut loop_builder = builder.tail_loop_builder([], [(qb_t(), qb0), (qb_t(), qb1), (bool_t(), b)], type_row![]).unwrap();
+        let [loop_qb0, loop_qb1, loop_b] = loop_builder.input_wires_arr();
+        let [loop_qb0] = loop_builder.add_dataflow_op(TketOp::S, [loop_qb0]).unwrap().outputs_arr();
+        let tl = loop_builder.finish_with_outputs(loop_b, [loop_qb0, loop_qb1, loop_b]).unwrap();
+        let [qb0, qb1, b] = tl.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, b]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let tl_analysis = analysis.nested(tl.node()).unwrap();
+        let tl_in0 = tl_analysis.input_column(OutgoingPort::from(0)).unwrap();
+        let tl_out0 = tl_analysis.output_column(IncomingPort::from(0)).unwrap();
+        let tl_in1 = tl_analysis.input_column(OutgoingPort::from(1)).unwrap();
+        let tl_out1 = tl_analysis.output_column(IncomingPort::from(1)).unwrap();
+        // Only Z commutes with every power of S, so neither X nor Y relations survive any number of iterations
+        assert_eq!(tl_analysis.tableau().nb_stabs, 3);
+        assert!(tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_in0, tl_out0], &[], false)));
+        assert!(!tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[], &[tl_in0, tl_out0], false)));
+        assert!(!tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_out0], &[tl_in0, tl_out0], false)));
+        assert!(!tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_in0, tl_out0], &[tl_in0, tl_out0], true)));
+        // The untouched qubit keeps the full identity
+        assert!(tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_in1, tl_out1], &[], false)));
+        assert!(tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[], &[tl_in1, tl_out1], false)));
+    }
+
+    #[test]
+    fn test_loop_just_outputs() {
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.input_wires_arr();
+        let mut loop_builder = builder.tail_loop_builder([], [(qb_t(), qb0)], vec![qb_t()].into()).unwrap();
+        let [loop_qb0] = loop_builder.input_wires_arr();
+        let [loop_qb0] = loop_builder.add_dataflow_op(TketOp::H, [loop_qb0]).unwrap().outputs_arr();
+        // The loop breaks out with a freshly reset qubit
+        let [fresh] = loop_builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [fresh] = loop_builder.add_dataflow_op(TketOp::Reset, [fresh]).unwrap().outputs_arr();
+        let tl_sig = loop_builder.loop_signature().unwrap().clone();
+        let brk = loop_builder.make_break(tl_sig, [fresh]).unwrap();
+        let tl = loop_builder.finish_with_outputs(brk, [loop_qb0]).unwrap();
+        let [fresh, qb0] = tl.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, fresh]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let tl_analysis = analysis.nested(tl.node()).unwrap();
+        assert_eq!(tl_analysis.tableau().nb_qubits, 3);
+        let tl_in = tl_analysis.input_column(OutgoingPort::from(0)).unwrap();
+        let tl_out = tl_analysis.output_column(IncomingPort::from(1)).unwrap();
+        let tl_fresh = tl_analysis.output_column(IncomingPort::from(0)).unwrap();
+        assert!(tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_fresh], &[], false)));
+        // The carried qubit has an odd number of Hadamards applied, so no relation holds for it
+        assert!(!tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_in], &[tl_out], false)));
+        assert!(!tl_analysis.tableau().stabilizes(&tl_analysis.tableau().pauli(&[tl_in, tl_out], &[], false)));
+        // The +Z stabilizer reaches the output of the whole circuit
+        let out = analysis.output_column(IncomingPort::from(1)).unwrap();
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[out], &[], false)));
+    }
+
+    #[test]
+    fn test_nested_dfg() {
+        // H;CX on the inputs, once directly and once wrapped in a nested DFG
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let flat_hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let mut nested_builder = builder.dfg_builder(endo_sig(vec![qb_t(), qb_t()]), [qb0, qb1]).unwrap();
+        let [qb0, qb1] = nested_builder.input_wires_arr();
+        let [qb0] = nested_builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = nested_builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let nested = nested_builder.finish_with_outputs([qb0, qb1]).unwrap();
+        let nested_hugr = builder.finish_hugr_with_outputs(nested.outputs()).unwrap();
+        let flat = StabilizerDataflow::run_dfg(&flat_hugr, flat_hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&nested_hugr, nested_hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // The DFG is not treated as opaque, so its summary is the full unitary
+        let summary = analysis.nested(nested.node()).unwrap();
+        assert_eq!(summary.tableau().nb_qubits, 4);
+        assert_eq!(summary.tableau().nb_stabs, 4);
+        // Over the boundary, both give the same stabilizers
+        let boundary = |a: &StabilizerDataflow<_>| {
+            let unified_index = (0..2).flat_map(|i| [
+                (a.input_column(OutgoingPort::from(i)).unwrap(), 2*i),
+                (a.output_column(IncomingPort::from(i)).unwrap(), 2*i + 1),
+            ]).collect();
+            restrict_tab(a.tableau(), &unified_index)
+        };
+        assert!(boundary(&analysis).equivalent(&boundary(&flat)));
+        assert_eq!(boundary(&analysis).nb_stabs, 4);
+    }
+
+    #[test]
+    fn test_doubly_nested_dfg() {
+        // H;CX;S split over two levels of nested DFG: H in the outer one, CX in the inner one, and S after both
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::S, [qb1]).unwrap().outputs_arr();
+        let flat_hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let mut outer_builder = builder.dfg_builder(endo_sig(vec![qb_t(), qb_t()]), [qb0, qb1]).unwrap();
+        let [qb0, qb1] = outer_builder.input_wires_arr();
+        let [qb0] = outer_builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let mut inner_builder = outer_builder.dfg_builder(endo_sig(vec![qb_t(), qb_t()]), [qb0, qb1]).unwrap();
+        let [qb0, qb1] = inner_builder.input_wires_arr();
+        let cx = inner_builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap();
+        let inner = inner_builder.finish_with_outputs(cx.outputs()).unwrap();
+        let outer = outer_builder.finish_with_outputs(inner.outputs()).unwrap();
+        let [qb0, qb1] = outer.outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::S, [qb1]).unwrap().outputs_arr();
+        let nested_hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let flat = StabilizerDataflow::run_dfg(&flat_hugr, flat_hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&nested_hugr, nested_hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // Each level is summarised over its own boundary, the inner one within the analysis of the outer one
+        let outer_summary = analysis.nested(outer.node()).unwrap();
+        assert_eq!(outer_summary.tableau().nb_qubits, 4);
+        assert_eq!(outer_summary.tableau().nb_stabs, 4);
+        let inner_summary = outer_summary.nested(inner.node()).unwrap();
+        assert_eq!(inner_summary.tableau().nb_qubits, 4);
+        assert_eq!(inner_summary.tableau().nb_stabs, 4);
+        assert!(analysis.internal_column(&[cx.node()], IncomingPort::from(0)).is_none());
+        let boundary = |a: &StabilizerDataflow<_>| {
+            let unified_index = (0..2).flat_map(|i| [
+                (a.input_column(OutgoingPort::from(i)).unwrap(), 2*i),
+                (a.output_column(IncomingPort::from(i)).unwrap(), 2*i + 1),
+            ]).collect();
+            restrict_tab(a.tableau(), &unified_index)
+        };
+        assert!(boundary(&analysis).equivalent(&boundary(&flat)));
+        assert_eq!(boundary(&analysis).nb_stabs, 4);
+        // Without the S, the circuit would not be equivalent
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let other_hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let other = StabilizerDataflow::run_dfg(&other_hugr, other_hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(!boundary(&analysis).equivalent(&boundary(&other)));
+    }
+
+    #[test]
+    fn test_phase_polynomial() {
+        // T q0; CX q0 q1; T q1; CX q0 q1; T q1
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let in0 = analysis.input_column(OutgoingPort::from(0)).unwrap();
+        let in1 = analysis.input_column(OutgoingPort::from(1)).unwrap();
+        let polynomial = analysis.phase_polynomial(&hugr);
+        assert_eq!(polynomial.len(), 3);
+        assert_eq!(polynomial.get(&vec![in0]), Some(&0.25));
+        assert_eq!(polynomial.get(&vec![in0, in1]), Some(&0.25));
+        assert_eq!(polynomial.get(&vec![in1]), Some(&0.25));
+
+        // T and Tdg on the same parity cancel, while two Ts on the same parity combine; an X before a T negates its angle
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Tdg, [qb0]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::X, [qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let in1 = analysis.input_column(OutgoingPort::from(1)).unwrap();
+        let polynomial = analysis.phase_polynomial(&hugr);
+        assert_eq!(polynomial.len(), 1);
+        assert_eq!(polynomial.get(&vec![in1]), Some(&1.75));
+
+        // After an H, the parity is no longer over the inputs
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb0] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(analysis.phase_polynomial(&hugr).is_empty());
+    }
+
+    #[test]
+    fn test_t_count_lower_bound() {
+        let bound = |nb_qubits: usize, gates: &[(TketOp, &[usize])]| {
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(); nb_qubits])).unwrap();
+            let mut qbs = builder.input_wires().collect_vec();
+            for (op, args) in gates {
+                let outs = builder.add_dataflow_op(*op, args.iter().map(|q| qbs[*q])).unwrap().outputs().collect_vec();
+                for (q, out) in args.iter().zip(outs) {
+                    qbs[*q] = out;
+                }
+            }
+            let hugr = builder.finish_hugr_with_outputs(qbs).unwrap();
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            analysis.t_count_lower_bound(&hugr)
+        };
+        assert_eq!(bound(1, &[(TketOp::T, &[0])]), 1);
+        // T;T is an S gate, and T;Tdg the identity
+        assert_eq!(bound(1, &[(TketOp::T, &[0]), (TketOp::T, &[0])]), 0);
+        assert_eq!(bound(1, &[(TketOp::T, &[0]), (TketOp::Tdg, &[0])]), 0);
+        assert_eq!(bound(2, &[(TketOp::T, &[0]), (TketOp::T, &[1])]), 2);
+        // The usual decomposition of Toffoli has 7 T gates, and CCZ has nullity 3
+        let toffoli: &[(TketOp, &[usize])] = &[
+            (TketOp::H, &[2]), (TketOp::CX, &[1, 2]), (TketOp::Tdg, &[2]), (TketOp::CX, &[0, 2]), (TketOp::T, &[2]), (TketOp::CX, &[1, 2]), (TketOp::Tdg, &[2]), (TketOp::CX, &[0, 2]),
+            (TketOp::T, &[1]), (TketOp::T, &[2]), (TketOp::H, &[2]), (TketOp::CX, &[0, 1]), (TketOp::T, &[0]), (TketOp::Tdg, &[1]), (TketOp::CX, &[0, 1]),
+        ];
+        assert_eq!(bound(3, toffoli), 3);
+        // The axes of T;H;T do not commute, so only the trivial bound is known
+        assert_eq!(bound(1, &[(TketOp::T, &[0]), (TketOp::H, &[0]), (TketOp::T, &[0])]), 0);
+    }
+
+    #[test]
+    fn test_cfg_diamond() {
+        // As in test_if_simple, but branching between two blocks of a CFG rather than the cases of a Conditional
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), bool_t()])).unwrap();
+        let [qb0, qb1, b] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap().outputs_arr();
+        let mut cfg_builder = builder.cfg_builder([(bool_t(), b), (qb_t(), qb0), (qb_t(), qb1)], vec![qb_t(); 2].into()).unwrap();
+        let entry_builder = cfg_builder.entry_builder([type_row![], type_row![]], vec![qb_t(); 2].into()).unwrap();
+        let [pred, qb0, qb1] = entry_builder.input_wires_arr();
+        let entry = entry_builder.finish_with_outputs(pred, [qb0, qb1]).unwrap();
+        let mut cx_builder = cfg_builder.simple_block_builder(endo_sig(vec![qb_t(); 2]), 1).unwrap();
+        let [qb0, qb1] = cx_builder.input_wires_arr();
+        let [qb0, qb1] = cx_builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let unit = cx_builder.add_load_value(Value::unary_unit_sum());
+        let cx_block = cx_builder.finish_with_outputs(unit, [qb0, qb1]).unwrap();
+        let mut id_builder = cfg_builder.simple_block_builder(endo_sig(vec![qb_t(); 2]), 1).unwrap();
+        let [qb0, qb1] = id_builder.input_wires_arr();
+        let unit = id_builder.add_load_value(Value::unary_unit_sum());
+        let id_block = id_builder.finish_with_outputs(unit, [qb0, qb1]).unwrap();
+        let exit = cfg_builder.exit_block();
+        cfg_builder.branch(&entry, 0, &cx_block).unwrap();
+        cfg_builder.branch(&entry, 1, &id_block).unwrap();
+        cfg_builder.branch(&cx_block, 0, &exit).unwrap();
+        cfg_builder.branch(&id_block, 0, &exit).unwrap();
+        let cfg = cfg_builder.finish_sub_container().unwrap();
+        let [qb0, qb1] = cfg.outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Tdg, [qb0]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, b]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let cfg_analysis = analysis.nested(cfg.node()).unwrap();
+        for block in [entry.node(), cx_block.node(), id_block.node()] {
+            assert!(cfg_analysis.nested(block).is_some());
+        }
+        // Port 0 of the CFG is the bool, so the qubits are on ports 1 and 2
+        assert_eq!(cfg_analysis.tableau().nb_qubits, 4);
+        assert_eq!(cfg_analysis.input_column(OutgoingPort::from(1)).unwrap(), 0);
+        assert_eq!(cfg_analysis.input_column(OutgoingPort::from(2)).unwrap(), 1);
+        assert_eq!(cfg_analysis.output_column(IncomingPort::from(0)).unwrap(), 2);
+        assert_eq!(cfg_analysis.output_column(IncomingPort::from(1)).unwrap(), 3);
+        // Both paths preserve Z on the control and X on the target, but not Z on the target
+        assert!(cfg_analysis.tableau().stabilizes(&cfg_analysis.tableau().pauli(&[0, 2], &[], false)));
+        assert!(cfg_analysis.tableau().stabilizes(&cfg_analysis.tableau().pauli(&[], &[1, 3], false)));
+        assert!(!cfg_analysis.tableau().stabilizes(&cfg_analysis.tableau().pauli(&[1, 3], &[], false)));
+        // So Z on the T and Tdg remain related across the CFG
+        let t_out = analysis.internal_column(&[hugr.single_linked_output(cfg.node(), IncomingPort::from(1)).unwrap().0], OutgoingPort::from(0)).unwrap();
+        let tdg_in = analysis.internal_column(&[hugr.single_linked_input(cfg.node(), OutgoingPort::from(0)).unwrap().0], IncomingPort::from(0)).unwrap();
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[t_out, tdg_in], &[], false)));
+    }
+
+    #[test]
+    fn test_call_indirect() {
+        let mut module = ModuleBuilder::new();
+        let mut fun_builder = module.define_function("t_gate", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = fun_builder.input_wires_arr();
+        let t = fun_builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let fun = fun_builder.finish_with_outputs(t.outputs()).unwrap();
+        let fun_type = Type::new_function(Signature::new_endo(vec![qb_t()]));
+        let mut main_builder = module.define_function("main", Signature::new(vec![fun_type, qb_t()], vec![qb_t()])).unwrap();
+        let [fun_arg, qb] = main_builder.input_wires_arr();
+        // The first call can be resolved through the LoadFunction, but not the second through the function value passed in
+        let fun_value = main_builder.load_func(fun.handle(), &[]).unwrap();
+        let call0 = main_builder.add_dataflow_op(CallIndirect { signature: Signature::new_endo(vec![qb_t()]) }, [fun_value, qb]).unwrap();
+        let call1 = main_builder.add_dataflow_op(CallIndirect { signature: Signature::new_endo(vec![qb_t()]) }, [fun_arg, call0.out_wire(0)]).unwrap();
+        let main = main_builder.finish_with_outputs(call1.outputs()).unwrap();
+        let hugr = module.finish_hugr().unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), &FunctionOpacity::Boundary).unwrap();
+        // The resolved call is summarised as a Call would be, with the qubit on port 1 after the function value
+        let summary = analysis.nested(call0.node()).unwrap();
+        assert_eq!(summary.tableau().nb_stabs, 1);
+        assert_eq!(summary.input_column(OutgoingPort::from(1)).unwrap(), 0);
+        // The unresolved call is opaque over its qubit ports only
+        assert!(analysis.nested(call1.node()).is_none());
+        assert!(analysis.internal_column(&[call1.node()], IncomingPort::from(0)).is_none());
+        let call1_in = analysis.internal_column(&[call1.node()], IncomingPort::from(1)).unwrap();
+        let call1_out = analysis.internal_column(&[call1.node()], OutgoingPort::from(0)).unwrap();
+        assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[call1_in, call1_out], &[], false)));
+        // The resolved call's port columns are consumed by composing with its summary, through which Z passes on to the opaque call while X is lost to the T
+        let qb_in = analysis.input_column(OutgoingPort::from(1)).unwrap();
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[qb_in, call1_in], &[], false)));
+        assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[qb_in, call1_in], false)));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing() {
+        use std::sync::{Arc, Mutex};
+        use tracing_subscriber::{layer::{Context, SubscriberExt}, registry::Registry, Layer};
+
+        // Records the names of spans and events as they are emitted
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        impl<S: tracing::Subscriber> Layer<S> for Recorder {
+            fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, _id: &tracing::span::Id, _ctx: Context<'_, S>) {
+                self.0.lock().unwrap().push(format!("span:{}", attrs.metadata().name()));
+            }
+            fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+                self.0.lock().unwrap().push(format!("event:{}", event.metadata().name()));
+            }
+        }
+
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.input_wires_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let names = Arc::new(Mutex::new(vec![]));
+        let subscriber = Registry::default().with(Recorder(names.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        });
+        let names = names.lock().unwrap();
+        assert_eq!(names.first().unwrap(), "span:stabilizer_dataflow");
+        let count = |name: &str| names.iter().filter(|n| *n == name).count();
+        // Input, QAlloc, H, CX, T, Output
+        assert_eq!(count("event:run_dfg_node"), 6);
+        assert_eq!(count("span:apply_quantum_gate"), 4);
+        assert_eq!(count("event:apply_quantum_gate"), 4);
+        assert_eq!(count("span:apply_opaque"), 0);
+        assert_eq!(count("span:visit_node"), 6);
+        assert!(count("event:add_col") > 0);
+        // The T imposes its relation, dropping a row
+        assert!(count("event:add_relation") > 0);
+    }
+
+    #[test]
+    fn test_recursive_call() {
+        // f applies a T and then calls itself
+        let mut module = ModuleBuilder::new();
+        let mut f_builder = module.define_function("f", Signature::new_endo(vec![qb_t()])).unwrap();
+        let f_handle = FuncID::<true>::from(f_builder.container_node());
+        let [qb] = f_builder.input_wires_arr();
+        let [qb] = f_builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let f_call = f_builder.call(&f_handle, &[], [qb]).unwrap();
+        let f = f_builder.finish_with_outputs(f_call.outputs()).unwrap();
+        let mut main_builder = module.define_function("main", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = main_builder.input_wires_arr();
+        let call0 = main_builder.call(f.handle(), &[], [qb]).unwrap();
+        let call1 = main_builder.call(f.handle(), &[], call0.outputs()).unwrap();
+        let main = main_builder.finish_with_outputs(call1.outputs()).unwrap();
+        let hugr = module.finish_hugr().unwrap();
+        for fun_op in [FunctionOpacity::Boundary, FunctionOpacity::Inline] {
+            let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), &fun_op).unwrap();
+            // f is analysed at each call site, with the recursive call inside treated as opaque
+            for call in [call0.node(), call1.node()] {
+                let f_analysis = analysis.nested(call).unwrap();
+                assert!(f_analysis.nested(f_call.node()).is_none());
+                // Nothing is known through the opaque call, so the output of f is unrelated to its input
+                let in_col = analysis.internal_column(&[call], IncomingPort::from(0)).unwrap();
+                let out_col = analysis.internal_column(&[call], OutgoingPort::from(0)).unwrap();
+                assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[in_col, out_col], &[], false)));
+            }
+            // The port columns of both calls are consumed by composing with the summaries of f, so nothing relates the input of main to its output
+            let in_col = analysis.input_column(OutgoingPort::from(0)).unwrap();
+            let out_col = analysis.output_column(IncomingPort::from(0)).unwrap();
+            assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[in_col, out_col], &[], false)));
+            assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[in_col, out_col], false)));
+        }
+        // Analysing the recursive function directly also terminates
+        let analysis = StabilizerDataflow::run_dfg(&hugr, f.node(), &FunctionOpacity::Inline).unwrap();
+        assert!(analysis.nested(f_call.node()).is_none());
+    }
+
+    #[test]
+    fn test_opacity_policy() {
+        let mut module = ModuleBuilder::new();
+        let mut small_builder = module.define_function("small", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = small_builder.input_wires_arr();
+        let small_t = small_builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let small = small_builder.finish_with_outputs(small_t.outputs()).unwrap();
+        let mut big_builder = module.define_function("big", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = big_builder.input_wires_arr();
+        let big_t = big_builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let big = big_builder.finish_with_outputs(big_t.outputs()).unwrap();
+        let mut main_builder = module.define_function("main", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = main_builder.input_wires_arr();
+        let small_call = main_builder.call(small.handle(), &[], [qb]).unwrap();
+        let big_call = main_builder.call(big.handle(), &[], small_call.outputs()).unwrap();
+        let main = main_builder.finish_with_outputs(big_call.outputs()).unwrap();
+        let hugr = module.finish_hugr().unwrap();
+        let inlined = |analysis: &StabilizerDataflow<_>, call: Node, t: Node| analysis.internal_column(&[call, t], IncomingPort::from(0)).is_some();
+
+        // Only small is inlined, big is summarised over its boundary
+        let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), AnalysisConfig::default_boundary().inline("small")).unwrap();
+        assert!(inlined(&analysis, small_call.node(), small_t.node()));
+        assert!(!inlined(&analysis, big_call.node(), big_t.node()));
+        assert!(analysis.nested(big_call.node()).is_some());
+
+        // Only big is opaque
+        let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), AnalysisConfig::default_inline().opaque("big")).unwrap();
+        assert!(inlined(&analysis, small_call.node(), small_t.node()));
+        assert!(analysis.nested(big_call.node()).is_none());
+
+        // Overrides by node take precedence over those by name
+        let policy = AnalysisConfig::default_opaque().boundary("big").with_node(big.node(), FunctionOpacity::Inline);
+        assert!(matches!(policy.for_function(&hugr, big.node()), FunctionOpacity::Inline));
+        assert!(matches!(policy.for_function(&hugr, small.node()), FunctionOpacity::Opaque));
+        let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), policy).unwrap();
+        assert!(analysis.nested(small_call.node()).is_none());
+        assert!(inlined(&analysis, big_call.node(), big_t.node()));
+    }
+
+    #[test]
+    fn test_run_module() {
+        let mut module = ModuleBuilder::new();
+        let mut fun_builder = module.define_function("t_gate", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = fun_builder.input_wires_arr();
+        let t = fun_builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let fun = fun_builder.finish_with_outputs(t.outputs()).unwrap();
+        let mut main_builder = module.define_function("main", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = main_builder.input_wires_arr();
+        let call = main_builder.call(fun.handle(), &[], [qb]).unwrap();
+        let main = main_builder.finish_with_outputs(call.outputs()).unwrap();
+        let mut hugr = module.finish_hugr().unwrap();
+        let analyses = StabilizerDataflow::run_module(&hugr, &FunctionOpacity::Boundary).unwrap();
+        assert_eq!(analyses.len(), 2);
+        // t_gate on its own keeps the internal columns of its T, while main sees the call through its summary
+        let fun_analysis = analyses.get(&fun.node()).unwrap();
+        assert!(fun_analysis.internal_column(&[t.node()], IncomingPort::from(0)).is_some());
+        let main_analysis = analyses.get(&main.node()).unwrap();
+        assert!(main_analysis.nested(call.node()).is_some());
+        assert_eq!(main_analysis.tableau().nb_qubits, StabilizerDataflow::run_function(&hugr, main.node(), &FunctionOpacity::Boundary).unwrap().tableau().nb_qubits);
+        // Once an entry point is marked, only that function is analysed
+        hugr.set_entrypoint(main.node());
+        let analyses = StabilizerDataflow::run_module(&hugr, &FunctionOpacity::Boundary).unwrap();
+        assert_eq!(analyses.keys().copied().collect_vec(), vec![main.node()]);
+    }
+
+    /// A module of eight independent functions with different numbers of qubits and T gates, so that any mix-up between them shows in the columns, along with their FuncDefn nodes
+    fn independent_functions() -> (hugr::Hugr, Vec<Node>) {
+        let mut module = ModuleBuilder::new();
+        let funcs = (0..8).map(|i| {
+            let mut fun_builder = module.define_function(format!("f{i}"), Signature::new_endo(vec![qb_t(); 1 + i % 3])).unwrap();
+            let mut qbs = fun_builder.input_wires().collect_vec();
+            for _ in 0..i {
+                qbs[0] = fun_builder.add_dataflow_op(TketOp::T, [qbs[0]]).unwrap().out_wire(0);
+            }
+            fun_builder.finish_with_outputs(qbs).unwrap().node()
+        }).collect_vec();
+        (module.finish_hugr().unwrap(), funcs)
+    }
+
+    #[test]
+    fn test_run_module_independent() {
+        fn assert_send<T: Send>() {}
+        assert_send::<StabilizerDataflow<hugr::Hugr>>();
+        assert_send::<crate::tableau::Tableau>();
+        let (hugr, funcs) = independent_functions();
+        let analyses = StabilizerDataflow::run_module(&hugr, &FunctionOpacity::Boundary).unwrap();
+        assert_eq!(analyses.len(), 8);
+        for f in funcs {
+            let alone = StabilizerDataflow::run_function(&hugr, f, &FunctionOpacity::Boundary).unwrap();
+            assert_eq!(analyses[&f].tableau().nb_qubits, alone.tableau().nb_qubits);
+            assert_eq!(analyses[&f].statistics(&hugr), alone.statistics(&hugr));
+            assert!(StabilizerDataflow::check_clifford_equivalence(&analyses[&f], &alone));
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_run_module_par() {
+        // The parallel analyses are exactly the serial ones, down to the column numbering and the rows
+        let (hugr, funcs) = independent_functions();
+        let serial = StabilizerDataflow::run_module(&hugr, &FunctionOpacity::Boundary).unwrap();
+        let parallel = StabilizerDataflow::run_module_par(&hugr, &FunctionOpacity::Boundary).unwrap();
+        assert_eq!(parallel.len(), serial.len());
+        let rows = |analysis: &StabilizerDataflow<hugr::Hugr>| analysis.tableau().stabs.iter().map(|row| row.to_string()).collect_vec();
+        for f in funcs {
+            assert_eq!(parallel[&f].column_to_wire(), serial[&f].column_to_wire());
+            assert_eq!(rows(&parallel[&f]), rows(&serial[&f]));
+        }
+    }
+
+    #[test]
+    fn test_malformed_dfg() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let h = builder.add_dataflow_op(TketOp::H, [qb]).unwrap();
+        let mut hugr = builder.finish_hugr_with_outputs(h.outputs()).unwrap();
+        // A node with no children has no Input node to start the analysis from
+        assert_eq!(StabilizerDataflow::run_dfg(&hugr, h.node(), &FunctionOpacity::Opaque).err(), Some(DataflowError::MissingInput { parent: h.node() }));
+        // Disconnecting the output of the H leaves its qubit with nowhere to go
+        hugr.disconnect(h.node(), OutgoingPort::from(0));
+        assert_eq!(StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).err(), Some(DataflowError::DanglingQubitWire { node: h.node(), port: OutgoingPort::from(0) }));
+    }
+
+    #[test]
+    fn test_malformed_call() {
+        let mut module = ModuleBuilder::new();
+        let fun_builder = module.define_function("id", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = fun_builder.input_wires_arr();
+        let fun = fun_builder.finish_with_outputs([qb]).unwrap();
+        let mut main_builder = module.define_function("main", Signature::new_endo(vec![qb_t()])).unwrap();
+        let [qb] = main_builder.input_wires_arr();
+        let call = main_builder.call(fun.handle(), &[], [qb]).unwrap();
+        let main = main_builder.finish_with_outputs(call.outputs()).unwrap();
+        let mut hugr = module.finish_hugr().unwrap();
+        assert_eq!(StabilizerDataflow::run_function(&hugr, call.node(), &FunctionOpacity::Boundary).err(), Some(DataflowError::UnexpectedOp { node: call.node() }));
+        // Without its static edge, we cannot tell which function is called
+        let static_port = hugr.get_optype(call.node()).static_input_port().unwrap();
+        hugr.disconnect(call.node(), static_port);
+        assert_eq!(StabilizerDataflow::run_function(&hugr, main.node(), &FunctionOpacity::Boundary).err(), Some(DataflowError::MissingStaticEdge { node: call.node() }));
+    }
+
+    #[test]
+    fn test_deterministic_columns() {
+        let mut module = ModuleBuilder::new();
+        let mut fun_builder = module.define_function("cx_t", Signature::new_endo(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = fun_builder.input_wires_arr();
+        let [qb0, qb1] = fun_builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1] = fun_builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let fun = fun_builder.finish_with_outputs([qb0, qb1]).unwrap();
+        let mut main_builder = module.define_function("main", Signature::new_endo(vec![qb_t(), qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, qb2] = main_builder.input_wires_arr();
+        let [qb0] = main_builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = main_builder.call(fun.handle(), &[], [qb0, qb1]).unwrap().outputs_arr();
+        let [qb1, qb2] = main_builder.call(fun.handle(), &[], [qb1, qb2]).unwrap().outputs_arr();
+        let [qb2] = main_builder.add_dataflow_op(TketOp::Tdg, [qb2]).unwrap().outputs_arr();
+        let main = main_builder.finish_with_outputs([qb0, qb1, qb2]).unwrap();
+        let hugr = module.finish_hugr().unwrap();
+        let first = StabilizerDataflow::run_dfg(&hugr, main.node(), &FunctionOpacity::Inline).unwrap();
+        // Every HashMap gets a freshly seeded hasher, so each run iterates the maps in a different order
+        for _ in 0..10 {
+            let analysis = StabilizerDataflow::run_dfg(&hugr, main.node(), &FunctionOpacity::Inline).unwrap();
+            assert_eq!(analysis.column_to_wire(), first.column_to_wire());
+            assert_eq!(analysis.tableau().nb_qubits, first.tableau().nb_qubits);
+            assert_eq!(analysis.tableau().nb_stabs, first.tableau().nb_stabs);
+            for (p, q) in analysis.tableau().stabs.iter().zip(first.tableau().stabs.iter()) {
+                assert_eq!((&p.z, &p.x, p.sign), (&q.z, &q.x, q.sign));
+            }
+        }
+    }
+
+    #[test]
+    fn test_clifford_equivalence() {
+        let circuit = |gates: &[(TketOp, Vec<usize>)]| {
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+            let mut qbs = builder.input_wires().collect_vec();
+            for (gate, qubits) in gates {
+                let outs = builder.add_dataflow_op(*gate, qubits.iter().map(|q| qbs[*q])).unwrap().outputs().collect_vec();
+                for (q, wire) in qubits.iter().zip(outs) {
+                    qbs[*q] = wire;
+                }
+            }
+            builder.finish_hugr_with_outputs(qbs).unwrap()
+        };
+        let equivalent = |a: &[(TketOp, Vec<usize>)], b: &[(TketOp, Vec<usize>)]| {
+            let (hugr_a, hugr_b) = (circuit(a), circuit(b));
+            let analysis_a = StabilizerDataflow::run_dfg(&hugr_a, hugr_a.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            let analysis_b = StabilizerDataflow::run_dfg(&hugr_b, hugr_b.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            StabilizerDataflow::check_clifford_equivalence(&analysis_a, &analysis_b)
+        };
+        // CZ decomposes as CX conjugated by H on the target, and also as the symmetric decomposition on the other qubit
+        let cz = [(TketOp::CZ, vec![0, 1])];
+        assert!(equivalent(&cz, &[(TketOp::H, vec![1]), (TketOp::CX, vec![0, 1]), (TketOp::H, vec![1])]));
+        assert!(equivalent(&cz, &[(TketOp::H, vec![0]), (TketOp::CX, vec![1, 0]), (TketOp::H, vec![0])]));
+        // H is self-inverse, and S is V conjugated by H
+        assert!(equivalent(&[(TketOp::H, vec![0]), (TketOp::H, vec![0])], &[]));
+        assert!(equivalent(&[(TketOp::S, vec![0])], &[(TketOp::H, vec![0]), (TketOp::V, vec![0]), (TketOp::H, vec![0])]));
+        // Differing only in signs or in the direction of a CX is not enough
+        assert!(!equivalent(&[(TketOp::S, vec![0])], &[(TketOp::Sdg, vec![0])]));
+        assert!(!equivalent(&[(TketOp::CX, vec![0, 1])], &[(TketOp::CX, vec![1, 0])]));
+        // Non-Clifford phases are invisible to the comparison
+        assert!(equivalent(&[(TketOp::T, vec![0])], &[(TketOp::Tdg, vec![0])]));
+    }
+
+    #[test]
+    fn test_relation() {
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let relation = analysis.relation(WireRef::Output(IncomingPort::from(0)), WireRef::Output(IncomingPort::from(1)));
+        let relation = relation.iter().map(|(a, b, sign)| (a.pauli_at(0), b.pauli_at(0), *sign)).collect_vec();
+        assert_eq!(relation, vec![('Z', 'Z', false), ('X', 'X', false)]);
+        // A wire the analysis does not track has no relations
+        assert!(analysis.relation(WireRef::Output(IncomingPort::from(0)), WireRef::Output(IncomingPort::from(2))).is_empty());
+    }
+
+    #[test]
+    fn test_relation_opaque() {
+        // As in test_opaque, one half of a Bell pair passes through an opaque op before being entangled with the other half again
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let opaque_op = builder.add_dataflow_op(test_op("op"), [qb1]).unwrap();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [opaque_op.out_wire(0)]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // Z on the first output is still Z on the qubit entering the opaque op, since it is the control of the final CX
+        let relation = analysis.relation(WireRef::Output(IncomingPort::from(0)), WireRef::node_port(opaque_op.node(), IncomingPort::from(0)));
+        let relation = relation.iter().map(|(a, b, sign)| (a.pauli_at(0), b.pauli_at(0), *sign)).collect_vec();
+        assert_eq!(relation, vec![('Z', 'Z', false)]);
+        // The outputs are only related through the opaque op, so nothing relates them directly
+        assert!(analysis.relation(WireRef::Output(IncomingPort::from(0)), WireRef::Output(IncomingPort::from(1))).is_empty());
+        // Nor is anything known across the opaque op itself
+        let summary = analysis.relations_for_node(&[opaque_op.node()]).unwrap();
+        assert_eq!(summary.ports, vec![Port::from(IncomingPort::from(0)), Port::from(OutgoingPort::from(0))]);
+        assert!(summary.relations.is_empty());
+        // Clifford gates are applied directly, so have no summary
+        let cx = hugr.single_linked_input(opaque_op.node(), OutgoingPort::from(0)).unwrap().0;
+        assert!(analysis.relations_for_node(&[cx]).is_none());
+    }
+
+    #[test]
+    fn test_relation_same_wire() {
+        // A fresh qubit in |+> and an input qubit passed straight through to the output
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb_in] = builder.input_wires_arr();
+        let alloc = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap();
+        let h = builder.add_dataflow_op(TketOp::H, [alloc.out_wire(0)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([h.out_wire(0), qb_in]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), AnalysisConfig::default_opaque().with_provenance(true)).unwrap();
+        // A wire related to itself gives the stabilizers of the wire alone
+        let plus = WireRef::Output(IncomingPort::from(0));
+        let relation = analysis.relation(plus.clone(), plus.clone());
+        let relation = relation.iter().map(|(a, b, sign)| (a.pauli_at(0), b.pauli_at(0), *sign)).collect_vec();
+        assert_eq!(relation, vec![('X', 'I', false)]);
+        assert_eq!(analysis.explain_relation(plus.clone(), plus), vec![alloc.node(), h.node()]);
+        // Nothing is known on the input qubit alone, though its two ends are tracked by separate columns related by the identity
+        let (inp, out) = (WireRef::Input(OutgoingPort::from(0)), WireRef::Output(IncomingPort::from(1)));
+        assert!(analysis.relation(inp.clone(), inp.clone()).is_empty());
+        let relation = analysis.relation(inp, out);
+        let relation = relation.iter().map(|(a, b, sign)| (a.pauli_at(0), b.pauli_at(0), *sign)).collect_vec();
+        assert_eq!(relation, vec![('Z', 'Z', false), ('X', 'X', false)]);
+    }
+
+    #[test]
+    fn test_mergeable_rotations() {
+        // T;Tdg on a wire merges to the identity, since the angles add to zero
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let tdg = builder.add_dataflow_op(TketOp::Tdg, [t.out_wire(0)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([tdg.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let candidates = analysis.mergeable_rotations(&hugr);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!((candidates[0].first.clone(), candidates[0].second.clone()), (vec![t.node()], vec![tdg.node()]));
+        assert!(!candidates[0].relation.sign);
+        // T;X;T has opposite axes, so the angles subtract
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::X, [qb]).unwrap().outputs_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let candidates = analysis.mergeable_rotations(&hugr);
+        assert_eq!(candidates.len(), 1);
+        assert!(candidates[0].relation.sign);
+        // T;H;T does not merge
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::H, [qb]).unwrap().outputs_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(analysis.mergeable_rotations(&hugr).is_empty());
+        // T;CX;CX;T on the control merges across the CX pair
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let t0 = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [t0.out_wire(0), qb1]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let t1 = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([t1.out_wire(0), qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let candidates = analysis.mergeable_rotations(&hugr);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!((candidates[0].first.clone(), candidates[0].second.clone()), (vec![t0.node()], vec![t1.node()]));
+        assert!(!candidates[0].relation.sign);
+    }
+
+    /// A DFG on two qubits and two angles applying the interaction op called name with the angle a, then the gates, then the same interaction with the angle b
+    fn interaction_pair(name: &str, gates: &[(TketOp, usize)]) -> (hugr::Hugr, Node, Node) {
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t(), rotation_type(), rotation_type()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, a, b] = builder.input_wires_arr();
+        let first = builder.add_dataflow_op(test_op(name), [qb0, qb1, a]).unwrap();
+        let mut qbs = first.outputs().collect_vec();
+        for (gate, q) in gates {
+            qbs[*q] = builder.add_dataflow_op(*gate, [qbs[*q]]).unwrap().out_wire(0);
+        }
+        let second = builder.add_dataflow_op(test_op(name), [qbs[0], qbs[1], b]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(second.outputs()).unwrap();
+        (hugr, first.node(), second.node())
+    }
+
+    #[test]
+    fn test_interaction_mergeable() {
+        // ZZPhase;S⊗Z;ZZPhase merges, as S and Z commute with Z⊗Z
+        let (hugr, first, second) = interaction_pair("ZZPhase", &[(TketOp::S, 0), (TketOp::Z, 1)]);
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let candidates = analysis.mergeable_rotations(&hugr);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!((candidates[0].first.clone(), candidates[0].second.clone()), (vec![first], vec![second]));
+        assert!(!candidates[0].relation.sign);
+        // An X on one qubit maps Z⊗Z to -Z⊗Z, so the angles subtract
+        let (hugr, _, _) = interaction_pair("ZZPhase", &[(TketOp::X, 1)]);
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let candidates = analysis.mergeable_rotations(&hugr);
+        assert_eq!(candidates.len(), 1);
+        assert!(candidates[0].relation.sign);
+        // An H does not commute with Z⊗Z
+        let (hugr, _, _) = interaction_pair("ZZPhase", &[(TketOp::H, 0)]);
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(analysis.mergeable_rotations(&hugr).is_empty());
+        // XXPhase merges across an X, but not across an S
+        let (hugr, _, _) = interaction_pair("XXPhase", &[(TketOp::X, 0)]);
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.mergeable_rotations(&hugr).len(), 1);
+        let (hugr, _, _) = interaction_pair("XXPhase", &[(TketOp::S, 0)]);
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(analysis.mergeable_rotations(&hugr).is_empty());
+        // Unregistered ops are opaque, unless added to the registry
+        let (hugr, _, _) = interaction_pair("Rzz", &[]);
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(analysis.mergeable_rotations(&hugr).is_empty());
+        let policy = AnalysisConfig::default_opaque().with_interactions(InteractionRegistry::default().with("Rzz", Interaction::parametric(InteractionAxis::ZZ, 2)));
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), policy).unwrap();
+        assert_eq!(analysis.mergeable_rotations(&hugr).len(), 1);
+    }
+
+    #[test]
+    fn test_interaction_clifford() {
+        // ZZPhase(1/2) is the Clifford ZZMax, and ZZPhase(1) is Z⊗Z, both applied without internal columns
+        let interaction = |name: &str, half_turns: Option<f64>| {
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+            let mut inputs = builder.input_wires().collect_vec();
+            if let Some(half_turns) = half_turns {
+                inputs.push(builder.add_load_value(Value::extension(ConstRotation::new(half_turns).unwrap())));
+            }
+            let node = builder.add_dataflow_op(test_op(name), inputs).unwrap();
+            builder.finish_hugr_with_outputs(node.outputs()).unwrap()
+        };
+        let zz_max = interaction("ZZMax", None);
+        let zz_half = interaction("ZZPhase", Some(0.5));
+        let zz_one = interaction("ZZPhase", Some(1.));
+        let analyses = [&zz_max, &zz_half, &zz_one].map(|hugr| StabilizerDataflow::run_dfg(hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap());
+        for analysis in analyses.iter() {
+            assert_eq!(analysis.columns().count(ColumnRole::InternalIn), 0);
+        }
+        assert!(StabilizerDataflow::check_clifford_equivalence(&analyses[0], &analyses[1]));
+        assert!(!StabilizerDataflow::check_clifford_equivalence(&analyses[0], &analyses[2]));
+        // Z⊗Z maps X on each qubit to -X and leaves Z alone
+        let analysis = &analyses[2];
+        for port in 0..2 {
+            let col_in = analysis.input_column(OutgoingPort::from(port)).unwrap();
+            let col_out = analysis.output_column(IncomingPort::from(port)).unwrap();
+            assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[col_in, col_out], true)));
+            assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[col_in, col_out], &[], false)));
+        }
+    }
+
+    #[test]
+    fn test_declared_stabilizers() {
+        // An opaque op declared to preserve Z keeps the Z relation from input to output, while X is still lost
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let noise = builder.add_dataflow_op(test_op("noise"), [qb]).unwrap();
+        let mut hugr = builder.finish_hugr_with_outputs(noise.outputs()).unwrap();
+        let preserves = |analysis: &StabilizerDataflow<hugr::Hugr>, z: bool| {
+            let col_in = analysis.input_column(OutgoingPort::from(0)).unwrap();
+            let col_out = analysis.output_column(IncomingPort::from(0)).unwrap();
+            let cols = [col_in, col_out];
+            let p = if z { analysis.tableau().pauli(&cols, &[], false) } else { analysis.tableau().pauli(&[], &cols, false) };
+            analysis.tableau().stabilizes(&p)
+        };
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(!preserves(&analysis, true));
+        hugr.set_metadata(noise.node(), STABILIZERS_METADATA_KEY, vec!["+Z"]);
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(preserves(&analysis, true));
+        assert!(!preserves(&analysis, false));
+        assert_eq!(analysis.statistics(&hugr).opaque_nodes, 1);
+        // The same through OpacityHints, for an op without metadata
+        hugr.remove_metadata(noise.node(), STABILIZERS_METADATA_KEY);
+        let policy = AnalysisConfig::default_opaque().with_hints(OpacityHints::default().with("noise", ["Z"]));
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), policy).unwrap();
+        assert!(preserves(&analysis, true));
+        assert!(!preserves(&analysis, false));
+        // Declarations of the wrong width or that contradict each other are rejected
+        for stabilizers in [vec!["ZZ"], vec!["Z", "-Z"], vec!["X", "Z", "-Y"], vec!["W"]] {
+            hugr.set_metadata(noise.node(), STABILIZERS_METADATA_KEY, stabilizers);
+            let result = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque);
+            assert_eq!(result.err(), Some(DataflowError::InvalidStabilizers { node: noise.node() }));
+        }
+        // X, Z and Y together declare the identity, where Y is implied by the other two
+        hugr.set_metadata(noise.node(), STABILIZERS_METADATA_KEY, vec!["X", "Z", "Y"]);
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(preserves(&analysis, true) && preserves(&analysis, false));
+    }
+
+    #[test]
+    fn test_controlled_s() {
+        // CS keeps Z on both qubits but not X on either, and CSX keeps Z on the control and X on the target
+        let relations = |name: &str| {
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+            let [qb0, qb1] = builder.input_wires_arr();
+            let node = builder.add_dataflow_op(test_op(name), [qb0, qb1]).unwrap();
+            let hugr = builder.finish_hugr_with_outputs(node.outputs()).unwrap();
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            (0..2).map(|port| {
+                let cols = [analysis.input_column(OutgoingPort::from(port)).unwrap(), analysis.output_column(IncomingPort::from(port)).unwrap()];
+                (analysis.tableau().stabilizes(&analysis.tableau().pauli(&cols, &[], false)), analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &cols, false)))
+            }).collect_vec()
+        };
+        assert_eq!(relations("CS"), vec![(true, false), (true, false)]);
+        assert_eq!(relations("CSdg"), vec![(true, false), (true, false)]);
+        assert_eq!(relations("CSX"), vec![(true, false), (false, true)]);
+        assert_eq!(relations("other"), vec![(false, false), (false, false)]);
+    }
+
+    #[test]
+    fn test_tuple() {
+        // Whether each input qubit keeps both its Z and X relations with the output on the same port
+        let identities = |hugr: &hugr::Hugr| {
+            let analysis = StabilizerDataflow::run_dfg(hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            (0..2).map(|port| {
+                let cols = [analysis.input_column(OutgoingPort::from(port)).unwrap(), analysis.output_column(IncomingPort::from(port)).unwrap()];
+                analysis.tableau().stabilizes(&analysis.tableau().pauli(&cols, &[], false)) && analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &cols, false))
+            }).collect_vec()
+        };
+        // Packing two qubits and unpacking them straight away is the identity
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let tuple = builder.make_tuple([qb0, qb1]).unwrap();
+        let unpack = builder.add_dataflow_op(UnpackTuple::new(vec![qb_t(), qb_t()].into()), [tuple]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(unpack.outputs()).unwrap();
+        assert_eq!(identities(&hugr), vec![true, true]);
+        // The same for a nested tuple passed through a Noop
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let inner = builder.make_tuple([qb1]).unwrap();
+        let outer = builder.make_tuple([qb0, inner]).unwrap();
+        let outer_t = Type::new_tuple(vec![qb_t(), Type::new_tuple(vec![qb_t()])]);
+        let [outer] = builder.add_dataflow_op(Noop::new(outer_t), [outer]).unwrap().outputs_arr();
+        let [qb0, inner] = builder.add_dataflow_op(UnpackTuple::new(vec![qb_t(), Type::new_tuple(vec![qb_t()])].into()), [outer]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(UnpackTuple::new(vec![qb_t()].into()), [inner]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        assert_eq!(identities(&hugr), vec![true, true]);
+        // A tuple through a genuinely opaque op loses every qubit in it, but not those outside it
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let tuple = builder.make_tuple([qb0]).unwrap();
+        let [tuple] = builder.add_dataflow_op(test_op("tuple_op"), [tuple]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(UnpackTuple::new(vec![qb_t()].into()), [tuple]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        assert_eq!(identities(&hugr), vec![false, true]);
+    }
+
+    #[test]
+    fn test_array() {
+        let identities = |hugr: &hugr::Hugr| {
+            let analysis = StabilizerDataflow::run_dfg(hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            (0..2).map(|port| {
+                let cols = [analysis.input_column(OutgoingPort::from(port)).unwrap(), analysis.output_column(IncomingPort::from(port)).unwrap()];
+                analysis.tableau().stabilizes(&analysis.tableau().pauli(&cols, &[], false)) && analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &cols, false))
+            }).collect_vec()
+        };
+        // Building an array of two qubits and unpacking it straight away is the identity
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [array] = builder.add_dataflow_op(ArrayOpDef::new_array.to_concrete(qb_t(), 2), [qb0, qb1]).unwrap().outputs_arr();
+        let unpack = builder.add_dataflow_op(ArrayOpDef::unpack.to_concrete(qb_t(), 2), [array]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(unpack.outputs()).unwrap();
+        assert_eq!(identities(&hugr), vec![true, true]);
+        // A swap at runtime indices (returning a Sum, here unwrapped by an opaque op) loses the qubits rather than failing
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t(), usize_t(), usize_t()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, i, j] = builder.input_wires_arr();
+        let [array] = builder.add_dataflow_op(ArrayOpDef::new_array.to_concrete(qb_t(), 2), [qb0, qb1]).unwrap().outputs_arr();
+        let swap = ArrayOpDef::swap.to_concrete(qb_t(), 2);
+        let [swapped] = builder.add_dataflow_op(swap, [array, i, j]).unwrap().outputs_arr();
+        let [array] = builder.add_dataflow_op(test_op("unwrap"), [swapped]).unwrap().outputs_arr();
+        let unpack = builder.add_dataflow_op(ArrayOpDef::unpack.to_concrete(qb_t(), 2), [array]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(unpack.outputs()).unwrap();
+        assert_eq!(identities(&hugr), vec![false, false]);
+    }
+
+    #[test]
+    fn test_linear_resource() {
+        // A linear handle threaded alongside the qubits, through an opaque op acting on both and a tuple packing both, leaves the qubit relations as without it
+        let handle_t = Type::new_extension(TEST_EXTENSION.get_type("handle").unwrap().instantiate([]).unwrap());
+        let build = |handle: bool| {
+            let extra = if handle { vec![handle_t.clone()] } else { vec![] };
+            let mut builder = DFGBuilder::new(endo_sig([vec![qb_t(), qb_t()], extra.clone()].concat())).unwrap();
+            let mut wires = builder.input_wires().collect_vec();
+            let [qb0] = builder.add_dataflow_op(TketOp::H, [wires[0]]).unwrap().outputs_arr();
+            let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, wires[1]]).unwrap().outputs_arr();
+            let acquire = builder.add_dataflow_op(test_op(if handle { "acquire" } else { "op" }), [qb1].into_iter().chain(wires.drain(2..))).unwrap();
+            let mut outs = acquire.outputs().collect_vec();
+            let tuple = builder.make_tuple(outs.clone()).unwrap();
+            let unpack = builder.add_dataflow_op(UnpackTuple::new([vec![qb_t()], extra].concat().into()), [tuple]).unwrap();
+            outs = unpack.outputs().collect_vec();
+            let [qb1] = builder.add_dataflow_op(TketOp::H, [outs[0]]).unwrap().outputs_arr();
+            builder.finish_hugr_with_outputs([qb0, qb1].into_iter().chain(outs.drain(1..))).unwrap()
+        };
+        let with_handle = build(true);
+        let without_handle = build(false);
+        let a = StabilizerDataflow::run_dfg(&with_handle, with_handle.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let b = StabilizerDataflow::run_dfg(&without_handle, without_handle.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(StabilizerDataflow::check_clifford_equivalence(&a, &b));
+        assert_eq!(a.columns().inputs().count(), 2);
+        // X on the first input becomes Z through the H, which the control of the CX preserves
+        let relation = a.relation(WireRef::Input(OutgoingPort::from(0)), WireRef::Output(IncomingPort::from(0)));
+        assert_eq!(relation.iter().map(|(p, q, _)| (p.pauli_at(0), q.pauli_at(0))).collect_vec(), vec![('X', 'Z')]);
+    }
+
+    #[test]
+    fn test_tag() {
+        // A classical value lifted into a Sum between the gates of a Bell pair does not disturb the qubits
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t(), bool_t()], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, b] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let tag = builder.add_dataflow_op(Tag::new(0, vec![vec![bool_t()].into(), type_row![]]), [b]).unwrap();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.tableau().nb_qubits, 4);
+        assert!(analysis.relations_for_node(&[tag.node()]).is_none());
+        // X on the first input becomes Z through the H, which the control of the CX preserves
+        let relation = analysis.relation(WireRef::Input(OutgoingPort::from(0)), WireRef::Output(IncomingPort::from(0)));
+        assert_eq!(relation.iter().map(|(a, b, _)| (a.pauli_at(0), b.pauli_at(0))).collect_vec(), vec![('X', 'Z')]);
+    }
+
+    #[test]
+    fn test_tag_qubits() {
+        // A loop body that entangles two fresh qubits and breaks out with both, packed into the Sum by the Tag of make_break
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let mut loop_builder = builder.tail_loop_builder([], [], vec![qb_t(), qb_t()].into()).unwrap();
+        let [qb0] = loop_builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb1] = loop_builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0] = loop_builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = loop_builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let tl_sig = loop_builder.loop_signature().unwrap().clone();
+        let brk = loop_builder.make_break(tl_sig, [qb0, qb1]).unwrap();
+        let tl = loop_builder.finish_with_outputs(brk, []).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(tl.outputs()).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // The Tag only moves the qubits, so has no columns of its own and is not opaque
+        let tag = brk.node();
+        let body = analysis.nested(tl.node()).unwrap().nested(tl.node()).unwrap();
+        assert!(matches!(hugr.get_optype(tag), OpType::Tag(_)));
+        assert!(body.relations_for_node(&[tag]).is_none());
+        assert_eq!(analysis.statistics(&hugr).opaque_nodes, 0);
+        // The Bell pair is followed out of the loop through the Sum
+        let relation = analysis.relation(WireRef::Output(IncomingPort::from(0)), WireRef::Output(IncomingPort::from(1)));
+        let relation = relation.iter().map(|(a, b, sign)| (a.pauli_at(0), b.pauli_at(0), *sign)).collect_vec();
+        assert_eq!(relation, vec![('Z', 'Z', false), ('X', 'X', false)]);
+    }
+
+    #[test]
+    fn test_deterministic_measurements() {
+        // Reset;Measure always gives 0, and Reset;X;MeasureFree always gives 1
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::Reset, [qb0]).unwrap().outputs_arr();
+        let meas = builder.add_dataflow_op(TketOp::Measure, [qb0]).unwrap();
+        let [qb1] = builder.add_dataflow_op(TketOp::Reset, [qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::X, [qb1]).unwrap().outputs_arr();
+        let meas_free = builder.add_dataflow_op(TketOp::MeasureFree, [qb1]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([meas.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let outcomes = analysis.deterministic_measurements(&hugr);
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.contains(&(vec![meas.node()], false)));
+        assert!(outcomes.contains(&(vec![meas_free.node()], true)));
+        // H;Measure after Reset is random
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::Reset, [qb]).unwrap().outputs_arr();
+        let [qb] = builder.add_dataflow_op(TketOp::H, [qb]).unwrap().outputs_arr();
+        let meas = builder.add_dataflow_op(TketOp::Measure, [qb]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([meas.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(analysis.deterministic_measurements(&hugr).is_empty());
+        // Measuring an input qubit is not deterministic either
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let meas = builder.add_dataflow_op(TketOp::Measure, [qb]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([meas.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(analysis.deterministic_measurements(&hugr).is_empty());
+    }
+
+    #[test]
+    fn test_non_clifford_dependency_graph() {
+        // T on each of two wires are independent
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let graph = analysis.non_clifford_dependency_graph();
+        assert_eq!(graph.node_count(), 2);
+        assert_eq!(graph.edge_count(), 0);
+        // T;H;Rz on a wire gives a single edge from the T to the Rz
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), rotation_type()], vec![qb_t()])).unwrap();
+        let [qb, angle] = builder.input_wires_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let [qb] = builder.add_dataflow_op(TketOp::H, [t.out_wire(0)]).unwrap().outputs_arr();
+        let rz = builder.add_dataflow_op(TketOp::Rz, [qb, angle]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([rz.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let graph = analysis.non_clifford_dependency_graph();
+        assert_eq!(graph.node_count(), 2);
+        let edges = graph.raw_edges().iter().map(|e| (graph[e.source()].clone(), graph[e.target()].clone())).collect_vec();
+        assert_eq!(edges, vec![(vec![t.node()], vec![rz.node()])]);
+        // T;T;T on a wire has an edge from each T to every later one, as the graph is not transitively reduced
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let t0 = builder.add_dataflow_op(TketOp::T, [qb]).unwrap();
+        let t1 = builder.add_dataflow_op(TketOp::T, [t0.out_wire(0)]).unwrap();
+        let t2 = builder.add_dataflow_op(TketOp::T, [t1.out_wire(0)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([t2.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let graph = analysis.non_clifford_dependency_graph();
+        let edges = graph.raw_edges().iter().map(|e| (graph[e.source()].clone(), graph[e.target()].clone())).collect_vec();
+        assert_eq!(edges, vec![(vec![t0.node()], vec![t1.node()]), (vec![t0.node()], vec![t2.node()]), (vec![t1.node()], vec![t2.node()])]);
+    }
+
+    #[test]
+    fn test_redundant_resets() {
+        // A Reset straight after QAlloc is redundant, but not after an H
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let reset0 = builder.add_dataflow_op(TketOp::Reset, [qb0]).unwrap();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        let reset1 = builder.add_dataflow_op(TketOp::Reset, [qb1]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([reset0.out_wire(0), reset1.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.redundant_resets(&hugr), vec![vec![reset0.node()]]);
+        // Both outputs are in |0> regardless
+        for port in 0..2 {
+            let out = analysis.output_column(IncomingPort::from(port)).unwrap();
+            assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[out], &[], false)));
+        }
+    }
+
+    #[test]
+    fn test_measure_reset_pairs() {
+        // Measure;Reset needs an X when the outcome is 1, and Measure;X;Reset when it is 0
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![qb_t(), qb_t(), bool_t(), bool_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let meas0 = builder.add_dataflow_op(TketOp::Measure, [qb0]).unwrap();
+        let reset0 = builder.add_dataflow_op(TketOp::Reset, [meas0.out_wire(0)]).unwrap();
+        let meas1 = builder.add_dataflow_op(TketOp::Measure, [qb1]).unwrap();
+        let [qb1] = builder.add_dataflow_op(TketOp::X, [meas1.out_wire(0)]).unwrap().outputs_arr();
+        let reset1 = builder.add_dataflow_op(TketOp::Reset, [qb1]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([reset0.out_wire(0), reset1.out_wire(0), meas0.out_wire(1), meas1.out_wire(1)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let pairs = analysis.measure_reset_pairs(&hugr);
+        assert_eq!(pairs.len(), 2);
+        assert!(pairs.contains(&(vec![meas0.node()], vec![reset0.node()], false)));
+        assert!(pairs.contains(&(vec![meas1.node()], vec![reset1.node()], true)));
+        // Neither Reset is redundant, as the inputs are arbitrary
+        assert!(analysis.redundant_resets(&hugr).is_empty());
+    }
+
+    #[test]
+    fn test_verify_consistency() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let cx = builder.add_dataflow_op(TketOp::CX, [t.out_wire(0), qb1]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(cx.outputs()).unwrap();
+        let analyse = || StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let errors = |analysis: &StabilizerDataflow<hugr::Hugr>| analysis.verify_consistency().unwrap_err();
+        let analysis = analyse();
+        assert_eq!(analysis.verify_consistency(), Ok(()));
+        let (n_cols, n_rows) = (analysis.tab.nb_qubits, analysis.tab.nb_stabs);
+        let mut bad = analyse();
+        bad.cols.set_input(OutgoingPort::from(5), n_cols + 3);
+        assert_eq!(errors(&bad), vec![format!("Column {} for input[5] is out of range for {n_cols} columns", n_cols + 3)]);
+        // The T consumed the frontier column it now has as its internal input
+        let col_in = analysis.cols.column_for_node_port(&[t.node()], IncomingPort::from(0)).unwrap();
+        let mut bad = analyse();
+        bad.cols.set_frontier(cx.node(), IncomingPort::from(0), col_in);
+        assert_eq!(errors(&bad), vec![format!("Column {col_in} is both on the frontier and the internal input on port 0 of {:?}", t.node())]);
+        let mut bad = analyse();
+        bad.tab.nb_stabs += 1;
+        assert_eq!(errors(&bad)[0], format!("The tableau has {n_rows} rows but records {}", n_rows + 1));
+        let invalid = vec!["The rows of the tableau do not generate a valid stabilizer group".to_string()];
+        // Z and X on the same column anticommute
+        let mut bad = analyse();
+        bad.tab.add_row(bad.tab.pauli(&[0], &[], false));
+        bad.tab.add_row(bad.tab.pauli(&[], &[0], false));
+        assert_eq!(errors(&bad), invalid);
+        // A row repeated with its sign flipped makes -I a product of the rows
+        let mut bad = analyse();
+        let mut negated = bad.tab.stabs[0].clone();
+        negated.sign = !negated.sign;
+        bad.tab.add_row(negated);
+        assert_eq!(errors(&bad), invalid);
+    }
+
+    #[test]
+    fn test_statistics() {
+        // The circuit of test_nonclifford
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t(), qb_t(), rotation_type()], vec![qb_t(), qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, qb2, angle] = builder.input_wires_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let tdg = builder.add_dataflow_op(TketOp::Tdg, [t.out_wire(0)]).unwrap();
+        let rz = builder.add_dataflow_op(TketOp::Rz, [tdg.out_wire(0), angle]).unwrap();
+        let meas = builder.add_dataflow_op(TketOp::Measure, [rz.out_wire(0)]).unwrap();
+        let ry = builder.add_dataflow_op(TketOp::Ry, [qb1, angle]).unwrap();
+        let rx = builder.add_dataflow_op(TketOp::Rx, [qb2, angle]).unwrap();
+        let crz = builder.add_dataflow_op(TketOp::CRz, [meas.out_wire(0), ry.out_wire(0), angle]).unwrap();
+        let toffoli = builder.add_dataflow_op(TketOp::Toffoli, [crz.out_wire(0), crz.out_wire(1), rx.out_wire(0)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs(toffoli.outputs_arr::<3>()).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        // Each relation imposed (one per qubit of each non-Clifford gate and the Measure) replaces a single row
+        // T, Tdg and Rz are all related through the wire between them, giving three mergeable pairs
+        assert_eq!(analysis.statistics(&hugr), AnalysisStats {
+            t_count: 2,
+            rz_count: 1,
+            crz_count: 1,
+            toffoli_count: 1,
+            opaque_nodes: 0,
+            columns_allocated: 28,
+            rows_projected: 11,
+            nested_analyses: 0,
+            truncated_nodes: 0,
+            mergeable_pairs: 3,
+        });
+    }
+
+    #[test]
+    fn test_max_columns() {
+        // Four T gates on one qubit and an H on the other; each T needs two internal columns on top of the four for the inputs
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [mut qb0, qb1] = builder.input_wires_arr();
+        for _ in 0..4 {
+            [qb0] = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap().outputs_arr();
+        }
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let across = |analysis: &StabilizerDataflow<hugr::Hugr>, q: usize| analysis.relation(WireRef::Input(OutgoingPort::from(q)), WireRef::Output(IncomingPort::from(q))).iter().map(|(a, b, _)| (a.pauli_at(0), b.pauli_at(0))).collect_vec();
+        let full = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(full.tableau().nb_qubits, 12);
+        assert_eq!(across(&full, 0), vec![('Z', 'Z')]);
+        // Within the budget nothing changes
+        let bounded = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), AnalysisConfig::default_opaque().with_max_columns(12)).unwrap();
+        assert_eq!(bounded.statistics(&hugr), full.statistics(&hugr));
+        assert_eq!(bounded.tableau().nb_qubits, full.tableau().nb_qubits);
+        assert!(full.tableau().stabs.iter().all(|row| bounded.tableau().stabilizes(row)) && bounded.tableau().stabs.iter().all(|row| full.tableau().stabilizes(row)));
+        // Once the first T has spent a budget of six columns, the other three are applied without any, forgetting the relation across qubit 0
+        let truncated = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), AnalysisConfig::default_opaque().with_max_columns(6)).unwrap();
+        let stats = truncated.statistics(&hugr);
+        assert_eq!((stats.t_count, stats.truncated_nodes, stats.columns_allocated), (4, 3, 6));
+        assert_eq!(truncated.tableau().nb_qubits, 6);
+        assert!(across(&truncated, 0).is_empty());
+        // The H acts in place, so qubit 1 keeps its relation
+        assert_eq!(across(&truncated, 1).len(), 2);
+    }
+
+    #[test]
+    fn test_column_gc() {
+        // A long chain of T gates, each adding an internal input and output column
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [mut qb] = builder.input_wires_arr();
+        for _ in 0..40 {
+            [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        }
+        let hugr = builder.finish_hugr_with_outputs([qb]).unwrap();
+        let across = |analysis: &StabilizerDataflow<hugr::Hugr>| analysis.relation(WireRef::Input(OutgoingPort::from(0)), WireRef::Output(IncomingPort::from(0))).iter().map(|(a, b, _)| (a.pauli_at(0), b.pauli_at(0))).collect_vec();
+        let full = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(full.tableau().nb_qubits, 2 + 2 * 40);
+        // Collecting every internal column every 7 nodes leaves at most the columns of the last 7 T gates, with the relation across the chain intact
+        let collected = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), AnalysisConfig::default_opaque().with_column_gc(7, |_| false)).unwrap();
+        assert!(collected.tableau().nb_qubits <= 2 + 2 * 7);
+        assert_eq!(collected.statistics(&hugr).columns_allocated, full.statistics(&hugr).columns_allocated);
+        assert_eq!(across(&collected), vec![('Z', 'Z')]);
+        assert_eq!(across(&collected), across(&full));
+        assert!(collected.mergeable_rotations(&hugr).len() < full.mergeable_rotations(&hugr).len());
+        // By default the internal columns are all kept, so the rotations can still be merged
+        let mut kept = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), AnalysisConfig::default_opaque().with_column_gc(7, keep_node_columns)).unwrap();
+        assert_eq!(kept.tableau().nb_qubits, full.tableau().nb_qubits);
+        assert_eq!(kept.mergeable_rotations(&hugr).len(), full.mergeable_rotations(&hugr).len());
+        // Collecting afterwards gives the same relations over the boundary as collecting during the analysis
+        assert_eq!(kept.gc_internal_columns(&|_| false), 2 * 40);
+        assert_eq!(kept.tableau().nb_qubits, 2);
+        assert_eq!(across(&kept), across(&full));
+    }
+
+    #[test]
+    fn test_reanalyze() {
+        // An H, then a T in each of two nested DFGs
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let h_handle = builder.add_dataflow_op(TketOp::H, [qb]).unwrap();
+        let (h, mut qb) = (h_handle.node(), h_handle.out_wire(0));
+        let (mut dfgs, mut ts) = (vec![], vec![]);
+        for _ in 0..2 {
+            let mut nested_builder = builder.dfg_builder(endo_sig(vec![qb_t()]), [qb]).unwrap();
+            let [inner] = nested_builder.input_wires_arr();
+            let t = nested_builder.add_dataflow_op(TketOp::T, [inner]).unwrap();
+            let dfg = nested_builder.finish_with_outputs(t.outputs()).unwrap();
+            qb = dfg.out_wire(0);
+            dfgs.push(dfg.node());
+            ts.push(t.node());
+        }
+        let mut hugr = builder.finish_hugr_with_outputs([qb]).unwrap();
+        let fresh = |hugr: &hugr::Hugr| StabilizerDataflow::run_dfg(hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let up_to_date = |analysis: &StabilizerDataflow<hugr::Hugr>, hugr: &hugr::Hugr| {
+            let expected = fresh(hugr);
+            StabilizerDataflow::check_clifford_equivalence(analysis, &expected) && analysis.statistics(hugr) == expected.statistics(hugr)
+        };
+        let mut analysis = fresh(&hugr);
+        // A gate changed within a nested DFG
+        hugr.replace_op(ts[1], TketOp::S);
+        analysis.invalidate_region(&[ts[1]]);
+        analysis.reanalyze(&hugr).unwrap();
+        assert!(up_to_date(&analysis, &hugr));
+        assert_eq!(analysis.statistics(&hugr).t_count, 1);
+        // A gate changed in the region itself
+        hugr.replace_op(h, TketOp::X);
+        analysis.invalidate_region(&[h]);
+        analysis.reanalyze(&hugr).unwrap();
+        assert!(up_to_date(&analysis, &hugr));
+        // A change that is not reported leaves the analysis of its DFG as it was, showing that it is reused
+        hugr.replace_op(ts[0], TketOp::S);
+        hugr.replace_op(h, TketOp::H);
+        analysis.invalidate_region(&[h]);
+        analysis.reanalyze(&hugr).unwrap();
+        assert_eq!(analysis.statistics(&hugr).t_count, 1);
+        assert!(!up_to_date(&analysis, &hugr));
+        analysis.invalidate_region(&[ts[0]]);
+        analysis.reanalyze(&hugr).unwrap();
+        assert!(up_to_date(&analysis, &hugr));
+        assert_eq!(analysis.statistics(&hugr).t_count, 0);
+        // A removed node cannot be located, so the analysis starts afresh
+        let [inp, out] = hugr.get_io(dfgs[1]).unwrap();
+        hugr.remove_node(ts[1]);
+        hugr.connect(inp, OutgoingPort::from(0), out, IncomingPort::from(0));
+        analysis.invalidate_region(&[ts[1]]);
+        analysis.reanalyze(&hugr).unwrap();
+        assert!(up_to_date(&analysis, &hugr));
+        // With nothing invalidated there is nothing to do
+        analysis.reanalyze(&hugr).unwrap();
+        assert!(up_to_date(&analysis, &hugr));
+    }
+
+    #[test]
+    fn test_reanalyze_checkpoints() {
+        // A chain of H and CX gates on two qubits, with a T in the middle
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(); 2])).unwrap();
+        let [mut a, mut b] = builder.input_wires_arr();
+        let mut gates = vec![];
+        for i in 0..10 {
+            let op = if i == 5 { TketOp::T } else { TketOp::H };
+            let h = builder.add_dataflow_op(op, [a]).unwrap();
+            let cx = builder.add_dataflow_op(TketOp::CX, [h.out_wire(0), b]).unwrap();
+            [a, b] = cx.outputs_arr();
+            gates.extend([h.node(), cx.node()]);
+        }
+        let mut hugr = builder.finish_hugr_with_outputs([a, b]).unwrap();
+        let policy = AnalysisConfig::default_opaque().with_checkpoints(1);
+        let fresh = |hugr: &hugr::Hugr| StabilizerDataflow::run_dfg(hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let up_to_date = |analysis: &StabilizerDataflow<hugr::Hugr>, hugr: &hugr::Hugr| {
+            let expected = fresh(hugr);
+            StabilizerDataflow::check_clifford_equivalence(analysis, &expected) && analysis.statistics(hugr) == expected.statistics(hugr)
+        };
+        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), policy.clone()).unwrap();
+        let n_nodes = hugr.children(hugr.entrypoint()).count();
+        // Changing the T resumes just before it, so only it, the gates after it and the Output are applied again
+        hugr.replace_op(gates[10], TketOp::S);
+        analysis.invalidate_region(&[gates[10]]);
+        assert_eq!(analysis.reanalyze(&hugr).unwrap(), gates.len() - 10 + 1);
+        assert!(up_to_date(&analysis, &hugr));
+        assert_eq!(analysis.statistics(&hugr).t_count, 0);
+        // Checkpoints are kept for the nodes applied again, so a later edit resumes from its own position
+        hugr.replace_op(gates[16], TketOp::T);
+        analysis.invalidate_region(&[gates[16]]);
+        assert_eq!(analysis.reanalyze(&hugr).unwrap(), gates.len() - 16 + 1);
+        assert!(up_to_date(&analysis, &hugr));
+        // An earlier edit goes back further
+        hugr.replace_op(gates[2], TketOp::X);
+        analysis.invalidate_region(&[gates[2], gates[16]]);
+        assert_eq!(analysis.reanalyze(&hugr).unwrap(), gates.len() - 2 + 1);
+        assert!(up_to_date(&analysis, &hugr));
+        // Without checkpoints the whole region is applied again
+        let mut full = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), AnalysisConfig::default_opaque().without_checkpoints()).unwrap();
+        hugr.replace_op(gates[16], TketOp::H);
+        full.invalidate_region(&[gates[16]]);
+        assert_eq!(full.reanalyze(&hugr).unwrap(), n_nodes);
+        assert!(up_to_date(&full, &hugr));
+        // By default a checkpoint is kept every DEFAULT_CHECKPOINT_INTERVAL nodes, so changing the last of a long chain of gates (after the Input) only applies it and the Output again
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [mut qb] = builder.input_wires_arr();
+        let mut gates = vec![];
+        for _ in 0..2*DEFAULT_CHECKPOINT_INTERVAL {
+            let h = builder.add_dataflow_op(TketOp::H, [qb]).unwrap();
+            qb = h.out_wire(0);
+            gates.push(h.node());
+        }
+        let mut hugr = builder.finish_hugr_with_outputs([qb]).unwrap();
+        let mut analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let last = *gates.last().unwrap();
+        hugr.replace_op(last, TketOp::S);
+        analysis.invalidate_region(&[last]);
+        assert_eq!(analysis.reanalyze(&hugr).unwrap(), 2);
+        assert!(up_to_date(&analysis, &hugr));
+    }
+
+    #[test]
+    fn test_qubit_lifetimes() {
+        // The Bell circuit, with a third qubit wired straight through and a fourth freed after an X
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(); 4], vec![qb_t(); 3])).unwrap();
+        let [qb0, qb1, qb2, qb3] = builder.input_wires_arr();
+        let h = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap();
+        let cx = builder.add_dataflow_op(TketOp::CX, [h.out_wire(0), qb1]).unwrap();
+        let x = builder.add_dataflow_op(TketOp::X, [qb3]).unwrap();
+        let free = builder.add_dataflow_op(TketOp::QFree, [x.out_wire(0)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([cx.out_wire(0), cx.out_wire(1), qb2]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let column = |port: usize| analysis.input_column(OutgoingPort::from(port)).unwrap();
+        let lifetimes = analysis.qubit_lifetimes(&hugr);
+        assert_eq!(lifetimes.len(), 3);
+        assert_eq!(lifetimes[&column(0)], (h.node(), cx.node()));
+        assert_eq!(lifetimes[&column(1)], (cx.node(), cx.node()));
+        assert_eq!(lifetimes[&column(3)], (x.node(), free.node()));
+        assert!(!lifetimes.contains_key(&column(2)));
+    }
+
+    #[test]
+    fn test_explain_relation() {
+        // A Bell pair from fresh qubits, with the second qubit then going into an opaque op
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let alloc0 = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap();
+        let alloc1 = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap();
+        let h = builder.add_dataflow_op(TketOp::H, [alloc0.out_wire(0)]).unwrap();
+        let cx = builder.add_dataflow_op(TketOp::CX, [h.out_wire(0), alloc1.out_wire(0)]).unwrap();
+        let noise = builder.add_dataflow_op(test_op("noise"), [cx.out_wire(1)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([cx.out_wire(0), noise.out_wire(0)]).unwrap();
+        let (a, b) = (WireRef::Output(IncomingPort::from(0)), WireRef::node_port(noise.node(), IncomingPort::from(0)));
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), AnalysisConfig::default_opaque().with_provenance(true)).unwrap();
+        assert_eq!(analysis.relation(a.clone(), b.clone()).len(), 2);
+        // The relations come from the fresh qubits through the H and CX, while the opaque op only added rows over its output
+        let explanation = analysis.explain_relation(a.clone(), b.clone());
+        assert_eq!(explanation.len(), 4);
+        assert!([alloc0.node(), alloc1.node(), h.node(), cx.node()].iter().all(|n| explanation.contains(n)));
+        assert!(explanation.iter().position(|n| *n == h.node()) < explanation.iter().position(|n| *n == cx.node()));
+        // Without provenance there is nothing to explain, though the relations are the same
+        let untracked = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(untracked.relation(a.clone(), b.clone()).len(), 2);
+        assert!(untracked.explain_relation(a, b).is_empty());
+    }
+
+    #[test]
+    fn test_find_dead_gates() {
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t(); 3])).unwrap();
+        let [a] = builder.input_wires_arr();
+        // CZ with one qubit in |0> does nothing, whatever the state of the other
+        let [fresh] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let cz = builder.add_dataflow_op(TketOp::CZ, [a, fresh]).unwrap();
+        let [a, fresh] = cz.outputs_arr();
+        // S on |0> only adds a global phase, but S on |+> does not
+        let s0 = builder.add_dataflow_op(TketOp::S, [fresh]).unwrap();
+        let [plus] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let h = builder.add_dataflow_op(TketOp::H, [plus]).unwrap();
+        let s_plus = builder.add_dataflow_op(TketOp::S, [h.out_wire(0)]).unwrap();
+        // The input qubit is unconstrained, so X on it is not dead
+        let x = builder.add_dataflow_op(TketOp::X, [a]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([x.out_wire(0), s0.out_wire(0), s_plus.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let dead = analysis.find_dead_gates(&hugr).unwrap();
+        assert_eq!(dead.iter().copied().sorted().collect_vec(), vec![cz.node(), s0.node()].into_iter().sorted().collect_vec());
+        assert!(!dead.contains(&h.node()) && !dead.contains(&s_plus.node()) && !dead.contains(&x.node()));
+    }
+
+    #[test]
+    fn test_find_dead_gates_after_opaque() {
+        // A T gate gives no information about the state after it, so the Z after it is not dead even though Z after |0> would be
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t()])).unwrap();
+        let [qb] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let z0 = builder.add_dataflow_op(TketOp::Z, [qb]).unwrap();
+        let t = builder.add_dataflow_op(TketOp::T, [z0.out_wire(0)]).unwrap();
+        let z1 = builder.add_dataflow_op(TketOp::Z, [t.out_wire(0)]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([z1.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.find_dead_gates(&hugr).unwrap(), vec![z0.node()]);
+    }
+
+    #[test]
+    fn test_output_state_stabilizers() {
+        let expected = |paulis: &[(&[usize], &[usize])], n: usize| {
+            let mut tab = ChoiTableau::new(n);
+            for (z, x) in paulis {
+                tab.add_row(tab.pauli(z, x, false));
+            }
+            tab
+        };
+        let from_rows = |rows: Vec<PauliProduct>, n: usize| {
+            let mut tab = ChoiTableau::new(n);
+            for row in rows {
+                tab.add_row(row);
+            }
+            tab
+        };
+        // Bell state prepared from fresh qubits
+        let mut builder = DFGBuilder::new(Signature::new(vec![], vec![qb_t(), qb_t()])).unwrap();
+        let [qb0] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let stabs = analysis.output_state_stabilizers();
+        assert_eq!(stabs.len(), 2);
+        assert!(from_rows(stabs, 2).equivalent(&expected(&[(&[], &[0, 1]), (&[0, 1], &[])], 2)));
+        // Bell state prepared from input qubits, which are taken to start in |0>
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let stabs = analysis.output_state_stabilizers();
+        assert_eq!(stabs.len(), 2);
+        assert!(from_rows(stabs, 2).equivalent(&expected(&[(&[], &[0, 1]), (&[0, 1], &[])], 2)));
+        // A T gate leaves |0> alone, but after H we lose the X stabilizer
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1] = builder.input_wires_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::T, [qb1]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let stabs = analysis.output_state_stabilizers();
+        assert_eq!(stabs.len(), 1);
+        assert!(from_rows(stabs, 2).equivalent(&expected(&[(&[0], &[])], 2)));
+    }
+
+    #[test]
+    fn test_load_constant() {
+        // A constant predicate selects its case, so the X in the true case is applied exactly
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let b = builder.add_load_const(Value::true_val());
+        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], b), [(qb_t(), qb)], vec![qb_t()].into()).unwrap();
+        let cond0_builder = cond_builder.case_builder(0).unwrap();
+        let [c0q] = cond0_builder.input_wires_arr();
+        cond0_builder.finish_with_outputs([c0q]).unwrap();
+        let mut cond1_builder = cond_builder.case_builder(1).unwrap();
+        let [c1q] = cond1_builder.input_wires_arr();
+        let [c1q] = cond1_builder.add_dataflow_op(TketOp::X, [c1q]).unwrap().outputs_arr();
+        cond1_builder.finish_with_outputs([c1q]).unwrap();
+        let cond = cond_builder.finish_sub_container().unwrap();
+        let hugr = builder.finish_hugr_with_outputs(cond.outputs()).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert!(analysis.internal_columns(&[b.node()]).is_empty());
+        let (in_col, out_col) = (analysis.input_column(OutgoingPort::from(0)).unwrap(), analysis.output_column(IncomingPort::from(0)).unwrap());
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[in_col, out_col], false)));
+        assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[in_col, out_col], &[], false)));
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[in_col, out_col], &[], true)));
+        assert_eq!(analysis.statistics(&hugr).opaque_nodes, 0);
+    }
+
+    #[test]
+    fn test_known_classical() {
+        // The cases (identity and H) have nothing in common, so the join loses the qubit, while a known predicate keeps the case it selects
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), bool_t()], vec![qb_t()])).unwrap();
+        let [qb, b] = builder.input_wires_arr();
+        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], b), [(qb_t(), qb)], vec![qb_t()].into()).unwrap();
+        let cond0_builder = cond_builder.case_builder(0).unwrap();
+        let [c0q] = cond0_builder.input_wires_arr();
+        cond0_builder.finish_with_outputs([c0q]).unwrap();
+        let mut cond1_builder = cond_builder.case_builder(1).unwrap();
+        let [c1q] = cond1_builder.input_wires_arr();
+        let [c1q] = cond1_builder.add_dataflow_op(TketOp::H, [c1q]).unwrap().outputs_arr();
+        cond1_builder.finish_with_outputs([c1q]).unwrap();
+        let cond = cond_builder.finish_sub_container().unwrap();
+        let hugr = builder.finish_hugr_with_outputs(cond.outputs()).unwrap();
+        let [input, _] = hugr.get_io(hugr.entrypoint()).unwrap();
+        // Whether X and Z on the input are related to Z and X on the output, as (XX, ZZ, XZ, ZX)
+        let relations = |policy: AnalysisConfig<Node>| {
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), policy).unwrap();
+            let (in_col, out_col) = (analysis.input_column(OutgoingPort::from(0)).unwrap(), analysis.output_column(IncomingPort::from(0)).unwrap());
+            let related = |z: &[usize], x: &[usize]| analysis.tableau().stabilizes(&analysis.tableau().pauli(z, x, false));
+            (related(&[], &[in_col, out_col]), related(&[in_col, out_col], &[]), related(&[out_col], &[in_col]), related(&[in_col], &[out_col]))
+        };
+        assert_eq!(relations(AnalysisConfig::default_opaque()), (false, false, false, false));
+        assert_eq!(relations(AnalysisConfig::default_opaque().with_known_classical(input, OutgoingPort::from(1), false)), (true, true, false, false));
+        assert_eq!(relations(AnalysisConfig::default_opaque().with_known_classical(input, OutgoingPort::from(1), true)), (false, false, true, true));
+    }
+
+    #[test]
+    fn test_classical_relation() {
+        // Measuring the same qubit twice gives equal bits, even though each is random
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t(), bool_t(), bool_t(), bool_t()])).unwrap();
+        let [qb] = builder.input_wires_arr();
+        let meas0 = builder.add_dataflow_op(TketOp::Measure, [qb]).unwrap();
+        let [qb, b0] = meas0.outputs_arr();
+        let meas1 = builder.add_dataflow_op(TketOp::Measure, [qb]).unwrap();
+        let [qb, b1] = meas1.outputs_arr();
+        let not = builder.add_dataflow_op(LogicOp::Not, [b1]).unwrap();
+        let hugr = builder.finish_hugr_with_outputs([qb, b0, b1, not.out_wire(0)]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.classical_relation((meas0.node(), OutgoingPort::from(1)), (meas1.node(), OutgoingPort::from(1))), Some(false));
+        assert_eq!(analysis.classical_relation((meas0.node(), OutgoingPort::from(1)), (not.node(), OutgoingPort::from(0))), Some(true));
+        // The qubits of a Bell pair give equal bits, so their Xor is always 0, but an H before one measurement breaks the correlation
+        for h in [false, true] {
+            // MeasureFree gives tket.bool bits, so they are combined with the tket.bool Xor
+            let mut builder = DFGBuilder::new(Signature::new(vec![qb_t(), qb_t()], vec![bool_type(), bool_t(), bool_type()])).unwrap();
+            let [qb0, qb1] = builder.input_wires_arr();
+            let [qb0] = builder.add_dataflow_op(TketOp::Reset, [qb0]).unwrap().outputs_arr();
+            let [qb1] = builder.add_dataflow_op(TketOp::Reset, [qb1]).unwrap().outputs_arr();
+            let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+            let [qb0, mut qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+            if h {
+                [qb1] = builder.add_dataflow_op(TketOp::H, [qb1]).unwrap().outputs_arr();
+            }
+            let meas0 = builder.add_dataflow_op(TketOp::MeasureFree, [qb0]).unwrap();
+            let meas1 = builder.add_dataflow_op(TketOp::MeasureFree, [qb1]).unwrap();
+            let xor = builder.add_dataflow_op(BoolOp::xor, [meas0.out_wire(0), meas1.out_wire(0)]).unwrap();
+            let cst = builder.add_load_value(Value::false_val());
+            let hugr = builder.finish_hugr_with_outputs([meas0.out_wire(0), cst, xor.out_wire(0)]).unwrap();
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            let expected = if h { None } else { Some(false) };
+            assert_eq!(analysis.classical_relation((meas0.node(), OutgoingPort::from(0)), (meas1.node(), OutgoingPort::from(0))), expected);
+            assert_eq!(analysis.classical_relation((xor.node(), OutgoingPort::from(0)), (cst.node(), cst.source())), expected);
+        }
+    }
+
+    #[test]
+    fn test_pauli_frame_teleportation() {
+        // One-bit teleportation: after CX;H, measuring qb0 leaves qb1 in Z^m |psi>, which the Conditional corrects
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [qb0] = builder.input_wires_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [b] = builder.add_dataflow_op(TketOp::MeasureFree, [qb0]).unwrap().outputs_arr();
+        let [b] = builder.add_dataflow_op(BoolOp::read, [b]).unwrap().outputs_arr();
+        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], b), [(qb_t(), qb1)], vec![qb_t()].into()).unwrap();
+        let cond0_builder = cond_builder.case_builder(0).unwrap();
+        let [c0q] = cond0_builder.input_wires_arr();
+        cond0_builder.finish_with_outputs([c0q]).unwrap();
+        let mut cond1_builder = cond_builder.case_builder(1).unwrap();
+        let [c1q] = cond1_builder.input_wires_arr();
+        let [c1q] = cond1_builder.add_dataflow_op(TketOp::Z, [c1q]).unwrap().outputs_arr();
+        cond1_builder.finish_with_outputs([c1q]).unwrap();
+        let cond = cond_builder.finish_sub_container().unwrap();
+        let hugr = builder.finish_hugr_with_outputs(cond.outputs()).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let (in_col, out_col) = (analysis.input_column(OutgoingPort::from(0)).unwrap(), analysis.output_column(IncomingPort::from(0)).unwrap());
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[in_col, out_col], false)));
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[in_col, out_col], &[], false)));
+        // Without the correction, the X relation is lost
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t(), bool_type()])).unwrap();
+        let [qb0] = builder.input_wires_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let [b] = builder.add_dataflow_op(TketOp::MeasureFree, [qb0]).unwrap().outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb1, b]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let (in_col, out_col) = (analysis.input_column(OutgoingPort::from(0)).unwrap(), analysis.output_column(IncomingPort::from(0)).unwrap());
+        assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[in_col, out_col], false)));
+        assert!(!analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[in_col, out_col], true)));
+        assert!(analysis.tableau().stabilizes(&analysis.tableau().pauli(&[in_col, out_col], &[], false)));
+    }
+
+    #[test]
+    fn test_analysis_config() {
+        // One-bit teleportation as in test_pauli_frame_teleportation, also returning the negated measured bit
+        let mut builder = DFGBuilder::new(Signature::new(vec![qb_t()], vec![qb_t(), bool_t()])).unwrap();
+        let [qb0] = builder.input_wires_arr();
+        let [qb1] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+        let [qb0, qb1] = builder.add_dataflow_op(TketOp::CX, [qb0, qb1]).unwrap().outputs_arr();
+        let [qb0] = builder.add_dataflow_op(TketOp::H, [qb0]).unwrap().outputs_arr();
+        let meas = builder.add_dataflow_op(TketOp::MeasureFree, [qb0]).unwrap();
+        let [b] = builder.add_dataflow_op(BoolOp::read, [meas.out_wire(0)]).unwrap().outputs_arr();
+        let not = builder.add_dataflow_op(LogicOp::Not, [b]).unwrap();
+        let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], b), [(qb_t(), qb1)], vec![qb_t()].into()).unwrap();
+        let cond0_builder = cond_builder.case_builder(0).unwrap();
+        let [c0q] = cond0_builder.input_wires_arr();
+        cond0_builder.finish_with_outputs([c0q]).unwrap();
+        let mut cond1_builder = cond_builder.case_builder(1).unwrap();
+        let [c1q] = cond1_builder.input_wires_arr();
+        let [c1q] = cond1_builder.add_dataflow_op(TketOp::Z, [c1q]).unwrap().outputs_arr();
+        cond1_builder.finish_with_outputs([c1q]).unwrap();
+        let cond = cond_builder.finish_sub_container().unwrap();
+        let hugr = builder.finish_hugr_with_outputs([cond.out_wire(0), not.out_wire(0)]).unwrap();
+        let bit_relation = |analysis: &StabilizerDataflow<hugr::Hugr>| analysis.classical_relation((meas.node(), OutgoingPort::from(0)), (not.node(), OutgoingPort::from(0)));
+        let keeps_across = |analysis: &StabilizerDataflow<hugr::Hugr>, sign: bool| {
+            let (in_col, out_col) = (analysis.input_column(OutgoingPort::from(0)).unwrap(), analysis.output_column(IncomingPort::from(0)).unwrap());
+            analysis.tableau().stabilizes(&analysis.tableau().pauli(&[], &[in_col, out_col], sign))
+        };
+        // A bare FunctionOpacity is the default configuration, which tracks the measured bit and so the Pauli frame it controls
+        let bare = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        let tracked = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), AnalysisConfig::default_opaque()).unwrap();
+        assert_eq!(bare.statistics(&hugr), tracked.statistics(&hugr));
+        assert!(bare.tableau().stabs.iter().all(|row| tracked.tableau().stabilizes(row)) && tracked.tableau().stabs.iter().all(|row| bare.tableau().stabilizes(row)));
+        assert_eq!(bit_relation(&tracked), Some(true));
+        assert!(keeps_across(&tracked, false));
+        // Without classical tracking the bit is unknown, so the cases of the Conditional are joined and the relation the correction fixes is lost
+        let untracked = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), AnalysisConfig::default_opaque().with_classical_tracking(false)).unwrap();
+        assert_eq!(bit_relation(&untracked), None);
+        assert!(!keeps_across(&untracked, false) && !keeps_across(&untracked, true));
+        // Two T gates on one qubit need six columns, so a budget of four truncates the second, with or without classical tracking
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let [mut qb] = builder.input_wires_arr();
+        for _ in 0..2 {
+            [qb] = builder.add_dataflow_op(TketOp::T, [qb]).unwrap().outputs_arr();
+        }
+        let hugr = builder.finish_hugr_with_outputs([qb]).unwrap();
+        for track in [false, true] {
+            let config = AnalysisConfig::default_opaque().with_classical_tracking(track);
+            let full = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), config.clone()).unwrap();
+            assert_eq!((full.statistics(&hugr).truncated_nodes, full.tableau().nb_qubits), (0, 6));
+            let bounded = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), config.with_max_columns(4)).unwrap();
+            assert_eq!((bounded.statistics(&hugr).truncated_nodes, bounded.tableau().nb_qubits), (1, 4));
+        }
+    }
+
+    #[test]
+    fn test_pauli_correction() {
+        // Over columns (in, out), the identity and Z differ by Z on out, but the identity and H do not differ by any Pauli
+        let mut identity = ChoiTableau::new(2);
+        identity.add_row(identity.pauli(&[], &[0, 1], false));
+        identity.add_row(identity.pauli(&[0, 1], &[], false));
+        let mut z = identity.clone();
+        z.append_z(1);
+        let correction = pauli_correction(&identity, &z, &[1]).unwrap();
+        assert_eq!((correction.get_z(0), correction.get_x(0), correction.get_z(1), correction.get_x(1)), (false, false, true, false));
+        let mut h = identity.clone();
+        h.append_h(1);
+        assert!(pauli_correction(&identity, &h, &[1]).is_none());
+    }
+
+    #[test]
+    fn test_local_function() {
+        // A function defined within the DFG (applying X) is analysed at its call, according to the policy
+        let mut fun_builder = FunctionBuilder::new("flip", endo_sig(vec![qb_t()])).unwrap();
+        let [qb] = fun_builder.input_wires_arr();
+        let x = fun_builder.add_dataflow_op(TketOp::X, [qb]).unwrap();
+        let fun_hugr = fun_builder.finish_hugr_with_outputs(x.outputs()).unwrap();
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t()])).unwrap();
+        let module_root = builder.hugr().module_root();
+        let fun = builder.hugr_mut().insert_hugr(module_root, fun_hugr).inserted_entrypoint;
+        let [qb] = builder.input_wires_arr();
+        let call = builder.call(&FuncID::<true>::from(fun), &[], [qb]).unwrap();
+        let mut hugr = builder.finish_hugr_with_outputs(call.outputs()).unwrap();
+        // Validation only allows function definitions at module level, so the function is only moved into the DFG once built, but the analysis should still skip one where it finds it
+        let root = hugr.entrypoint();
+        hugr.set_parent(fun, root);
+        // Type aliases are skipped too
+        hugr.add_node_with_parent(root, AliasDecl::new("alias", TypeBound::Linear));
+        for (policy, z_sign) in [(FunctionOpacity::Opaque, None), (FunctionOpacity::Boundary, Some(true)), (FunctionOpacity::Inline, Some(true))] {
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &policy).unwrap();
+            let (in_col, out_col) = (analysis.input_column(OutgoingPort::from(0)).unwrap(), analysis.output_column(IncomingPort::from(0)).unwrap());
+            let sign = [false, true].into_iter().find(|sign| analysis.tableau().stabilizes(&analysis.tableau().pauli(&[in_col, out_col], &[], *sign)));
+            assert_eq!(sign, z_sign);
+            assert!(analysis.internal_columns(&[fun.node()]).is_empty());
+            assert_eq!(analysis.statistics(&hugr).opaque_nodes, matches!(policy, FunctionOpacity::Opaque) as usize);
+        }
+    }
+
+    #[test]
+    fn test_node_qubit_count() {
+        let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(), qb_t(), qb_t()])).unwrap();
+        let [qb0, qb1, qb2] = builder.input_wires_arr();
+        let toffoli = builder.add_dataflow_op(TketOp::Toffoli, [qb0, qb1, qb2]).unwrap();
+        let [qb0, qb1, qb2] = toffoli.outputs_arr();
+        let t = builder.add_dataflow_op(TketOp::T, [qb0]).unwrap();
+        let h = builder.add_dataflow_op(TketOp::H, t.outputs()).unwrap();
+        let [qb0] = h.outputs_arr();
+        let hugr = builder.finish_hugr_with_outputs([qb0, qb1, qb2]).unwrap();
+        let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+        assert_eq!(analysis.node_qubit_count(toffoli.node()), (3, 3));
+        assert_eq!(analysis.node_qubit_count(t.node()), (1, 1));
+        assert_eq!(analysis.node_qubit_count(h.node()), (1, 1));
+        let [input, _] = hugr.get_io(hugr.entrypoint()).unwrap();
+        assert_eq!(analysis.node_qubit_count(input), (0, 0));
+        let counts = analysis.all_node_qubit_counts();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts[&toffoli.node()], (3, 3));
+    }
+
+    #[test]
+    fn test_clifford_angle_rz() {
+        // Rz and CRz by constant angles, against the Clifford gates they amount to (with None for a non-Clifford angle)
+        let cases: [(TketOp, f64, Option<Vec<Command>>); 6] = [
+            (TketOp::Rz, 0.5, Some(vec![(TketOp::S, vec![0])])),
+            (TketOp::Rz, -0.5 + 1e-12, Some(vec![(TketOp::Sdg, vec![0])])),
+            (TketOp::Rz, 3.0, Some(vec![(TketOp::Z, vec![0])])),
+            (TketOp::Rz, 0.25, None),
+            (TketOp::CRz, 1.0, Some(vec![(TketOp::CZ, vec![0, 1]), (TketOp::Sdg, vec![0])])),
+            (TketOp::CRz, 0.5, None),
+        ];
+        for (op, half_turns, gates) in cases {
+            let n_qubits = if op == TketOp::CRz { 2 } else { 1 };
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(); n_qubits])).unwrap();
+            let mut wires = builder.input_wires().collect_vec();
+            let angle = builder.add_load_value(Value::extension(ConstRotation::new(half_turns.rem_euclid(2.)).unwrap()));
+            wires.push(angle);
+            let rz = builder.add_dataflow_op(op, wires).unwrap();
+            let hugr = builder.finish_hugr_with_outputs(rz.outputs()).unwrap();
+            let analysis = StabilizerDataflow::run_dfg(&hugr, hugr.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            let Some(gates) = gates else {
+                assert!(!analysis.internal_columns(&[rz.node()]).is_empty());
+                continue;
+            };
+            let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(); n_qubits])).unwrap();
+            let mut wires = builder.input_wires().collect_vec();
+            for (gate, qubits) in gates {
+                let outs = builder.add_dataflow_op(gate, qubits.iter().map(|q| wires[*q])).unwrap().outputs().collect_vec();
+                for (q, out) in qubits.into_iter().zip(outs) {
+                    wires[q] = out;
+                }
+            }
+            let clifford = builder.finish_hugr_with_outputs(wires).unwrap();
+            let expected = StabilizerDataflow::run_dfg(&clifford, clifford.entrypoint(), &FunctionOpacity::Opaque).unwrap();
+            // No extra columns are allocated, and the tableau matches that of the Clifford gates
+            assert_eq!(analysis.tableau().nb_qubits, 2 * n_qubits, "{:?}({})", op, half_turns);
+            assert!(analysis.tableau().equivalent(expected.tableau()), "{:?}({})", op, half_turns);
+            assert_eq!(analysis.statistics(&hugr), AnalysisStats { columns_allocated: 2 * n_qubits, ..AnalysisStats::default() });
+        }
+    }
+}
@@ -1,79 +1,412 @@
-use tket2::passes::fast_todd::bit_vector::BitVector;
+use std::collections::BTreeSet;
+use crate::bit_vector::BitVector;
+use crate::pauli_product::PauliProduct;
 
 #[derive(Debug, Clone)]
-pub strict ChoiTableau {
+pub struct ChoiTableau {
     // Total number of qubits in the system; each may represent an input, output, or intermediary point in the original circuit, but are uniformly considered outputs of the Choi-state considered here
     pub nb_qubits: usize,
 
     // Number of rows (stabilizers) in the tableau; we do not impose any requirements on how this compares to nb_qubits
-    pub nb_rows: usize,
+    pub nb_stabs: usize,
 
-    // Binary tables; since we expect to perform a lot of row multiplications, we use a RowMajor ordering - first index for row, then index into the BitVector for qubits
-    pub z: Vec<BitVector>,
-    pub x: Vec<BitVector>,
+    // Since we expect to perform a lot of row multiplications, we use a RowMajor ordering - one PauliProduct per row, each holding BitVectors indexed by qubit
+    // Every row is kept at the same BitVector width so rows can be multiplied together directly
+    pub stabs: Vec<PauliProduct>,
 
-    // Keep signs in a single vector
-    pub signs: BitVector,
+    // If enabled, the tags responsible for each row (in the same order as stabs): whichever tag was current when the row was added or changed by a gate, and every tag of the rows multiplied into it
+    // Tags are opaque identifiers chosen by the owner of the tableau (e.g. for the node being applied), and provenance is only tracked through the row operations here, so tableaux built by join start without it
+    pub provenance: Option<Vec<BTreeSet<usize>>>,
+
+    // The tag recorded in the provenance of rows added or changed, if any
+    pub current_tag: Option<usize>,
 }
 
 impl ChoiTableau {
     pub fn new(nb_qubits: usize) -> Self {
-        //TODO:: Implement
+        ChoiTableau {
+            nb_qubits,
+            nb_stabs: 0,
+            stabs: Vec::new(),
+            provenance: None,
+            current_tag: None,
+        }
+    }
+
+    // Starts tracking the provenance of each row, with the existing rows attributed to no tag
+    pub fn with_provenance(mut self) -> Self {
+        self.provenance = Some(vec![BTreeSet::new(); self.nb_stabs]);
+        self
+    }
+
+    // Records the current tag in the provenance of each row that changed picks out, before a gate is applied to the rows
+    fn tag_rows(&mut self, changed: impl Fn(&PauliProduct) -> bool) {
+        if let (Some(provenance), Some(tag)) = (self.provenance.as_mut(), self.current_tag) {
+            for (row, tags) in self.stabs.iter().zip(provenance.iter_mut()) {
+                if changed(row) {
+                    tags.insert(tag);
+                }
+            }
+        }
+    }
+
+    // Builds a row over the current qubits with Z on z_qubits and X on x_qubits (a qubit in both gives Y)
+    pub fn pauli(&self, z_qubits: &[usize], x_qubits: &[usize], sign: bool) -> PauliProduct {
+        let mut z = BitVector::new(self.nb_qubits);
+        for q in z_qubits {
+            z.xor_bit(*q);
+        }
+        let mut x = BitVector::new(self.nb_qubits);
+        for q in x_qubits {
+            x.xor_bit(*q);
+        }
+        PauliProduct::new(z, x, sign)
     }
-    pub fn add_row(&mut self, z: BitVector, x: BitVector, sign: bool) {
-        //TODO:: Implement
+
+    pub fn add_row(&mut self, mut row: PauliProduct) {
+        row.z.resize(self.nb_qubits);
+        row.x.resize(self.nb_qubits);
+        self.stabs.push(row);
+        self.nb_stabs += 1;
+        if let Some(provenance) = self.provenance.as_mut() {
+            provenance.push(self.current_tag.into_iter().collect());
+        }
     }
 
     pub fn add_col(&mut self) -> usize {
-        //TODO:: Implement
+        self.nb_qubits += 1;
+        for row in self.stabs.iter_mut() {
+            row.z.resize(self.nb_qubits);
+            row.x.resize(self.nb_qubits);
+        }
+        self.nb_qubits - 1
+    }
+
+    // Tensor product with other, placing its qubits after our existing ones; returns the column offset of other's qubits
+    pub fn tensor(&mut self, other: &ChoiTableau) -> usize {
+        let offset = self.nb_qubits;
+        self.nb_qubits += other.nb_qubits;
+        for row in self.stabs.iter_mut() {
+            row.z.resize(self.nb_qubits);
+            row.x.resize(self.nb_qubits);
+        }
+        let indices: Vec<usize> = (offset..self.nb_qubits).collect();
+        for (r, row) in other.stabs.iter().enumerate() {
+            let z = row.z.bit_scatter(&indices, self.nb_qubits);
+            let x = row.x.bit_scatter(&indices, self.nb_qubits);
+            self.add_row(PauliProduct::new(z, x, row.sign));
+            // The tags of other must already mean the same as ours
+            if let (Some(provenance), Some(other_provenance)) = (self.provenance.as_mut(), other.provenance.as_ref()) {
+                provenance.last_mut().unwrap().extend(other_provenance[r].iter().copied());
+            }
+        }
+        offset
+    }
+
+    // Remove qubits from the tableau, shifting later qubits down to fill the gaps
+    // The removed qubits should already have been projected out so no row acts on them
+    pub fn remove_cols(&mut self, cols: &[usize]) {
+        let keep: Vec<usize> = (0..self.nb_qubits).filter(|q| !cols.contains(q)).collect();
+        for row in self.stabs.iter_mut() {
+            debug_assert!(cols.iter().all(|q| !row.z.get(*q) && !row.x.get(*q)));
+            let mut z = BitVector::new(keep.len());
+            let mut x = BitVector::new(keep.len());
+            for (new_q, old_q) in keep.iter().enumerate() {
+                if row.z.get(*old_q) { z.xor_bit(new_q); }
+                if row.x.get(*old_q) { x.xor_bit(new_q); }
+            }
+            row.z = z;
+            row.x = x;
+        }
+        self.nb_qubits = keep.len();
+    }
+
+    pub fn remove_row(&mut self, row: usize) -> PauliProduct {
+        self.nb_stabs -= 1;
+        if let Some(provenance) = self.provenance.as_mut() {
+            provenance.remove(row);
+        }
+        self.stabs.remove(row)
     }
 
     pub fn append_z(&mut self, qubit: usize) {
-        //TODO:: Implement
+        self.tag_rows(|row| row.x.get(qubit));
+        for row in self.stabs.iter_mut() {
+            row.sign ^= row.x.get(qubit);
+        }
     }
+
     pub fn append_x(&mut self, qubit: usize) {
-        //TODO:: Implement
+        self.tag_rows(|row| row.z.get(qubit));
+        for row in self.stabs.iter_mut() {
+            row.sign ^= row.z.get(qubit);
+        }
     }
+
     pub fn append_s(&mut self, qubit: usize) {
-        //TODO:: Implement
+        // X -> Y, Y -> -X
+        self.tag_rows(|row| row.x.get(qubit));
+        for row in self.stabs.iter_mut() {
+            if row.x.get(qubit) {
+                row.sign ^= row.z.get(qubit);
+                row.z.xor_bit(qubit);
+            }
+        }
     }
+
     pub fn append_v(&mut self, qubit: usize) {
-        //TODO:: Implement
+        // Z -> -Y, Y -> Z
+        self.tag_rows(|row| row.z.get(qubit));
+        for row in self.stabs.iter_mut() {
+            if row.z.get(qubit) {
+                row.sign ^= !row.x.get(qubit);
+                row.x.xor_bit(qubit);
+            }
+        }
     }
+
     pub fn append_h(&mut self, qubit: usize) {
-        //TODO:: Implement
+        // X <-> Z, Y -> -Y
+        self.tag_rows(|row| row.z.get(qubit) || row.x.get(qubit));
+        for row in self.stabs.iter_mut() {
+            let z = row.z.get(qubit);
+            let x = row.x.get(qubit);
+            if z != x {
+                row.z.xor_bit(qubit);
+                row.x.xor_bit(qubit);
+            }
+            row.sign ^= z && x;
+        }
     }
-    pub fn append_cx(&mut self, qubit: usize) {
-        //TODO:: Implement
-    }
-    pub fn append_cy(&mut self, qubit: usize) {
-        //TODO:: Implement
+
+    pub fn append_cx(&mut self, qubits: Vec<usize>) {
+        let (c, t) = (qubits[0], qubits[1]);
+        self.tag_rows(|row| row.x.get(c) || row.z.get(t));
+        for row in self.stabs.iter_mut() {
+            let (zc, xc, zt, xt) = (row.z.get(c), row.x.get(c), row.z.get(t), row.x.get(t));
+            row.sign ^= xc && zt && !(xt ^ zc);
+            if xc { row.x.xor_bit(t); }
+            if zt { row.z.xor_bit(c); }
+        }
     }
-    pub fn append_cz(&mut self, qubit: usize) {
-        //TODO:: Implement
+
+    pub fn append_cz(&mut self, qubits: Vec<usize>) {
+        let (a, b) = (qubits[0], qubits[1]);
+        self.tag_rows(|row| row.x.get(a) || row.x.get(b));
+        for row in self.stabs.iter_mut() {
+            let (za, xa, zb, xb) = (row.z.get(a), row.x.get(a), row.z.get(b), row.x.get(b));
+            row.sign ^= xa && xb && (za ^ zb);
+            if xb { row.z.xor_bit(a); }
+            if xa { row.z.xor_bit(b); }
+        }
     }
 
-    // Compute i^coeff row[rr] * row[rw] and store in row[rw]
-    pub fn row_mult(&mut self, rr: usize, rw: usize, coeff: usize) {
-        //TODO:: Implement
+    // Compute row[rr] * row[rw] and store in row[rw]
+    // Rows of a stabilizer group commute, so the product is always Hermitian and we never need an imaginary coefficient
+    pub fn row_mult(&mut self, rr: usize, rw: usize) {
+        let p = self.stabs[rr].clone();
+        self.stabs[rw].pauli_product_mult(&p);
+        if let Some(provenance) = self.provenance.as_mut() {
+            let tags = provenance[rr].clone();
+            provenance[rw].extend(tags);
+        }
     }
 
     // Reduce to row echelon form
     // Given the ordering of columns (qubit, false=z/true=x), call row_mult to achieve reduced row-echelon form
     // col_order need not include every column, in which case we terminate after solving just the columns provided
-    // Feel free to suggest a better interface here
-    // We may also want a version that allows us to simultaneously perform this over a pair of tableaux
-    pub fn echelon(&mut self, col_order: Vec<(usize, bool)>) {
-        //TODO:: Implement
+    // Returns the number of pivot rows found, which are moved to the top of the tableau in the order of their pivot columns
+    pub fn echelon(&mut self, col_order: &[(usize, bool)]) -> usize {
+        let mut n_pivots = 0;
+        for (qubit, is_x) in col_order.iter() {
+            if n_pivots == self.nb_stabs { break; }
+            if let Some(index) = (n_pivots..self.nb_stabs).find(|r| ChoiTableau::get(&self.stabs[*r], *qubit, *is_x)) {
+                self.stabs.swap(n_pivots, index);
+                if let Some(provenance) = self.provenance.as_mut() {
+                    provenance.swap(n_pivots, index);
+                }
+                for r in 0..self.nb_stabs {
+                    if r != n_pivots && ChoiTableau::get(&self.stabs[r], *qubit, *is_x) {
+                        self.row_mult(n_pivots, r);
+                    }
+                }
+                n_pivots += 1;
+            }
+        }
+        n_pivots
     }
 
     // Call echelon to minimise the number of rows with non-zero components in the given columns, then remove those rows with such non-zero components
-    pub fn project(&mut self, cols: Vec<(usize, bool)>) {
-        //TODO:: Implement
+    pub fn project(&mut self, cols: &[(usize, bool)]) {
+        let n_pivots = self.echelon(cols);
+        self.stabs.drain(0..n_pivots);
+        self.nb_stabs -= n_pivots;
+        if let Some(provenance) = self.provenance.as_mut() {
+            provenance.drain(0..n_pivots);
+        }
     }
 
     // Apply row combinations to leave at most one row anticommuting with the target Pauli string, and remove it
-    pub fn project_commuting_with(&mut self, z: BitVector, x: BitVector) {
-        //TODO:: Implement
+    pub fn project_commuting_with(&mut self, p: &PauliProduct) {
+        if let Some(index) = self.stabs.iter().position(|row| !row.commutes_with(p)) {
+            for r in (index + 1)..self.nb_stabs {
+                if !self.stabs[r].commutes_with(p) {
+                    self.row_mult(index, r);
+                }
+            }
+            self.remove_row(index);
+        }
+    }
+
+    // Move each qubit q to position new_index[q]; new_index must be a permutation of 0..nb_qubits
+    pub fn reorder_cols(&mut self, new_index: &[usize]) {
+        assert_eq!(new_index.len(), self.nb_qubits);
+        for row in self.stabs.iter_mut() {
+            row.z = row.z.bit_scatter(new_index, self.nb_qubits);
+            row.x = row.x.bit_scatter(new_index, self.nb_qubits);
+        }
+    }
+
+    // Check whether p (including its sign) is in the stabilizer group
+    pub fn stabilizes(&self, p: &PauliProduct) -> bool {
+        self.stabilizes_all(std::slice::from_ref(p))
+    }
+
+    // Check whether two tableaux over the same qubits generate the same stabilizer group (including signs), regardless of the choice of generators
+    pub fn equivalent(&self, other: &ChoiTableau) -> bool {
+        self.nb_qubits == other.nb_qubits && self.stabilizes_all(&other.stabs) && other.stabilizes_all(&self.stabs)
+    }
+
+    // Sequential composition: place other alongside us and connect each pair (our column, other's column) as though by a wire, then trace out the connected columns
+    // Returns the new tableau, with our remaining columns in order followed by other's remaining columns in order
+    pub fn compose(&self, other: &ChoiTableau, cols: &[(usize, usize)]) -> ChoiTableau {
+        let mut tab = self.clone();
+        let offset = tab.tensor(other);
+        let mut removed = Vec::new();
+        for (a, b) in cols.iter() {
+            // Project the pair onto the Bell state with XX and ZZ
+            for p in [tab.pauli(&[], &[*a, *b + offset], false), tab.pauli(&[*a, *b + offset], &[], false)] {
+                tab.project_commuting_with(&p);
+                tab.add_row(p);
+            }
+            removed.push(*a);
+            removed.push(*b + offset);
+        }
+        tab.project(&removed.iter().flat_map(|c| [(*c, false), (*c, true)]).collect::<Vec<(usize, bool)>>());
+        tab.remove_cols(&removed);
+        tab
+    }
+
+    // Whether the rows generate a stabilizer group of some state: they pairwise commute, and no product of them is -I
+    pub fn is_valid_stabilizer_group(&self) -> bool {
+        let commuting = (0..self.nb_stabs).all(|i| ((i + 1)..self.nb_stabs).all(|j| self.stabs[i].commutes_with(&self.stabs[j])));
+        // Rows left without a pivot by echelon are products of the others, so reduce to the identity and must do so with a positive sign
+        let mut tab = self.clone();
+        let n_pivots = tab.echelon(&self.all_cols());
+        commuting && tab.stabs[n_pivots..].iter().all(|row| !row.sign)
+    }
+
+    // The independent rows in reduced row echelon form over the qubits in order (Z before X on each), which depends only on the stabilizer group, so two tableaux over the same qubits are equivalent exactly when these are equal
+    pub fn canonical_rows(&self) -> Vec<PauliProduct> {
+        let mut tab = self.clone();
+        let n_pivots = tab.echelon(&self.all_cols());
+        tab.stabs.truncate(n_pivots);
+        tab.stabs
+    }
+
+    // Number of independent rows, which may be fewer than nb_stabs if some rows are products of others
+    pub fn stabilizer_rank(&self) -> usize {
+        let mut tab = self.clone();
+        tab.echelon(&self.all_cols())
+    }
+
+    // Fraction of a complete set of stabilizers over the qubits that we have, i.e. stabilizer_rank / nb_qubits
+    pub fn analysis_completeness(&self) -> f64 {
+        if self.nb_qubits == 0 {
+            return 1.0;
+        }
+        self.stabilizer_rank() as f64 / self.nb_qubits as f64
+    }
+
+    // Compute the join of two tableaux over the same qubits, i.e. the intersection of their stabilizer groups (including signs)
+    // These are exactly the relations that hold whichever of the two states we actually have, e.g. after either branch of a conditional
+    pub fn join(&self, other: &ChoiTableau) -> ChoiTableau {
+        assert_eq!(self.nb_qubits, other.nb_qubits);
+        let col_order = self.all_cols();
+        // Zassenhaus: eliminate the left halves of the rows (a | a) and (b | 0), so those left with zero on the left carry a spanning set of the intersection of the (unsigned) row spaces on the right
+        let mut rows: Vec<(PauliProduct, PauliProduct)> = self.stabs.iter().map(|a| (a.clone(), a.clone()))
+            .chain(other.stabs.iter().map(|b| (b.clone(), self.pauli(&[], &[], false))))
+            .collect();
+        let mut n_pivots = 0;
+        for (qubit, is_x) in col_order.iter() {
+            if let Some(index) = (n_pivots..rows.len()).find(|r| ChoiTableau::get(&rows[*r].0, *qubit, *is_x)) {
+                rows.swap(n_pivots, index);
+                let (pivot_l, pivot_r) = rows[n_pivots].clone();
+                for row in rows.iter_mut().skip(n_pivots + 1) {
+                    if ChoiTableau::get(&row.0, *qubit, *is_x) {
+                        row.0.z.xor(&pivot_l.z);
+                        row.0.x.xor(&pivot_l.x);
+                        row.1.z.xor(&pivot_r.z);
+                        row.1.x.xor(&pivot_r.x);
+                    }
+                }
+                n_pivots += 1;
+            }
+        }
+        // Each candidate is a product of our rows up to sign, so recover its sign from reducing against our group
+        let mut tab_self = self.clone();
+        let n_self_pivots = tab_self.echelon(&col_order);
+        let mut joined = ChoiTableau::new(self.nb_qubits);
+        for (_, mut row) in rows.drain(n_pivots..) {
+            row.sign = false;
+            row.sign = tab_self.reduce(n_self_pivots, &row).sign;
+            joined.add_row(row);
+        }
+        let n_joined = joined.echelon(&col_order);
+        joined.stabs.truncate(n_joined);
+        joined.nb_stabs = n_joined;
+        // The sign mismatch with other is linear over products of the rows, so fix up any mismatching rows using one of them and drop it
+        let mut tab_other = other.clone();
+        let n_other_pivots = tab_other.echelon(&col_order);
+        let mismatched = (0..n_joined).filter(|r| tab_other.reduce(n_other_pivots, &joined.stabs[*r]).sign).collect::<Vec<usize>>();
+        if let Some((first, rest)) = mismatched.split_first() {
+            for r in rest {
+                joined.row_mult(*first, *r);
+            }
+            joined.remove_row(*first);
+        }
+        joined
+    }
+
+    fn stabilizes_all(&self, ps: &[PauliProduct]) -> bool {
+        let mut tab = self.clone();
+        let n_pivots = tab.echelon(&tab.all_cols());
+        ps.iter().all(|p| {
+            let p = tab.reduce(n_pivots, p);
+            p.z.popcount() == 0 && p.x.popcount() == 0 && !p.sign
+        })
+    }
+
+    fn all_cols(&self) -> Vec<(usize, bool)> {
+        (0..self.nb_qubits).flat_map(|q| [(q, false), (q, true)]).collect()
+    }
+
+    // Assuming the first n_pivots rows are in reduced row-echelon form from echelon(all_cols()), multiply p by pivot rows to clear each pivot it touches
+    // If p is in the stabilizer group up to sign, the residual is the identity with the sign of the mismatch
+    fn reduce(&self, n_pivots: usize, p: &PauliProduct) -> PauliProduct {
+        let col_order = self.all_cols();
+        let mut p = p.clone();
+        for row in self.stabs[0..n_pivots].iter() {
+            // In reduced form, the first non-zero entry of each pivot row is its pivot and no other pivot row touches it
+            let (qubit, is_x) = *col_order.iter().find(|(q, x)| ChoiTableau::get(row, *q, *x)).unwrap();
+            if ChoiTableau::get(&p, qubit, is_x) {
+                p.pauli_product_mult(row);
+            }
+        }
+        p
+    }
+
+    fn get(row: &PauliProduct, qubit: usize, is_x: bool) -> bool {
+        if is_x { row.x.get(qubit) } else { row.z.get(qubit) }
     }
 }
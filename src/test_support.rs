@@ -0,0 +1,199 @@
+use hugr::builder::{endo_sig, DFGBuilder, Dataflow, DataflowHugr, DataflowSubContainer, SubContainer};
+use hugr::extension::prelude::qb_t;
+use hugr::extension::{Extension, ExtensionId, Version};
+use hugr::ops::{ExtensionOp, OpTrait, OpType, Value};
+use hugr::types::Signature;
+use hugr::{type_row, Hugr, Wire};
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use std::sync::{Arc, LazyLock};
+use tket::extension::rotation::{rotation_type, ConstRotation};
+use tket::TketOp;
+use crate::column_allocator::WireRef;
+use crate::pauli_product::PauliProduct;
+use crate::stabilizer_dataflow::{AnalysisHugr, FunctionOpacity, StabilizerDataflow};
+use crate::tableau_interface::ChoiTableau;
+
+/// Analyses the region under root, treating calls as opaque, and asserts that its stabilizers over the wires of column_labels (in that order) generate the same group as expected
+/// Each expected stabilizer is a Pauli string over those wires with an optional sign, e.g. "-XZ"; relations involving any other column are projected out first
+/// On failure, both groups are printed as Pauli strings in canonical form (as from ChoiTableau::canonical_rows), along with the stabilizers each has that the other lacks
+pub fn assert_analysis_stabilizers<H: AnalysisHugr>(hugr: &H, root: H::Node, expected: &[&str], column_labels: &[WireRef<H::Node>]) {
+    let analysis = StabilizerDataflow::run_dfg(hugr, root, &FunctionOpacity::Opaque).unwrap_or_else(|e| panic!("Analysis of {root:?} failed: {e}"));
+    let cols = column_labels.iter().map(|wire| analysis.columns().column(wire).unwrap_or_else(|| panic!("No column is tracking {wire:?}"))).collect_vec();
+    let actual = analysis.relations_over(&cols);
+    let mut expected_tab = ChoiTableau::new(cols.len());
+    for row in expected {
+        let p: PauliProduct = row.parse().unwrap_or_else(|e| panic!("{e}"));
+        assert_eq!(p.z.nb_bits(), cols.len(), "Expected stabilizer {row:?} is not over the {} wires given", cols.len());
+        expected_tab.add_row(p);
+    }
+    if actual.equivalent(&expected_tab) {
+        return;
+    }
+    let (expected_rows, actual_rows) = (expected_tab.canonical_rows(), actual.canonical_rows());
+    let strings = |rows: &[PauliProduct], other: Option<&ChoiTableau>| rows.iter().filter(|row| other.is_none_or(|tab| !tab.stabilizes(row))).join(", ");
+    panic!(
+        "Stabilizers over {column_labels:?} differ\n  expected:   [{}]\n  actual:     [{}]\n  missing:    [{}]\n  unexpected: [{}]",
+        strings(&expected_rows, None),
+        strings(&actual_rows, None),
+        strings(&expected_rows, Some(&actual)),
+        strings(&actual_rows, Some(&expected_tab)),
+    );
+}
+
+/// The name of the extension defining the opaque ops of random circuits
+pub const RANDOM_EXTENSION_ID: ExtensionId = ExtensionId::new_unchecked("random");
+
+/// The extension defining the opaque ops of random circuits, "op1" on one qubit and "op2" on two, which the analysis knows nothing about
+/// Defining them (rather than using OpaqueOps) lets the circuits be validated
+pub static RANDOM_EXTENSION: LazyLock<Arc<Extension>> = LazyLock::new(|| {
+    Extension::new_arc(RANDOM_EXTENSION_ID, Version::new(0, 1, 0), |ext, ext_ref| {
+        for width in 1..=2 {
+            ext.add_op(format!("op{width}").into(), String::new(), Signature::new_endo(vec![qb_t(); width]), ext_ref).unwrap();
+        }
+    })
+});
+
+/// The shape of the circuits generated by random_circuit
+#[derive(Debug, Clone)]
+pub struct RandomCircuitConfig {
+    /// The number of qubits the circuit takes and returns, which must be at least 1
+    pub nb_qubits: usize,
+    /// The number of steps (each a gate or a block) in the outer region; the region of a block has at most half as many steps as the region around it
+    pub depth: usize,
+    /// The gates to choose from, which may be any TketOp whose inputs are qubits and rotations and whose outputs are qubits and copyable values; a gate on more qubits than are available is never chosen
+    pub gates: Vec<TketOp>,
+    /// The probability of a step being a Conditional on the measurement of a random qubit, with a random region in each of its two cases
+    pub conditional_prob: f64,
+    /// The probability of a step being a TailLoop with a random body, which ends by measuring a random qubit to decide whether to continue
+    pub tail_loop_prob: f64,
+    /// The probability of a step being an opaque op on one or two random qubits
+    pub opaque_prob: f64,
+    /// The probability of a step being a QAlloc, followed by a random region on the fresh qubit along with the others and then a QFree of a random qubit
+    pub alloc_prob: f64,
+    pub seed: u64,
+}
+
+impl Default for RandomCircuitConfig {
+    fn default() -> Self {
+        RandomCircuitConfig {
+            nb_qubits: 3,
+            depth: 12,
+            gates: vec![
+                TketOp::H, TketOp::S, TketOp::Sdg, TketOp::V, TketOp::Vdg, TketOp::X, TketOp::Y, TketOp::Z, TketOp::T, TketOp::Tdg,
+                TketOp::Rz, TketOp::CX, TketOp::CY, TketOp::CZ, TketOp::CRz, TketOp::Measure, TketOp::Reset,
+            ],
+            conditional_prob: 0.1,
+            tail_loop_prob: 0.1,
+            opaque_prob: 0.05,
+            alloc_prob: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+/// Generates a random DFG on config.nb_qubits qubits, the same for the same config
+/// Qubit wires are always used linearly: each step takes its qubits from the current wires and puts its qubit outputs back in their place, a gate that consumes a qubit (e.g. MeasureFree) is followed by a QAlloc, and one that produces one (e.g. QAlloc) by a QFree
+/// Rotations are by random constant multiples of an eighth turn, so some of them are Clifford gates
+pub fn random_circuit(config: &RandomCircuitConfig) -> Hugr {
+    assert!(config.nb_qubits > 0, "A random circuit needs at least one qubit, to measure for its control flow");
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut builder = DFGBuilder::new(endo_sig(vec![qb_t(); config.nb_qubits])).unwrap();
+    let mut qubits = builder.input_wires().collect_vec();
+    add_random_steps(&mut builder, &mut qubits, config.depth, config, &mut rng);
+    builder.finish_hugr_with_outputs(qubits).unwrap()
+}
+
+/// Adds depth random steps to the region of builder, updating qubits to the wires carrying them afterwards
+fn add_random_steps<D: Dataflow>(builder: &mut D, qubits: &mut Vec<Wire>, depth: usize, config: &RandomCircuitConfig, rng: &mut StdRng) {
+    let nested_depth = depth / 2;
+    // The step kinds in the order of their probabilities, with anything past their sum being a gate
+    let thresholds = [config.conditional_prob, config.tail_loop_prob, config.opaque_prob, config.alloc_prob].iter().scan(0., |sum, p| {
+        *sum += p;
+        Some(*sum)
+    }).collect_vec();
+    for _ in 0..depth {
+        let roll: f64 = rng.random();
+        // A block with a region of its own needs depth left for the region, so is replaced by a gate at the innermost level
+        let step = thresholds.iter().position(|threshold| roll < *threshold).filter(|step| *step == 2 || nested_depth > 0);
+        if step == Some(0) {
+            let predicate = measure_random_qubit(builder, qubits, rng);
+            let inputs = qubits.iter().map(|wire| (qb_t(), *wire)).collect_vec();
+            let mut cond_builder = builder.conditional_builder(([type_row![], type_row![]], predicate), inputs, vec![qb_t(); qubits.len()].into()).unwrap();
+            for case in 0..2 {
+                let mut case_builder = cond_builder.case_builder(case).unwrap();
+                let mut case_qubits = case_builder.input_wires().collect_vec();
+                add_random_steps(&mut case_builder, &mut case_qubits, rng.random_range(0..=nested_depth), config, rng);
+                case_builder.finish_with_outputs(case_qubits).unwrap();
+            }
+            *qubits = cond_builder.finish_sub_container().unwrap().outputs().collect_vec();
+        } else if step == Some(1) {
+            let inputs = qubits.iter().map(|wire| (qb_t(), *wire)).collect_vec();
+            let mut loop_builder = builder.tail_loop_builder([], inputs, type_row![]).unwrap();
+            let mut body_qubits = loop_builder.input_wires().collect_vec();
+            add_random_steps(&mut loop_builder, &mut body_qubits, rng.random_range(0..=nested_depth), config, rng);
+            let control = measure_random_qubit(&mut loop_builder, &mut body_qubits, rng);
+            *qubits = loop_builder.finish_with_outputs(control, body_qubits).unwrap().outputs().collect_vec();
+        } else if step == Some(2) {
+            let nb_targets = rng.random_range(1..=qubits.len().min(2));
+            let targets = rand::seq::index::sample(rng, qubits.len(), nb_targets).into_vec();
+            let op = ExtensionOp::new(RANDOM_EXTENSION.get_op(format!("op{nb_targets}").as_str()).unwrap().clone(), []).unwrap();
+            let outputs = builder.add_dataflow_op(op, targets.iter().map(|q| qubits[*q])).unwrap().outputs().collect_vec();
+            targets.iter().zip(outputs).for_each(|(q, wire)| qubits[*q] = wire);
+        } else if step == Some(3) {
+            let [fresh] = builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().outputs_arr();
+            qubits.push(fresh);
+            add_random_steps(builder, qubits, rng.random_range(0..=nested_depth), config, rng);
+            let freed = qubits.remove(rng.random_range(0..qubits.len()));
+            builder.add_dataflow_op(TketOp::QFree, [freed]).unwrap();
+        } else {
+            add_random_gate(builder, qubits, config, rng);
+        }
+    }
+}
+
+/// Adds one of config.gates on distinct random qubits, doing nothing if no gate acts on few enough qubits
+fn add_random_gate<D: Dataflow>(builder: &mut D, qubits: &mut [Wire], config: &RandomCircuitConfig, rng: &mut StdRng) {
+    let signature = |op: TketOp| OpType::from(op).dataflow_signature().unwrap().into_owned();
+    let nb_qubit_inputs = |sig: &Signature| sig.input_types().iter().filter(|t| **t == qb_t()).count();
+    let candidates = config.gates.iter().copied().filter(|op| nb_qubit_inputs(&signature(*op)) <= qubits.len()).collect_vec();
+    let Some(op) = candidates.choose(rng).copied() else {
+        return;
+    };
+    let sig = signature(op);
+    assert!(sig.output_types().iter().all(|t| *t == qb_t() || t.copyable()), "{op:?} has a linear output other than a qubit");
+    let mut targets = rand::seq::index::sample(rng, qubits.len(), nb_qubit_inputs(&sig)).into_iter();
+    let mut inputs = vec![];
+    let mut used = vec![];
+    for t in sig.input_types().iter() {
+        if *t == qb_t() {
+            let q = targets.next().unwrap();
+            inputs.push(qubits[q]);
+            used.push(q);
+        } else {
+            assert!(*t == rotation_type(), "{op:?} has an input other than a qubit or a rotation");
+            inputs.push(builder.add_load_value(Value::extension(ConstRotation::new(rng.random_range(0..8) as f64 / 4.).unwrap())));
+        }
+    }
+    let gate = builder.add_dataflow_op(op, inputs).unwrap();
+    let mut outputs = sig.output_types().iter().enumerate().filter(|(_, t)| **t == qb_t()).map(|(p, _)| gate.out_wire(p)).collect_vec().into_iter();
+    for q in used {
+        qubits[q] = match outputs.next() {
+            Some(wire) => wire,
+            None => builder.add_dataflow_op(TketOp::QAlloc, []).unwrap().out_wire(0),
+        };
+    }
+    for extra in outputs {
+        builder.add_dataflow_op(TketOp::QFree, [extra]).unwrap();
+    }
+}
+
+/// Measures a random qubit, putting the measured qubit back in its place and returning the wire carrying the outcome
+fn measure_random_qubit<D: Dataflow>(builder: &mut D, qubits: &mut [Wire], rng: &mut StdRng) -> Wire {
+    let q = rng.random_range(0..qubits.len());
+    let [qb, outcome] = builder.add_dataflow_op(TketOp::Measure, [qubits[q]]).unwrap().outputs_arr();
+    qubits[q] = qb;
+    outcome
+}